@@ -404,7 +404,7 @@ public final class Native {
 
   public static native void PreKeyBundle_Destroy(long handle);
   public static native int PreKeyBundle_GetDeviceId(long obj) throws Exception;
-  public static native long PreKeyBundle_GetIdentityKey(long p) throws Exception;
+  public static native long PreKeyBundle_GetIdentityKey(long obj) throws Exception;
   public static native int PreKeyBundle_GetKyberPreKeyId(long obj) throws Exception;
   public static native long PreKeyBundle_GetKyberPreKeyPublic(long bundle) throws Exception;
   public static native byte[] PreKeyBundle_GetKyberPreKeySignature(long bundle) throws Exception;
@@ -498,6 +498,7 @@ public final class Native {
   public static native long SenderCertificate_GetKey(long obj) throws Exception;
   public static native String SenderCertificate_GetSenderE164(long obj) throws Exception;
   public static native String SenderCertificate_GetSenderUuid(long obj) throws Exception;
+  public static native byte[] SenderCertificate_GetSenderUuidBytes(long obj) throws Exception;
   public static native byte[] SenderCertificate_GetSerialized(long obj) throws Exception;
   public static native long SenderCertificate_GetServerCertificate(long cert) throws Exception;
   public static native byte[] SenderCertificate_GetSignature(long obj) throws Exception;
@@ -65,6 +65,13 @@ public class SenderCertificate implements NativeHandleGuard.Owner {
     }
   }
 
+  public byte[] getSenderUuidBytes() {
+    try (NativeHandleGuard guard = new NativeHandleGuard(this)) {
+      return filterExceptions(
+          () -> Native.SenderCertificate_GetSenderUuidBytes(guard.nativeHandle()));
+    }
+  }
+
   public Optional<String> getSenderE164() {
     try (NativeHandleGuard guard = new NativeHandleGuard(this)) {
       return Optional.ofNullable(
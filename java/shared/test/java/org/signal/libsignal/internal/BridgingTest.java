@@ -20,6 +20,15 @@ public class BridgingTest {
         IllegalArgumentException.class, () -> Native.TESTING_ErrorOnBorrowIo(-1, null).get());
   }
 
+  @Test
+  public void testDestroyToleratesZeroedHandle() {
+    long handle = Native.ECPrivateKey_Generate();
+    Native.ECPrivateKey_Destroy(handle);
+    // Owners zero their handle after the first free, so a second destroy must be a no-op.
+    handle = 0;
+    Native.ECPrivateKey_Destroy(handle);
+  }
+
   @Test
   public void testPanicOnBorrow() throws Exception {
     assertThrows(AssertionError.class, () -> Native.TESTING_PanicOnBorrowSync(null));
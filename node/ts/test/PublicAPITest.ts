@@ -335,6 +335,8 @@ describe('SignalClient', () => {
       SignalClient.hkdf(42, secret, label, salt).toString('hex'),
       '3cb25f25faacd57a90434f64d0362f2a2d2d0a90cf1a5a4c5db02d56ecc4c5bf34007208d5b887185865'
     );
+
+    assert.throws(() => SignalClient.hkdf(0, secret, empty, empty));
   });
   describe('ServiceId', () => {
     const testingUuid = '8c78cd2a-16ff-427d-83dc-1a5e36ce713d';
@@ -8,12 +8,13 @@
 
 use futures_util::FutureExt;
 use libsignal_bridge::ffi::*;
+use libsignal_bridge::rng::BridgeRng;
 #[cfg(feature = "testing-fns")]
 #[allow(unused_imports)]
 use libsignal_bridge_testing::*;
 use libsignal_protocol::*;
 
-use std::ffi::{c_char, c_uchar, c_uint, CString};
+use std::ffi::{c_char, c_int, c_uchar, c_uint, c_void, CString};
 use std::panic::AssertUnwindSafe;
 
 pub mod logging;
@@ -31,6 +32,9 @@ pub unsafe extern "C" fn signal_free_string(buf: *const c_char) {
     drop(CString::from_raw(buf as _));
 }
 
+/// Frees a byte buffer returned by libsignal, such as a serialized key.
+///
+/// Does nothing if `buf` is null.
 #[no_mangle]
 pub unsafe extern "C" fn signal_free_buffer(buf: *const c_uchar, buf_len: usize) {
     if buf.is_null() {
@@ -63,6 +67,14 @@ pub unsafe extern "C" fn signal_free_bytestring_array(array: BytestringArray) {
     drop(array.into_boxed_parts())
 }
 
+/// Writes the version of libsignal this library was built from to `out`, such as "0.54.0".
+///
+/// The string must be freed with `signal_free_string`.
+#[no_mangle]
+pub unsafe extern "C" fn signal_get_version_string(out: *mut *const c_char) -> *mut SignalFfiError {
+    run_ffi_safe(|| write_result_to(out, env!("CARGO_PKG_VERSION")))
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn signal_error_get_message(
     err: *const SignalFfiError,
@@ -174,6 +186,26 @@ pub unsafe extern "C" fn signal_error_get_unknown_fields(
     })
 }
 
+/// Writes the backtrace recorded when `err` was created to `out`.
+///
+/// Backtraces are only recorded when built with the `capture-backtrace` feature; otherwise this
+/// writes an empty string. The string must be freed with `signal_free_string`.
+#[no_mangle]
+pub unsafe extern "C" fn signal_error_get_backtrace(
+    err: *const SignalFfiError,
+    out: *mut *const c_char,
+) -> *mut SignalFfiError {
+    let result = (|| {
+        let err = err.as_ref().ok_or(NullPointerError)?;
+        write_result_to(out, err.backtrace().unwrap_or_default())
+    })();
+
+    match result {
+        Ok(()) => std::ptr::null_mut(),
+        Err(e) => Box::into_raw(Box::new(e)),
+    }
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn signal_error_free(err: *mut SignalFfiError) {
     if !err.is_null() {
@@ -181,6 +213,31 @@ pub unsafe extern "C" fn signal_error_free(err: *mut SignalFfiError) {
     }
 }
 
+/// Returns the code of the most recent error returned on the calling thread, or 0 if there is none.
+///
+/// Errors are still returned (and must still be freed) as usual; this is only a convenience for
+/// callers that don't inspect every result.
+#[no_mangle]
+pub unsafe extern "C" fn signal_error_get_last_type() -> u32 {
+    last_error_code().unwrap_or(0)
+}
+
+/// Writes the description of the most recent error returned on the calling thread to `out`, or
+/// null if there is none, and then forgets that error.
+///
+/// The string must be freed with `signal_free_string`.
+#[no_mangle]
+pub unsafe extern "C" fn signal_error_take_last_message(
+    out: *mut *const c_char,
+) -> *mut SignalFfiError {
+    run_ffi_safe(|| write_result_to(out, take_last_error_message()))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn signal_error_clear_last() {
+    clear_last_error()
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn signal_identitykeypair_deserialize(
     private_key: *mut *mut PrivateKey,
@@ -202,6 +259,7 @@ pub unsafe extern "C" fn signal_sealed_session_cipher_decrypt(
     sender_e164: *mut *const c_char,
     sender_uuid: *mut *const c_char,
     sender_device_id: *mut u32,
+    sender_service_id_type: *mut u8,
     ctext: BorrowedSliceOf<c_uchar>,
     trust_root: *const PublicKey,
     timestamp: u64,
@@ -225,6 +283,20 @@ pub unsafe extern "C" fn signal_sealed_session_cipher_decrypt(
         let local_e164 = Option::convert_from(local_e164)?;
         let local_uuid = Option::convert_from(local_uuid)?.ok_or(NullPointerError)?;
 
+        // Check the sender's ID before the real decryption, which commits the session and
+        // consumes any pre-key. Failing afterwards would lose the message.
+        let usmc = sealed_sender_decrypt_to_usmc(ctext, &identity_store)
+            .now_or_never()
+            .expect("synchronous")?;
+        let sender_service_id = ServiceId::parse_from_service_id_string(
+            usmc.sender()?.sender_uuid()?,
+        )
+        .ok_or_else(|| {
+            SignalProtocolError::InvalidSealedSenderMessage(
+                "sender UUID is not a valid service ID".to_owned(),
+            )
+        })?;
+
         let decrypted = sealed_sender_decrypt(
             ctext,
             trust_root,
@@ -244,7 +316,1602 @@ pub unsafe extern "C" fn signal_sealed_session_cipher_decrypt(
         write_result_to(sender_e164, decrypted.sender_e164)?;
         write_result_to(sender_uuid, decrypted.sender_uuid)?;
         write_result_to(sender_device_id, u32::from(decrypted.device_id))?;
+        write_result_to(sender_service_id_type, u8::from(sender_service_id.kind()))?;
         write_result_to(out, decrypted.message)?;
         Ok(())
     })
 }
+
+/// Decrypts a group message, writing the plaintext to `out` and the sender key iteration the
+/// message was encrypted with to `out_iteration`.
+///
+/// If the message came from `signal_group_encrypt_with_expiry`, writes `true` to `out_has_expiry`
+/// and the disappearing-message timer to `out_expiry_seconds`. Otherwise writes `false` and 0.
+#[no_mangle]
+pub unsafe extern "C" fn signal_group_decrypt_with_expiry(
+    out: *mut OwnedBufferOf<c_uchar>,
+    out_has_expiry: *mut bool,
+    out_expiry_seconds: *mut u32,
+    out_iteration: *mut u32,
+    sender: *const ProtocolAddress,
+    message: BorrowedSliceOf<c_uchar>,
+    store: *const FfiSenderKeyStoreStruct,
+) -> *mut SignalFfiError {
+    run_ffi_safe(|| {
+        let sender = native_handle_cast(sender)?;
+        let message = message.as_slice()?;
+        let mut store = store.as_ref().ok_or(NullPointerError)?;
+
+        let iteration = SenderKeyMessage::try_from(message)?.iteration();
+        let (plaintext, expiry_seconds) = group_decrypt_with_expiry(message, &mut store, sender)
+            .now_or_never()
+            .expect("synchronous")?;
+
+        write_result_to(out_has_expiry, expiry_seconds.is_some())?;
+        write_result_to(out_expiry_seconds, expiry_seconds.unwrap_or(0))?;
+        write_result_to(out_iteration, iteration)?;
+        write_result_to(out, plaintext)?;
+        Ok(())
+    })
+}
+
+/// Decrypts a message like `signal_decrypt_pre_key_message`, and also writes the version of the
+/// resulting session to `out_session_version`.
+///
+/// If `out_session_version` is null the version is not reported.
+#[no_mangle]
+#[allow(clippy::too_many_arguments)]
+pub unsafe extern "C" fn signal_decrypt_pre_key_message_with_session_version(
+    out: *mut OwnedBufferOf<c_uchar>,
+    out_session_version: *mut u32,
+    message: *const PreKeySignalMessage,
+    protocol_address: *const ProtocolAddress,
+    session_store: *const FfiSessionStoreStruct,
+    identity_key_store: *const FfiIdentityKeyStoreStruct,
+    prekey_store: *const FfiPreKeyStoreStruct,
+    signed_prekey_store: *const FfiSignedPreKeyStoreStruct,
+    kyber_prekey_store: *const FfiKyberPreKeyStoreStruct,
+) -> *mut SignalFfiError {
+    run_ffi_safe(|| {
+        let message = native_handle_cast(message)?;
+        let protocol_address = native_handle_cast(protocol_address)?;
+        let mut session_store = session_store.as_ref().ok_or(NullPointerError)?;
+        let mut identity_key_store = identity_key_store.as_ref().ok_or(NullPointerError)?;
+        let mut prekey_store = prekey_store.as_ref().ok_or(NullPointerError)?;
+        let signed_prekey_store = signed_prekey_store.as_ref().ok_or(NullPointerError)?;
+        let mut kyber_prekey_store = kyber_prekey_store.as_ref().ok_or(NullPointerError)?;
+
+        let plaintext = message_decrypt_prekey(
+            message,
+            protocol_address,
+            &mut session_store,
+            &mut identity_key_store,
+            &mut prekey_store,
+            &signed_prekey_store,
+            &mut kyber_prekey_store,
+            &mut libsignal_bridge::rng::BridgeRng,
+        )
+        .now_or_never()
+        .expect("synchronous")?;
+
+        if !out_session_version.is_null() {
+            let session_version = session_store
+                .load_session(protocol_address)
+                .now_or_never()
+                .expect("synchronous")?
+                .ok_or_else(|| SignalProtocolError::SessionNotFound(protocol_address.clone()))?
+                .session_version()?;
+            write_result_to(out_session_version, session_version)?;
+        }
+        write_result_to(out, plaintext)?;
+        Ok(())
+    })
+}
+
+/// Decrypts a message like `signal_decrypt_message`, and also writes to `out_identity_changed`
+/// whether the sender's identity key is untrusted by `identity_key_store`.
+///
+/// If `allow_identity_change` is false, an untrusted identity is an error, as in
+/// `signal_decrypt_message`. Otherwise the message is decrypted anyway, and the new identity is
+/// left for the caller to save if it accepts it.
+#[no_mangle]
+pub unsafe extern "C" fn signal_decrypt_message_reporting_identity_change(
+    out: *mut OwnedBufferOf<c_uchar>,
+    out_identity_changed: *mut bool,
+    message: *const SignalMessage,
+    protocol_address: *const ProtocolAddress,
+    session_store: *const FfiSessionStoreStruct,
+    identity_key_store: *const FfiIdentityKeyStoreStruct,
+    allow_identity_change: bool,
+) -> *mut SignalFfiError {
+    run_ffi_safe(|| {
+        let message = native_handle_cast(message)?;
+        let protocol_address = native_handle_cast(protocol_address)?;
+        let mut session_store = session_store.as_ref().ok_or(NullPointerError)?;
+        let mut identity_key_store = identity_key_store.as_ref().ok_or(NullPointerError)?;
+        if out_identity_changed.is_null() {
+            return Err(NullPointerError.into());
+        }
+
+        let (plaintext, identity_changed) = message_decrypt_signal_reporting_identity_change(
+            message,
+            protocol_address,
+            &mut session_store,
+            &mut identity_key_store,
+            allow_identity_change,
+            &mut libsignal_bridge::rng::BridgeRng,
+        )
+        .now_or_never()
+        .expect("synchronous")?;
+
+        write_result_to(out_identity_changed, identity_changed)?;
+        write_result_to(out, plaintext)?;
+        Ok(())
+    })
+}
+
+/// Copies `serialized` into `buffer`, always reporting the number of bytes needed in
+/// `out_written`.
+///
+/// If `buffer` is too small, nothing is copied and a [`BufferTooSmallError`] is returned, so the
+/// caller can retry with a buffer of the reported size.
+unsafe fn write_serialized_into(
+    out_written: *mut usize,
+    mut buffer: BorrowedMutableSliceOf<c_uchar>,
+    serialized: &[u8],
+) -> SignalFfiResult<()> {
+    write_result_to(out_written, serialized.len())?;
+    let buffer = buffer.as_slice_mut()?;
+    if buffer.len() < serialized.len() {
+        return Err(BufferTooSmallError {
+            required: serialized.len(),
+            provided: buffer.len(),
+        }
+        .into());
+    }
+    buffer[..serialized.len()].copy_from_slice(serialized);
+    Ok(())
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn signal_session_record_serialize_into(
+    out_written: *mut usize,
+    obj: *const SessionRecord,
+    buffer: BorrowedMutableSliceOf<c_uchar>,
+) -> *mut SignalFfiError {
+    run_ffi_safe(|| {
+        let obj = native_handle_cast(obj)?;
+        write_serialized_into(out_written, buffer, &obj.serialize()?)
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn signal_sender_key_record_serialize_into(
+    out_written: *mut usize,
+    obj: *const SenderKeyRecord,
+    buffer: BorrowedMutableSliceOf<c_uchar>,
+) -> *mut SignalFfiError {
+    run_ffi_safe(|| {
+        let obj = native_handle_cast(obj)?;
+        write_serialized_into(out_written, buffer, &obj.serialize()?)
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn signal_pre_key_record_serialize_into(
+    out_written: *mut usize,
+    obj: *const PreKeyRecord,
+    buffer: BorrowedMutableSliceOf<c_uchar>,
+) -> *mut SignalFfiError {
+    run_ffi_safe(|| {
+        let obj = native_handle_cast(obj)?;
+        write_serialized_into(out_written, buffer, &obj.serialize()?)
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn signal_signed_pre_key_record_serialize_into(
+    out_written: *mut usize,
+    obj: *const SignedPreKeyRecord,
+    buffer: BorrowedMutableSliceOf<c_uchar>,
+) -> *mut SignalFfiError {
+    run_ffi_safe(|| {
+        let obj = native_handle_cast(obj)?;
+        write_serialized_into(out_written, buffer, &obj.serialize()?)
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn signal_kyber_pre_key_record_serialize_into(
+    out_written: *mut usize,
+    obj: *const KyberPreKeyRecord,
+    buffer: BorrowedMutableSliceOf<c_uchar>,
+) -> *mut SignalFfiError {
+    run_ffi_safe(|| {
+        let obj = native_handle_cast(obj)?;
+        write_serialized_into(out_written, buffer, &obj.serialize()?)
+    })
+}
+
+/// Validates `count` sender certificates against `trust_root` at `timestamp`, writing one result
+/// per certificate to `out_results`.
+#[no_mangle]
+pub unsafe extern "C" fn signal_sender_certificate_validate_batch(
+    certs: *const *const SenderCertificate,
+    count: usize,
+    trust_root: *const PublicKey,
+    timestamp: u64,
+    out_results: *mut bool,
+) -> *mut SignalFfiError {
+    run_ffi_safe(|| {
+        let trust_root = native_handle_cast(trust_root)?;
+        if count == 0 {
+            return Ok(());
+        }
+        if certs.is_null() || out_results.is_null() {
+            return Err(NullPointerError.into());
+        }
+        let certs = std::slice::from_raw_parts(certs, count);
+        let results = std::slice::from_raw_parts_mut(out_results, count);
+        let time = Timestamp::from_epoch_millis(timestamp);
+        for (&cert, result) in certs.iter().zip(results) {
+            *result = native_handle_cast(cert)?.validate(trust_root, time)?;
+        }
+        Ok(())
+    })
+}
+
+/// The verification level reported for each address by
+/// [`signal_identity_store_get_verified_batch`].
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IdentityVerificationLevel {
+    /// No identity has been saved for the address.
+    Unknown = 0,
+    /// The saved identity has not been verified.
+    Untrusted = 1,
+    /// The saved identity has been verified.
+    Trusted = 2,
+}
+
+impl TryFrom<u8> for IdentityVerificationLevel {
+    type Error = SignalProtocolError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(Self::Unknown),
+            1 => Ok(Self::Untrusted),
+            2 => Ok(Self::Trusted),
+            _ => Err(SignalProtocolError::InvalidArgument(format!(
+                "invalid identity verification level {value}"
+            ))),
+        }
+    }
+}
+
+/// Reports the store's verification level for the identity saved for `address`.
+pub type GetIdentityVerificationLevel =
+    extern "C" fn(ctx: *mut c_void, levelp: *mut u8, address: *const ProtocolAddress) -> c_int;
+
+async fn identity_verification_level(
+    identity_store: &dyn IdentityKeyStore,
+    address: &ProtocolAddress,
+    ctx: *mut c_void,
+    get_verification_level: GetIdentityVerificationLevel,
+) -> Result<IdentityVerificationLevel, SignalProtocolError> {
+    if identity_store.get_identity(address).await?.is_none() {
+        return Ok(IdentityVerificationLevel::Unknown);
+    }
+    let mut level = u8::MAX;
+    let result = get_verification_level(ctx, &mut level, address);
+    CallbackError::check(result).map_err(SignalProtocolError::for_application_callback(
+        "get_identity_verification_level",
+    ))?;
+    level.try_into()
+}
+
+/// Looks up the verification level of each of `count` addresses, writing one level per address
+/// to `out_levels`: 0 if no identity is saved, and otherwise whatever `get_verification_level`
+/// reports for the address (1 if its identity has not been verified, 2 if it has).
+///
+/// `get_verification_level` is called with `ctx` and is only asked about addresses with a saved
+/// identity.
+#[no_mangle]
+pub unsafe extern "C" fn signal_identity_store_get_verified_batch(
+    identity_store: *const FfiIdentityKeyStoreStruct,
+    addresses: *const *const ProtocolAddress,
+    count: usize,
+    ctx: *mut c_void,
+    get_verification_level: GetIdentityVerificationLevel,
+    out_levels: *mut u8,
+) -> *mut SignalFfiError {
+    run_ffi_safe(|| {
+        let identity_store = identity_store.as_ref().ok_or(NullPointerError)?;
+        if count == 0 {
+            return Ok(());
+        }
+        if addresses.is_null() || out_levels.is_null() {
+            return Err(NullPointerError.into());
+        }
+        let addresses = std::slice::from_raw_parts(addresses, count);
+        let levels = std::slice::from_raw_parts_mut(out_levels, count);
+        for (&address, level) in addresses.iter().zip(levels) {
+            let address = native_handle_cast(address)?;
+            *level =
+                identity_verification_level(&identity_store, address, ctx, get_verification_level)
+                    .now_or_never()
+                    .expect("synchronous")? as u8;
+        }
+        Ok(())
+    })
+}
+
+/// Deserializes each of `count` public keys, where key `i` is the `lengths[i]` bytes at
+/// `data_array[i]`.
+///
+/// Writes a handle for each key to `out_handles` and whether it was valid to `out_results`. An
+/// invalid key, or a null entry in `data_array`, gets a null handle rather than failing the whole
+/// batch.
+#[no_mangle]
+pub unsafe extern "C" fn signal_publickey_deserialize_validate_batch(
+    data_array: *const *const c_uchar,
+    lengths: *const usize,
+    count: usize,
+    out_handles: *mut *mut PublicKey,
+    out_results: *mut bool,
+) -> *mut SignalFfiError {
+    run_ffi_safe(|| {
+        if count == 0 {
+            return Ok(());
+        }
+        if data_array.is_null()
+            || lengths.is_null()
+            || out_handles.is_null()
+            || out_results.is_null()
+        {
+            return Err(NullPointerError.into());
+        }
+        let data_array = std::slice::from_raw_parts(data_array, count);
+        let lengths = std::slice::from_raw_parts(lengths, count);
+        let handles = std::slice::from_raw_parts_mut(out_handles, count);
+        let results = std::slice::from_raw_parts_mut(out_results, count);
+        for (((&data, &length), handle), result) in
+            data_array.iter().zip(lengths).zip(handles).zip(results)
+        {
+            let key = if data.is_null() {
+                None
+            } else {
+                PublicKey::deserialize(std::slice::from_raw_parts(data, length)).ok()
+            };
+            match key {
+                Some(key) => {
+                    write_result_to(handle, key)?;
+                    *result = true;
+                }
+                None => {
+                    *handle = std::ptr::null_mut();
+                    *result = false;
+                }
+            }
+        }
+        Ok(())
+    })
+}
+
+/// Result written by [`signal_publickey_verify_batch`] for an item that couldn't be checked at all.
+pub const VERIFY_BATCH_MALFORMED_ITEM: u8 = 0xFF;
+
+/// Verifies each of `count` signatures, where item `i` is `signatures[i]` (`signature_lens[i]`
+/// bytes) over `messages[i]` (`message_lens[i]` bytes) by `keys[i]`.
+///
+/// Writes one result per item to `out_results`: 1 if the signature is valid, 0 if it is not, and
+/// [`VERIFY_BATCH_MALFORMED_ITEM`] if the key is null or a message or signature is null with a
+/// nonzero length. A malformed item does not stop the rest of the batch from being checked.
+#[no_mangle]
+pub unsafe extern "C" fn signal_publickey_verify_batch(
+    keys: *const *const PublicKey,
+    messages: *const *const c_uchar,
+    message_lens: *const usize,
+    signatures: *const *const c_uchar,
+    signature_lens: *const usize,
+    count: usize,
+    out_results: *mut u8,
+) -> *mut SignalFfiError {
+    run_ffi_safe(|| {
+        if count == 0 {
+            return Ok(());
+        }
+        if keys.is_null()
+            || messages.is_null()
+            || message_lens.is_null()
+            || signatures.is_null()
+            || signature_lens.is_null()
+            || out_results.is_null()
+        {
+            return Err(NullPointerError.into());
+        }
+        let keys = std::slice::from_raw_parts(keys, count);
+        let messages = std::slice::from_raw_parts(messages, count);
+        let message_lens = std::slice::from_raw_parts(message_lens, count);
+        let signatures = std::slice::from_raw_parts(signatures, count);
+        let signature_lens = std::slice::from_raw_parts(signature_lens, count);
+        let results = std::slice::from_raw_parts_mut(out_results, count);
+
+        let as_slice = |data: *const c_uchar, length: usize| -> Option<&[u8]> {
+            match (data.is_null(), length) {
+                (true, 0) => Some(&[]),
+                (true, _) => None,
+                (false, _) => Some(std::slice::from_raw_parts(data, length)),
+            }
+        };
+
+        for (i, result) in results.iter_mut().enumerate() {
+            let key = keys[i].as_ref();
+            let message = as_slice(messages[i], message_lens[i]);
+            let signature = as_slice(signatures[i], signature_lens[i]);
+            *result = match (key, message, signature) {
+                (Some(key), Some(message), Some(signature)) => {
+                    match key.verify_signature(message, signature) {
+                        Ok(valid) => valid.into(),
+                        Err(_) => VERIFY_BATCH_MALFORMED_ITEM,
+                    }
+                }
+                _ => VERIFY_BATCH_MALFORMED_ITEM,
+            };
+        }
+        Ok(())
+    })
+}
+
+/// Generates `count` one-time prekeys with consecutive ids starting at `start_id`, writing a
+/// handle for each to `out_handles`.
+///
+/// Ids are assigned modulo 2^32, so a batch that runs past `u32::MAX` continues from 0. Since
+/// that would repeat ids, `count` may not exceed 2^32.
+#[no_mangle]
+pub unsafe extern "C" fn signal_generate_pre_keys(
+    out_handles: *mut *mut PreKeyRecord,
+    start_id: u32,
+    count: usize,
+) -> *mut SignalFfiError {
+    run_ffi_safe(|| {
+        if count == 0 {
+            return Ok(());
+        }
+        if out_handles.is_null() {
+            return Err(NullPointerError.into());
+        }
+        if count as u64 > 1 << 32 {
+            return Err(SignalProtocolError::InvalidArgument(format!(
+                "cannot generate {count} prekeys with distinct ids"
+            ))
+            .into());
+        }
+        let handles = std::slice::from_raw_parts_mut(out_handles, count);
+        let mut rng = BridgeRng;
+        for (handle, id) in handles
+            .iter_mut()
+            .zip((0..).map(|i| start_id.wrapping_add(i)))
+        {
+            let key_pair = KeyPair::generate(&mut rng);
+            write_result_to(handle, PreKeyRecord::new(id.into(), &key_pair))?;
+        }
+        Ok(())
+    })
+}
+
+/// Generates a signed prekey with the given id and timestamp (in milliseconds since the epoch),
+/// signed by `identity_key_pair`.
+///
+/// This is also how a client rotates its signed prekey: each call generates a fresh key pair.
+#[no_mangle]
+pub unsafe extern "C" fn signal_generate_signed_pre_key(
+    out: *mut *mut SignedPreKeyRecord,
+    identity_key_pair: *const IdentityKeyPair,
+    signed_pre_key_id: u32,
+    timestamp: u64,
+) -> *mut SignalFfiError {
+    run_ffi_safe(|| {
+        let identity_key_pair = native_handle_cast(identity_key_pair)?;
+        let mut rng = BridgeRng;
+        let key_pair = KeyPair::generate(&mut rng);
+        let signature = identity_key_pair
+            .private_key()
+            .calculate_signature(&key_pair.public_key.serialize(), &mut rng)?;
+        let record = SignedPreKeyRecord::new(
+            signed_pre_key_id.into(),
+            Timestamp::from_epoch_millis(timestamp),
+            &key_pair,
+            &signature,
+        );
+        write_result_to(out, record)
+    })
+}
+
+/// Extracts the key pair of a signed prekey record as separate handles.
+///
+/// The keys are copies, so each can be destroyed independently of the other and of `record`.
+#[no_mangle]
+pub unsafe extern "C" fn signal_signed_pre_key_record_get_key_pair(
+    out_public: *mut *mut PublicKey,
+    out_private: *mut *mut PrivateKey,
+    record: *const SignedPreKeyRecord,
+) -> *mut SignalFfiError {
+    run_ffi_safe(|| {
+        let key_pair = native_handle_cast(record)?.key_pair()?;
+        // Check both outputs first so a failure can't leave one handle behind.
+        if out_public.is_null() || out_private.is_null() {
+            return Err(NullPointerError.into());
+        }
+        write_result_to(out_public, key_pair.public_key)?;
+        write_result_to(out_private, key_pair.private_key)
+    })
+}
+
+/// Destroys each of the `count` handles in `ptrs` in a single call, skipping nulls.
+///
+/// This is equivalent to calling `signal_pre_key_record_destroy` on each one, such as for the
+/// output of [`signal_generate_pre_keys`]. The array itself is not freed.
+#[no_mangle]
+pub unsafe extern "C" fn signal_pre_key_record_destroy_array(
+    ptrs: *const *mut PreKeyRecord,
+    count: usize,
+) -> *mut SignalFfiError {
+    run_ffi_safe(|| {
+        if count == 0 {
+            return Ok(());
+        }
+        if ptrs.is_null() {
+            return Err(NullPointerError.into());
+        }
+        for &handle in std::slice::from_raw_parts(ptrs, count) {
+            if !handle.is_null() {
+                drop(PreKeyRecord::from_raw_handle(handle));
+            }
+        }
+        Ok(())
+    })
+}
+
+/// Serializes each of the `count` records in `records`, writing a newly-allocated buffer for each
+/// to `out_buffers` and its length to `out_lens`.
+///
+/// Each buffer must be freed with [`signal_free_buffer`]. A null record fails the whole call
+/// (before anything is allocated) rather than being skipped.
+#[no_mangle]
+pub unsafe extern "C" fn signal_pre_key_record_serialize_all(
+    records: *const *const PreKeyRecord,
+    count: usize,
+    out_buffers: *mut *const c_uchar,
+    out_lens: *mut usize,
+) -> *mut SignalFfiError {
+    run_ffi_safe(|| {
+        if count == 0 {
+            return Ok(());
+        }
+        if records.is_null() || out_buffers.is_null() || out_lens.is_null() {
+            return Err(NullPointerError.into());
+        }
+        let serialized = std::slice::from_raw_parts(records, count)
+            .iter()
+            .map(|&record| Ok(native_handle_cast(record)?.serialize()?.into_boxed_slice()))
+            .collect::<Result<Vec<_>, SignalFfiError>>()?;
+
+        let buffers = std::slice::from_raw_parts_mut(out_buffers, count);
+        let lens = std::slice::from_raw_parts_mut(out_lens, count);
+        for ((bytes, buffer), len) in serialized.into_iter().zip(buffers).zip(lens) {
+            *len = bytes.len();
+            *buffer = Box::into_raw(bytes) as *const c_uchar;
+        }
+        Ok(())
+    })
+}
+
+unsafe fn find_duplicate_id<T: BridgeHandle, Id: Eq + std::hash::Hash>(
+    records: *const *const T,
+    count: usize,
+    out_found: *mut bool,
+    out_index: *mut usize,
+    id: impl Fn(&T) -> Result<Id, SignalProtocolError>,
+) -> Result<(), SignalFfiError> {
+    if out_found.is_null() || out_index.is_null() {
+        return Err(NullPointerError.into());
+    }
+    let records = if count == 0 {
+        &[]
+    } else if records.is_null() {
+        return Err(NullPointerError.into());
+    } else {
+        std::slice::from_raw_parts(records, count)
+    };
+
+    let mut seen = std::collections::HashSet::with_capacity(count);
+    for (i, &record) in records.iter().enumerate() {
+        if !seen.insert(id(native_handle_cast(record)?)?) {
+            *out_found = true;
+            *out_index = i;
+            return Ok(());
+        }
+    }
+    *out_found = false;
+    *out_index = 0;
+    Ok(())
+}
+
+/// Checks whether any two of the `count` records in `records` share an id.
+///
+/// If so, writes `true` to `out_found` and the index of the first record whose id appeared earlier
+/// in the array to `out_index`. Otherwise writes `false` and 0. A null record fails the whole call.
+#[no_mangle]
+pub unsafe extern "C" fn signal_pre_key_record_find_duplicate_id(
+    records: *const *const PreKeyRecord,
+    count: usize,
+    out_found: *mut bool,
+    out_index: *mut usize,
+) -> *mut SignalFfiError {
+    run_ffi_safe(|| find_duplicate_id(records, count, out_found, out_index, PreKeyRecord::id))
+}
+
+/// Like [`signal_pre_key_record_find_duplicate_id`], for signed prekeys.
+#[no_mangle]
+pub unsafe extern "C" fn signal_signed_pre_key_record_find_duplicate_id(
+    records: *const *const SignedPreKeyRecord,
+    count: usize,
+    out_found: *mut bool,
+    out_index: *mut usize,
+) -> *mut SignalFfiError {
+    run_ffi_safe(|| find_duplicate_id(records, count, out_found, out_index, SignedPreKeyRecord::id))
+}
+
+/// Starts incrementally decrypting a sealed-sender v1 message addressed to `identity_key_pair`.
+///
+/// See [`SealedSenderDecryptStream`] for which messages are supported.
+#[no_mangle]
+pub unsafe extern "C" fn signal_sealed_sender_decrypt_stream_new(
+    out: *mut *mut SealedSenderDecryptStream,
+    identity_key_pair: *const IdentityKeyPair,
+) -> *mut SignalFfiError {
+    run_ffi_safe(|| {
+        let identity_key_pair = native_handle_cast(identity_key_pair)?;
+        write_result_to(out, SealedSenderDecryptStream::new(*identity_key_pair))
+    })
+}
+
+/// Feeds the next chunk of a sealed-sender message to `stream`.
+///
+/// No plaintext is produced here; it is held back until
+/// [`signal_sealed_sender_decrypt_stream_finalize`] has verified the message.
+#[no_mangle]
+pub unsafe extern "C" fn signal_sealed_sender_decrypt_stream_update(
+    stream: *mut SealedSenderDecryptStream,
+    input: BorrowedSliceOf<c_uchar>,
+) -> *mut SignalFfiError {
+    run_ffi_safe(|| {
+        let stream = native_handle_cast_mut(stream)?;
+        stream.update(input.as_slice()?)?;
+        Ok(())
+    })
+}
+
+/// Verifies a sealed-sender message after all of it has been passed to
+/// [`signal_sealed_sender_decrypt_stream_update`], writing the plaintext to `out` only if the MAC
+/// matched.
+///
+/// A MAC mismatch is reported as `SignalErrorCodeSealedSenderMacVerificationFailed`, and leaves
+/// `out` untouched. The stream can't be used again either way, but must still be destroyed.
+#[no_mangle]
+pub unsafe extern "C" fn signal_sealed_sender_decrypt_stream_finalize(
+    out: *mut OwnedBufferOf<c_uchar>,
+    stream: *mut SealedSenderDecryptStream,
+) -> *mut SignalFfiError {
+    run_ffi_safe(|| {
+        let stream = native_handle_cast_mut(stream)?;
+        write_result_to(out, stream.finalize()?)
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::alloc::{GlobalAlloc, Layout, System};
+    use std::cell::Cell;
+    use std::ffi::{c_int, c_void};
+    use std::mem::MaybeUninit;
+
+    use super::*;
+
+    thread_local! {
+        /// Bytes allocated on the current thread and not yet freed.
+        static LIVE_BYTES: Cell<isize> = const { Cell::new(0) };
+    }
+
+    struct TrackingAllocator;
+
+    unsafe impl GlobalAlloc for TrackingAllocator {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            let _ = LIVE_BYTES.try_with(|live| live.set(live.get() + layout.size() as isize));
+            System.alloc(layout)
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            let _ = LIVE_BYTES.try_with(|live| live.set(live.get() - layout.size() as isize));
+            System.dealloc(ptr, layout)
+        }
+    }
+
+    #[global_allocator]
+    static ALLOCATOR: TrackingAllocator = TrackingAllocator;
+
+    /// The layout of [`OwnedBufferOf`] as seen by C callers.
+    #[repr(C)]
+    struct RawBuffer {
+        base: *const c_uchar,
+        length: usize,
+    }
+
+    #[test]
+    fn free_buffer_releases_serialized_public_key() {
+        let key = PublicKey::from_djb_public_key_bytes(&[9; 32]).expect("valid");
+        let live_before = LIVE_BYTES.with(Cell::get);
+
+        let mut out = MaybeUninit::<OwnedBufferOf<c_uchar>>::uninit();
+        unsafe { write_result_to(out.as_mut_ptr(), key.serialize().into_vec()) }
+            .expect("can write");
+        let out: RawBuffer = unsafe { std::mem::transmute(out.assume_init()) };
+        assert_eq!(out.length, 33);
+        assert!(LIVE_BYTES.with(Cell::get) > live_before);
+
+        unsafe { signal_free_buffer(out.base, out.length) };
+        assert_eq!(LIVE_BYTES.with(Cell::get), live_before);
+    }
+
+    #[test]
+    fn pre_key_record_destroy_array_frees_every_handle() {
+        let live_before = LIVE_BYTES.with(Cell::get);
+
+        let mut handles = [std::ptr::null_mut(); 51];
+        let error = unsafe { signal_generate_pre_keys(handles.as_mut_ptr(), 1, 50) };
+        assert!(error.is_null());
+        assert!(handles[50].is_null());
+        assert!(LIVE_BYTES.with(Cell::get) > live_before);
+
+        let error = unsafe { signal_pre_key_record_destroy_array(handles.as_ptr(), handles.len()) };
+        assert!(error.is_null());
+        assert_eq!(LIVE_BYTES.with(Cell::get), live_before);
+    }
+
+    fn panic_message(f: impl FnOnce() + std::panic::UnwindSafe) -> String {
+        let err = run_ffi_safe(|| {
+            f();
+            Ok(())
+        });
+        assert!(!err.is_null());
+
+        let mut message = std::ptr::null();
+        assert!(unsafe { signal_error_get_message(err, &mut message) }.is_null());
+        let result = unsafe { std::ffi::CStr::from_ptr(message) }
+            .to_str()
+            .expect("UTF-8")
+            .to_owned();
+        unsafe {
+            signal_free_string(message);
+            signal_error_free(err);
+        }
+        result
+    }
+
+    #[test]
+    fn panic_message_is_reported() {
+        assert!(panic_message(|| panic!("boom")).contains("boom"));
+        let detail = "formatted";
+        assert!(panic_message(|| panic!("{detail} boom")).contains("formatted boom"));
+        assert!(panic_message(|| std::panic::panic_any(42)).contains("panic with unknown payload"));
+    }
+
+    #[test]
+    fn build_features_report_ffi() {
+        use libsignal_bridge::build_features::{
+            __bridge_fn_ffi_get_build_features as signal_get_build_features, BUILD_FEATURE_FFI,
+            BUILD_FEATURE_JNI, BUILD_FEATURE_NODE, BUILD_FEATURE_SEALED_SENDER,
+        };
+
+        let mut features = 0;
+        let error = unsafe { signal_get_build_features(&mut features) };
+        assert!(error.is_null());
+        assert_ne!(features & BUILD_FEATURE_FFI, 0);
+        assert_ne!(features & BUILD_FEATURE_SEALED_SENDER, 0);
+        assert_eq!(features & (BUILD_FEATURE_JNI | BUILD_FEATURE_NODE), 0);
+    }
+
+    #[test]
+    fn version_string_matches_crate_version() {
+        let mut version = std::ptr::null();
+        assert!(unsafe { signal_get_version_string(&mut version) }.is_null());
+        let result = unsafe { std::ffi::CStr::from_ptr(version) }
+            .to_str()
+            .expect("UTF-8")
+            .to_owned();
+        unsafe { signal_free_string(version) };
+
+        assert_eq!(result, env!("CARGO_PKG_VERSION"));
+        let release = result.split(['-', '+']).next().expect("non-empty");
+        let parts: Vec<&str> = release.split('.').collect();
+        assert_eq!(parts.len(), 3, "not a semver version: {result}");
+        for part in parts {
+            assert!(
+                part.parse::<u64>().is_ok(),
+                "not a semver version: {result}"
+            );
+        }
+    }
+
+    fn error_backtrace(err: *const SignalFfiError) -> String {
+        let mut backtrace = std::ptr::null();
+        assert!(unsafe { signal_error_get_backtrace(err, &mut backtrace) }.is_null());
+        let result = unsafe { std::ffi::CStr::from_ptr(backtrace) }
+            .to_str()
+            .expect("UTF-8")
+            .to_owned();
+        unsafe { signal_free_string(backtrace) };
+        result
+    }
+
+    #[test]
+    fn error_backtrace_matches_feature() {
+        let err = run_ffi_safe(|| Err(NullPointerError.into()));
+        assert!(!err.is_null());
+        let backtrace = error_backtrace(err);
+        unsafe { signal_error_free(err) };
+
+        if cfg!(feature = "capture-backtrace") {
+            assert!(!backtrace.is_empty());
+        } else {
+            assert_eq!(backtrace, "");
+        }
+    }
+
+    #[test]
+    fn free_buffer_ignores_null() {
+        unsafe { signal_free_buffer(std::ptr::null(), 0) };
+    }
+
+    #[test]
+    fn publickey_deserialize_reports_bad_key_length() {
+        use libsignal_bridge::protocol::__bridge_fn_ffi_publickey_deserialize as signal_publickey_deserialize;
+
+        let valid = PublicKey::from_djb_public_key_bytes(&[9; 32])
+            .expect("valid")
+            .serialize();
+        let mut long = valid.to_vec();
+        long.push(0);
+
+        for input in [&valid[..31], &long[..]] {
+            let mut key = std::ptr::null_mut();
+            let error = unsafe { signal_publickey_deserialize(&mut key, borrowed(input)) };
+            assert!(!error.is_null(), "{} bytes accepted", input.len());
+            assert_eq!(
+                unsafe { signal_error_get_type(error) },
+                SignalErrorCode::InvalidKeyLength as u32
+            );
+            unsafe { signal_error_free(error) };
+            assert!(key.is_null());
+        }
+    }
+
+    #[test]
+    fn publickey_deserialize_validate_batch_flags_malformed_keys() {
+        let valid = PublicKey::from_djb_public_key_bytes(&[9; 32])
+            .expect("valid")
+            .serialize();
+        let malformed = [0x05; 16];
+        let data = [valid.as_ptr(), malformed.as_ptr(), valid.as_ptr()];
+        let lengths = [valid.len(), malformed.len(), valid.len()];
+        let mut handles = [std::ptr::null_mut(); 3];
+        let mut results = [false; 3];
+
+        let error = unsafe {
+            signal_publickey_deserialize_validate_batch(
+                data.as_ptr(),
+                lengths.as_ptr(),
+                data.len(),
+                handles.as_mut_ptr(),
+                results.as_mut_ptr(),
+            )
+        };
+        assert!(error.is_null());
+        assert_eq!(results, [true, false, true]);
+        assert!(handles[1].is_null());
+
+        for handle in [handles[0], handles[2]] {
+            let key = unsafe { PublicKey::from_raw_handle(handle) };
+            assert_eq!(key.serialize(), valid);
+        }
+    }
+
+    #[test]
+    fn publickey_deserialize_validate_batch_skips_null_entries() {
+        let valid = PublicKey::from_djb_public_key_bytes(&[9; 32])
+            .expect("valid")
+            .serialize();
+        let data = [valid.as_ptr(), std::ptr::null(), valid.as_ptr()];
+        let lengths = [valid.len(), valid.len(), valid.len()];
+        let mut handles = [std::ptr::NonNull::dangling().as_ptr(); 3];
+        let mut results = [true; 3];
+
+        let error = unsafe {
+            signal_publickey_deserialize_validate_batch(
+                data.as_ptr(),
+                lengths.as_ptr(),
+                data.len(),
+                handles.as_mut_ptr(),
+                results.as_mut_ptr(),
+            )
+        };
+        assert!(error.is_null());
+        assert_eq!(results, [true, false, true]);
+        assert!(handles[1].is_null());
+
+        // Keys on both sides of the null entry were still handed out.
+        for handle in [handles[0], handles[2]] {
+            let key = unsafe { PublicKey::from_raw_handle(handle) };
+            assert_eq!(key.serialize(), valid);
+        }
+    }
+
+    #[test]
+    fn publickey_verify_batch_reports_each_item() {
+        let key_pair = KeyPair::generate(&mut rand::thread_rng());
+        let message = b"batch";
+        let signature = key_pair
+            .private_key
+            .calculate_signature(message, &mut rand::thread_rng())
+            .expect("can sign");
+        let mut bad_signature = signature.clone();
+        bad_signature[0] ^= 1;
+
+        let key: *const PublicKey = &key_pair.public_key;
+        let keys = [key, key, std::ptr::null(), key];
+        let messages = [message.as_ptr(); 4];
+        let message_lens = [message.len(); 4];
+        let signatures = [
+            signature.as_ptr(),
+            bad_signature.as_ptr(),
+            signature.as_ptr(),
+            std::ptr::null(),
+        ];
+        let signature_lens = [signature.len(); 4];
+        let mut results = [0; 4];
+
+        let error = unsafe {
+            signal_publickey_verify_batch(
+                keys.as_ptr(),
+                messages.as_ptr(),
+                message_lens.as_ptr(),
+                signatures.as_ptr(),
+                signature_lens.as_ptr(),
+                keys.len(),
+                results.as_mut_ptr(),
+            )
+        };
+        assert!(error.is_null());
+        assert_eq!(
+            results,
+            [
+                1,
+                0,
+                VERIFY_BATCH_MALFORMED_ITEM,
+                VERIFY_BATCH_MALFORMED_ITEM
+            ]
+        );
+    }
+
+    #[test]
+    fn generate_pre_keys_assigns_contiguous_ids() {
+        let mut handles = [std::ptr::null_mut(); 100];
+        let error = unsafe { signal_generate_pre_keys(handles.as_mut_ptr(), 1000, handles.len()) };
+        assert!(error.is_null());
+
+        let records = handles.map(|handle| unsafe { *Box::from_raw(handle) });
+        let ids: Vec<u32> = records
+            .iter()
+            .map(|record| record.id().expect("has id").into())
+            .collect();
+        assert_eq!(ids, (1000..1100).collect::<Vec<_>>());
+
+        let public_keys: std::collections::HashSet<_> = records
+            .iter()
+            .map(|record| record.public_key().expect("has key").serialize())
+            .collect();
+        assert_eq!(public_keys.len(), records.len());
+    }
+
+    #[test]
+    fn generate_pre_keys_wraps_ids() {
+        let mut handles = [std::ptr::null_mut(); 3];
+        let error =
+            unsafe { signal_generate_pre_keys(handles.as_mut_ptr(), u32::MAX - 1, handles.len()) };
+        assert!(error.is_null());
+
+        let ids = handles.map(|handle| {
+            let record = unsafe { *Box::from_raw(handle) };
+            u32::from(record.id().expect("has id"))
+        });
+        assert_eq!(ids, [u32::MAX - 1, u32::MAX, 0]);
+    }
+
+    #[test]
+    fn pre_key_record_serialize_all_round_trips() {
+        let mut handles = [std::ptr::null_mut(); 5];
+        let error = unsafe { signal_generate_pre_keys(handles.as_mut_ptr(), 10, handles.len()) };
+        assert!(error.is_null());
+        let records = handles.map(|handle| handle.cast_const());
+
+        let mut buffers = [std::ptr::null(); 5];
+        let mut lens = [0; 5];
+        let error = unsafe {
+            signal_pre_key_record_serialize_all(
+                records.as_ptr(),
+                records.len(),
+                buffers.as_mut_ptr(),
+                lens.as_mut_ptr(),
+            )
+        };
+        assert!(error.is_null());
+
+        for ((&record, &buffer), &len) in records.iter().zip(&buffers).zip(&lens) {
+            let bytes = unsafe { std::slice::from_raw_parts(buffer, len) };
+            let original = unsafe { native_handle_cast(record) }.expect("non-null");
+            let round_tripped = PreKeyRecord::deserialize(bytes).expect("valid");
+            assert_eq!(
+                round_tripped.id().expect("has id"),
+                original.id().expect("has id")
+            );
+            assert_eq!(
+                round_tripped.serialize().expect("can serialize"),
+                original.serialize().expect("can serialize")
+            );
+            unsafe { signal_free_buffer(buffer, len) };
+        }
+
+        let with_null = [records[0], std::ptr::null(), records[1]];
+        let mut buffers = [std::ptr::null(); 3];
+        let mut lens = [0; 3];
+        let error = unsafe {
+            signal_pre_key_record_serialize_all(
+                with_null.as_ptr(),
+                with_null.len(),
+                buffers.as_mut_ptr(),
+                lens.as_mut_ptr(),
+            )
+        };
+        assert!(!error.is_null());
+        unsafe { signal_error_free(error) };
+        assert!(buffers.iter().all(|buffer| buffer.is_null()));
+
+        let error = unsafe { signal_pre_key_record_destroy_array(handles.as_ptr(), handles.len()) };
+        assert!(error.is_null());
+    }
+
+    #[test]
+    fn pre_key_record_find_duplicate_id_reports_first_repeat() {
+        let key_pair = KeyPair::generate(&mut rand::thread_rng());
+        let handles = [1, 2, 3, 2, 1].map(|id| {
+            PreKeyRecord::new(id.into(), &key_pair)
+                .into_raw_handle()
+                .cast_const()
+        });
+
+        let mut found = false;
+        let mut index = usize::MAX;
+        let error = unsafe {
+            signal_pre_key_record_find_duplicate_id(
+                handles.as_ptr(),
+                handles.len(),
+                &mut found,
+                &mut index,
+            )
+        };
+        assert!(error.is_null());
+        assert!(found);
+        assert_eq!(index, 3);
+
+        let error = unsafe {
+            signal_pre_key_record_find_duplicate_id(handles.as_ptr(), 3, &mut found, &mut index)
+        };
+        assert!(error.is_null());
+        assert!(!found);
+        assert_eq!(index, 0);
+
+        let error = unsafe {
+            signal_pre_key_record_find_duplicate_id(std::ptr::null(), 0, &mut found, &mut index)
+        };
+        assert!(error.is_null());
+        assert!(!found);
+
+        for handle in handles {
+            drop(unsafe { PreKeyRecord::from_raw_handle(handle.cast_mut()) });
+        }
+    }
+
+    #[test]
+    fn signed_pre_key_record_find_duplicate_id_reports_first_repeat() {
+        let key_pair = KeyPair::generate(&mut rand::thread_rng());
+        let handles = [7, 7].map(|id| {
+            SignedPreKeyRecord::new(
+                id.into(),
+                Timestamp::from_epoch_millis(1234),
+                &key_pair,
+                &[],
+            )
+            .into_raw_handle()
+            .cast_const()
+        });
+
+        let mut found = false;
+        let mut index = usize::MAX;
+        let error = unsafe {
+            signal_signed_pre_key_record_find_duplicate_id(
+                handles.as_ptr(),
+                handles.len(),
+                &mut found,
+                &mut index,
+            )
+        };
+        assert!(error.is_null());
+        assert!(found);
+        assert_eq!(index, 1);
+
+        for handle in handles {
+            drop(unsafe { SignedPreKeyRecord::from_raw_handle(handle.cast_mut()) });
+        }
+    }
+
+    #[test]
+    fn signed_pre_key_record_key_pair_outlives_record() {
+        use libsignal_bridge::protocol::{
+            __bridge_handle_ffi_privatekey_destroy as signal_privatekey_destroy,
+            __bridge_handle_ffi_publickey_destroy as signal_publickey_destroy,
+            __bridge_handle_ffi_signed_pre_key_record_destroy as signal_signed_pre_key_record_destroy,
+        };
+
+        let key_pair = KeyPair::generate(&mut rand::rngs::OsRng);
+        let record =
+            SignedPreKeyRecord::new(7.into(), Timestamp::from_epoch_millis(1234), &key_pair, &[])
+                .into_raw_handle();
+
+        let mut public_key = std::ptr::null_mut();
+        let mut private_key = std::ptr::null_mut();
+        let error = unsafe {
+            signal_signed_pre_key_record_get_key_pair(&mut public_key, &mut private_key, record)
+        };
+        assert!(error.is_null());
+        assert!(unsafe { signal_signed_pre_key_record_destroy(record) }.is_null());
+
+        assert_eq!(
+            unsafe { native_handle_cast(public_key) }
+                .expect("non-null")
+                .serialize(),
+            key_pair.public_key.serialize()
+        );
+        assert_eq!(
+            unsafe { native_handle_cast(private_key) }
+                .expect("non-null")
+                .serialize(),
+            key_pair.private_key.serialize()
+        );
+        assert!(unsafe { signal_publickey_destroy(public_key) }.is_null());
+        assert!(unsafe { signal_privatekey_destroy(private_key) }.is_null());
+    }
+
+    #[test]
+    fn generate_signed_pre_key_is_signed_by_identity() {
+        let identity_key_pair = IdentityKeyPair::generate(&mut rand::rngs::OsRng);
+        let identity_handle = identity_key_pair.into_raw_handle();
+
+        let mut out = std::ptr::null_mut();
+        let error = unsafe { signal_generate_signed_pre_key(&mut out, identity_handle, 7, 1234) };
+        assert!(error.is_null());
+        let record = unsafe { *Box::from_raw(out) };
+        drop(unsafe { Box::from_raw(identity_handle) });
+
+        assert_eq!(u32::from(record.id().expect("has id")), 7);
+        assert_eq!(
+            record.timestamp().expect("has timestamp").epoch_millis(),
+            1234
+        );
+        assert!(identity_key_pair
+            .public_key()
+            .verify_signature(
+                &record.public_key().expect("has key").serialize(),
+                &record.signature().expect("has signature"),
+            )
+            .expect("valid signature format"));
+    }
+
+    #[test]
+    fn pre_key_bundle_identity_key_is_independently_destroyable() {
+        use libsignal_bridge::protocol::{
+            __bridge_fn_ffi_pre_key_bundle_get_identity_key as signal_pre_key_bundle_get_identity_key,
+            __bridge_handle_ffi_publickey_destroy as signal_publickey_destroy,
+        };
+
+        let identity_key = IdentityKeyPair::generate(&mut rand::thread_rng());
+        let signed_pre_key = KeyPair::generate(&mut rand::thread_rng());
+        let bundle = PreKeyBundle::new(
+            1,
+            1.into(),
+            None,
+            2.into(),
+            signed_pre_key.public_key,
+            vec![0; 64],
+            *identity_key.identity_key(),
+        )
+        .expect("valid bundle");
+
+        for _ in 0..2 {
+            let mut key = std::ptr::null_mut();
+            assert!(unsafe { signal_pre_key_bundle_get_identity_key(&mut key, &bundle) }.is_null());
+            assert_eq!(
+                unsafe { native_handle_cast(key) }.expect("non-null"),
+                identity_key.public_key()
+            );
+            assert!(unsafe { signal_publickey_destroy(key) }.is_null());
+        }
+
+        assert_eq!(
+            bundle.identity_key().expect("still readable"),
+            identity_key.identity_key()
+        );
+    }
+
+    /// A [`BorrowedSliceOf`] viewing `bytes`, as a C caller would pass it.
+    fn borrowed(bytes: &[u8]) -> BorrowedSliceOf<c_uchar> {
+        unsafe {
+            std::mem::transmute(RawBuffer {
+                base: bytes.as_ptr(),
+                length: bytes.len(),
+            })
+        }
+    }
+
+    #[test]
+    fn sealed_sender_decrypt_stream_withholds_plaintext_on_bad_mac() {
+        let mut rng = rand::rngs::OsRng;
+        let alice_identity = IdentityKeyPair::generate(&mut rng);
+        let bob_identity = IdentityKeyPair::generate(&mut rng);
+        let bob_address =
+            ProtocolAddress::new("796abedb-ca4e-4f18-8803-1fde5b921f9f".to_owned(), 1.into());
+
+        let trust_root = KeyPair::generate(&mut rng);
+        let server_key = KeyPair::generate(&mut rng);
+        let server_cert =
+            ServerCertificate::new(1, server_key.public_key, &trust_root.private_key, &mut rng)
+                .expect("valid");
+        let sender_cert = SenderCertificate::new(
+            "9d0652a3-dcc3-4d11-975f-74d61598733f".to_owned(),
+            None,
+            *alice_identity.public_key(),
+            1.into(),
+            Timestamp::from_epoch_millis(1605722925),
+            server_cert,
+            &server_key.private_key,
+            &mut rng,
+        )
+        .expect("valid");
+        let usmc = UnidentifiedSenderMessageContent::new(
+            CiphertextMessageType::Plaintext,
+            sender_cert,
+            b"hi bob".to_vec(),
+            ContentHint::Default,
+            None,
+        )
+        .expect("valid");
+
+        let mut alice_store = InMemIdentityKeyStore::new(alice_identity, 1);
+        alice_store
+            .save_identity(&bob_address, bob_identity.identity_key())
+            .now_or_never()
+            .expect("sync")
+            .expect("can save");
+        let mut ciphertext =
+            sealed_sender_encrypt_from_usmc(&bob_address, &usmc, &alice_store, &mut rng)
+                .now_or_never()
+                .expect("sync")
+                .expect("can encrypt");
+        *ciphertext.last_mut().expect("not empty") ^= 1;
+
+        let stream = SealedSenderDecryptStream::new(bob_identity).into_raw_handle();
+        for chunk in ciphertext.chunks(7) {
+            let error =
+                unsafe { signal_sealed_sender_decrypt_stream_update(stream, borrowed(chunk)) };
+            assert!(error.is_null());
+        }
+
+        let mut out = MaybeUninit::<OwnedBufferOf<c_uchar>>::zeroed();
+        let error =
+            unsafe { signal_sealed_sender_decrypt_stream_finalize(out.as_mut_ptr(), stream) };
+        assert!(!error.is_null());
+        assert_eq!(
+            unsafe { signal_error_get_type(error) },
+            SignalErrorCode::SealedSenderMacVerificationFailed as u32
+        );
+        unsafe { signal_error_free(error) };
+
+        let out: RawBuffer = unsafe { std::mem::transmute(out.assume_init()) };
+        assert!(out.base.is_null(), "no plaintext handed out");
+        assert_eq!(out.length, 0);
+
+        drop(unsafe { SealedSenderDecryptStream::from_raw_handle(stream) });
+    }
+
+    #[test]
+    fn tagged_entry_points_reject_untagged_handles() {
+        use libsignal_bridge::protocol::{
+            __bridge_fn_ffi_message_get_counter as signal_message_get_counter,
+            __bridge_handle_ffi_message_destroy as signal_message_destroy,
+        };
+
+        let key = PrivateKey::deserialize(&[7; 32]).expect("valid");
+        let handle = key.into_raw_handle();
+        let wrong_type = handle.cast::<SignalMessage>();
+
+        let mut counter = 0;
+        let error = unsafe { signal_message_get_counter(&mut counter, wrong_type) };
+        assert!(!error.is_null());
+        assert_eq!(
+            unsafe { signal_error_get_type(error) },
+            SignalErrorCode::InvalidType as u32
+        );
+        unsafe { signal_error_free(error) };
+
+        let error = unsafe { signal_message_destroy(wrong_type) };
+        assert!(!error.is_null());
+        assert_eq!(
+            unsafe { signal_error_get_type(error) },
+            SignalErrorCode::InvalidType as u32
+        );
+        unsafe { signal_error_free(error) };
+
+        // Neither call touched the key.
+        let key_after = unsafe { PrivateKey::from_raw_handle(handle) };
+        assert_eq!(key_after.serialize(), key.serialize());
+    }
+
+    /// The layout of [`FfiIdentityKeyStoreStruct`] as seen by C callers.
+    #[repr(C)]
+    struct RawIdentityKeyStore {
+        ctx: *mut c_void,
+        get_identity_key_pair: extern "C" fn(*mut c_void, *mut *mut PrivateKey) -> c_int,
+        get_local_registration_id: extern "C" fn(*mut c_void, *mut u32) -> c_int,
+        save_identity:
+            extern "C" fn(*mut c_void, *const ProtocolAddress, *const PublicKey) -> c_int,
+        get_identity:
+            extern "C" fn(*mut c_void, *mut *mut PublicKey, *const ProtocolAddress) -> c_int,
+        is_trusted_identity:
+            extern "C" fn(*mut c_void, *const ProtocolAddress, *const PublicKey, c_uint) -> c_int,
+    }
+
+    /// An identity store that keeps a clone of every key it is shown, like a real store would.
+    fn keeping_identity_store(kept: &mut Vec<*mut PublicKey>) -> FfiIdentityKeyStoreStruct {
+        extern "C" fn no_key_pair(_ctx: *mut c_void, _key: *mut *mut PrivateKey) -> c_int {
+            -1
+        }
+        extern "C" fn no_registration_id(_ctx: *mut c_void, _id: *mut u32) -> c_int {
+            -1
+        }
+        extern "C" fn no_identity(
+            _ctx: *mut c_void,
+            _key: *mut *mut PublicKey,
+            _address: *const ProtocolAddress,
+        ) -> c_int {
+            -1
+        }
+        extern "C" fn keep(ctx: *mut c_void, key: *const PublicKey) -> c_int {
+            use libsignal_bridge::protocol::__bridge_handle_ffi_publickey_clone as signal_publickey_clone;
+
+            let kept = unsafe { &mut *(ctx as *mut Vec<*mut PublicKey>) };
+            let mut clone = std::ptr::null_mut();
+            let error = unsafe { signal_publickey_clone(&mut clone, key) };
+            if !error.is_null() {
+                unsafe { signal_error_free(error) };
+                return -1;
+            }
+            kept.push(clone);
+            1
+        }
+        extern "C" fn save_identity(
+            ctx: *mut c_void,
+            _address: *const ProtocolAddress,
+            key: *const PublicKey,
+        ) -> c_int {
+            keep(ctx, key)
+        }
+        extern "C" fn is_trusted_identity(
+            ctx: *mut c_void,
+            _address: *const ProtocolAddress,
+            key: *const PublicKey,
+            _direction: c_uint,
+        ) -> c_int {
+            keep(ctx, key)
+        }
+
+        let raw = RawIdentityKeyStore {
+            ctx: kept as *mut Vec<*mut PublicKey> as *mut c_void,
+            get_identity_key_pair: no_key_pair,
+            get_local_registration_id: no_registration_id,
+            save_identity,
+            get_identity: no_identity,
+            is_trusted_identity,
+        };
+        unsafe { std::mem::transmute(raw) }
+    }
+
+    #[test]
+    fn identity_store_callbacks_may_keep_the_public_key() {
+        use libsignal_bridge::protocol::__bridge_handle_ffi_publickey_destroy as signal_publickey_destroy;
+
+        let address = ProtocolAddress::new("+14155550100".to_owned(), 1.into());
+        let identity =
+            IdentityKey::new(PublicKey::from_djb_public_key_bytes(&[9; 32]).expect("valid"));
+        let mut kept = Vec::with_capacity(2);
+        let live_before = LIVE_BYTES.with(Cell::get);
+
+        let store = keeping_identity_store(&mut kept);
+        let mut store_ref = &store;
+        assert!(store_ref
+            .save_identity(&address, &identity)
+            .now_or_never()
+            .expect("sync")
+            .expect("callback succeeds"));
+        assert!(store_ref
+            .is_trusted_identity(&address, &identity, Direction::Sending)
+            .now_or_never()
+            .expect("sync")
+            .expect("callback succeeds"));
+
+        // The kept handles outlive the calls that lent them out.
+        assert_eq!(kept.len(), 2);
+        for handle in kept.drain(..) {
+            let key = unsafe { native_handle_cast(handle) }.expect("non-null");
+            assert_eq!(key, identity.public_key());
+            assert!(unsafe { signal_publickey_destroy(handle) }.is_null());
+        }
+        assert_eq!(LIVE_BYTES.with(Cell::get), live_before);
+    }
+
+    #[test]
+    fn verified_batch_reports_the_store_verification_level() {
+        type Levels = std::collections::HashMap<String, u8>;
+
+        extern "C" fn no_key_pair(_ctx: *mut c_void, _key: *mut *mut PrivateKey) -> c_int {
+            -1
+        }
+        extern "C" fn no_registration_id(_ctx: *mut c_void, _id: *mut u32) -> c_int {
+            -1
+        }
+        extern "C" fn no_save(
+            _ctx: *mut c_void,
+            _address: *const ProtocolAddress,
+            _key: *const PublicKey,
+        ) -> c_int {
+            -1
+        }
+        extern "C" fn get_identity(
+            ctx: *mut c_void,
+            key: *mut *mut PublicKey,
+            address: *const ProtocolAddress,
+        ) -> c_int {
+            let levels = unsafe { &*(ctx as *const Levels) };
+            let address = unsafe { native_handle_cast(address) }.expect("non-null");
+            let identity = PublicKey::from_djb_public_key_bytes(&[9; 32]).expect("valid");
+            unsafe {
+                *key = if levels.contains_key(address.name()) {
+                    identity.into_raw_handle()
+                } else {
+                    std::ptr::null_mut()
+                }
+            };
+            0
+        }
+        // Trust on first use: every saved identity is trusted for sending, verified or not.
+        extern "C" fn always_trusted(
+            _ctx: *mut c_void,
+            _address: *const ProtocolAddress,
+            _key: *const PublicKey,
+            _direction: c_uint,
+        ) -> c_int {
+            1
+        }
+        extern "C" fn get_verification_level(
+            ctx: *mut c_void,
+            level: *mut u8,
+            address: *const ProtocolAddress,
+        ) -> c_int {
+            let levels = unsafe { &*(ctx as *const Levels) };
+            let address = unsafe { native_handle_cast(address) }.expect("non-null");
+            unsafe { *level = levels[address.name()] };
+            0
+        }
+
+        let levels = Levels::from([
+            (
+                "+14155550101".to_owned(),
+                IdentityVerificationLevel::Trusted as u8,
+            ),
+            (
+                "+14155550102".to_owned(),
+                IdentityVerificationLevel::Untrusted as u8,
+            ),
+        ]);
+        let ctx = &levels as *const Levels as *mut c_void;
+        let store: FfiIdentityKeyStoreStruct = unsafe {
+            std::mem::transmute(RawIdentityKeyStore {
+                ctx,
+                get_identity_key_pair: no_key_pair,
+                get_local_registration_id: no_registration_id,
+                save_identity: no_save,
+                get_identity,
+                is_trusted_identity: always_trusted,
+            })
+        };
+
+        let addresses = ["+14155550101", "+14155550102", "+14155550103"]
+            .map(|name| ProtocolAddress::new(name.to_owned(), 1.into()));
+        let address_handles = addresses.each_ref().map(|address| address as *const _);
+        let mut out = [u8::MAX; 3];
+        let err = unsafe {
+            signal_identity_store_get_verified_batch(
+                &store,
+                address_handles.as_ptr(),
+                address_handles.len(),
+                ctx,
+                get_verification_level,
+                out.as_mut_ptr(),
+            )
+        };
+        assert!(err.is_null());
+        assert_eq!(
+            out,
+            [
+                IdentityVerificationLevel::Trusted as u8,
+                IdentityVerificationLevel::Untrusted as u8,
+                IdentityVerificationLevel::Unknown as u8,
+            ]
+        );
+    }
+
+    #[test]
+    fn public_key_handle_outlives_destroyed_clone() {
+        use libsignal_bridge::protocol::{
+            __bridge_handle_ffi_publickey_clone as signal_publickey_clone,
+            __bridge_handle_ffi_publickey_destroy as signal_publickey_destroy,
+        };
+
+        let key = PublicKey::from_djb_public_key_bytes(&[9; 32]).expect("valid");
+        let original = key.into_raw_handle();
+
+        let mut clone = std::ptr::null_mut();
+        assert!(unsafe { signal_publickey_clone(&mut clone, original) }.is_null());
+        assert_eq!(
+            clone, original,
+            "shared handles are reference-counted, not copied"
+        );
+
+        // Raw pointers aren't Send, so smuggle the address across.
+        let clone = clone as usize;
+        let (destroyed_tx, destroyed_rx) = std::sync::mpsc::channel();
+        let reader = std::thread::spawn(move || {
+            let clone = clone as *mut PublicKey;
+            destroyed_rx.recv().expect("original destroyed");
+            let read = unsafe { native_handle_cast(clone) }
+                .expect("non-null")
+                .serialize();
+            assert!(unsafe { signal_publickey_destroy(clone) }.is_null());
+            read
+        });
+
+        assert!(unsafe { signal_publickey_destroy(original) }.is_null());
+        destroyed_tx.send(()).expect("reader running");
+
+        assert_eq!(reader.join().expect("no panic"), key.serialize());
+    }
+}
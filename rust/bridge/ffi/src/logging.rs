@@ -4,6 +4,7 @@
 //
 
 use std::ffi::{c_char, c_void, CString};
+use std::panic::{catch_unwind, AssertUnwindSafe};
 
 #[repr(C)]
 pub enum LogLevel {
@@ -63,24 +64,17 @@ pub struct FfiLogger {
 unsafe impl Send for FfiLogger {}
 unsafe impl Sync for FfiLogger {}
 
-impl log::Log for FfiLogger {
-    fn enabled(&self, metadata: &log::Metadata) -> bool {
-        libsignal_bridge::logging::log_enabled_in_apps(metadata)
-    }
-
-    fn log(&self, record: &log::Record) {
-        if !libsignal_bridge::logging::log_enabled_in_apps(record.metadata()) {
-            return;
-        }
+/// Converts `s` to a C string, escaping any NULs rather than failing.
+fn to_c_string(s: &str) -> CString {
+    CString::new(s)
+        .unwrap_or_else(|_| CString::new(s.replace('\0', "\\0")).expect("We escaped any NULLs"))
+}
 
-        let target = CString::new(record.target()).expect("no 0 bytes in log target");
-        let file = record
-            .file()
-            .map(|file| CString::new(file).expect("no 0 bytes in file"));
-        let message = CString::new(record.args().to_string()).unwrap_or_else(|_| {
-            CString::new(record.args().to_string().replace('\0', "\\0"))
-                .expect("We escaped any NULLs")
-        });
+impl FfiLogger {
+    fn log_impl(&self, record: &log::Record) {
+        let target = to_c_string(record.target());
+        let file = record.file().map(to_c_string);
+        let message = to_c_string(&record.args().to_string());
         (self.log)(
             self.ctx,
             target.as_ptr(),
@@ -92,6 +86,24 @@ impl log::Log for FfiLogger {
             message.as_ptr(),
         );
     }
+}
+
+impl log::Log for FfiLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        libsignal_bridge::logging::log_enabled_in_apps(metadata)
+    }
+
+    fn log(&self, record: &log::Record) {
+        if !libsignal_bridge::logging::log_enabled_in_apps(record.metadata()) {
+            return;
+        }
+
+        // Formatting the message can panic. Don't let that escape into the code that was logging,
+        // or abort the process if the panic hook is the one logging.
+        if catch_unwind(AssertUnwindSafe(|| self.log_impl(record))).is_err() {
+            // Drop the error; it's not like we can log it!
+        }
+    }
 
     fn flush(&self) {
         (self.flush)(self.ctx)
@@ -118,3 +130,120 @@ pub unsafe extern "C" fn signal_init_logger(max_level: LogLevel, logger: FfiLogg
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::ffi::CStr;
+    use std::sync::Mutex;
+
+    use log::Log as _;
+
+    use super::*;
+
+    type Captured = Mutex<Vec<(i32, String, String)>>;
+
+    extern "C" fn capture(
+        ctx: *mut c_void,
+        target: *const c_char,
+        level: LogLevel,
+        _file: *const c_char,
+        _line: u32,
+        message: *const c_char,
+    ) {
+        let captured = unsafe { &*(ctx as *const Captured) };
+        let (target, message) = unsafe { (CStr::from_ptr(target), CStr::from_ptr(message)) };
+        captured.lock().expect("not poisoned").push((
+            level as i32,
+            target.to_string_lossy().into_owned(),
+            message.to_string_lossy().into_owned(),
+        ));
+    }
+
+    extern "C" fn ignore_flush(_ctx: *mut c_void) {}
+
+    fn capturing_logger(captured: &Captured) -> FfiLogger {
+        FfiLogger {
+            ctx: captured as *const Captured as *mut c_void,
+            log: capture,
+            flush: ignore_flush,
+        }
+    }
+
+    struct PanicsOnDisplay;
+
+    impl std::fmt::Display for PanicsOnDisplay {
+        fn fmt(&self, _f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            panic!("cannot display")
+        }
+    }
+
+    #[test]
+    fn forwards_level_target_and_message() {
+        let captured = Captured::default();
+        let logger = capturing_logger(&captured);
+
+        logger.log(
+            &log::Record::builder()
+                .level(log::Level::Warn)
+                .target("libsignal_test")
+                .args(format_args!("hello {}", 42))
+                .build(),
+        );
+        // Filtered out by log_enabled_in_apps.
+        logger.log(
+            &log::Record::builder()
+                .level(log::Level::Error)
+                .target("dependency")
+                .args(format_args!("ignored"))
+                .build(),
+        );
+
+        assert_eq!(
+            *captured.lock().expect("not poisoned"),
+            [(
+                LogLevel::Warn as i32,
+                "libsignal_test".to_owned(),
+                "hello 42".to_owned()
+            )]
+        );
+    }
+
+    #[test]
+    fn escapes_nuls() {
+        let captured = Captured::default();
+        let logger = capturing_logger(&captured);
+
+        logger.log(
+            &log::Record::builder()
+                .level(log::Level::Info)
+                .target("libsignal_\0test")
+                .args(format_args!("a\0b"))
+                .build(),
+        );
+
+        assert_eq!(
+            *captured.lock().expect("not poisoned"),
+            [(
+                LogLevel::Info as i32,
+                "libsignal_\\0test".to_owned(),
+                "a\\0b".to_owned()
+            )]
+        );
+    }
+
+    #[test]
+    fn swallows_panics_while_formatting() {
+        let captured = Captured::default();
+        let logger = capturing_logger(&captured);
+
+        logger.log(
+            &log::Record::builder()
+                .level(log::Level::Error)
+                .target("libsignal_test")
+                .args(format_args!("{}", PanicsOnDisplay))
+                .build(),
+        );
+
+        assert!(captured.lock().expect("not poisoned").is_empty());
+    }
+}
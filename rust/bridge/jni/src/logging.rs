@@ -109,7 +109,10 @@ impl log::Log for JniLogger {
         if !self.enabled(record.metadata()) {
             return;
         }
-        if self.log_impl(record).is_err() {
+        // Catch panics too (say, from formatting the message), so they don't escape into the code
+        // that was logging.
+        let result = catch_unwind(AssertUnwindSafe(|| self.log_impl(record)));
+        if !matches!(result, Ok(Ok(()))) {
             // Drop the error; it's not like we can log it!
         }
     }
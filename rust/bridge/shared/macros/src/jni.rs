@@ -174,3 +174,17 @@ fn bridge_io_body(
 pub(crate) fn name_from_ident(ident: &Ident) -> String {
     ident.to_string().replace('_', "_1")
 }
+
+/// Returns `true` if every underscore in `name` is part of a JNI escape sequence.
+///
+/// Explicit JNI names are used as-is, so a bare `_` would produce a symbol the JVM never looks up
+/// (it expects `_1` for an underscore in the Java method name).
+pub(crate) fn is_escaped_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    while let Some(c) = chars.next() {
+        if c == '_' && !matches!(chars.next(), Some('0'..='3')) {
+            return false;
+        }
+    }
+    true
+}
@@ -293,6 +293,13 @@ fn bridge_fn_impl(
     let jni_name = match name_for_meta_key(&item_names, "jni", || {
         jni::name_from_ident(&function.sig.ident)
     }) {
+        Ok(Some(name)) if !jni::is_escaped_name(&name) => {
+            let span = value_for_meta_key(&item_names, "jni")
+                .map_or_else(|| function.sig.ident.span(), Spanned::span);
+            return Error::new(span, "underscores in JNI names must be escaped as `_1`")
+                .to_compile_error()
+                .into();
+        }
         Ok(name) => name,
         Err(error) => return error.to_compile_error().into(),
     };
@@ -471,3 +478,36 @@ mod return_type_test {
         }
     }
 }
+
+#[cfg(test)]
+mod jni_name_tests {
+    use super::*;
+
+    #[test]
+    fn default_names_are_escaped() {
+        for ident in [
+            format_ident!("SignalMessage_GetSenderRatchetKey"),
+            format_ident!("PreKeySignalMessage_GetBaseKey"),
+            format_ident!("SessionRecord_GetLocalRegistrationId"),
+            format_ident!("HKDF_DeriveSecrets"),
+        ] {
+            let name = jni::name_from_ident(&ident);
+            assert!(jni::is_escaped_name(&name), "{name}");
+        }
+        assert_eq!(
+            jni::name_from_ident(&format_ident!("SignalMessage_GetSenderRatchetKey")),
+            "SignalMessage_1GetSenderRatchetKey"
+        );
+    }
+
+    #[test]
+    fn explicit_names() {
+        assert!(jni::is_escaped_name("PreKeySignalMessage_1GetSerialized"));
+        assert!(jni::is_escaped_name("Native_1Method_0004e"));
+        assert!(jni::is_escaped_name("NoUnderscores"));
+
+        assert!(!jni::is_escaped_name("PreKeySignalMessage_GetSerialized"));
+        assert!(!jni::is_escaped_name("Trailing_"));
+        assert!(!jni::is_escaped_name("Double__1Underscore"));
+    }
+}
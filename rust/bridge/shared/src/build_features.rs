@@ -0,0 +1,52 @@
+//
+// Copyright 2024 Signal Messenger, LLC.
+// SPDX-License-Identifier: AGPL-3.0-only
+//
+
+//! Reports which optional parts of the bridge were compiled in, so that host apps can check before
+//! calling into them rather than failing on a missing symbol.
+
+use libsignal_bridge_macros::*;
+
+use crate::support::*;
+use crate::*;
+
+/// Built with the C (Swift) bridge.
+pub const BUILD_FEATURE_FFI: u32 = 1 << 0;
+/// Built with the JNI (Java) bridge.
+pub const BUILD_FEATURE_JNI: u32 = 1 << 1;
+/// Built with the Node bridge.
+pub const BUILD_FEATURE_NODE: u32 = 1 << 2;
+/// Sealed sender is always compiled in; the bit exists so callers don't have to assume that.
+pub const BUILD_FEATURE_SEALED_SENDER: u32 = 1 << 3;
+/// Built with `testing-seeded-rng`, so bridge randomness may be seeded. Never set in release builds.
+pub const BUILD_FEATURE_TESTING_SEEDED_RNG: u32 = 1 << 4;
+/// Built with `signal-media`.
+pub const BUILD_FEATURE_SIGNAL_MEDIA: u32 = 1 << 5;
+/// Built with `testing-fns`.
+pub const BUILD_FEATURE_TESTING_FNS: u32 = 1 << 6;
+
+/// The `BUILD_FEATURE_*` bits for everything compiled into this build.
+pub fn build_features() -> u32 {
+    [
+        (cfg!(feature = "ffi"), BUILD_FEATURE_FFI),
+        (cfg!(feature = "jni"), BUILD_FEATURE_JNI),
+        (cfg!(feature = "node"), BUILD_FEATURE_NODE),
+        (true, BUILD_FEATURE_SEALED_SENDER),
+        (
+            cfg!(feature = "testing-seeded-rng"),
+            BUILD_FEATURE_TESTING_SEEDED_RNG,
+        ),
+        (cfg!(feature = "signal-media"), BUILD_FEATURE_SIGNAL_MEDIA),
+        (cfg!(feature = "testing-fns"), BUILD_FEATURE_TESTING_FNS),
+    ]
+    .into_iter()
+    .filter_map(|(enabled, bit)| enabled.then_some(bit))
+    .fold(0, |features, bit| features | bit)
+}
+
+/// Returns a bitmask of the `BUILD_FEATURE_*` capabilities compiled into this library.
+#[bridge_fn(ffi = "get_build_features", jni = false, node = false)]
+fn GetBuildFeatures() -> u32 {
+    build_features()
+}
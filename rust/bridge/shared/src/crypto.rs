@@ -6,6 +6,7 @@
 use ::signal_crypto;
 use libsignal_bridge_macros::*;
 use signal_crypto::{Aes256Ctr32, CryptographicHash, CryptographicMac, Error, Result};
+use subtle::ConstantTimeEq;
 
 use aes_gcm_siv::aead::generic_array::typenum::Unsigned;
 use aes_gcm_siv::{AeadCore, AeadInPlace, KeyInit};
@@ -136,6 +137,14 @@ fn Aes256GcmSiv_Decrypt(
     Ok(buf)
 }
 
+/// Compares `a` and `b` in time that depends only on their lengths.
+///
+/// Inputs of different lengths are unequal rather than an error.
+#[bridge_fn(ffi = "constant_time_equals", jni = false, node = false)]
+fn ConstantTimeEquals(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && bool::from(a.ct_eq(b))
+}
+
 #[bridge_fn(ffi = false, node = false)]
 fn CryptographicHash_New(algo: String) -> Result<CryptographicHash> {
     CryptographicHash::new(&algo)
@@ -0,0 +1,88 @@
+//
+// Copyright 2024 Signal Messenger, LLC.
+// SPDX-License-Identifier: AGPL-3.0-only
+//
+
+use base64::prelude::{Engine, BASE64_STANDARD};
+use libsignal_bridge_macros::*;
+
+use crate::support::*;
+use crate::*;
+
+/// Encodes `input` as lowercase hex.
+#[bridge_fn(ffi = "hex_encode", jni = false, node = false)]
+fn HexEncode(input: &[u8]) -> String {
+    hex::encode(input)
+}
+
+/// Decodes hex in either case, rejecting odd lengths and non-hex characters.
+#[bridge_fn(ffi = "hex_decode", jni = false, node = false)]
+fn HexDecode(input: String) -> Result<Vec<u8>, hex::FromHexError> {
+    hex::decode(input)
+}
+
+/// Encodes `input` as standard, padded base64.
+#[bridge_fn(ffi = "base64_encode", jni = false, node = false)]
+fn Base64Encode(input: &[u8]) -> String {
+    BASE64_STANDARD.encode(input)
+}
+
+/// Decodes standard, padded base64, rejecting missing or extra padding and invalid characters.
+#[bridge_fn(ffi = "base64_decode", jni = false, node = false)]
+fn Base64Decode(input: String) -> Result<Vec<u8>, base64::DecodeError> {
+    BASE64_STANDARD.decode(input)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn hex_round_trip() {
+        let bytes = [0x00, 0x01, 0xab, 0xff];
+        assert_eq!(HexEncode(&bytes), "0001abff");
+        assert_eq!(HexDecode("0001abff".to_owned()).expect("valid"), bytes);
+        assert_eq!(HexDecode("0001ABFF".to_owned()).expect("valid"), bytes);
+        assert_eq!(HexDecode(String::new()).expect("valid"), b"");
+    }
+
+    #[test]
+    fn hex_rejects_malformed_input() {
+        assert!(matches!(
+            HexDecode("abc".to_owned()),
+            Err(hex::FromHexError::OddLength)
+        ));
+        assert!(matches!(
+            HexDecode("zz".to_owned()),
+            Err(hex::FromHexError::InvalidHexCharacter { c: 'z', index: 0 })
+        ));
+    }
+
+    #[test]
+    fn base64_round_trip() {
+        let inputs: [&[u8]; 5] = [b"", b"f", b"fo", b"foo", &[0xfb, 0xff]];
+        for input in inputs {
+            let encoded = Base64Encode(input);
+            assert_eq!(Base64Decode(encoded).expect("valid"), input);
+        }
+        assert_eq!(Base64Encode(b"fo"), "Zm8=");
+        assert_eq!(Base64Encode(&[0xfb, 0xff]), "+/8=");
+    }
+
+    #[test]
+    fn base64_rejects_bad_padding() {
+        for input in ["Zm8", "Zm8==", "Zg=", "Z==="] {
+            assert!(Base64Decode(input.to_owned()).is_err(), "{input}");
+        }
+    }
+
+    #[test]
+    fn base64_rejects_invalid_characters() {
+        assert!(matches!(
+            Base64Decode("Zm9v!A==".to_owned()),
+            Err(base64::DecodeError::InvalidByte(4, b'!'))
+        ));
+        // URL-safe characters aren't part of the standard alphabet.
+        assert!(Base64Decode("-_8=".to_owned()).is_err());
+    }
+}
@@ -11,7 +11,8 @@ compile_error!("Feature \"ffi\", \"jni\", or \"node\" must be enabled for this c
 
 pub use libsignal_bridge_types::{
     bridge_as_handle, bridge_deserialize, bridge_fixed_length_serializable_fns, bridge_get,
-    bridge_handle_fns, bridge_serializable_handle_fns, describe_panic, io, support,
+    bridge_get_object, bridge_get_optional_string, bridge_get_uuid, bridge_handle_fns,
+    bridge_serializable_handle_fns, describe_panic, io, support,
 };
 
 #[cfg(feature = "node")]
@@ -30,9 +31,12 @@ pub use libsignal_bridge_types::jni;
 #[cfg(feature = "node")]
 pub use libsignal_bridge_types::node;
 
+pub mod build_features;
 pub mod logging;
+pub mod rng;
 
 pub mod crypto;
+pub mod encoding;
 pub mod protocol;
 
 // Desktop does not make use of device transfer certificates
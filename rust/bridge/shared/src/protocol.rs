@@ -15,6 +15,7 @@ use uuid::Uuid;
 #[allow(unused_imports)]
 use futures_util::FutureExt;
 
+use crate::rng::{BridgeRng, CallerRng};
 use crate::support::*;
 use crate::*;
 
@@ -30,28 +31,51 @@ pub(crate) use libsignal_protocol::Timestamp;
 bridge_handle_fns!(CiphertextMessage, clone = false, jni = false);
 bridge_handle_fns!(DecryptionErrorMessage);
 bridge_handle_fns!(Fingerprint, jni = NumericFingerprintGenerator);
+bridge_handle_fns!(IdentityKey, jni = false);
+bridge_handle_fns!(IdentityKeyPair, jni = false);
 bridge_handle_fns!(PlaintextContent);
 bridge_handle_fns!(PreKeyBundle);
 bridge_handle_fns!(PreKeyRecord);
-bridge_handle_fns!(PreKeySignalMessage);
-bridge_handle_fns!(PrivateKey, ffi = privatekey, jni = ECPrivateKey);
+bridge_handle_fns!(PreKeySignalMessage, tagged = true);
+bridge_handle_fns!(
+    PrivateKey,
+    ffi = privatekey,
+    jni = ECPrivateKey,
+    zeroize = true
+);
 bridge_handle_fns!(ProtocolAddress, ffi = address);
-bridge_handle_fns!(PublicKey, ffi = publickey, jni = ECPublicKey);
+bridge_handle_fns!(PublicKey, ffi = publickey, jni = ECPublicKey, shared = true);
 bridge_handle_fns!(SenderCertificate);
 bridge_handle_fns!(SenderKeyDistributionMessage);
-bridge_handle_fns!(SenderKeyMessage);
+bridge_handle_fns!(SenderKeyMessage, tagged = true);
 bridge_handle_fns!(SenderKeyRecord);
 bridge_handle_fns!(ServerCertificate);
 bridge_handle_fns!(SessionRecord);
-bridge_handle_fns!(SignalMessage, ffi = message);
+bridge_handle_fns!(SignalMessage, ffi = message, tagged = true);
 bridge_handle_fns!(SignedPreKeyRecord);
 bridge_handle_fns!(KyberPreKeyRecord);
 bridge_handle_fns!(UnidentifiedSenderMessageContent, clone = false);
 bridge_handle_fns!(SealedSenderDecryptionResult, ffi = false, jni = false);
+bridge_handle_fns!(
+    SealedSenderDecryptStream,
+    clone = false,
+    jni = false,
+    node = false
+);
 bridge_handle_fns!(KyberKeyPair);
 bridge_handle_fns!(KyberPublicKey);
 bridge_handle_fns!(KyberSecretKey);
 
+/// Rejects empty HKDF outputs, which are almost certainly a caller bug.
+fn check_hkdf_output_length(output_length: usize) -> Result<()> {
+    if output_length == 0 {
+        return Err(SignalProtocolError::InvalidArgument(
+            "output length must be nonzero".to_owned(),
+        ));
+    }
+    Ok(())
+}
+
 #[bridge_fn(ffi = false)]
 fn HKDF_DeriveSecrets(
     output_length: u32,
@@ -59,6 +83,7 @@ fn HKDF_DeriveSecrets(
     label: Option<&[u8]>,
     salt: Option<&[u8]>,
 ) -> Result<Vec<u8>> {
+    check_hkdf_output_length(output_length as usize)?;
     let label = label.unwrap_or(&[]);
     let mut buffer = vec![0; output_length as usize];
     hkdf::Hkdf::<sha2::Sha256>::new(salt, ikm)
@@ -72,6 +97,7 @@ fn HKDF_DeriveSecrets(
 // Alternate implementation to fill an existing buffer.
 #[bridge_fn(jni = false, node = false)]
 fn HKDF_Derive(output: &mut [u8], ikm: &[u8], label: &[u8], salt: &[u8]) -> Result<()> {
+    check_hkdf_output_length(output.len())?;
     hkdf::Hkdf::<sha2::Sha256>::new(Some(salt), ikm)
         .expand(label, output)
         .map_err(|_| {
@@ -80,6 +106,17 @@ fn HKDF_Derive(output: &mut [u8], ikm: &[u8], label: &[u8], salt: &[u8]) -> Resu
     Ok(())
 }
 
+/// Derives the key used to encrypt link preview thumbnails from a message's master key.
+#[bridge_fn(ffi = "derive_link_preview_key", jni = false, node = false)]
+fn LinkPreview_DeriveKey(output: &mut [u8], master_key: &[u8]) -> Result<()> {
+    hkdf::Hkdf::<sha2::Sha256>::new(None, master_key)
+        .expand(b"Signal_LinkPreview_ThumbnailKey", output)
+        .map_err(|_| {
+            SignalProtocolError::InvalidArgument(format!("output too long ({})", output.len()))
+        })?;
+    Ok(())
+}
+
 // FIXME: Use bridge_get! when it works on values instead of references.
 #[bridge_fn]
 fn ServiceId_ServiceIdBinary(value: ServiceId) -> Vec<u8> {
@@ -117,11 +154,25 @@ fn ProtocolAddress_New(name: String, device_id: u32) -> ProtocolAddress {
     ProtocolAddress::new(name, device_id.into())
 }
 
-#[bridge_fn(ffi = "publickey_deserialize", jni = false)]
+#[bridge_fn(ffi = false, jni = false)]
 fn PublicKey_Deserialize(data: &[u8]) -> Result<PublicKey> {
     PublicKey::deserialize(data)
 }
 
+// C callers get an exact-length check, so that trailing bytes are reported as a bad key length
+// rather than silently dropped. The other bridges keep accepting them, as the protocol crate does.
+#[bridge_fn(ffi = "publickey_deserialize", jni = false, node = false)]
+fn PublicKey_DeserializeExact(data: &[u8]) -> Result<PublicKey> {
+    let key = PublicKey::deserialize(data)?;
+    if data.len() != key.serialized_len() {
+        return Err(SignalProtocolError::BadKeyLength(
+            key.key_type(),
+            data.len(),
+        ));
+    }
+    Ok(key)
+}
+
 // Alternate implementation to deserialize from an offset.
 #[bridge_fn(ffi = false, node = false)]
 fn ECPublicKey_Deserialize(data: &[u8], offset: u32) -> Result<PublicKey> {
@@ -140,6 +191,54 @@ bridge_get!(
     jni = "ECPublicKey_1GetPublicKeyBytes"
 );
 
+#[bridge_fn(ffi = "publickey_serialized_len", jni = false, node = false)]
+fn PublicKey_SerializedLen(key: &PublicKey) -> usize {
+    key.serialized_len()
+}
+
+#[bridge_fn(ffi = "publickey_get_type", jni = false, node = false)]
+fn PublicKey_GetType(key: &PublicKey) -> u8 {
+    key.key_type().value()
+}
+
+/// Encodings accepted by [`PublicKey_SerializeWithFormat`] and [`PublicKey_DeserializeWithFormat`].
+enum PublicKeyFormat {
+    /// The default encoding, prefixed with the key type.
+    Prefixed,
+    /// The bare 32-byte DJB public key.
+    Raw,
+}
+
+impl TryFrom<u8> for PublicKeyFormat {
+    type Error = SignalProtocolError;
+
+    fn try_from(value: u8) -> Result<Self> {
+        match value {
+            0 => Ok(Self::Prefixed),
+            1 => Ok(Self::Raw),
+            _ => Err(SignalProtocolError::InvalidArgument(format!(
+                "unknown public key format {value}"
+            ))),
+        }
+    }
+}
+
+#[bridge_fn(ffi = "publickey_serialize_with_format", jni = false, node = false)]
+fn PublicKey_SerializeWithFormat(key: &PublicKey, format: u8) -> Result<Vec<u8>> {
+    match PublicKeyFormat::try_from(format)? {
+        PublicKeyFormat::Prefixed => Ok(key.serialize().into_vec()),
+        PublicKeyFormat::Raw => Ok(key.public_key_bytes()?.to_vec()),
+    }
+}
+
+#[bridge_fn(ffi = "publickey_deserialize_with_format", jni = false, node = false)]
+fn PublicKey_DeserializeWithFormat(data: &[u8], format: u8) -> Result<PublicKey> {
+    match PublicKeyFormat::try_from(format)? {
+        PublicKeyFormat::Prefixed => PublicKey::deserialize(data),
+        PublicKeyFormat::Raw => PublicKey::from_djb_public_key_bytes(data),
+    }
+}
+
 #[bridge_fn(ffi = "address_get_device_id")]
 fn ProtocolAddress_DeviceId(obj: &ProtocolAddress) -> u32 {
     obj.device_id().into()
@@ -150,6 +249,18 @@ fn ProtocolAddress_Name(obj: &ProtocolAddress) -> &str {
     obj.name()
 }
 
+#[bridge_fn(ffi = "address_to_string", jni = false, node = false)]
+fn ProtocolAddress_ToString(obj: &ProtocolAddress) -> String {
+    obj.to_string()
+}
+
+#[bridge_fn(ffi = "address_parse", jni = false, node = false)]
+fn ProtocolAddress_Parse(s: String) -> Result<ProtocolAddress> {
+    ProtocolAddress::parse(&s).ok_or_else(|| {
+        SignalProtocolError::InvalidArgument(format!("not a valid protocol address: {s:?}"))
+    })
+}
+
 #[bridge_fn(ffi = "publickey_equals", node = "PublicKey_Equals")]
 fn ECPublicKey_Equals(lhs: &PublicKey, rhs: &PublicKey) -> bool {
     lhs == rhs
@@ -180,9 +291,14 @@ bridge_get!(
     jni = "ECPrivateKey_1Serialize"
 );
 
+#[bridge_fn(ffi = "privatekey_serialized_len", jni = false, node = false)]
+fn PrivateKey_SerializedLen(key: &PrivateKey) -> usize {
+    key.serialized_len()
+}
+
 #[bridge_fn(ffi = "privatekey_generate", node = "PrivateKey_Generate")]
 fn ECPrivateKey_Generate() -> PrivateKey {
-    let mut rng = rand::rngs::OsRng;
+    let mut rng = BridgeRng;
     let keypair = KeyPair::generate(&mut rng);
     keypair.private_key
 }
@@ -194,7 +310,7 @@ fn ECPrivateKey_GetPublicKey(k: &PrivateKey) -> Result<PublicKey> {
 
 #[bridge_fn(ffi = "privatekey_sign", node = "PrivateKey_Sign")]
 fn ECPrivateKey_Sign(key: &PrivateKey, message: &[u8]) -> Result<Vec<u8>> {
-    let mut rng = rand::rngs::OsRng;
+    let mut rng = BridgeRng;
     Ok(key.calculate_signature(message, &mut rng)?.into_vec())
 }
 
@@ -261,7 +377,7 @@ fn IdentityKeyPair_SignAlternateIdentity(
     private_key: &PrivateKey,
     other_identity: &PublicKey,
 ) -> Result<Vec<u8>> {
-    let mut rng = rand::rngs::OsRng;
+    let mut rng = BridgeRng;
     let identity_key_pair = IdentityKeyPair::new(IdentityKey::new(*public_key), *private_key);
     let other_identity = IdentityKey::new(*other_identity);
     Ok(identity_key_pair
@@ -269,6 +385,48 @@ fn IdentityKeyPair_SignAlternateIdentity(
         .into_vec())
 }
 
+#[bridge_fn(jni = false, node = false)]
+fn IdentityKeyPair_Generate() -> IdentityKeyPair {
+    let mut rng = BridgeRng;
+    IdentityKeyPair::generate(&mut rng)
+}
+
+bridge_deserialize!(IdentityKeyPair::try_from, jni = false, node = false);
+
+/// Serializes in the same `IdentityKeyPairStructure` format as [`IdentityKeyPair_Serialize`].
+#[bridge_fn(ffi = "identity_key_pair_serialize", jni = false, node = false)]
+fn IdentityKeyPair_SerializeHandle(key_pair: &IdentityKeyPair) -> Vec<u8> {
+    key_pair.serialize().into_vec()
+}
+
+#[bridge_fn(jni = false, node = false)]
+fn IdentityKeyPair_GetPublicKey(key_pair: &IdentityKeyPair) -> PublicKey {
+    *key_pair.public_key()
+}
+
+#[bridge_fn(jni = false, node = false)]
+fn IdentityKeyPair_GetPrivateKey(key_pair: &IdentityKeyPair) -> PrivateKey {
+    *key_pair.private_key()
+}
+
+#[bridge_fn(jni = false, node = false)]
+fn IdentityKey_FromPublicKey(public_key: &PublicKey) -> IdentityKey {
+    IdentityKey::new(*public_key)
+}
+
+bridge_deserialize!(IdentityKey::decode, jni = false, node = false);
+
+/// Serializes in the same format as `PublicKey_Serialize` on the underlying key.
+#[bridge_fn(ffi = "identity_key_serialize", jni = false, node = false)]
+fn IdentityKey_SerializeHandle(identity_key: &IdentityKey) -> Vec<u8> {
+    identity_key.serialize().into_vec()
+}
+
+#[bridge_fn(jni = false, node = false)]
+fn IdentityKey_GetPublicKey(identity_key: &IdentityKey) -> PublicKey {
+    *identity_key.public_key()
+}
+
 #[bridge_fn(ffi = "identitykey_verify_alternate_identity")]
 fn IdentityKey_VerifyAlternateIdentity(
     public_key: &PublicKey,
@@ -280,6 +438,15 @@ fn IdentityKey_VerifyAlternateIdentity(
     identity.verify_alternate_identity(&other_identity, signature)
 }
 
+/// Returns `true` if `incoming_key` differs from `stored_key`.
+///
+/// A missing `stored_key` means this is the first contact, which is trusted on first use, so it
+/// counts as unchanged. (`PublicKey`'s equality is constant-time.)
+#[bridge_fn(jni = false, node = false)]
+fn IdentityKey_Changed(stored_key: Option<&PublicKey>, incoming_key: &PublicKey) -> bool {
+    stored_key.is_some_and(|stored_key| stored_key != incoming_key)
+}
+
 #[bridge_fn(jni = false)]
 fn Fingerprint_New(
     iterations: u32,
@@ -332,6 +499,11 @@ bridge_get!(
     jni = "NumericFingerprintGenerator_1GetDisplayString"
 );
 
+#[bridge_fn(jni = false, node = false)]
+fn Fingerprint_GetVersion(obj: &Fingerprint) -> u32 {
+    obj.scannable.version()
+}
+
 #[bridge_fn(ffi = "fingerprint_compare")]
 fn ScannableFingerprint_Compare(fprint1: &[u8], fprint2: &[u8]) -> Result<bool> {
     ScannableFingerprint::deserialize(fprint1)?.compare(fprint2)
@@ -345,6 +517,12 @@ fn SignalMessage_Deserialize(data: &[u8]) -> Result<SignalMessage> {
 bridge_get!(SignalMessage::body -> &[u8], ffi = "message_get_body");
 bridge_get!(SignalMessage::serialized -> &[u8], ffi = "message_get_serialized");
 bridge_get!(SignalMessage::counter -> u32, ffi = "message_get_counter");
+bridge_get!(
+    SignalMessage::previous_counter -> u32,
+    ffi = "message_get_previous_counter",
+    jni = false,
+    node = false
+);
 bridge_get!(SignalMessage::message_version -> u32, ffi = "message_get_message_version");
 
 #[bridge_fn(ffi = "message_new")]
@@ -384,6 +562,11 @@ fn SignalMessage_VerifyMac(
     )
 }
 
+#[bridge_fn(ffi = "message_expected_mac_length", jni = false, node = false)]
+fn SignalMessage_ExpectedMacLength(version: u8) -> Result<usize> {
+    SignalMessage::expected_mac_length(version)
+}
+
 #[bridge_fn(ffi = "message_get_sender_ratchet_key", node = false)]
 fn SignalMessage_GetSenderRatchetKey(m: &SignalMessage) -> PublicKey {
     *m.sender_ratchet_key()
@@ -446,8 +629,8 @@ bridge_get!(
 bridge_get!(SenderKeyMessage::distribution_id -> Uuid);
 bridge_get!(SenderKeyMessage::chain_id -> u32);
 bridge_get!(SenderKeyMessage::iteration -> u32);
+bridge_get!(SenderKeyMessage::signature -> &[u8], jni = false, node = false);
 
-// For testing
 #[bridge_fn]
 fn SenderKeyMessage_New(
     message_version: u8,
@@ -457,7 +640,7 @@ fn SenderKeyMessage_New(
     ciphertext: &[u8],
     pk: &PrivateKey,
 ) -> Result<SenderKeyMessage> {
-    let mut csprng = rand::rngs::OsRng;
+    let mut csprng = BridgeRng;
     SenderKeyMessage::new(
         message_version,
         distribution_id,
@@ -484,6 +667,11 @@ bridge_get!(
 bridge_get!(SenderKeyDistributionMessage::distribution_id -> Uuid);
 bridge_get!(SenderKeyDistributionMessage::chain_id -> u32);
 bridge_get!(SenderKeyDistributionMessage::iteration -> u32);
+bridge_get!(
+    SenderKeyDistributionMessage::digest as Digest -> [u8; 32],
+    jni = false,
+    node = false
+);
 
 // For testing
 #[bridge_fn]
@@ -617,10 +805,7 @@ fn PreKeyBundle_New(
     }
 }
 
-#[bridge_fn]
-fn PreKeyBundle_GetIdentityKey(p: &PreKeyBundle) -> Result<PublicKey> {
-    Ok(*p.identity_key()?.public_key())
-}
+bridge_get_object!(PreKeyBundle::identity_key -> PublicKey);
 
 bridge_get!(PreKeyBundle::signed_pre_key_signature -> &[u8]);
 bridge_get!(PreKeyBundle::registration_id -> u32);
@@ -644,6 +829,16 @@ fn PreKeyBundle_GetKyberPreKeySignature(bundle: &PreKeyBundle) -> Result<&[u8]>
         .map(|maybe_sig| maybe_sig.unwrap_or(&[]))
 }
 
+#[bridge_fn(jni = false, node = false)]
+fn PreKeyBundle_MatchesAddress(bundle: &PreKeyBundle, address: &ProtocolAddress) -> Result<bool> {
+    Ok(bundle.device_id()? == address.device_id())
+}
+
+#[bridge_fn(jni = false, node = false)]
+fn PreKeyBundle_VerifySignedPreKey(bundle: &PreKeyBundle) -> Result<bool> {
+    bundle.verify_signed_pre_key_signature()
+}
+
 bridge_deserialize!(SignedPreKeyRecord::deserialize);
 bridge_get!(SignedPreKeyRecord::signature -> Vec<u8>);
 bridge_get!(
@@ -704,12 +899,34 @@ fn PreKeyRecord_New(id: u32, pub_key: &PublicKey, priv_key: &PrivateKey) -> PreK
     PreKeyRecord::new(id.into(), &keypair)
 }
 
+/// Generates a random registration id, matching the ranges used by `KeyHelper` in Java.
+///
+/// By default the id is in `1..=16380`, which keeps its protobuf encoding small; `extended`
+/// allows anything in `1..i32::MAX`.
+#[bridge_fn(ffi = "generate_registration_id", jni = false, node = false)]
+fn KeyHelper_GenerateRegistrationId(extended: bool) -> u32 {
+    use rand::Rng as _;
+    let mut rng = BridgeRng;
+    if extended {
+        rng.gen_range(1..i32::MAX as u32)
+    } else {
+        rng.gen_range(1..=16380)
+    }
+}
+
 bridge_deserialize!(SenderKeyRecord::deserialize);
 bridge_get!(
     SenderKeyRecord::serialize as Serialize -> Vec<u8>,
     jni = "SenderKeyRecord_1GetSerialized"
 );
 
+#[bridge_fn(jni = false, node = false)]
+fn SenderKeyRecord_NewEmpty() -> SenderKeyRecord {
+    SenderKeyRecord::new_empty()
+}
+
+bridge_get!(SenderKeyRecord::is_empty as IsEmpty -> bool, jni = false, node = false);
+
 bridge_deserialize!(ServerCertificate::deserialize);
 bridge_get!(ServerCertificate::serialized -> &[u8]);
 bridge_get!(ServerCertificate::certificate -> &[u8]);
@@ -723,16 +940,25 @@ fn ServerCertificate_New(
     server_key: &PublicKey,
     trust_root: &PrivateKey,
 ) -> Result<ServerCertificate> {
-    let mut rng = rand::rngs::OsRng;
+    let mut rng = BridgeRng;
     ServerCertificate::new(key_id, *server_key, trust_root, &mut rng)
 }
 
+#[bridge_fn(jni = false, node = false)]
+fn ServerCertificate_Validate(cert: &ServerCertificate, trust_root: &PublicKey) -> Result<bool> {
+    cert.validate(trust_root)
+}
+
 bridge_deserialize!(SenderCertificate::deserialize);
 bridge_get!(SenderCertificate::serialized -> &[u8]);
 bridge_get!(SenderCertificate::certificate -> &[u8]);
 bridge_get!(SenderCertificate::signature -> &[u8]);
 bridge_get!(SenderCertificate::sender_uuid -> &str);
-bridge_get!(SenderCertificate::sender_e164 -> Option<&str>);
+bridge_get_uuid!(
+    SenderCertificate::sender_uuid as GetSenderUuidBytes,
+    node = false
+);
+bridge_get_optional_string!(SenderCertificate::sender_e164);
 bridge_get!(SenderCertificate::expiration -> Timestamp);
 bridge_get!(SenderCertificate::sender_device_id as GetDeviceId -> u32);
 bridge_get!(SenderCertificate::key -> PublicKey);
@@ -746,6 +972,15 @@ fn SenderCertificate_Validate(
     cert.validate(key, time)
 }
 
+#[bridge_fn(jni = false, node = false)]
+fn SenderCertificate_ValidatePinned(
+    cert: &SenderCertificate,
+    pinned_server_key: &PublicKey,
+    time: Timestamp,
+) -> Result<bool> {
+    cert.validate_with_pinned_server_key(pinned_server_key, time)
+}
+
 #[bridge_fn]
 fn SenderCertificate_GetServerCertificate(cert: &SenderCertificate) -> Result<ServerCertificate> {
     Ok(cert.signer()?.clone())
@@ -761,7 +996,7 @@ fn SenderCertificate_New(
     signer_cert: &ServerCertificate,
     signer_key: &PrivateKey,
 ) -> Result<SenderCertificate> {
-    let mut rng = rand::rngs::OsRng;
+    let mut rng = BridgeRng;
 
     SenderCertificate::new(
         sender_uuid,
@@ -926,13 +1161,26 @@ fn CiphertextMessage_FromPlaintextContent(m: &PlaintextContent) -> CiphertextMes
     CiphertextMessage::PlaintextContent(m.clone())
 }
 
+#[bridge_fn(jni = false, node = false)]
+fn CiphertextMessage_FromSerialized(message_type: u8, data: &[u8]) -> Result<CiphertextMessage> {
+    let message_type = CiphertextMessageType::try_from(message_type).map_err(|_| {
+        SignalProtocolError::InvalidArgument(format!("unknown message type {}", message_type))
+    })?;
+    Ok(match message_type {
+        CiphertextMessageType::Whisper => CiphertextMessage::SignalMessage(data.try_into()?),
+        CiphertextMessageType::PreKey => CiphertextMessage::PreKeySignalMessage(data.try_into()?),
+        CiphertextMessageType::SenderKey => CiphertextMessage::SenderKeyMessage(data.try_into()?),
+        CiphertextMessageType::Plaintext => CiphertextMessage::PlaintextContent(data.try_into()?),
+    })
+}
+
 #[bridge_fn(ffi = false, node = false)]
 fn SessionRecord_NewFresh() -> SessionRecord {
     SessionRecord::new_fresh()
 }
 
 // For historical reasons Android assumes this function will return zero if there is no session state
-#[bridge_fn(ffi = false, node = false)]
+#[bridge_fn(node = false)]
 fn SessionRecord_GetSessionVersion(s: &SessionRecord) -> Result<u32> {
     match s.session_version() {
         Ok(v) => Ok(v),
@@ -956,8 +1204,47 @@ fn SessionRecord_CurrentRatchetKeyMatches(s: &SessionRecord, key: &PublicKey) ->
     s.current_ratchet_key_matches(key)
 }
 
+#[bridge_fn(jni = false, node = false)]
+fn SessionRecord_VerifyChainContinuity(
+    s: &SessionRecord,
+    from_counter: u32,
+    to_counter: u32,
+) -> Result<bool> {
+    s.verify_chain_continuity(from_counter, to_counter)
+}
+
+#[bridge_fn(jni = false, node = false)]
+fn SessionRecord_WipeSecrets(session_record: &mut SessionRecord) -> Result<()> {
+    session_record.wipe_secrets()
+}
+
+#[bridge_fn(jni = false, node = false)]
+fn SessionRecord_TrimHistory(session_record: &mut SessionRecord, keep_messages: u32) -> Result<()> {
+    session_record.trim_history(keep_messages as usize)
+}
+
 bridge_deserialize!(SessionRecord::deserialize);
+
+/// Replaces the contents of `session_record` in place, leaving it untouched if `data` doesn't
+/// parse.
+#[bridge_fn(jni = false, node = false)]
+fn SessionRecord_DeserializeInto(session_record: &mut SessionRecord, data: &[u8]) -> Result<()> {
+    *session_record = SessionRecord::deserialize(data)?;
+    Ok(())
+}
+
+#[bridge_fn(jni = false, node = false)]
+fn SessionRecord_DeserializeLegacy(data: &[u8]) -> Result<SessionRecord> {
+    SessionRecord::deserialize_legacy(data)
+}
+
 bridge_get!(SessionRecord::serialize as Serialize -> Vec<u8>);
+
+#[bridge_fn(jni = false, node = false)]
+fn SessionRecord_SerializeVersion(s: &SessionRecord, target_version: u32) -> Result<Vec<u8>> {
+    s.serialize_for_version(target_version)
+}
+
 bridge_get!(SessionRecord::alice_base_key -> &[u8], ffi = false, node = false);
 bridge_get!(
     SessionRecord::local_identity_key_bytes as GetLocalIdentityKeyPublic -> Vec<u8>,
@@ -971,6 +1258,62 @@ bridge_get!(
 );
 bridge_get!(SessionRecord::local_registration_id -> u32);
 bridge_get!(SessionRecord::remote_registration_id -> u32);
+bridge_get!(
+    SessionRecord::sender_counter as SenderCounter -> u32,
+    jni = false,
+    node = false
+);
+bridge_get!(
+    SessionRecord::sender_ratchet_key as GetSenderRatchetKey -> PublicKey,
+    jni = false,
+    node = false
+);
+bridge_get!(
+    SessionRecord::receiver_counter as ReceiverCounter -> u32,
+    jni = false,
+    node = false
+);
+bridge_get!(
+    SessionRecord::has_current_session_state as HasCurrentSessionState -> bool,
+    jni = false,
+    node = false
+);
+bridge_get!(
+    SessionRecord::has_sender_chain as HasSenderChain -> bool,
+    jni = false,
+    node = false
+);
+bridge_get!(
+    SessionRecord::needs_persist as NeedsPersist -> bool,
+    jni = false,
+    node = false
+);
+
+/// Returns the concatenated 32-byte commitments from [`SessionRecord::key_commitments`].
+#[bridge_fn(jni = false, node = false)]
+fn SessionRecord_KeyCommitments(s: &SessionRecord) -> Result<Vec<u8>> {
+    Ok(s.key_commitments()?.concat())
+}
+
+#[bridge_fn(jni = false, node = false)]
+fn SessionRecord_DeriveEphemeralSignalKey(s: &SessionRecord, output: &mut [u8]) -> Result<()> {
+    s.derive_ephemeral_signal_key(output)
+}
+
+bridge_get!(
+    SessionRecord::state_hash as StateHash -> [u8; 32],
+    jni = false,
+    node = false
+);
+
+#[bridge_fn(jni = false, node = false)]
+async fn SessionRecord_ImportVerified(
+    data: &[u8],
+    identity_store: &mut dyn IdentityKeyStore,
+    address: &ProtocolAddress,
+) -> Result<SessionRecord> {
+    SessionRecord::deserialize_verified(data, address, identity_store).await
+}
 
 bridge_get!(SealedSenderDecryptionResult::sender_uuid -> String, ffi = false, jni = false);
 bridge_get!(SealedSenderDecryptionResult::sender_e164 -> Option<String>, ffi = false, jni = false);
@@ -1017,7 +1360,7 @@ fn SessionRecord_InitializeAliceSession(
 
     let their_identity_key = IdentityKey::new(*their_identity_key);
 
-    let mut csprng = rand::rngs::OsRng;
+    let mut csprng = BridgeRng;
 
     let parameters = AliceSignalProtocolParameters::new(
         our_identity_key_pair,
@@ -1076,7 +1419,7 @@ async fn SessionBuilder_ProcessPreKeyBundle(
     identity_key_store: &mut dyn IdentityKeyStore,
     now: Timestamp,
 ) -> Result<()> {
-    let mut csprng = rand::rngs::OsRng;
+    let mut csprng = BridgeRng;
     process_prekey_bundle(
         protocol_address,
         session_store,
@@ -1113,7 +1456,7 @@ async fn SessionCipher_DecryptSignalMessage(
     session_store: &mut dyn SessionStore,
     identity_key_store: &mut dyn IdentityKeyStore,
 ) -> Result<Vec<u8>> {
-    let mut csprng = rand::rngs::OsRng;
+    let mut csprng = BridgeRng;
     message_decrypt_signal(
         message,
         protocol_address,
@@ -1134,7 +1477,7 @@ async fn SessionCipher_DecryptPreKeySignalMessage(
     signed_prekey_store: &mut dyn SignedPreKeyStore,
     kyber_prekey_store: &mut dyn KyberPreKeyStore,
 ) -> Result<Vec<u8>> {
-    let mut csprng = rand::rngs::OsRng;
+    let mut csprng = BridgeRng;
     message_decrypt_prekey(
         message,
         protocol_address,
@@ -1148,13 +1491,104 @@ async fn SessionCipher_DecryptPreKeySignalMessage(
     .await
 }
 
+#[bridge_fn(ffi = "decrypt_message_with_limit", jni = false, node = false)]
+async fn SessionCipher_DecryptSignalMessageWithLimit(
+    message: &SignalMessage,
+    protocol_address: &ProtocolAddress,
+    session_store: &mut dyn SessionStore,
+    identity_key_store: &mut dyn IdentityKeyStore,
+    max_plaintext_len: u32,
+) -> Result<Vec<u8>> {
+    let mut csprng = BridgeRng;
+    message_decrypt_signal_with_max_plaintext_len(
+        message,
+        protocol_address,
+        session_store,
+        identity_key_store,
+        max_plaintext_len as usize,
+        &mut csprng,
+    )
+    .await
+}
+
+/// Like `SessionCipher_DecryptSignalMessage`, but rejects a message that would leave more than
+/// `max_skipped_keys` keys stored for skipped messages in its receiving chain, with
+/// `SignalErrorCodeTooManySkippedKeys`. The limit can only be lowered from the default of 2,000.
+#[bridge_fn(
+    ffi = "decrypt_message_with_max_skipped_keys",
+    jni = false,
+    node = false
+)]
+async fn SessionCipher_DecryptSignalMessageWithMaxSkippedKeys(
+    message: &SignalMessage,
+    protocol_address: &ProtocolAddress,
+    session_store: &mut dyn SessionStore,
+    identity_key_store: &mut dyn IdentityKeyStore,
+    max_skipped_keys: u32,
+) -> Result<Vec<u8>> {
+    let mut csprng = BridgeRng;
+    message_decrypt_signal_with_max_skipped_keys(
+        message,
+        protocol_address,
+        session_store,
+        identity_key_store,
+        max_skipped_keys,
+        &mut csprng,
+    )
+    .await
+}
+
+#[allow(clippy::too_many_arguments)]
+#[bridge_fn(ffi = "decrypt_pre_key_message_with_limit", jni = false, node = false)]
+async fn SessionCipher_DecryptPreKeySignalMessageWithLimit(
+    message: &PreKeySignalMessage,
+    protocol_address: &ProtocolAddress,
+    session_store: &mut dyn SessionStore,
+    identity_key_store: &mut dyn IdentityKeyStore,
+    prekey_store: &mut dyn PreKeyStore,
+    signed_prekey_store: &mut dyn SignedPreKeyStore,
+    kyber_prekey_store: &mut dyn KyberPreKeyStore,
+    max_plaintext_len: u32,
+) -> Result<Vec<u8>> {
+    let mut csprng = BridgeRng;
+    message_decrypt_prekey_with_max_plaintext_len(
+        message,
+        protocol_address,
+        session_store,
+        identity_key_store,
+        prekey_store,
+        signed_prekey_store,
+        kyber_prekey_store,
+        max_plaintext_len as usize,
+        &mut csprng,
+    )
+    .await
+}
+
 #[bridge_fn(node = "SealedSender_Encrypt")]
 async fn SealedSessionCipher_Encrypt(
     destination: &ProtocolAddress,
     content: &UnidentifiedSenderMessageContent,
     identity_key_store: &mut dyn IdentityKeyStore,
 ) -> Result<Vec<u8>> {
-    let mut rng = rand::rngs::OsRng;
+    let mut rng = BridgeRng;
+    sealed_sender_encrypt_from_usmc(destination, content, identity_key_store, &mut rng).await
+}
+
+/// Like `signal_sealed_session_cipher_encrypt`, but draws the message's ephemeral key from
+/// `random`. Only available in builds with `testing-fns`.
+///
+/// Never pass the same `random` bytes for two messages: they would share an ephemeral key, and
+/// so an encryption keystream, which breaks the confidentiality of both.
+#[cfg(feature = "testing-fns")]
+#[bridge_fn(jni = false, node = false)]
+async fn SealedSessionCipher_EncryptWithRandomness(
+    destination: &ProtocolAddress,
+    content: &UnidentifiedSenderMessageContent,
+    identity_key_store: &mut dyn IdentityKeyStore,
+    random: &[u8],
+) -> Result<Vec<u8>> {
+    let mut rng = CallerRng::new(random)?;
     sealed_sender_encrypt_from_usmc(destination, content, identity_key_store, &mut rng).await
 }
 
@@ -1166,7 +1600,34 @@ async fn SealedSender_MultiRecipientEncrypt(
     content: &UnidentifiedSenderMessageContent,
     identity_key_store: &mut dyn IdentityKeyStore,
 ) -> Result<Vec<u8>> {
-    let mut rng = rand::rngs::OsRng;
+    let mut rng = BridgeRng;
+    sealed_sender_multi_recipient_encrypt(
+        recipients,
+        recipient_sessions,
+        excluded_recipients,
+        content,
+        identity_key_store,
+        &mut rng,
+    )
+    .await
+}
+
+/// Like `signal_sealed_sender_multi_recipient_encrypt`, but draws the message's ephemeral keys
+/// from `random`. Only available in builds with `testing-fns`.
+///
+/// Never pass the same `random` bytes for two messages: they would share ephemeral keys, and so
+/// encryption keystreams, which breaks the confidentiality of both.
+#[cfg(feature = "testing-fns")]
+#[bridge_fn(jni = false, node = false)]
+async fn SealedSender_MultiRecipientEncryptWithRandomness(
+    recipients: &[&ProtocolAddress],
+    recipient_sessions: &[&SessionRecord],
+    excluded_recipients: ServiceIdSequence<'_>,
+    content: &UnidentifiedSenderMessageContent,
+    identity_key_store: &mut dyn IdentityKeyStore,
+    random: &[u8],
+) -> Result<Vec<u8>> {
+    let mut rng = CallerRng::new(random)?;
     sealed_sender_multi_recipient_encrypt(
         recipients,
         recipient_sessions,
@@ -1187,7 +1648,7 @@ async fn SealedSender_MultiRecipientEncryptNode(
     content: &UnidentifiedSenderMessageContent,
     identity_key_store: &mut dyn IdentityKeyStore,
 ) -> Result<Vec<u8>> {
-    let mut rng = rand::rngs::OsRng;
+    let mut rng = BridgeRng;
     sealed_sender_multi_recipient_encrypt(
         recipients,
         &recipient_sessions.iter().collect::<Vec<&SessionRecord>>(),
@@ -1216,6 +1677,11 @@ fn SealedSender_MultiRecipientMessageForSingleRecipient(
     Ok(result)
 }
 
+#[bridge_fn(jni = false, node = false)]
+fn SealedSender_EnvelopeFingerprint(ciphertext: &[u8]) -> Result<[u8; 32]> {
+    sealed_sender_envelope_fingerprint(ciphertext)
+}
+
 #[bridge_fn(node = "SealedSender_DecryptToUsmc")]
 async fn SealedSessionCipher_DecryptToUsmc(
     ctext: &[u8],
@@ -1261,7 +1727,7 @@ async fn SenderKeyDistributionMessage_Create(
     distribution_id: Uuid,
     store: &mut dyn SenderKeyStore,
 ) -> Result<SenderKeyDistributionMessage> {
-    let mut csprng = rand::rngs::OsRng;
+    let mut csprng = BridgeRng;
     create_sender_key_distribution_message(sender, distribution_id, store, &mut csprng).await
 }
 
@@ -1284,11 +1750,50 @@ async fn GroupCipher_EncryptMessage(
     message: &[u8],
     store: &mut dyn SenderKeyStore,
 ) -> Result<CiphertextMessage> {
-    let mut rng = rand::rngs::OsRng;
+    let mut rng = BridgeRng;
     let ctext = group_encrypt(store, sender, distribution_id, message, &mut rng).await?;
     Ok(CiphertextMessage::SenderKeyMessage(ctext))
 }
 
+// Alternate version for FFI that takes caller-supplied randomness; see [`CallerRng`].
+#[bridge_fn(
+    ffi = "group_encrypt_message_with_randomness",
+    jni = false,
+    node = false
+)]
+async fn GroupCipher_EncryptMessageWithRandomness(
+    sender: &ProtocolAddress,
+    distribution_id: Uuid,
+    message: &[u8],
+    store: &mut dyn SenderKeyStore,
+    random: &[u8],
+) -> Result<CiphertextMessage> {
+    let mut rng = CallerRng::new(random)?;
+    let ctext = group_encrypt(store, sender, distribution_id, message, &mut rng).await?;
+    Ok(CiphertextMessage::SenderKeyMessage(ctext))
+}
+
+#[bridge_fn(ffi = "group_encrypt_with_expiry", jni = false, node = false)]
+async fn GroupCipher_EncryptMessageWithExpiry(
+    sender: &ProtocolAddress,
+    distribution_id: Uuid,
+    message: &[u8],
+    expiry_seconds: u32,
+    store: &mut dyn SenderKeyStore,
+) -> Result<CiphertextMessage> {
+    let mut rng = BridgeRng;
+    let ctext = group_encrypt_with_expiry(
+        store,
+        sender,
+        distribution_id,
+        message,
+        expiry_seconds,
+        &mut rng,
+    )
+    .await?;
+    Ok(CiphertextMessage::SenderKeyMessage(ctext))
+}
+
 #[bridge_fn(ffi = "group_decrypt_message")]
 async fn GroupCipher_DecryptMessage(
     sender: &ProtocolAddress,
@@ -1297,3 +1802,111 @@ async fn GroupCipher_DecryptMessage(
 ) -> Result<Vec<u8>> {
     group_decrypt(message, store, sender).await
 }
+
+/// Like `GroupCipher_DecryptMessage`, but rejects a message that would advance the sender's chain
+/// by more than `max_iteration_jump` messages. The limit can only be lowered from the default.
+#[bridge_fn(ffi = "group_decrypt_message_with_max_jump", jni = false, node = false)]
+async fn GroupCipher_DecryptMessageWithMaxJump(
+    sender: &ProtocolAddress,
+    message: &[u8],
+    store: &mut dyn SenderKeyStore,
+    max_iteration_jump: u32,
+) -> Result<Vec<u8>> {
+    group_decrypt_with_max_jump(message, store, sender, max_iteration_jump).await
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::rng::with_seeded_rng;
+
+    #[test]
+    fn identity_key_round_trips_through_public_key() {
+        let public_key = KeyPair::generate(&mut rand::rngs::OsRng).public_key;
+
+        let identity_key = IdentityKey_FromPublicKey(&public_key);
+        assert_eq!(IdentityKey_GetPublicKey(&identity_key), public_key);
+        assert_eq!(
+            IdentityKey_SerializeHandle(&identity_key),
+            public_key.serialize().into_vec()
+        );
+
+        let deserialized =
+            IdentityKey_Deserialize(&public_key.serialize()).expect("valid identity key");
+        assert_eq!(deserialized, identity_key);
+        assert!(IdentityKey_Deserialize(&[0x05; 16]).is_err());
+    }
+
+    #[test]
+    fn sender_key_message_signature_is_reproducible_with_seeded_rng() {
+        let signing_key = PrivateKey::deserialize(&[0x42; 32]).expect("valid");
+        let distribution_id = Uuid::from_bytes([0xd1; 16]);
+        let new_message = || {
+            SenderKeyMessage_New(3, distribution_id, 7, 42, &[1, 2, 3], &signing_key)
+                .expect("valid")
+                .serialized()
+                .to_vec()
+        };
+
+        let first = with_seeded_rng([1; 32], new_message);
+        assert_eq!(first, with_seeded_rng([1; 32], new_message));
+        assert_ne!(first, with_seeded_rng([2; 32], new_message));
+        assert_ne!(
+            new_message(),
+            new_message(),
+            "unseeded signatures are randomized"
+        );
+
+        let message = SenderKeyMessage::try_from(&first[..]).expect("valid");
+        assert!(message
+            .verify_signature(&signing_key.public_key().expect("valid"))
+            .expect("can verify"));
+    }
+
+    #[test]
+    fn group_encrypt_is_reproducible_with_supplied_randomness() {
+        let sender = ProtocolAddress::new("+14159999999".to_owned(), 1.into());
+        let distribution_id = Uuid::from_bytes([0xd1; 16]);
+        let mut store = InMemSenderKeyStore::new();
+        create_sender_key_distribution_message(
+            &sender,
+            distribution_id,
+            &mut store,
+            &mut rand::rngs::OsRng,
+        )
+        .now_or_never()
+        .expect("sync")
+        .expect("can create sender key");
+
+        // Each call starts from the same chain state, so only the randomness differs.
+        let encrypt = |random: &[u8]| {
+            GroupCipher_EncryptMessageWithRandomness(
+                &sender,
+                distribution_id,
+                b"hello",
+                &mut store.clone(),
+                random,
+            )
+            .now_or_never()
+            .expect("sync")
+            .map(|message| message.serialize().to_vec())
+        };
+
+        let first = encrypt(&[1; 32]).expect("can encrypt");
+        assert_eq!(first, encrypt(&[1; 32]).expect("can encrypt"));
+        assert_eq!(
+            first,
+            encrypt(&[[1; 32], [2; 32]].concat()).expect("can encrypt")
+        );
+        assert_ne!(first, encrypt(&[2; 32]).expect("can encrypt"));
+        assert_ne!(
+            encrypt(&[]).expect("can encrypt"),
+            encrypt(&[]).expect("can encrypt"),
+            "no supplied randomness falls back to the default RNG"
+        );
+        assert!(matches!(
+            encrypt(&[1; 31]),
+            Err(SignalProtocolError::InvalidArgument(_))
+        ));
+    }
+}
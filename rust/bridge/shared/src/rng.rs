@@ -0,0 +1,132 @@
+//
+// Copyright 2024 Signal Messenger, LLC.
+// SPDX-License-Identifier: AGPL-3.0-only
+//
+
+//! The source of randomness for bridge functions.
+//!
+//! Production builds always use [`OsRng`](rand::rngs::OsRng). With the `testing-seeded-rng`
+//! feature (and in this crate's own tests), [`with_seeded_rng`] substitutes a seeded
+//! [`ChaCha20Rng`](rand_chacha::ChaCha20Rng) on the current thread, so that signatures and
+//! ciphertexts can be checked against fixed vectors.
+//!
+//! Some bridge functions also accept entropy from the caller directly; see [`CallerRng`].
+
+use libsignal_protocol::SignalProtocolError;
+use rand::{CryptoRng, RngCore, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+
+#[cfg(not(any(test, feature = "testing-seeded-rng")))]
+pub use rand::rngs::OsRng as BridgeRng;
+
+#[cfg(any(test, feature = "testing-seeded-rng"))]
+pub use seeded::*;
+
+#[cfg(any(test, feature = "testing-seeded-rng"))]
+mod seeded {
+    use std::cell::RefCell;
+
+    use super::*;
+
+    thread_local! {
+        static SEEDED_RNG: RefCell<Option<ChaCha20Rng>> = const { RefCell::new(None) };
+    }
+
+    /// Draws from the current thread's seeded RNG if there is one, and from
+    /// [`OsRng`](rand::rngs::OsRng) otherwise.
+    pub struct BridgeRng;
+
+    impl BridgeRng {
+        fn with<T>(f: impl FnOnce(&mut dyn RngCore) -> T) -> T {
+            SEEDED_RNG.with(|slot| match slot.borrow_mut().as_mut() {
+                Some(rng) => f(rng),
+                None => f(&mut rand::rngs::OsRng),
+            })
+        }
+    }
+
+    impl RngCore for BridgeRng {
+        fn next_u32(&mut self) -> u32 {
+            Self::with(|rng| rng.next_u32())
+        }
+
+        fn next_u64(&mut self) -> u64 {
+            Self::with(|rng| rng.next_u64())
+        }
+
+        fn fill_bytes(&mut self, dest: &mut [u8]) {
+            Self::with(|rng| rng.fill_bytes(dest))
+        }
+
+        fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+            Self::with(|rng| rng.try_fill_bytes(dest))
+        }
+    }
+
+    impl CryptoRng for BridgeRng {}
+
+    /// Runs `f` with bridge functions on the current thread drawing from a [`ChaCha20Rng`] seeded
+    /// with `seed`.
+    pub fn with_seeded_rng<T>(seed: [u8; 32], f: impl FnOnce() -> T) -> T {
+        let previous = SEEDED_RNG.with(|slot| slot.replace(Some(ChaCha20Rng::from_seed(seed))));
+        let _restore = scopeguard::guard(previous, |previous| {
+            SEEDED_RNG.with(|slot| *slot.borrow_mut() = previous);
+        });
+        f()
+    }
+}
+
+/// The source of randomness for bridge functions that take caller-supplied entropy.
+///
+/// An empty buffer means the caller has no preference, and draws from [`BridgeRng`]. Otherwise the
+/// first 32 bytes seed a [`ChaCha20Rng`], so the same bytes always produce the same output.
+/// That includes any keys generated from them, so callers must not reuse entropy across messages.
+pub enum CallerRng {
+    Default(BridgeRng),
+    Seeded(ChaCha20Rng),
+}
+
+const CALLER_SEED_LEN: usize = 32;
+
+impl CallerRng {
+    pub fn new(random: &[u8]) -> Result<Self, SignalProtocolError> {
+        if random.is_empty() {
+            return Ok(Self::Default(BridgeRng));
+        }
+        let seed = random.first_chunk::<CALLER_SEED_LEN>().ok_or_else(|| {
+            SignalProtocolError::InvalidArgument(format!(
+                "need at least {} random bytes, got {}",
+                CALLER_SEED_LEN,
+                random.len()
+            ))
+        })?;
+        Ok(Self::Seeded(ChaCha20Rng::from_seed(*seed)))
+    }
+
+    fn as_dyn(&mut self) -> &mut dyn RngCore {
+        match self {
+            Self::Default(rng) => rng,
+            Self::Seeded(rng) => rng,
+        }
+    }
+}
+
+impl RngCore for CallerRng {
+    fn next_u32(&mut self) -> u32 {
+        self.as_dyn().next_u32()
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.as_dyn().next_u64()
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.as_dyn().fill_bytes(dest)
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.as_dyn().try_fill_bytes(dest)
+    }
+}
+
+impl CryptoRng for CallerRng {}
@@ -18,4 +18,58 @@ pub fn test_only_fn_returns_123() -> u32 {
     123
 }
 
+/// Derives the AES-CBC IV that message `counter` of a 1:1 session chain is encrypted with, given
+/// the chain's key at index 0.
+///
+/// Exposed so that interop test vectors can be checked against each client.
+#[cfg(feature = "interop-testing")]
+#[bridge_fn(ffi = "derive_nonce_from_counter", jni = false, node = false)]
+pub fn test_only_derive_nonce_from_counter(chain_key: &[u8; 32], counter: u32) -> [u8; 16] {
+    libsignal_protocol::derive_message_iv(*chain_key, counter)
+}
+
+/// Hashes the public inputs of an X3DH or PQXDH handshake, using the same computation as the
+/// `transcript_hash` methods on `AliceSignalProtocolParameters` and
+/// `BobSignalProtocolParameters`.
+///
+/// Keys are in their serialized form. Pass an empty `bob_one_time_pre_key` if the handshake didn't
+/// use one, and an empty `bob_kyber_pre_key` and `kyber_ciphertext` for a pre-Kyber handshake.
+#[cfg(feature = "interop-testing")]
+#[bridge_fn(ffi = "x3dh_transcript_hash", jni = false, node = false)]
+pub fn test_only_x3dh_transcript_hash(
+    alice_identity_key: &[u8],
+    alice_base_key: &[u8],
+    bob_identity_key: &[u8],
+    bob_signed_pre_key: &[u8],
+    bob_one_time_pre_key: &[u8],
+    bob_kyber_pre_key: &[u8],
+    kyber_ciphertext: &[u8],
+) -> Result<[u8; 32], libsignal_protocol::SignalProtocolError> {
+    use libsignal_protocol::{kem, IdentityKey, PublicKey, SignalProtocolError};
+
+    let bob_one_time_pre_key = match bob_one_time_pre_key {
+        [] => None,
+        key => Some(PublicKey::deserialize(key)?),
+    };
+    let bob_kyber_pre_key = match (bob_kyber_pre_key, kyber_ciphertext) {
+        ([], []) => None,
+        ([], _) | (_, []) => {
+            return Err(SignalProtocolError::InvalidArgument(
+                "Kyber pre-key and ciphertext must both be present or both be empty".to_owned(),
+            ))
+        }
+        (key, ciphertext) => Some((kem::PublicKey::deserialize(key)?, ciphertext)),
+    };
+    Ok(libsignal_protocol::handshake_transcript_hash(
+        &IdentityKey::decode(alice_identity_key)?,
+        &PublicKey::deserialize(alice_base_key)?,
+        &IdentityKey::decode(bob_identity_key)?,
+        &PublicKey::deserialize(bob_signed_pre_key)?,
+        bob_one_time_pre_key.as_ref(),
+        bob_kyber_pre_key
+            .as_ref()
+            .map(|(key, ciphertext)| (key, *ciphertext)),
+    ))
+}
+
 pub mod message_backup;
@@ -3,6 +3,7 @@
 // SPDX-License-Identifier: AGPL-3.0-only
 //
 
+use std::collections::HashMap;
 use std::ffi::{c_char, c_uchar, CStr};
 use std::fmt::Display;
 use std::num::{NonZeroU64, ParseIntError};
@@ -504,7 +505,115 @@ impl ResultTypeInfo for crate::zkgroup::Timestamp {
 ///
 /// When we do this, we hand the lifetime over to the app. Since we don't know how long the object
 /// will be kept alive, it can't (safely) have references to anything with a non-static lifetime.
-pub trait BridgeHandle: 'static {}
+///
+/// By default handles are uniquely-owned `Box`es. Types registered with `shared = true` are
+/// instead backed by an `Arc`, so that cloning a handle only bumps a reference count and a read
+/// through one handle stays valid even if another thread destroys a different handle to the same
+/// object. Either way, the pointer refers directly to the `T`, so borrowing works the same.
+///
+/// Types registered with `tagged = true` are tracked by [`TaggedHandle`]. Borrowing such a handle
+/// checks it against the table of live handles first, so a handle of any other type is rejected
+/// with a [`WrongHandleTypeError`] rather than being reinterpreted.
+pub trait BridgeHandle: 'static {
+    /// Moves `self` to the heap and returns a new handle to it.
+    fn into_raw_handle(self) -> *mut Self
+    where
+        Self: Sized,
+    {
+        Box::into_raw(Box::new(self))
+    }
+
+    /// Takes back ownership of a value from a handle, consuming the handle.
+    ///
+    /// # Safety
+    ///
+    /// `handle` must have been produced by [`BridgeHandle::into_raw_handle`] (or a generated
+    /// `clone` function) and not already destroyed.
+    unsafe fn from_raw_handle(handle: *mut Self) -> Self
+    where
+        Self: Sized,
+    {
+        *Box::from_raw(handle)
+    }
+
+    /// Checks that `handle` refers to a `Self` before it is dereferenced.
+    ///
+    /// Only tagged handles can actually be checked; for everything else this always succeeds.
+    ///
+    /// # Safety
+    ///
+    /// `handle` must be non-null and refer to a live handle.
+    unsafe fn check_raw_handle(_handle: *const Self) -> Result<(), WrongHandleTypeError> {
+        Ok(())
+    }
+}
+
+/// Computes the tag for a handle type from its FFI name (64-bit FNV-1a).
+pub const fn handle_tag(ffi_name: &str) -> u64 {
+    let bytes = ffi_name.as_bytes();
+    let mut hash = 0xcbf2_9ce4_8422_2325_u64;
+    let mut i = 0;
+    while i < bytes.len() {
+        hash ^= bytes[i] as u64;
+        hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+        i += 1;
+    }
+    hash
+}
+
+/// Bookkeeping for handles of types registered with `tagged = true`.
+///
+/// Every live tagged handle is recorded along with its type's tag, so checking a handle never has
+/// to read through it. A pointer that isn't in the table (an untagged handle, a freed one, or
+/// anything else) is rejected the same way as a tagged handle of another type. The handle itself
+/// is an ordinary `Box`, so it can be borrowed like any other handle.
+pub struct TaggedHandle;
+
+static LIVE_TAGGED_HANDLES: once_cell::sync::Lazy<std::sync::Mutex<HashMap<usize, u64>>> =
+    once_cell::sync::Lazy::new(Default::default);
+
+impl TaggedHandle {
+    fn live_handles() -> std::sync::MutexGuard<'static, HashMap<usize, u64>> {
+        // The table is never left half-updated, so a panic elsewhere doesn't invalidate it.
+        LIVE_TAGGED_HANDLES
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+
+    /// Moves `value` to the heap, records it under `tag`, and returns a handle to it.
+    pub fn into_raw<T>(value: T, tag: u64) -> *mut T {
+        let raw = Box::into_raw(Box::new(value));
+        Self::live_handles().insert(raw as usize, tag);
+        raw
+    }
+
+    /// Checks that `handle` is a live handle recorded under `expected`.
+    ///
+    /// Nothing is read through `handle`, so this is safe to call with any pointer.
+    pub fn check<T>(
+        handle: *const T,
+        expected: u64,
+        type_name: &'static str,
+    ) -> Result<(), WrongHandleTypeError> {
+        if Self::live_handles().get(&(handle as usize)) != Some(&expected) {
+            return Err(WrongHandleTypeError {
+                expected: type_name,
+            });
+        }
+        Ok(())
+    }
+
+    /// Takes back ownership of the value behind `handle`, removing it from the table.
+    ///
+    /// # Safety
+    ///
+    /// `handle` must have been produced by [`TaggedHandle::into_raw`] for this `T` and not already
+    /// freed.
+    pub unsafe fn from_raw<T>(handle: *mut T) -> T {
+        Self::live_handles().remove(&(handle as usize));
+        *Box::from_raw(handle)
+    }
+}
 
 impl<T: BridgeHandle> SimpleArgTypeInfo for &T {
     type ArgType = *const T;
@@ -542,6 +651,9 @@ impl<'a, T: BridgeHandle> ArgTypeInfo<'a> for &'a [&'a T] {
         if slice_of_pointers.contains(&std::ptr::null()) {
             return Err(NullPointerError.into());
         }
+        for &handle in slice_of_pointers {
+            unsafe { T::check_raw_handle(handle) }?;
+        }
 
         Ok(foreign)
     }
@@ -561,7 +673,7 @@ impl<'a, T: BridgeHandle> ArgTypeInfo<'a> for &'a [&'a T] {
 impl<T: BridgeHandle> ResultTypeInfo for T {
     type ResultType = *mut T;
     fn convert_into(self) -> SignalFfiResult<Self::ResultType> {
-        Ok(Box::into_raw(Box::new(self)))
+        Ok(self.into_raw_handle())
     }
 }
 
@@ -759,16 +871,82 @@ macro_rules! ffi_bridge_handle_clone {
     };
 }
 
+/// Like [`ffi_bridge_handle_clone`], but for handles backed by an `Arc`.
+///
+/// The new handle is the same pointer with its reference count incremented.
+#[macro_export]
+macro_rules! ffi_bridge_shared_handle_clone {
+    ( $typ:ty as $ffi_name:ident ) => {
+        ::paste::paste! {
+            #[export_name = concat!(
+                env!("LIBSIGNAL_BRIDGE_FN_PREFIX_FFI"),
+                stringify!($ffi_name),
+                "_clone",
+            )]
+            pub unsafe extern "C" fn [<__bridge_handle_ffi_ $ffi_name _clone>](
+                new_obj: *mut *mut $typ,
+                obj: *const $typ,
+            ) -> *mut $crate::ffi::SignalFfiError {
+                $crate::ffi::run_ffi_safe(|| {
+                    if new_obj.is_null() || obj.is_null() {
+                        return Err($crate::ffi::NullPointerError.into());
+                    }
+                    ::std::sync::Arc::increment_strong_count(obj);
+                    *new_obj = obj.cast_mut();
+                    Ok(())
+                })
+            }
+        }
+    };
+}
+
 /// Implements `crate::ffi::BridgeHandle` for the given type.
+///
+/// With `shared = true`, handles are backed by an `Arc` rather than a `Box`.
 #[macro_export]
 macro_rules! ffi_bridge_as_handle {
     ( $typ:ty as false $(, $($_:tt)*)? ) => {};
     ( $typ:ty as $ffi_name:ident ) => {
         impl $crate::ffi::BridgeHandle for $typ {}
     };
-    ( $typ:ty ) => {
+    ( $typ:ty as $ffi_name:ident, shared = true ) => {
+        impl $crate::ffi::BridgeHandle for $typ {
+            fn into_raw_handle(self) -> *mut Self {
+                ::std::sync::Arc::into_raw(::std::sync::Arc::new(self)).cast_mut()
+            }
+
+            unsafe fn from_raw_handle(handle: *mut Self) -> Self {
+                ::std::sync::Arc::unwrap_or_clone(::std::sync::Arc::from_raw(handle))
+            }
+        }
+    };
+    ( $typ:ty as $ffi_name:ident, tagged = true ) => {
+        impl $crate::ffi::BridgeHandle for $typ {
+            fn into_raw_handle(self) -> *mut Self {
+                $crate::ffi::TaggedHandle::into_raw(
+                    self,
+                    $crate::ffi::handle_tag(stringify!($ffi_name)),
+                )
+            }
+
+            unsafe fn from_raw_handle(handle: *mut Self) -> Self {
+                $crate::ffi::TaggedHandle::from_raw(handle)
+            }
+
+            unsafe fn check_raw_handle(
+                handle: *const Self,
+            ) -> Result<(), $crate::ffi::WrongHandleTypeError> {
+                $crate::ffi::TaggedHandle::check(
+                    handle,
+                    $crate::ffi::handle_tag(stringify!($ffi_name)),
+                    stringify!($typ),
+                )
+            }
+        }
+    };
+    ( $typ:ty $(, shared = $shared:tt)? $(, tagged = $tagged:tt)? ) => {
         ::paste::paste! {
-            $crate::ffi_bridge_as_handle!($typ as [<$typ:snake>] );
+            $crate::ffi_bridge_as_handle!($typ as [<$typ:snake>] $(, shared = $shared)? $(, tagged = $tagged)? );
         }
     };
 }
@@ -777,16 +955,24 @@ macro_rules! ffi_bridge_as_handle {
 #[macro_export]
 macro_rules! ffi_bridge_handle_fns {
     ( $typ:ty as false $(, $($_:tt)*)? ) => {};
-    ( $typ:ty as $ffi_name:ident, clone = false ) => {
-        $crate::ffi_bridge_handle_destroy!($typ as $ffi_name);
+    ( $typ:ty as $ffi_name:ident, shared = true ) => {
+        $crate::ffi_bridge_handle_destroy!($typ as $ffi_name, shared = true);
+        $crate::ffi_bridge_shared_handle_clone!($typ as $ffi_name);
     };
-    ( $typ:ty as $ffi_name:ident ) => {
-        $crate::ffi_bridge_handle_fns!($typ as $ffi_name, clone = false);
+    ( $typ:ty as $ffi_name:ident, tagged = true ) => {
+        $crate::ffi_bridge_handle_destroy!($typ as $ffi_name, tagged = true);
+        $crate::ffi_bridge_handle_clone!($typ as $ffi_name);
+    };
+    ( $typ:ty as $ffi_name:ident, clone = false $(, zeroize = $zeroize:tt)? ) => {
+        $crate::ffi_bridge_handle_destroy!($typ as $ffi_name $(, zeroize = $zeroize)?);
+    };
+    ( $typ:ty as $ffi_name:ident $(, zeroize = $zeroize:tt)? ) => {
+        $crate::ffi_bridge_handle_fns!($typ as $ffi_name, clone = false $(, zeroize = $zeroize)?);
         $crate::ffi_bridge_handle_clone!($typ as $ffi_name);
     };
-    ( $typ:ty $(, clone = $_:tt)? ) => {
+    ( $typ:ty $(, clone = $_:tt)? $(, zeroize = $zeroize:tt)? $(, shared = $shared:tt)? $(, tagged = $tagged:tt)? ) => {
         ::paste::paste! {
-            $crate::ffi_bridge_handle_fns!($typ as [<$typ:snake>] $(, clone = $_)? );
+            $crate::ffi_bridge_handle_fns!($typ as [<$typ:snake>] $(, clone = $_)? $(, zeroize = $zeroize)? $(, shared = $shared)? $(, tagged = $tagged)? );
         }
     };
 }
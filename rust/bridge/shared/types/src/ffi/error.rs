@@ -18,10 +18,29 @@ use signal_pin::Error as PinError;
 use usernames::{UsernameError, UsernameLinkError};
 use zkgroup::{ZkGroupDeserializationFailure, ZkGroupVerificationFailure};
 
-use crate::support::describe_panic;
-
-use super::{FutureCancelled, NullPointerError, UnexpectedPanic};
+use super::{
+    BufferTooSmallError, FutureCancelled, NullPointerError, UnexpectedPanic, WrongHandleTypeError,
+};
 
+/// The error categories reported to C callers by `signal_error_get_type`.
+///
+/// These values are part of the public FFI and must never be renumbered or reused; add new
+/// variants with fresh values instead. Notable mappings:
+///
+/// - [`InvalidKey`](Self::InvalidKey): malformed or mistyped keys
+/// - [`InvalidKeyLength`](Self::InvalidKeyLength): keys of the wrong size for their type
+/// - [`UntrustedIdentity`](Self::UntrustedIdentity): the identity store rejected a remote key
+/// - [`SessionNotFound`](Self::SessionNotFound): no session (or sender key state) for an address
+/// - [`DuplicatedMessage`](Self::DuplicatedMessage): a message key was already used
+/// - [`IterationTooLarge`](Self::IterationTooLarge): a group message skips too far ahead in its
+///   sender's chain
+/// - [`TooManySkippedKeys`](Self::TooManySkippedKeys): a 1:1 message would store more skipped
+///   message keys than the caller allowed
+/// - [`InvalidMessage`](Self::InvalidMessage): malformed messages, including MAC failures
+/// - [`SealedSenderMacVerificationFailed`](Self::SealedSenderMacVerificationFailed): a streamed
+///   sealed sender message whose MAC did not match
+/// - [`NullParameter`](Self::NullParameter): a required pointer argument was null
+/// - [`InternalError`](Self::InternalError): unexpected panics and other bugs in libsignal
 #[derive(Debug)]
 #[repr(C)]
 pub enum SignalErrorCode {
@@ -34,6 +53,7 @@ pub enum SignalErrorCode {
     InvalidType = 6,
     InvalidUtf8String = 7,
     Cancelled = 8,
+    BufferTooSmall = 9,
 
     ProtobufError = 10,
 
@@ -43,10 +63,12 @@ pub enum SignalErrorCode {
 
     InvalidMessage = 30,
     SealedSenderSelfSend = 31,
+    SealedSenderMacVerificationFailed = 32,
 
     InvalidKey = 40,
     InvalidSignature = 41,
     InvalidAttestationData = 42,
+    InvalidKeyLength = 43,
 
     FingerprintVersionMismatch = 51,
     FingerprintParsingError = 52,
@@ -61,6 +83,8 @@ pub enum SignalErrorCode {
     InvalidSenderKeySession = 83,
 
     DuplicatedMessage = 90,
+    IterationTooLarge = 91,
+    TooManySkippedKeys = 92,
 
     CallbackError = 100,
 
@@ -145,11 +169,26 @@ pub trait FfiError: UpcastAsAny + fmt::Debug + Send + 'static {
 ///
 /// [ThinBox]: https://doc.rust-lang.org/std/boxed/struct.ThinBox.html
 #[derive(Debug)]
-pub struct SignalFfiError(Box<dyn FfiError + Send>);
+pub struct SignalFfiError {
+    error: Box<dyn FfiError + Send>,
+    backtrace: Option<String>,
+}
 
 impl SignalFfiError {
+    fn new(error: Box<dyn FfiError + Send>) -> Self {
+        // Only captured with the `capture-backtrace` feature, since capturing is slow.
+        let backtrace = cfg!(feature = "capture-backtrace")
+            .then(|| std::backtrace::Backtrace::force_capture().to_string());
+        Self { error, backtrace }
+    }
+
     pub fn downcast_ref<T: FfiError>(&self) -> Option<&T> {
-        (*self.0).upcast_as_any().downcast_ref()
+        (*self.error).upcast_as_any().downcast_ref()
+    }
+
+    /// Where this error was converted to a `SignalFfiError`, if built with `capture-backtrace`.
+    pub fn backtrace(&self) -> Option<&str> {
+        self.backtrace.as_deref()
     }
 }
 
@@ -160,13 +199,13 @@ impl std::ops::Deref for SignalFfiError {
     type Target = dyn FfiError;
 
     fn deref(&self) -> &Self::Target {
-        &*self.0
+        &*self.error
     }
 }
 
 impl fmt::Display for SignalFfiError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        f.write_str(&self.0.describe())
+        f.write_str(&self.error.describe())
     }
 }
 
@@ -190,12 +229,12 @@ impl<T: FfiError> From<T> for SignalFfiError {
                         std::mem::replace(e, SignalProtocolError::InvalidPreKeyId)
                     });
                 if let Some(original_error) = original_error {
-                    Self(Box::new(original_error))
+                    Self::new(Box::new(original_error))
                 } else {
-                    Self(Box::new(value))
+                    Self::new(Box::new(value))
                 }
             }
-            None => Self(Box::new(value)),
+            None => Self::new(Box::new(value)),
         }
     }
 }
@@ -223,11 +262,12 @@ impl FfiError for SignalProtocolError {
             Self::FingerprintParsingError => SignalErrorCode::FingerprintParsingError,
             Self::NoKeyTypeIdentifier
             | Self::BadKeyType(_)
-            | Self::BadKeyLength(_, _)
             | Self::InvalidMacKeyLength(_)
             | Self::BadKEMKeyType(_)
-            | Self::WrongKEMKeyType(_, _)
-            | Self::BadKEMKeyLength(_, _) => SignalErrorCode::InvalidKey,
+            | Self::WrongKEMKeyType(_, _) => SignalErrorCode::InvalidKey,
+            Self::BadKeyLength(_, _) | Self::BadKEMKeyLength(_, _) => {
+                SignalErrorCode::InvalidKeyLength
+            }
             Self::SignatureValidationFailed => SignalErrorCode::InvalidSignature,
             Self::UntrustedIdentity(_) => SignalErrorCode::UntrustedIdentity,
             Self::InvalidPreKeyId | Self::InvalidSignedPreKeyId | Self::InvalidKyberPreKeyId => {
@@ -240,9 +280,14 @@ impl FfiError for SignalProtocolError {
             Self::InvalidSenderKeySession { .. } => SignalErrorCode::InvalidSenderKeySession,
             Self::InvalidRegistrationId(_, _) => SignalErrorCode::InvalidRegistrationId,
             Self::DuplicatedMessage(_, _) => SignalErrorCode::DuplicatedMessage,
+            Self::IterationTooLarge(_, _) => SignalErrorCode::IterationTooLarge,
+            Self::TooManySkippedKeys(_) => SignalErrorCode::TooManySkippedKeys,
             Self::FfiBindingError(_) => SignalErrorCode::InternalError,
             Self::ApplicationCallbackError(_, _) => SignalErrorCode::CallbackError,
             Self::SealedSenderSelfSend => SignalErrorCode::SealedSenderSelfSend,
+            Self::SealedSenderMacVerificationFailed => {
+                SignalErrorCode::SealedSenderMacVerificationFailed
+            }
         }
     }
 
@@ -577,6 +622,26 @@ impl FfiError for http::uri::InvalidUri {
     }
 }
 
+impl FfiError for hex::FromHexError {
+    fn describe(&self) -> String {
+        format!("invalid hex: {self}")
+    }
+
+    fn code(&self) -> SignalErrorCode {
+        SignalErrorCode::InvalidArgument
+    }
+}
+
+impl FfiError for base64::DecodeError {
+    fn describe(&self) -> String {
+        format!("invalid base64: {self}")
+    }
+
+    fn code(&self) -> SignalErrorCode {
+        SignalErrorCode::InvalidArgument
+    }
+}
+
 #[cfg(feature = "signal-media")]
 impl FfiError for signal_media::sanitize::mp4::Error {
     fn describe(&self) -> String {
@@ -660,9 +725,32 @@ impl FfiError for NullPointerError {
     }
 }
 
+impl FfiError for WrongHandleTypeError {
+    fn describe(&self) -> String {
+        format!("wrong handle type: expected {}", self.expected)
+    }
+
+    fn code(&self) -> SignalErrorCode {
+        SignalErrorCode::InvalidType
+    }
+}
+
+impl FfiError for BufferTooSmallError {
+    fn describe(&self) -> String {
+        format!(
+            "buffer too small: {} bytes required, {} provided",
+            self.required, self.provided
+        )
+    }
+
+    fn code(&self) -> SignalErrorCode {
+        SignalErrorCode::BufferTooSmall
+    }
+}
+
 impl FfiError for UnexpectedPanic {
     fn describe(&self) -> String {
-        format!("unexpected panic: {}", describe_panic(&self.0))
+        format!("unexpected panic: {}", self.0)
     }
 
     fn code(&self) -> SignalErrorCode {
@@ -715,3 +803,61 @@ impl fmt::Display for CallbackError {
 }
 
 impl std::error::Error for CallbackError {}
+
+#[cfg(test)]
+mod test {
+    use test_case::test_case;
+
+    use super::*;
+
+    fn test_address() -> ProtocolAddress {
+        ProtocolAddress::new("+14155550100".to_owned(), 1.into())
+    }
+
+    #[test_case(SignalProtocolError::BadKeyType(0xff), 40; "invalid key")]
+    #[test_case(SignalProtocolError::UntrustedIdentity(test_address()), 60; "untrusted identity")]
+    #[test_case(SignalProtocolError::SessionNotFound(test_address()), 80; "no session")]
+    #[test_case(SignalProtocolError::DuplicatedMessage(2, 1), 90; "duplicated message")]
+    #[test_case(SignalProtocolError::IterationTooLarge(0, 25_001), 91; "iteration too large")]
+    #[test_case(SignalProtocolError::TooManySkippedKeys(2000), 92; "too many skipped keys")]
+    #[test_case(SignalProtocolError::InvalidMessage(CiphertextMessageType::Whisper, "MAC verification failed"), 30; "MAC failure")]
+    #[test_case(SignalProtocolError::SealedSenderMacVerificationFailed, 32; "sealed sender MAC failure")]
+    fn protocol_error_codes(error: SignalProtocolError, expected: u32) {
+        assert_eq!(SignalFfiError::from(error).code() as u32, expected);
+    }
+
+    #[test_case(31; "short")]
+    #[test_case(34; "long")]
+    fn key_length_codes(len: usize) {
+        let private_key_error = PrivateKey::deserialize(&vec![9; len]).unwrap_err();
+        assert_eq!(SignalFfiError::from(private_key_error).code() as u32, 43);
+    }
+
+    #[test]
+    fn null_pointer_code() {
+        assert_eq!(SignalFfiError::from(NullPointerError).code() as u32, 4);
+    }
+
+    #[test]
+    fn wrong_handle_type_code() {
+        let error = WrongHandleTypeError {
+            expected: "SignalMessage",
+        };
+        assert_eq!(SignalFfiError::from(error).code() as u32, 6);
+    }
+
+    #[test]
+    fn buffer_too_small_code() {
+        let error = BufferTooSmallError {
+            required: 10,
+            provided: 4,
+        };
+        assert_eq!(SignalFfiError::from(error).code() as u32, 9);
+    }
+
+    #[test]
+    fn unexpected_panic_code() {
+        let panic = UnexpectedPanic::from_payload(Box::new("boom"));
+        assert_eq!(SignalFfiError::from(panic).code() as u32, 3);
+    }
+}
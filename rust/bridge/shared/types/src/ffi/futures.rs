@@ -80,16 +80,19 @@ impl<T: ResultTypeInfo + std::panic::UnwindSafe> ResultReporter for FutureResult
 
         let result = result.and_then(|result| {
             std::panic::catch_unwind(|| result.convert_into())
-                .unwrap_or_else(|panic| Err(UnexpectedPanic(panic).into()))
+                .unwrap_or_else(|panic| Err(UnexpectedPanic::from_payload(panic).into()))
         });
 
         match result {
             Ok(value) => (promise.complete)(std::ptr::null_mut(), &value, promise.context),
-            Err(err) => (promise.complete)(
-                Box::into_raw(Box::new(err)),
-                std::ptr::null(),
-                promise.context,
-            ),
+            Err(err) => {
+                record_last_error(&err);
+                (promise.complete)(
+                    Box::into_raw(Box::new(err)),
+                    std::ptr::null(),
+                    promise.context,
+                )
+            }
         }
     }
 }
@@ -137,5 +140,37 @@ pub fn catch_unwind<T>(
 ) -> impl Future<Output = SignalFfiResult<T>> + Send + std::panic::UnwindSafe + 'static {
     future
         .catch_unwind()
-        .unwrap_or_else(|panic| Err(UnexpectedPanic(panic).into()))
+        .unwrap_or_else(|panic| Err(UnexpectedPanic::from_payload(panic).into()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    extern "C" fn free_error(
+        error: *mut SignalFfiError,
+        _result: *const i32,
+        _context: *const std::ffi::c_void,
+    ) {
+        assert!(!error.is_null());
+        drop(unsafe { Box::from_raw(error) });
+    }
+
+    #[test]
+    fn async_errors_are_recorded_as_last_error() {
+        clear_last_error();
+        let completer = PromiseCompleter::<i32> {
+            promise: CPromise {
+                complete: free_error,
+                context: std::ptr::null(),
+                cancellation_id: 0,
+            },
+        };
+        FutureResultReporter::<i32>::new(Err(NullPointerError.into())).report_to(completer);
+        assert_eq!(
+            last_error_code(),
+            Some(SignalFfiError::from(NullPointerError).code() as u32)
+        );
+        assert!(take_last_error_message().is_some());
+    }
 }
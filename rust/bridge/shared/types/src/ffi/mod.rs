@@ -3,6 +3,7 @@
 // SPDX-License-Identifier: AGPL-3.0-only
 //
 
+use std::cell::RefCell;
 use std::ffi::CString;
 
 use derive_where::derive_where;
@@ -32,6 +33,22 @@ use crate::support::describe_panic;
 #[derive(Debug)]
 pub struct NullPointerError;
 
+/// Something other than a live handle of the expected type was passed where a tagged handle was
+/// expected.
+///
+/// See [`TaggedHandle`].
+#[derive(Debug)]
+pub struct WrongHandleTypeError {
+    pub expected: &'static str,
+}
+
+/// A caller-provided output buffer was too small to hold the result.
+#[derive(Debug)]
+pub struct BufferTooSmallError {
+    pub required: usize,
+    pub provided: usize,
+}
+
 #[repr(C)]
 pub struct BorrowedSliceOf<T> {
     base: *const T,
@@ -39,6 +56,11 @@ pub struct BorrowedSliceOf<T> {
 }
 
 impl<T> BorrowedSliceOf<T> {
+    /// Views the borrowed buffer as a slice.
+    ///
+    /// A null `base` is accepted when `length` is zero and produces an empty slice; callers
+    /// commonly pass this for an empty message or plaintext. A null `base` with a nonzero
+    /// `length` is a [`NullPointerError`].
     pub unsafe fn as_slice(&self) -> Result<&[T], NullPointerError> {
         if self.base.is_null() {
             if self.length != 0 {
@@ -192,16 +214,49 @@ pub struct FfiResponseAndDebugInfo {
     debug_info: FfiChatServiceDebugInfo,
 }
 
-struct UnexpectedPanic(Box<dyn std::any::Any + Send>);
-
-impl std::fmt::Debug for UnexpectedPanic {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.debug_tuple("UnexpectedPanic")
-            .field(&describe_panic(&self.0))
-            .finish()
+/// A panic caught at the bridge boundary, with its message extracted up front.
+///
+/// Panic payloads aren't `Sync` and usually aren't inspectable once they've been type-erased, so
+/// we keep only a readable description.
+#[derive(Debug)]
+struct UnexpectedPanic(String);
+
+impl UnexpectedPanic {
+    fn from_payload(payload: Box<dyn std::any::Any + Send>) -> Self {
+        if payload.is::<&str>() || payload.is::<String>() {
+            Self(describe_panic(&payload))
+        } else {
+            Self("panic with unknown payload".to_owned())
+        }
     }
 }
 
+thread_local! {
+    /// The code and description of the most recent error returned on this thread, either by
+    /// [`run_ffi_safe`] or by completing a promise (see [`FutureResultReporter`]).
+    static LAST_ERROR: RefCell<Option<(u32, String)>> = const { RefCell::new(None) };
+}
+
+fn record_last_error(error: &SignalFfiError) {
+    let entry = (error.code() as u32, error.to_string());
+    LAST_ERROR.with(|slot| *slot.borrow_mut() = Some(entry));
+}
+
+/// Returns the code of the most recent error returned on the current thread, if there is one.
+pub fn last_error_code() -> Option<u32> {
+    LAST_ERROR.with(|slot| slot.borrow().as_ref().map(|(code, _)| *code))
+}
+
+/// Removes and returns the description of the most recent error returned on the current thread.
+pub fn take_last_error_message() -> Option<String> {
+    LAST_ERROR.with(|slot| slot.borrow_mut().take().map(|(_, message)| message))
+}
+
+/// Forgets the most recent error returned on the current thread.
+pub fn clear_last_error() {
+    LAST_ERROR.with(|slot| *slot.borrow_mut() = None);
+}
+
 #[inline(always)]
 pub fn run_ffi_safe<F: FnOnce() -> Result<(), SignalFfiError> + std::panic::UnwindSafe>(
     f: F,
@@ -209,29 +264,38 @@ pub fn run_ffi_safe<F: FnOnce() -> Result<(), SignalFfiError> + std::panic::Unwi
     let result = match std::panic::catch_unwind(f) {
         Ok(Ok(())) => Ok(()),
         Ok(Err(e)) => Err(e),
-        Err(r) => Err(UnexpectedPanic(r).into()),
+        Err(r) => Err(UnexpectedPanic::from_payload(r).into()),
     };
 
     // When ThinBox is stabilized, we can return that instead of double-boxing.
     // (Unfortunately, Box<dyn MyTrait> is two pointers wide and not FFI-safe.)
     match result {
         Ok(()) => std::ptr::null_mut(),
-        Err(e) => Box::into_raw(Box::new(e)),
+        Err(e) => {
+            record_last_error(&e);
+            Box::into_raw(Box::new(e))
+        }
     }
 }
 
-pub unsafe fn native_handle_cast<T>(handle: *const T) -> Result<&'static T, SignalFfiError> {
+pub unsafe fn native_handle_cast<T: BridgeHandle>(
+    handle: *const T,
+) -> Result<&'static T, SignalFfiError> {
     if handle.is_null() {
         return Err(NullPointerError.into());
     }
+    T::check_raw_handle(handle)?;
 
     Ok(&*(handle))
 }
 
-pub unsafe fn native_handle_cast_mut<T>(handle: *mut T) -> Result<&'static mut T, SignalFfiError> {
+pub unsafe fn native_handle_cast_mut<T: BridgeHandle>(
+    handle: *mut T,
+) -> Result<&'static mut T, SignalFfiError> {
     if handle.is_null() {
         return Err(NullPointerError.into());
     }
+    T::check_raw_handle(handle)?;
 
     Ok(&mut *handle)
 }
@@ -252,7 +316,56 @@ pub unsafe fn write_result_to<T: ResultTypeInfo>(
 /// Not intended to be invoked directly.
 #[macro_export]
 macro_rules! ffi_bridge_handle_destroy {
-    ( $typ:ty as $ffi_name:ident ) => {
+    ( $typ:ty as $ffi_name:ident, shared = true ) => {
+        ::paste::paste! {
+            #[cfg(feature = "ffi")]
+            #[export_name = concat!(
+                env!("LIBSIGNAL_BRIDGE_FN_PREFIX_FFI"),
+                stringify!($ffi_name),
+                "_destroy",
+            )]
+            #[allow(non_snake_case)]
+            pub unsafe extern "C" fn [<__bridge_handle_ffi_ $ffi_name _destroy>](
+                p: *mut $typ
+            ) -> *mut ffi::SignalFfiError {
+                // Only releases this handle's reference; the value is dropped once the last
+                // handle to it is destroyed.
+                let p = std::panic::AssertUnwindSafe(p);
+                ffi::run_ffi_safe(|| {
+                    if !p.is_null() {
+                        drop(::std::sync::Arc::from_raw(*p));
+                    }
+                    Ok(())
+                })
+            }
+        }
+    };
+    ( $typ:ty as $ffi_name:ident, tagged = true ) => {
+        ::paste::paste! {
+            #[cfg(feature = "ffi")]
+            #[export_name = concat!(
+                env!("LIBSIGNAL_BRIDGE_FN_PREFIX_FFI"),
+                stringify!($ffi_name),
+                "_destroy",
+            )]
+            #[allow(non_snake_case)]
+            pub unsafe extern "C" fn [<__bridge_handle_ffi_ $ffi_name _destroy>](
+                p: *mut $typ
+            ) -> *mut ffi::SignalFfiError {
+                // A handle of the wrong type is reported rather than freed, since we don't know
+                // how to drop it.
+                let p = std::panic::AssertUnwindSafe(p);
+                ffi::run_ffi_safe(|| {
+                    if !p.is_null() {
+                        <$typ as ffi::BridgeHandle>::check_raw_handle(*p)?;
+                        drop(<$typ as ffi::BridgeHandle>::from_raw_handle(*p));
+                    }
+                    Ok(())
+                })
+            }
+        }
+    };
+    ( $typ:ty as $ffi_name:ident $(, zeroize = $zeroize:tt)? ) => {
         ::paste::paste! {
             #[cfg(feature = "ffi")]
             #[export_name = concat!(
@@ -272,7 +385,7 @@ macro_rules! ffi_bridge_handle_destroy {
                 let p = std::panic::AssertUnwindSafe(p);
                 ffi::run_ffi_safe(|| {
                     if !p.is_null() {
-                        drop(Box::from_raw(*p));
+                        $crate::bridge_handle_drop!(Box::from_raw(*p) $(, zeroize = $zeroize)?);
                     }
                     Ok(())
                 })
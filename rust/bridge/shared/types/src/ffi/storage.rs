@@ -18,11 +18,13 @@ type GetIdentityKey = extern "C" fn(
     public_keyp: *mut *mut PublicKey,
     address: *const ProtocolAddress,
 ) -> c_int;
+/// `public_key` is only valid for the duration of the call; clone the handle to keep it.
 type SaveIdentityKey = extern "C" fn(
     store_ctx: *mut c_void,
     address: *const ProtocolAddress,
     public_key: *const PublicKey,
 ) -> c_int;
+/// `public_key` is only valid for the duration of the call; clone the handle to keep it.
 type IsTrustedIdentity = extern "C" fn(
     store_ctx: *mut c_void,
     address: *const ProtocolAddress,
@@ -30,6 +32,18 @@ type IsTrustedIdentity = extern "C" fn(
     direction: c_uint,
 ) -> c_int;
 
+/// Lends `key` to a host callback as a handle of its own.
+///
+/// `PublicKey` handles are reference-counted, and a callback that keeps the key will clone the
+/// handle it was given, so it can't just be a pointer to a key we've borrowed. The callback's
+/// reference is released once it returns; any clones it made stay valid.
+fn with_public_key_handle<T>(key: &PublicKey, callback: impl FnOnce(*const PublicKey) -> T) -> T {
+    let handle = (*key).into_raw_handle();
+    let result = callback(handle);
+    drop(unsafe { PublicKey::from_raw_handle(handle) });
+    result
+}
+
 #[derive(Debug)]
 #[repr(C)]
 pub enum FfiDirection {
@@ -87,7 +101,9 @@ impl IdentityKeyStore for &FfiIdentityKeyStoreStruct {
         address: &ProtocolAddress,
         identity: &IdentityKey,
     ) -> Result<bool, SignalProtocolError> {
-        let result = (self.save_identity)(self.ctx, address, identity.public_key());
+        let result = with_public_key_handle(identity.public_key(), |public_key| {
+            (self.save_identity)(self.ctx, address, public_key)
+        });
 
         match result {
             0 => Ok(false),
@@ -110,8 +126,9 @@ impl IdentityKeyStore for &FfiIdentityKeyStoreStruct {
             Direction::Sending => FfiDirection::Sending,
             Direction::Receiving => FfiDirection::Receiving,
         };
-        let result =
-            (self.is_trusted_identity)(self.ctx, address, identity.public_key(), direction as u32);
+        let result = with_public_key_handle(identity.public_key(), |public_key| {
+            (self.is_trusted_identity)(self.ctx, address, public_key, direction as u32)
+        });
 
         match result {
             0 => Ok(false),
@@ -139,9 +156,9 @@ impl IdentityKeyStore for &FfiIdentityKeyStoreStruct {
             return Ok(None);
         }
 
-        let pk = unsafe { Box::from_raw(key) };
+        let pk = unsafe { PublicKey::from_raw_handle(key) };
 
-        Ok(Some(IdentityKey::new(*pk)))
+        Ok(Some(IdentityKey::new(pk)))
     }
 }
 
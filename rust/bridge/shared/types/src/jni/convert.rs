@@ -1210,15 +1210,15 @@ macro_rules! jni_bridge_as_handle {
 #[macro_export]
 macro_rules! jni_bridge_handle_fns {
     ( $typ:ty as false $(, $($_:tt)*)? ) => {};
-    ( $typ:ty as $jni_name:ident ) => {
-        $crate::jni_bridge_handle_destroy!($typ as $jni_name);
+    ( $typ:ty as $jni_name:ident $(, zeroize = $zeroize:tt)? ) => {
+        $crate::jni_bridge_handle_destroy!($typ as $jni_name $(, zeroize = $zeroize)?);
     };
-    ( $typ:ty ) => {
+    ( $typ:ty $(, zeroize = $zeroize:tt)? ) => {
         // `paste!` turns the type back into an identifier.
         // We can't specify an identifier here because the main `bridge_handle_fns!` accepts any type
         // and just passes it down.
         ::paste::paste! {
-            $crate::jni_bridge_handle_fns!($typ as $typ);
+            $crate::jni_bridge_handle_fns!($typ as $typ $(, zeroize = $zeroize)?);
         }
     };
 }
@@ -413,6 +413,9 @@ impl<'env> ConsumableException<'env> {
             | SignalJniError::Protocol(SignalProtocolError::CiphertextMessageTooShort(_))
             | SignalJniError::Protocol(SignalProtocolError::InvalidProtobufEncoding)
             | SignalJniError::Protocol(SignalProtocolError::InvalidSealedSenderMessage(_))
+            | SignalJniError::Protocol(SignalProtocolError::SealedSenderMacVerificationFailed)
+            | SignalJniError::Protocol(SignalProtocolError::IterationTooLarge(_, _))
+            | SignalJniError::Protocol(SignalProtocolError::TooManySkippedKeys(_))
             | SignalJniError::Protocol(SignalProtocolError::BadKEMCiphertextLength(_, _))
             | SignalJniError::SignalCrypto(SignalCryptoError::InvalidTag) => (
                 ClassName("org.signal.libsignal.protocol.InvalidMessageException"),
@@ -992,10 +995,13 @@ impl<'a> CiphertextMessageRef<'a> {
 
 /// Used by [`bridge_handle_fns`](crate::support::bridge_handle_fns).
 ///
+/// The generated `<Type>_1Destroy` takes the handle as a Java `long` and treats zero as a no-op,
+/// so Java owners can zero a handle after freeing it and safely destroy it again.
+///
 /// Not intended to be invoked directly.
 #[macro_export]
 macro_rules! jni_bridge_handle_destroy {
-    ( $typ:ty as $jni_name:ident ) => {
+    ( $typ:ty as $jni_name:ident $(, zeroize = $zeroize:tt)? ) => {
         ::paste::paste! {
             #[export_name = concat!(
                 env!("LIBSIGNAL_BRIDGE_FN_PREFIX_JNI"),
@@ -1009,7 +1015,8 @@ macro_rules! jni_bridge_handle_destroy {
                 handle: $crate::jni::ObjectHandle,
             ) {
                 if handle != 0 {
-                    let _boxed_value = Box::from_raw(handle as *mut $typ);
+                    let boxed_value = Box::from_raw(handle as *mut $typ);
+                    $crate::bridge_handle_drop!(boxed_value $(, zeroize = $zeroize)?);
                 }
             }
         }
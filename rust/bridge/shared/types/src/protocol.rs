@@ -22,24 +22,27 @@ pub type KyberSecretKey = kem::SecretKey;
 bridge_as_handle!(CiphertextMessage, jni = false);
 bridge_as_handle!(DecryptionErrorMessage);
 bridge_as_handle!(Fingerprint, jni = NumericFingerprintGenerator);
+bridge_as_handle!(IdentityKey, jni = false, node = false);
+bridge_as_handle!(IdentityKeyPair, jni = false, node = false);
 bridge_as_handle!(PlaintextContent);
 bridge_as_handle!(PreKeyBundle);
 bridge_as_handle!(PreKeyRecord);
-bridge_as_handle!(PreKeySignalMessage);
+bridge_as_handle!(PreKeySignalMessage, tagged = true);
 bridge_as_handle!(PrivateKey, ffi = privatekey, jni = ECPrivateKey);
 bridge_as_handle!(ProtocolAddress, ffi = address);
-bridge_as_handle!(PublicKey, ffi = publickey, jni = ECPublicKey);
+bridge_as_handle!(PublicKey, ffi = publickey, jni = ECPublicKey, shared = true);
 bridge_as_handle!(SenderCertificate);
 bridge_as_handle!(SenderKeyDistributionMessage);
-bridge_as_handle!(SenderKeyMessage);
+bridge_as_handle!(SenderKeyMessage, tagged = true);
 bridge_as_handle!(SenderKeyRecord);
 bridge_as_handle!(ServerCertificate);
 bridge_as_handle!(SessionRecord, mut = true);
-bridge_as_handle!(SignalMessage, ffi = message);
+bridge_as_handle!(SignalMessage, ffi = message, tagged = true);
 bridge_as_handle!(SignedPreKeyRecord);
 bridge_as_handle!(KyberPreKeyRecord);
 bridge_as_handle!(UnidentifiedSenderMessageContent);
 bridge_as_handle!(SealedSenderDecryptionResult, ffi = false, jni = false);
+bridge_as_handle!(SealedSenderDecryptStream, mut = true, jni = false, node = false);
 bridge_as_handle!(KyberKeyPair);
 bridge_as_handle!(KyberPublicKey);
 bridge_as_handle!(KyberSecretKey);
@@ -71,6 +71,22 @@ pub(crate) unsafe fn extend_lifetime<'a, 'b: 'a, T: ?Sized>(some_ref: &'a T) ->
 /// - If `clone = true` is passed to `bridge_handle_fns`, a `signal_foo_clone` function will be
 ///   generated for the FFI bridge as well. `Foo` must adopt `Clone`.
 ///
+/// - If `zeroize = true` is passed to `bridge_handle_fns`, the generated "destroy" functions zero
+///   the value before freeing it. `Foo` must adopt [`zeroize::Zeroize`].
+///
+/// - If `shared = true` is passed to both `bridge_as_handle` and `bridge_handle_fns`, FFI handles
+///   are backed by an `Arc` instead of a `Box`: `signal_foo_clone` returns the same pointer with
+///   an extra reference, and `signal_foo_destroy` only frees the value when the last reference is
+///   released. This makes it safe to read through one handle while another thread destroys a
+///   clone of it. `Foo` must adopt `Clone`. This is currently FFI-only; JNI and Node handles are
+///   unaffected.
+///
+/// - If `tagged = true` is passed to both `bridge_as_handle` and `bridge_handle_fns`, FFI handles
+///   are recorded with a type tag that is checked whenever they're borrowed or destroyed, so
+///   passing a handle of any other type fails with an "invalid type" error instead of being
+///   misread. `Foo` must adopt `Clone`. This is also FFI-only, and can't be combined with `shared`
+///   or `zeroize`.
+///
 /// # Representation
 ///
 /// Each bridge represents a boxed Rust value differently:
@@ -94,9 +110,9 @@ pub(crate) unsafe fn extend_lifetime<'a, 'b: 'a, T: ?Sized>(some_ref: &'a T) ->
 /// [`node::AsyncArgTypeInfo`]: crate::node::AsyncArgTypeInfo
 #[macro_export]
 macro_rules! bridge_as_handle {
-    ($typ:ty $(, mut = $_mut:tt)? $(, ffi = $ffi_name:ident)? $(, jni = $jni_name:ident)? $(, node = $node_name:ident)?) => {
+    ($typ:ty $(, mut = $_mut:tt)? $(, ffi = $ffi_name:ident)? $(, jni = $jni_name:ident)? $(, node = $node_name:ident)? $(, shared = $shared:tt)? $(, tagged = $tagged:tt)?) => {
         #[cfg(feature = "ffi")]
-        $crate::ffi_bridge_as_handle!($typ $(as $ffi_name)?);
+        $crate::ffi_bridge_as_handle!($typ $(as $ffi_name)? $(, shared = $shared)? $(, tagged = $tagged)?);
         #[cfg(feature = "jni")]
         $crate::jni_bridge_as_handle!($typ $(as $jni_name)?);
         #[cfg(feature = "node")]
@@ -107,15 +123,38 @@ macro_rules! bridge_as_handle {
 /// See [`bridge_as_handle`].
 #[macro_export]
 macro_rules! bridge_handle_fns {
-    ($typ:ty $(, clone = $_clone:tt)? $(, ffi = $ffi_name:ident)? $(, jni = $jni_name:ident)? $(, node = $node_name:ident)?) => {
+    ($typ:ty $(, clone = $_clone:tt)? $(, ffi = $ffi_name:ident)? $(, jni = $jni_name:ident)? $(, node = $node_name:ident)? $(, zeroize = $zeroize:tt)? $(, shared = $shared:tt)? $(, tagged = $tagged:tt)?) => {
         #[cfg(feature = "ffi")]
-        $crate::ffi_bridge_handle_fns!($typ $(as $ffi_name)? $(, clone = $_clone)?);
+        $crate::ffi_bridge_handle_fns!($typ $(as $ffi_name)? $(, clone = $_clone)? $(, zeroize = $zeroize)? $(, shared = $shared)? $(, tagged = $tagged)?);
         #[cfg(feature = "jni")]
-        $crate::jni_bridge_handle_fns!($typ $(as $jni_name)?);
+        $crate::jni_bridge_handle_fns!($typ $(as $jni_name)? $(, zeroize = $zeroize)?);
         // Node doesn't need any generated bridging functions
     };
 }
 
+/// Used by the generated "destroy" functions to free a boxed handle value.
+///
+/// Not intended to be invoked directly.
+#[macro_export]
+#[doc(hidden)]
+macro_rules! bridge_handle_drop {
+    ($value:expr) => {
+        drop($value)
+    };
+    ($value:expr, zeroize = false) => {
+        drop($value)
+    };
+    ($value:expr, zeroize = true) => {
+        $crate::support::zeroize_and_drop($value)
+    };
+}
+
+/// Zeroes `value` before freeing it.
+pub fn zeroize_and_drop<T: zeroize::Zeroize>(mut value: Box<T>) {
+    value.zeroize();
+    drop(value)
+}
+
 // Allow referring to the macro by path in doc comments.
 #[cfg(doc)]
 pub use {bridge_as_handle, bridge_handle_fns};
@@ -170,7 +209,7 @@ macro_rules! bridge_deserialize {
 /// }
 ///
 /// # #[cfg(ignore_even_when_running_all_tests)]
-/// bridge_get!(Foo::bar as GetBar -> &str, ffi = "foo_get_bar", jni = "Foo_GetBar", node = "Foo_GetBar");
+/// bridge_get!(Foo::bar as GetBar -> &str, ffi = "foo_get_bar", jni = "Foo_1GetBar", node = "Foo_GetBar");
 /// ```
 ///
 /// The `as GetBar` can be omitted if the default name is acceptable (camel-case and prefix with
@@ -214,6 +253,92 @@ macro_rules! bridge_get {
     };
 }
 
+/// Exposes a getter method that borrows another bridged object as a `bridge_fn`.
+///
+/// ```ignore
+/// # #[cfg(ignore_even_when_running_all_tests)]
+/// bridge_get_object!(Foo::bar -> Bar);
+/// # #[cfg(ignore_even_when_running_all_tests)]
+/// bridge_get_object!(Foo::bar as GetBaz -> Baz, node = false);
+/// ```
+///
+/// The underlying method returns a reference (possibly wrapped in `Result`), which is cloned and
+/// converted into `$result` with `From`. The caller gets a fresh handle to the result, so it can be
+/// destroyed independently of `obj`. All additional arguments are forwarded to `bridge_fn`.
+#[macro_export]
+macro_rules! bridge_get_object {
+    ($typ:ident :: $method:ident as $name:ident -> $result:ty $(, $param:ident = $val:tt)* ) => {
+        ::paste::paste! {
+            #[bridge_fn($($param = $val),*)]
+            fn [<$typ _ $name>](obj: &$typ) -> Result<$result> {
+                let borrowed = TransformHelper($typ::$method(obj)).ok_if_needed()?;
+                Ok(<$result>::from(borrowed.0.clone()))
+            }
+        }
+    };
+    ($typ:ident :: $method:ident -> $result:ty $(, $param:ident = $val:tt)* ) => {
+        ::paste::paste! {
+            bridge_get_object!($typ::$method as [<Get $method:camel>] -> $result $(, $param = $val)*);
+        }
+    };
+}
+
+/// Exposes a getter method for an optional string as a `bridge_fn`.
+///
+/// This is [`bridge_get`] with the result fixed to `Option<&str>`, spelled out so that it's clear
+/// at the call site that `None` and `Some("")` stay distinct: FFI gets a null `const char *` for
+/// `None`, and JNI gets a `null` `String`. The underlying method may return `Option<&str>` directly
+/// or wrapped in `Result`.
+///
+/// ```ignore
+/// # #[cfg(ignore_even_when_running_all_tests)]
+/// bridge_get_optional_string!(Foo::nickname);
+/// ```
+#[macro_export]
+macro_rules! bridge_get_optional_string {
+    ($typ:ident :: $method:ident $(as $name:ident)? $(, $param:ident = $val:tt)* ) => {
+        bridge_get!($typ::$method $(as $name)? -> Option<&str> $(, $param = $val)*);
+    };
+}
+
+/// Exposes a getter method that returns a UUID string as a `bridge_fn` producing the raw 16 bytes.
+///
+/// ```ignore
+/// # #[cfg(ignore_even_when_running_all_tests)]
+/// bridge_get_uuid!(Foo::bar_uuid as GetBarUuidBytes, node = false);
+/// ```
+///
+/// The underlying method may return a string directly or wrapped in `Result` and/or `Option`. A
+/// missing value or one that doesn't parse as a UUID is reported as
+/// [`SignalProtocolError::InvalidState`](libsignal_protocol::SignalProtocolError::InvalidState).
+/// All additional arguments are forwarded to `bridge_fn`.
+#[macro_export]
+macro_rules! bridge_get_uuid {
+    ($typ:ident :: $method:ident as $name:ident $(, $param:ident = $val:tt)* ) => {
+        ::paste::paste! {
+            #[bridge_fn($($param = $val),*)]
+            fn [<$typ _ $name>](obj: &$typ) -> Result<[u8; 16]> {
+                let value = TransformHelper($typ::$method(obj))
+                    .ok_if_needed()?
+                    .some_if_needed()
+                    .ok_or_else(|| {
+                        ::libsignal_protocol::SignalProtocolError::InvalidState(
+                            stringify!($method),
+                            "missing UUID".to_owned(),
+                        )
+                    })?;
+                let uuid = ::uuid::Uuid::parse_str(&value.0).map_err(|_| {
+                    ::libsignal_protocol::SignalProtocolError::InvalidState(
+                        stringify!($method),
+                        "malformed UUID".to_owned(),
+                    )
+                })?;
+                Ok(uuid.into_bytes())
+            }
+        }
+    };
+}
+
 /// Reports a result from a future to some receiver.
 pub trait ResultReporter {
     /// The type that will receive the result.
@@ -321,3 +446,26 @@ where
         CancellationId::NotSupported
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    use super::*;
+
+    struct RecordsZeroize(Rc<Cell<bool>>);
+
+    impl zeroize::Zeroize for RecordsZeroize {
+        fn zeroize(&mut self) {
+            self.0.set(true);
+        }
+    }
+
+    #[test]
+    fn zeroize_and_drop_zeroes_before_freeing() {
+        let zeroized = Rc::new(Cell::new(false));
+        zeroize_and_drop(Box::new(RecordsZeroize(zeroized.clone())));
+        assert!(zeroized.get());
+    }
+}
@@ -730,6 +730,31 @@ impl ProtocolAddress {
     pub fn device_id(&self) -> DeviceId {
         self.device_id
     }
+
+    /// Parses the `<name>.<device_id>` form produced by this type's [`Display`](fmt::Display)
+    /// implementation.
+    ///
+    /// Only the last `.` separates the device ID, so names may themselves contain dots. Returns
+    /// `None` if there is no `.`, or if what follows it is not a decimal `u32`.
+    ///
+    ///```
+    /// use libsignal_core::ProtocolAddress;
+    ///
+    /// let address = ProtocolAddress::new("alice.example".to_string(), 3_u32.into());
+    /// let parsed = ProtocolAddress::parse(&address.to_string()).expect("valid");
+    /// assert_eq!(parsed, address);
+    ///
+    /// assert_eq!(ProtocolAddress::parse("alice"), None);
+    /// assert_eq!(ProtocolAddress::parse("alice.one"), None);
+    ///```
+    pub fn parse(s: &str) -> Option<Self> {
+        let (name, device_id) = s.rsplit_once('.')?;
+        if device_id.is_empty() || !device_id.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        let device_id: u32 = device_id.parse().ok()?;
+        Some(Self::new(name.to_owned(), device_id.into()))
+    }
 }
 
 impl fmt::Display for ProtocolAddress {
@@ -79,6 +79,72 @@ pub(crate) fn aes256_ctr_hmacsha256_decrypt(
     aes_256_ctr_decrypt(&ctext[..ptext_len], cipher_key)
 }
 
+/// Incremental counterpart to [`aes256_ctr_hmacsha256_decrypt`], for a ciphertext whose total
+/// length (including the trailing MAC) is known up front.
+///
+/// Plaintext produced by [`update`](Self::update) is unauthenticated until
+/// [`finalize`](Self::finalize) reports that the MAC matched.
+pub(crate) struct Aes256CtrHmacSha256Decryption {
+    cipher: ctr::Ctr32BE<Aes256>,
+    hmac: Hmac<Sha256>,
+    ciphertext_remaining: usize,
+    their_mac: Vec<u8>,
+}
+
+impl Aes256CtrHmacSha256Decryption {
+    const MAC_LEN: usize = 10;
+
+    pub(crate) fn new(
+        cipher_key: &[u8],
+        mac_key: &[u8],
+        ctext_len: usize,
+    ) -> Result<Self, DecryptionError> {
+        let ciphertext_remaining = ctext_len
+            .checked_sub(Self::MAC_LEN)
+            .ok_or(DecryptionError::BadCiphertext("truncated ciphertext"))?;
+        let key: [u8; 32] = cipher_key
+            .try_into()
+            .map_err(|_| DecryptionError::BadKeyOrIv)?;
+        let zero_nonce = [0u8; 16];
+        Ok(Self {
+            cipher: ctr::Ctr32BE::<Aes256>::new(key[..].into(), zero_nonce[..].into()),
+            hmac: Hmac::<Sha256>::new_from_slice(mac_key)
+                .expect("HMAC-SHA256 should accept any size key"),
+            ciphertext_remaining,
+            their_mac: Vec::with_capacity(Self::MAC_LEN),
+        })
+    }
+
+    /// Consumes `input`, appending any plaintext it contains to `output`.
+    pub(crate) fn update(
+        &mut self,
+        input: &[u8],
+        output: &mut Vec<u8>,
+    ) -> Result<(), DecryptionError> {
+        let (ctext, mac) = input.split_at(input.len().min(self.ciphertext_remaining));
+        self.ciphertext_remaining -= ctext.len();
+        if self.their_mac.len() + mac.len() > Self::MAC_LEN {
+            return Err(DecryptionError::BadCiphertext("trailing data after MAC"));
+        }
+        self.their_mac.extend_from_slice(mac);
+
+        self.hmac.update(ctext);
+        let start = output.len();
+        output.extend_from_slice(ctext);
+        self.cipher.apply_keystream(&mut output[start..]);
+        Ok(())
+    }
+
+    /// Checks the MAC once all of the ciphertext has been consumed, returning whether it matched.
+    pub(crate) fn finalize(self) -> Result<bool, DecryptionError> {
+        if self.ciphertext_remaining != 0 || self.their_mac.len() != Self::MAC_LEN {
+            return Err(DecryptionError::BadCiphertext("truncated ciphertext"));
+        }
+        let our_mac = self.hmac.finalize().into_bytes();
+        Ok(bool::from(our_mac[..Self::MAC_LEN].ct_eq(&self.their_mac)))
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
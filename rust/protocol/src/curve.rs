@@ -15,6 +15,7 @@ use arrayref::array_ref;
 use curve25519_dalek::scalar;
 use rand::{CryptoRng, Rng};
 use subtle::ConstantTimeEq;
+use zeroize::Zeroize;
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub enum KeyType {
@@ -28,7 +29,8 @@ impl fmt::Display for KeyType {
 }
 
 impl KeyType {
-    fn value(&self) -> u8 {
+    /// The type byte that prefixes serialized keys of this type.
+    pub fn value(&self) -> u8 {
         match &self {
             KeyType::Djb => 0x05u8,
         }
@@ -96,11 +98,16 @@ impl PublicKey {
         }
     }
 
-    pub fn serialize(&self) -> Box<[u8]> {
+    /// The length of [`serialize`](Self::serialize)'s output, without producing it.
+    pub fn serialized_len(&self) -> usize {
         let value_len = match &self.key {
             PublicKeyData::DjbPublicKey(v) => v.len(),
         };
-        let mut result = Vec::with_capacity(1 + value_len);
+        1 + value_len
+    }
+
+    pub fn serialize(&self) -> Box<[u8]> {
+        let mut result = Vec::with_capacity(self.serialized_len());
         result.push(self.key_type().value());
         match &self.key {
             PublicKeyData::DjbPublicKey(v) => result.extend_from_slice(v),
@@ -215,6 +222,10 @@ pub struct PrivateKey {
 }
 
 impl PrivateKey {
+    /// Parses a raw 32-byte Curve25519 scalar.
+    ///
+    /// Private keys have no type prefix, so this accepts scalars from any X25519 implementation.
+    /// The scalar is clamped per RFC 7748 before use.
     pub fn deserialize(value: &[u8]) -> Result<Self> {
         if value.len() != curve25519::PRIVATE_KEY_LENGTH {
             Err(SignalProtocolError::BadKeyLength(KeyType::Djb, value.len()))
@@ -229,6 +240,13 @@ impl PrivateKey {
         }
     }
 
+    /// The length of [`serialize`](Self::serialize)'s output, without producing it.
+    pub fn serialized_len(&self) -> usize {
+        match &self.key {
+            PrivateKeyData::DjbPrivateKey(v) => v.len(),
+        }
+    }
+
     pub fn serialize(&self) -> Vec<u8> {
         match &self.key {
             PrivateKeyData::DjbPrivateKey(v) => v.to_vec(),
@@ -282,6 +300,18 @@ impl PrivateKey {
     }
 }
 
+/// Overwrites the key material with zeros.
+///
+/// `PrivateKey` is `Copy`, so this can't happen automatically on drop; owners that want copies
+/// cleared (such as the app language bridges) must call this themselves.
+impl Zeroize for PrivateKey {
+    fn zeroize(&mut self) {
+        match &mut self.key {
+            PrivateKeyData::DjbPrivateKey(k) => k.zeroize(),
+        }
+    }
+}
+
 impl From<PrivateKeyData> for PrivateKey {
     fn from(key: PrivateKeyData) -> PrivateKey {
         Self { key }
@@ -393,6 +423,49 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_private_key_zeroize() -> Result<()> {
+        let mut private_key = PrivateKey::deserialize(&[0x48; 32])?;
+        assert_ne!(private_key.serialize(), [0u8; 32]);
+        private_key.zeroize();
+        assert_eq!(private_key.serialize(), [0u8; 32]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_private_key_from_raw_scalar() -> Result<()> {
+        use hex_literal::hex;
+
+        // Alice's key pair from RFC 7748 section 6.1.
+        let raw = hex!("77076d0a7318a57d3c16c17251b26645df4c2f87ebc0992ab177fba51db92c2a");
+        let private_key = PrivateKey::deserialize(&raw)?;
+        assert_eq!(
+            private_key.serialize(),
+            hex!("70076d0a7318a57d3c16c17251b26645df4c2f87ebc0992ab177fba51db92c6a")
+        );
+        assert_eq!(
+            private_key.public_key()?.public_key_bytes()?,
+            hex!("8520f0098930a754748b7ddcb43ef75a0dbf3a0d26381af4eba4a98eaa9b4e6a")
+        );
+
+        assert!(PrivateKey::deserialize(&raw[1..]).is_err());
+        assert!(PrivateKey::deserialize(&[0; 33]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_serialized_len() {
+        let key_pair = KeyPair::generate(&mut OsRng);
+        assert_eq!(
+            key_pair.public_key.serialized_len(),
+            key_pair.public_key.serialize().len()
+        );
+        assert_eq!(
+            key_pair.private_key.serialized_len(),
+            key_pair.private_key.serialize().len()
+        );
+    }
+
     #[test]
     fn test_decode_size() -> Result<()> {
         let mut csprng = OsRng;
@@ -75,6 +75,10 @@ pub enum SignalProtocolError {
 
     /// message with old counter {0} / {1}
     DuplicatedMessage(u32, u32),
+    /// message iteration {1} is too far ahead of current iteration {0}
+    IterationTooLarge(u32, u32),
+    /// message would leave more than {0} skipped message keys stored
+    TooManySkippedKeys(usize),
     /// invalid {0:?} message: {1}
     InvalidMessage(crate::CiphertextMessageType, &'static str),
 
@@ -92,6 +96,8 @@ pub enum SignalProtocolError {
     UnknownSealedSenderVersion(u8),
     /// self send of a sealed sender message
     SealedSenderSelfSend,
+    /// sealed sender message failed MAC verification
+    SealedSenderMacVerificationFailed,
 
     /// bad KEM key type <{0:#04x}>
     BadKEMKeyType(u8),
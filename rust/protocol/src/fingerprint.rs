@@ -97,6 +97,14 @@ impl ScannableFingerprint {
         })
     }
 
+    /// The format version of this fingerprint's scannable encoding.
+    ///
+    /// [`compare`](Self::compare) fails with [`SignalProtocolError::FingerprintVersionMismatch`]
+    /// when the two encodings' versions differ.
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
     pub fn serialize(&self) -> Result<Vec<u8>> {
         let combined_fingerprints = proto::fingerprint::CombinedFingerprints {
             version: Some(self.version),
@@ -542,6 +550,40 @@ mod test {
             hex::encode(a_fprint_v2.scannable.serialize()?)
         );
 
+        assert_eq!(a_fprint_v1.scannable.version(), 1);
+        assert_eq!(a_fprint_v2.scannable.version(), 2);
+        assert_eq!(
+            ScannableFingerprint::deserialize(&a_fprint_v2.scannable.serialize()?)?.version(),
+            2
+        );
+        assert!(matches!(
+            a_fprint_v1
+                .scannable
+                .compare(&a_fprint_v2.scannable.serialize()?),
+            Err(SignalProtocolError::FingerprintVersionMismatch(2, 1))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn fingerprint_rejects_zero_iterations() -> Result<()> {
+        let a_key = IdentityKey::decode(ALICE_IDENTITY)?;
+        let b_key = IdentityKey::decode(BOB_IDENTITY)?;
+
+        let result = Fingerprint::new(
+            1,
+            0,
+            ALICE_STABLE_ID.as_bytes(),
+            &a_key,
+            BOB_STABLE_ID.as_bytes(),
+            &b_key,
+        );
+        assert!(matches!(
+            result,
+            Err(SignalProtocolError::InvalidArgument(_))
+        ));
+
         Ok(())
     }
 }
@@ -3,14 +3,16 @@
 // SPDX-License-Identifier: AGPL-3.0-only
 //
 
+use prost::Message;
 use rand::{CryptoRng, Rng};
 use uuid::Uuid;
 
 use crate::protocol::SENDERKEY_MESSAGE_CURRENT_VERSION;
 use crate::sender_keys::{SenderKeyState, SenderMessageKey};
 use crate::{
-    consts, CiphertextMessageType, KeyPair, ProtocolAddress, Result, SenderKeyDistributionMessage,
-    SenderKeyMessage, SenderKeyRecord, SenderKeyStore, SignalProtocolError,
+    consts, proto, CiphertextMessageType, KeyPair, ProtocolAddress, Result,
+    SenderKeyDistributionMessage, SenderKeyMessage, SenderKeyRecord, SenderKeyStore,
+    SignalProtocolError,
 };
 
 pub async fn group_encrypt<R: Rng + CryptoRng>(
@@ -72,6 +74,7 @@ fn get_sender_key(
     state: &mut SenderKeyState,
     iteration: u32,
     distribution_id: Uuid,
+    max_forward_jumps: usize,
 ) -> Result<SenderMessageKey> {
     let sender_chain_key = state
         .sender_chain_key()
@@ -95,16 +98,16 @@ fn get_sender_key(
     }
 
     let jump = (iteration - current_iteration) as usize;
-    if jump > consts::MAX_FORWARD_JUMPS {
+    if jump > max_forward_jumps {
         log::error!(
             "SenderKey distribution {} Exceeded future message limit: {}, current iteration: {})",
             distribution_id,
-            consts::MAX_FORWARD_JUMPS,
+            max_forward_jumps,
             current_iteration
         );
-        return Err(SignalProtocolError::InvalidMessage(
-            CiphertextMessageType::SenderKey,
-            "message from too far into the future",
+        return Err(SignalProtocolError::IterationTooLarge(
+            current_iteration,
+            iteration,
         ));
     }
 
@@ -124,6 +127,29 @@ pub async fn group_decrypt(
     sender_key_store: &mut dyn SenderKeyStore,
     sender: &ProtocolAddress,
 ) -> Result<Vec<u8>> {
+    group_decrypt_with_max_jump(
+        skm_bytes,
+        sender_key_store,
+        sender,
+        consts::MAX_FORWARD_JUMPS as u32,
+    )
+    .await
+}
+
+/// Like [`group_decrypt`], but refuses to advance the sender's chain by more than
+/// `max_iteration_jump` messages to reach the one being decrypted.
+///
+/// Each skipped message costs a key derivation, so this bounds the work a single message can force.
+/// The limit can only be tightened: values above the default of 25,000 are treated as 25,000. A
+/// message past the limit fails with [`SignalProtocolError::IterationTooLarge`] before any keys
+/// are derived, and the stored record is left untouched.
+pub async fn group_decrypt_with_max_jump(
+    skm_bytes: &[u8],
+    sender_key_store: &mut dyn SenderKeyStore,
+    sender: &ProtocolAddress,
+    max_iteration_jump: u32,
+) -> Result<Vec<u8>> {
+    let max_forward_jumps = consts::MAX_FORWARD_JUMPS.min(max_iteration_jump as usize);
     let skm = SenderKeyMessage::try_from(skm_bytes)?;
 
     let distribution_id = skm.distribution_id();
@@ -161,7 +187,12 @@ pub async fn group_decrypt(
         return Err(SignalProtocolError::SignatureValidationFailed);
     }
 
-    let sender_key = get_sender_key(sender_key_state, skm.iteration(), distribution_id)?;
+    let sender_key = get_sender_key(
+        sender_key_state,
+        skm.iteration(),
+        distribution_id,
+        max_forward_jumps,
+    )?;
 
     let plaintext = match signal_crypto::aes_256_cbc_decrypt(
         skm.ciphertext(),
@@ -194,6 +225,67 @@ pub async fn group_decrypt(
     Ok(plaintext)
 }
 
+/// Prefixes the plaintext of a [`group_encrypt_with_expiry`] message, ahead of a serialized
+/// `SenderKeyContentWithExpiry`.
+///
+/// No protobuf message can start with 0xFF (its low bits would be wire type 7), so this can't be
+/// mistaken for the start of an ordinary serialized message.
+const EXPIRY_FORMAT_MARKER: &[u8] = b"\xFFexpiry\x01";
+
+/// Like [`group_encrypt`], but also seals a disappearing-message timer into the ciphertext.
+///
+/// The timer and `plaintext` are wrapped in a marked protobuf before encryption, so the timer is
+/// authenticated along with the message. Recipients must use [`group_decrypt_with_expiry`].
+pub async fn group_encrypt_with_expiry<R: Rng + CryptoRng>(
+    sender_key_store: &mut dyn SenderKeyStore,
+    sender: &ProtocolAddress,
+    distribution_id: Uuid,
+    plaintext: &[u8],
+    expiry_seconds: u32,
+    csprng: &mut R,
+) -> Result<SenderKeyMessage> {
+    let wrapped = proto::wire::SenderKeyContentWithExpiry {
+        expiry_seconds: Some(expiry_seconds),
+        content: Some(plaintext.to_vec()),
+    };
+    let mut content = Vec::with_capacity(EXPIRY_FORMAT_MARKER.len() + wrapped.encoded_len());
+    content.extend_from_slice(EXPIRY_FORMAT_MARKER);
+    wrapped
+        .encode(&mut content)
+        .expect("can always append to a Vec");
+    group_encrypt(sender_key_store, sender, distribution_id, &content, csprng).await
+}
+
+/// Decrypts a group message, returning the plaintext and, if the message came from
+/// [`group_encrypt_with_expiry`], the disappearing-message timer in seconds.
+///
+/// A message from plain [`group_encrypt`] has no timer, and its plaintext is returned unchanged.
+/// Fails with [`SignalProtocolError::InvalidMessage`] if the plaintext has the expiry format
+/// marker but the wrapper after it is malformed.
+pub async fn group_decrypt_with_expiry(
+    skm_bytes: &[u8],
+    sender_key_store: &mut dyn SenderKeyStore,
+    sender: &ProtocolAddress,
+) -> Result<(Vec<u8>, Option<u32>)> {
+    let content = group_decrypt(skm_bytes, sender_key_store, sender).await?;
+    let Some(wrapped) = content.strip_prefix(EXPIRY_FORMAT_MARKER) else {
+        return Ok((content, None));
+    };
+    let wrapped = proto::wire::SenderKeyContentWithExpiry::decode(wrapped).map_err(|_| {
+        SignalProtocolError::InvalidMessage(
+            CiphertextMessageType::SenderKey,
+            "invalid expiry timer wrapper",
+        )
+    })?;
+    let (Some(expiry_seconds), Some(content)) = (wrapped.expiry_seconds, wrapped.content) else {
+        return Err(SignalProtocolError::InvalidMessage(
+            CiphertextMessageType::SenderKey,
+            "missing expiry timer",
+        ));
+    };
+    Ok((content, Some(expiry_seconds)))
+}
+
 pub async fn process_sender_key_distribution_message(
     sender: &ProtocolAddress,
     skdm: &SenderKeyDistributionMessage,
@@ -53,7 +53,8 @@ pub use curve::{KeyPair, PrivateKey, PublicKey};
 pub use error::SignalProtocolError;
 pub use fingerprint::{DisplayableFingerprint, Fingerprint, ScannableFingerprint};
 pub use group_cipher::{
-    create_sender_key_distribution_message, group_decrypt, group_encrypt,
+    create_sender_key_distribution_message, group_decrypt, group_decrypt_with_expiry,
+    group_decrypt_with_max_jump, group_encrypt, group_encrypt_with_expiry,
     process_sender_key_distribution_message,
 };
 pub use identity_key::{IdentityKey, IdentityKeyPair};
@@ -62,21 +63,28 @@ pub use protocol::{
     CiphertextMessageType, DecryptionErrorMessage, KyberPayload, PlaintextContent,
     PreKeySignalMessage, SenderKeyDistributionMessage, SenderKeyMessage, SignalMessage,
 };
+#[cfg(feature = "interop-testing")]
+pub use ratchet::{derive_message_iv, handshake_transcript_hash};
 pub use ratchet::{
     initialize_alice_session_record, initialize_bob_session_record, AliceSignalProtocolParameters,
     BobSignalProtocolParameters,
 };
 pub use sealed_sender::{
     sealed_sender_decrypt, sealed_sender_decrypt_to_usmc, sealed_sender_encrypt,
-    sealed_sender_encrypt_from_usmc, sealed_sender_multi_recipient_encrypt,
+    sealed_sender_encrypt_from_usmc, sealed_sender_envelope_fingerprint,
+    sealed_sender_multi_recipient_encrypt,
     sealed_sender_multi_recipient_encrypt_using_legacy_ephemeral_key_derivation, ContentHint,
-    SealedSenderDecryptionResult, SealedSenderV2SentMessage, SealedSenderV2SentMessageRecipient,
-    SenderCertificate, ServerCertificate, UnidentifiedSenderMessageContent,
+    SealedSenderDecryptStream, SealedSenderDecryptionResult, SealedSenderV2SentMessage,
+    SealedSenderV2SentMessageRecipient, SenderCertificate, ServerCertificate,
+    UnidentifiedSenderMessageContent,
 };
 pub use sender_keys::SenderKeyRecord;
 pub use session::{process_prekey, process_prekey_bundle};
 pub use session_cipher::{
-    message_decrypt, message_decrypt_prekey, message_decrypt_signal, message_encrypt,
+    message_decrypt, message_decrypt_prekey, message_decrypt_prekey_with_max_plaintext_len,
+    message_decrypt_signal, message_decrypt_signal_reporting_identity_change,
+    message_decrypt_signal_with_max_plaintext_len, message_decrypt_signal_with_max_skipped_keys,
+    message_encrypt,
 };
 pub use state::{
     GenericSignedPreKey, KyberPreKeyId, KyberPreKeyRecord, PreKeyBundle, PreKeyBundleContent,
@@ -62,7 +62,6 @@ pub struct SignalMessage {
     message_version: u8,
     sender_ratchet_key: PublicKey,
     counter: u32,
-    #[allow(dead_code)]
     previous_counter: u32,
     ciphertext: Box<[u8]>,
     serialized: Box<[u8]>,
@@ -81,6 +80,9 @@ impl SignalMessage {
         sender_identity_key: &IdentityKey,
         receiver_identity_key: &IdentityKey,
     ) -> Result<Self> {
+        if mac_key.len() != 32 {
+            return Err(SignalProtocolError::InvalidMacKeyLength(mac_key.len()));
+        }
         let message = proto::wire::SignalMessage {
             ratchet_key: Some(sender_ratchet_key.serialize().into_vec()),
             counter: Some(counter),
@@ -125,6 +127,11 @@ impl SignalMessage {
         self.counter
     }
 
+    #[inline]
+    pub fn previous_counter(&self) -> u32 {
+        self.previous_counter
+    }
+
     #[inline]
     pub fn serialized(&self) -> &[u8] {
         &self.serialized
@@ -135,19 +142,34 @@ impl SignalMessage {
         &self.ciphertext
     }
 
+    /// Returns the length of the MAC appended to messages of `message_version`.
+    pub fn expected_mac_length(message_version: u8) -> Result<usize> {
+        match message_version {
+            CIPHERTEXT_MESSAGE_PRE_KYBER_VERSION..=CIPHERTEXT_MESSAGE_CURRENT_VERSION => {
+                Ok(Self::MAC_LENGTH)
+            }
+            v if v < CIPHERTEXT_MESSAGE_PRE_KYBER_VERSION => {
+                Err(SignalProtocolError::LegacyCiphertextVersion(v))
+            }
+            v => Err(SignalProtocolError::UnrecognizedCiphertextVersion(v)),
+        }
+    }
+
     pub fn verify_mac(
         &self,
         sender_identity_key: &IdentityKey,
         receiver_identity_key: &IdentityKey,
         mac_key: &[u8],
     ) -> Result<bool> {
-        let our_mac = &Self::compute_mac(
-            sender_identity_key,
-            receiver_identity_key,
-            mac_key,
-            &self.serialized[..self.serialized.len() - Self::MAC_LENGTH],
-        )?;
-        let their_mac = &self.serialized[self.serialized.len() - Self::MAC_LENGTH..];
+        let mac_length = Self::expected_mac_length(self.message_version)?;
+        if self.serialized.len() <= mac_length {
+            return Err(SignalProtocolError::CiphertextMessageTooShort(
+                self.serialized.len(),
+            ));
+        }
+        let (message, their_mac) = self.serialized.split_at(self.serialized.len() - mac_length);
+        let our_mac =
+            &Self::compute_mac(sender_identity_key, receiver_identity_key, mac_key, message)?;
         let result: bool = our_mac.ct_eq(their_mac).into();
         if !result {
             // A warning instead of an error because we try multiple sessions.
@@ -513,6 +535,12 @@ impl SenderKeyMessage {
         &self.ciphertext
     }
 
+    /// The signature over the rest of the serialized message.
+    #[inline]
+    pub fn signature(&self) -> &[u8] {
+        &self.serialized[self.serialized.len() - Self::SIGNATURE_LEN..]
+    }
+
     #[inline]
     pub fn serialized(&self) -> &[u8] {
         &self.serialized
@@ -647,6 +675,23 @@ impl SenderKeyDistributionMessage {
         Ok(&self.signing_key)
     }
 
+    /// A SHA-256 digest of the fields that identify this distribution message.
+    ///
+    /// Two messages that distribute the same chain state produce the same digest, even if they
+    /// were encoded differently, so this can be used to skip reprocessing duplicates.
+    pub fn digest(&self) -> [u8; 32] {
+        use sha2::Digest;
+
+        let mut hasher = Sha256::new();
+        hasher.update([self.message_version]);
+        hasher.update(self.distribution_id.as_bytes());
+        hasher.update(self.chain_id.to_be_bytes());
+        hasher.update(self.iteration.to_be_bytes());
+        hasher.update(&self.chain_key);
+        hasher.update(self.signing_key.serialize());
+        hasher.finalize().into()
+    }
+
     #[inline]
     pub fn serialized(&self) -> &[u8] {
         &self.serialized
@@ -947,9 +992,89 @@ mod tests {
         let deser_message =
             SignalMessage::try_from(message.as_ref()).expect("should deserialize without error");
         assert_signal_message_equals(&message, &deser_message);
+        assert_eq!(deser_message.counter(), 42);
+        assert_eq!(deser_message.previous_counter(), 41);
+        Ok(())
+    }
+
+    #[test]
+    fn test_signal_message_expected_mac_length() -> Result<()> {
+        for version in [
+            CIPHERTEXT_MESSAGE_PRE_KYBER_VERSION,
+            CIPHERTEXT_MESSAGE_CURRENT_VERSION,
+        ] {
+            assert_eq!(SignalMessage::expected_mac_length(version)?, 8);
+        }
+        assert!(matches!(
+            SignalMessage::expected_mac_length(CIPHERTEXT_MESSAGE_PRE_KYBER_VERSION - 1),
+            Err(SignalProtocolError::LegacyCiphertextVersion(_))
+        ));
+        assert!(matches!(
+            SignalMessage::expected_mac_length(CIPHERTEXT_MESSAGE_CURRENT_VERSION + 1),
+            Err(SignalProtocolError::UnrecognizedCiphertextVersion(_))
+        ));
         Ok(())
     }
 
+    #[test]
+    fn test_signal_message_verify_mac_rejects_unknown_version() -> Result<()> {
+        let mut csprng = OsRng;
+        let mac_key = [1u8; 32];
+        let sender_identity_key = IdentityKey::from(KeyPair::generate(&mut csprng).public_key);
+        let receiver_identity_key = IdentityKey::from(KeyPair::generate(&mut csprng).public_key);
+
+        for version in [
+            CIPHERTEXT_MESSAGE_PRE_KYBER_VERSION,
+            CIPHERTEXT_MESSAGE_CURRENT_VERSION,
+            CIPHERTEXT_MESSAGE_CURRENT_VERSION + 1,
+        ] {
+            let message = SignalMessage::new(
+                version,
+                &mac_key,
+                KeyPair::generate(&mut csprng).public_key,
+                1,
+                0,
+                b"ciphertext",
+                &sender_identity_key,
+                &receiver_identity_key,
+            )?;
+            let result = message.verify_mac(&sender_identity_key, &receiver_identity_key, &mac_key);
+            if version > CIPHERTEXT_MESSAGE_CURRENT_VERSION {
+                assert!(matches!(
+                    result,
+                    Err(SignalProtocolError::UnrecognizedCiphertextVersion(_))
+                ));
+            } else {
+                assert!(result?);
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_signal_message_new_rejects_bad_mac_key_length() {
+        let mut csprng = OsRng;
+        let sender_identity_key = KeyPair::generate(&mut csprng).public_key.into();
+        let receiver_identity_key = KeyPair::generate(&mut csprng).public_key.into();
+
+        for len in [0, 31, 33] {
+            let result = SignalMessage::new(
+                CIPHERTEXT_MESSAGE_CURRENT_VERSION,
+                &vec![0; len],
+                KeyPair::generate(&mut csprng).public_key,
+                1,
+                0,
+                b"ciphertext",
+                &sender_identity_key,
+                &receiver_identity_key,
+            );
+            assert!(
+                matches!(result, Err(SignalProtocolError::InvalidMacKeyLength(l)) if l == len),
+                "length {len}"
+            );
+        }
+    }
+
     #[test]
     fn test_pre_key_signal_message_serialize_deserialize() -> Result<()> {
         let mut csprng = OsRng;
@@ -1004,6 +1129,37 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_pre_key_signal_message_integer_fields() -> Result<()> {
+        let mut csprng = OsRng;
+        let identity_key_pair = KeyPair::generate(&mut csprng);
+        let base_key_pair = KeyPair::generate(&mut csprng);
+
+        for pre_key_id in [Some(12.into()), None] {
+            let message = create_signal_message(&mut csprng)?;
+            let pre_key_signal_message = PreKeySignalMessage::new(
+                3,
+                365,
+                pre_key_id,
+                97.into(),
+                None,
+                base_key_pair.public_key,
+                identity_key_pair.public_key.into(),
+                message,
+            )?;
+            let deser_pre_key_signal_message =
+                PreKeySignalMessage::try_from(pre_key_signal_message.as_ref())
+                    .expect("should deserialize without error");
+            for m in [&pre_key_signal_message, &deser_pre_key_signal_message] {
+                assert_eq!(m.message_version(), 3);
+                assert_eq!(m.registration_id(), 365);
+                assert_eq!(m.pre_key_id(), pre_key_id);
+                assert_eq!(m.signed_pre_key_id(), 97.into());
+            }
+        }
+        Ok(())
+    }
+
     #[test]
     fn test_sender_key_message_serialize_deserialize() -> Result<()> {
         let mut csprng = OsRng;
@@ -1042,6 +1198,71 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_sender_key_message_accessors() -> Result<()> {
+        let mut csprng = OsRng;
+        let signature_key_pair = KeyPair::generate(&mut csprng);
+        let other_key_pair = KeyPair::generate(&mut csprng);
+        let sender_key_message = SenderKeyMessage::new(
+            SENDERKEY_MESSAGE_CURRENT_VERSION,
+            Uuid::from_u128(0xd1d1d1d1_7000_11eb_b32a_33b8a8a487a6),
+            42,
+            7,
+            [1u8, 2, 3].into(),
+            &mut csprng,
+            &signature_key_pair.private_key,
+        )?;
+
+        assert_eq!(sender_key_message.chain_id(), 42);
+        assert_eq!(sender_key_message.iteration(), 7);
+        assert_eq!(
+            sender_key_message.signature().len(),
+            SenderKeyMessage::SIGNATURE_LEN
+        );
+        assert!(sender_key_message.verify_signature(&signature_key_pair.public_key)?);
+        assert!(!sender_key_message.verify_signature(&other_key_pair.public_key)?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_sender_key_distribution_message_digest() -> Result<()> {
+        let mut csprng = OsRng;
+        let signature_key_pair = KeyPair::generate(&mut csprng);
+        let distribution_id = Uuid::from_u128(0xd1d1d1d1_7000_11eb_b32a_33b8a8a487a6);
+        let skdm = SenderKeyDistributionMessage::new(
+            SENDERKEY_MESSAGE_CURRENT_VERSION,
+            distribution_id,
+            42,
+            7,
+            vec![1u8; 32],
+            signature_key_pair.public_key,
+        )?;
+
+        let deser_skdm = SenderKeyDistributionMessage::try_from(skdm.as_ref())?;
+        assert_eq!(skdm.digest(), deser_skdm.digest());
+
+        let next_iteration = SenderKeyDistributionMessage::new(
+            SENDERKEY_MESSAGE_CURRENT_VERSION,
+            distribution_id,
+            42,
+            8,
+            vec![1u8; 32],
+            signature_key_pair.public_key,
+        )?;
+        assert_ne!(skdm.digest(), next_iteration.digest());
+
+        let other_chain = SenderKeyDistributionMessage::new(
+            SENDERKEY_MESSAGE_CURRENT_VERSION,
+            distribution_id,
+            43,
+            7,
+            vec![1u8; 32],
+            signature_key_pair.public_key,
+        )?;
+        assert_ne!(skdm.digest(), other_chain.digest());
+        Ok(())
+    }
+
     #[test]
     fn test_decryption_error_message() -> Result<()> {
         let mut csprng = OsRng;
@@ -10,16 +10,68 @@ pub(crate) use self::keys::{ChainKey, MessageKeys, RootKey};
 pub use self::params::{AliceSignalProtocolParameters, BobSignalProtocolParameters};
 use crate::protocol::{CIPHERTEXT_MESSAGE_CURRENT_VERSION, CIPHERTEXT_MESSAGE_PRE_KYBER_VERSION};
 use crate::state::SessionState;
+#[cfg(feature = "interop-testing")]
+use crate::{kem, IdentityKey, PublicKey};
 use crate::{KeyPair, Result, SessionRecord};
 use rand::{CryptoRng, Rng};
 
-fn derive_keys(has_kyber: bool, secret_input: &[u8]) -> (RootKey, ChainKey) {
-    let label = if has_kyber {
-        b"WhisperText_X25519_SHA-256_CRYSTALS-KYBER-1024".as_slice()
+/// Derives the AES-CBC IV that message `counter` of a sending or receiving chain is encrypted with,
+/// given the chain's key at index 0.
+///
+/// This walks the chain one step per message, so it takes time linear in `counter`.
+#[cfg(feature = "interop-testing")]
+pub fn derive_message_iv(chain_key: [u8; 32], counter: u32) -> [u8; 16] {
+    let mut chain_key = ChainKey::new(chain_key, 0);
+    while chain_key.index() < counter {
+        chain_key = chain_key.next_chain_key();
+    }
+    *chain_key.message_keys().iv()
+}
+
+/// Hashes the public inputs of an X3DH or PQXDH handshake, so that two parties (or an auditor)
+/// can check they agree on a handshake without revealing any secrets.
+///
+/// The hash is SHA-256 over the handshake's own key derivation label, followed by the serialized
+/// keys: Alice's identity and base keys, then Bob's identity key, signed pre-key, and one-time
+/// pre-key if there was one, then for PQXDH Bob's Kyber pre-key and the ciphertext Alice
+/// encapsulated to it. Every key carries its type byte, so omitted keys are unambiguous.
+#[cfg(feature = "interop-testing")]
+pub fn handshake_transcript_hash(
+    alice_identity_key: &IdentityKey,
+    alice_base_key: &PublicKey,
+    bob_identity_key: &IdentityKey,
+    bob_signed_pre_key: &PublicKey,
+    bob_one_time_pre_key: Option<&PublicKey>,
+    bob_kyber_pre_key: Option<(&kem::PublicKey, &[u8])>,
+) -> [u8; 32] {
+    use sha2::{Digest, Sha256};
+
+    let mut hasher = Sha256::new();
+    hasher.update(handshake_label(bob_kyber_pre_key.is_some()));
+    hasher.update(alice_identity_key.serialize());
+    hasher.update(alice_base_key.serialize());
+    hasher.update(bob_identity_key.serialize());
+    hasher.update(bob_signed_pre_key.serialize());
+    if let Some(bob_one_time_pre_key) = bob_one_time_pre_key {
+        hasher.update(bob_one_time_pre_key.serialize());
+    }
+    if let Some((bob_kyber_pre_key, kyber_ciphertext)) = bob_kyber_pre_key {
+        hasher.update(bob_kyber_pre_key.serialize());
+        hasher.update(kyber_ciphertext);
+    }
+    hasher.finalize().into()
+}
+
+fn handshake_label(has_kyber: bool) -> &'static [u8] {
+    if has_kyber {
+        b"WhisperText_X25519_SHA-256_CRYSTALS-KYBER-1024"
     } else {
-        b"WhisperText".as_slice()
-    };
-    derive_keys_with_label(label, secret_input)
+        b"WhisperText"
+    }
+}
+
+fn derive_keys(has_kyber: bool, secret_input: &[u8]) -> (RootKey, ChainKey) {
+    derive_keys_with_label(handshake_label(has_kyber), secret_input)
 }
 
 fn message_version(has_kyber: bool) -> u8 {
@@ -148,6 +148,8 @@ impl fmt::Display for RootKey {
 
 #[cfg(test)]
 mod tests {
+    use hex_literal::hex;
+
     use super::*;
 
     #[test]
@@ -184,4 +186,27 @@ mod tests {
         assert_eq!(1, chain_key.next_chain_key().message_keys().counter());
         Ok(())
     }
+
+    #[test]
+    fn test_message_iv_derivation() {
+        let seed = [
+            0x8au8, 0xb7, 0x2d, 0x6f, 0x4c, 0xc5, 0xac, 0x0d, 0x38, 0x7e, 0xaf, 0x46, 0x33, 0x78,
+            0xdd, 0xb2, 0x8e, 0xdd, 0x07, 0x38, 0x5b, 0x1c, 0xb0, 0x12, 0x50, 0xc7, 0x15, 0x98,
+            0x2e, 0x7a, 0xd4, 0x8f,
+        ];
+        let expected_ivs = [
+            hex!("afa8207986b692a116d4b40bbff72d6c"),
+            hex!("a313b409a9113f7d31ce86be9938d42d"),
+            hex!("fc737c1e34d66ce4915de2c04158691d"),
+            hex!("b3a5970057296672db13287d061b4991"),
+            hex!("4d6048842db0b941c31f9d570cca24de"),
+            hex!("61accf1c13e0bd20ef8e096ce6ac7a82"),
+        ];
+
+        let mut chain_key = ChainKey::new(seed, 0);
+        for expected_iv in &expected_ivs {
+            assert_eq!(expected_iv, chain_key.message_keys().iv());
+            chain_key = chain_key.next_chain_key();
+        }
+    }
 }
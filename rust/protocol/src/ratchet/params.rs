@@ -4,6 +4,8 @@
 //
 
 use crate::{kem, IdentityKey, IdentityKeyPair, KeyPair, PublicKey};
+#[cfg(feature = "interop-testing")]
+use crate::{Result, SignalProtocolError};
 
 pub struct AliceSignalProtocolParameters {
     our_identity_key_pair: IdentityKeyPair,
@@ -87,6 +89,34 @@ impl AliceSignalProtocolParameters {
     pub fn their_ratchet_key(&self) -> &PublicKey {
         &self.their_ratchet_key
     }
+
+    /// Hashes the public inputs of this handshake with [`handshake_transcript_hash`].
+    ///
+    /// `kyber_ciphertext` is the ciphertext the new session encapsulated to Bob's Kyber pre-key;
+    /// it must be present exactly when the parameters include a Kyber pre-key.
+    ///
+    /// [`handshake_transcript_hash`]: crate::handshake_transcript_hash
+    #[cfg(feature = "interop-testing")]
+    pub fn transcript_hash(&self, kyber_ciphertext: Option<&[u8]>) -> Result<[u8; 32]> {
+        let bob_kyber_pre_key = match (&self.their_kyber_pre_key, kyber_ciphertext) {
+            (Some(public_key), Some(ciphertext)) => Some((public_key, ciphertext)),
+            (None, None) => None,
+            _ => {
+                return Err(SignalProtocolError::InvalidArgument(
+                    "Kyber ciphertext must be provided exactly when there is a Kyber pre-key"
+                        .to_owned(),
+                ))
+            }
+        };
+        Ok(super::handshake_transcript_hash(
+            self.our_identity_key_pair.identity_key(),
+            &self.our_base_key_pair.public_key,
+            &self.their_identity_key,
+            &self.their_signed_pre_key,
+            self.their_one_time_pre_key.as_ref(),
+            bob_kyber_pre_key,
+        ))
+    }
 }
 
 pub struct BobSignalProtocolParameters<'a> {
@@ -164,4 +194,34 @@ impl<'a> BobSignalProtocolParameters<'a> {
     pub fn their_kyber_ciphertext(&self) -> Option<&kem::SerializedCiphertext> {
         self.their_kyber_ciphertext
     }
+
+    /// Hashes the public inputs of this handshake with [`handshake_transcript_hash`].
+    ///
+    /// For a handshake that both sides completed, this matches Alice's
+    /// [`AliceSignalProtocolParameters::transcript_hash`].
+    ///
+    /// [`handshake_transcript_hash`]: crate::handshake_transcript_hash
+    #[cfg(feature = "interop-testing")]
+    pub fn transcript_hash(&self) -> Result<[u8; 32]> {
+        let bob_kyber_pre_key = match (&self.our_kyber_pre_key_pair, self.their_kyber_ciphertext) {
+            (Some(key_pair), Some(ciphertext)) => Some((&key_pair.public_key, &ciphertext[..])),
+            (None, None) => None,
+            _ => {
+                return Err(SignalProtocolError::InvalidArgument(
+                    "either both or none of the Kyber key pair and ciphertext can be provided"
+                        .to_owned(),
+                ))
+            }
+        };
+        Ok(super::handshake_transcript_hash(
+            &self.their_identity_key,
+            &self.their_base_key,
+            self.our_identity_key_pair.identity_key(),
+            &self.our_signed_pre_key_pair.public_key,
+            self.our_one_time_pre_key_pair
+                .as_ref()
+                .map(|key_pair| &key_pair.public_key),
+            bob_kyber_pre_key,
+        ))
+    }
 }
@@ -273,6 +273,27 @@ impl SenderCertificate {
             return Ok(false);
         }
 
+        self.validate_signed_by_server(validation_time)
+    }
+
+    /// Like [`validate`](Self::validate), but requires the server certificate's key to be exactly
+    /// `pinned_server_key`.
+    ///
+    /// A server certificate for any other key is rejected, even if it is signed by a trusted root.
+    pub fn validate_with_pinned_server_key(
+        &self,
+        pinned_server_key: &PublicKey,
+        validation_time: Timestamp,
+    ) -> Result<bool> {
+        if self.signer.public_key()? != *pinned_server_key {
+            log::error!("sender certificate contained server certificate with an unpinned key");
+            return Ok(false);
+        }
+
+        self.validate_signed_by_server(validation_time)
+    }
+
+    fn validate_signed_by_server(&self, validation_time: Timestamp) -> Result<bool> {
         if !self
             .signer
             .public_key()?
@@ -1785,6 +1806,22 @@ impl<'a> SealedSenderV2SentMessage<'a> {
     }
 }
 
+/// Compute a fingerprint of a sealed-sender envelope in either the v1 or v2 format.
+///
+/// This lets a server recognize duplicate uploads without decrypting anything: identical
+/// envelopes always produce the same fingerprint. The envelope is parsed to reject malformed
+/// input, but the fingerprint covers its exact bytes.
+pub fn sealed_sender_envelope_fingerprint(ciphertext: &[u8]) -> Result<[u8; 32]> {
+    use sha2::Digest;
+
+    UnidentifiedSenderMessage::deserialize(ciphertext)?;
+
+    let mut hasher = sha2::Sha256::new();
+    hasher.update(b"Signal_SealedSender_EnvelopeFingerprint");
+    hasher.update(ciphertext);
+    Ok(hasher.finalize().into())
+}
+
 /// Decrypt the payload of a sealed-sender message in either the v1 or v2 format.
 ///
 /// [`sealed_sender_decrypt`] consumes the output of this method to validate the sender's identity
@@ -1801,37 +1838,8 @@ pub async fn sealed_sender_decrypt_to_usmc(
             encrypted_static,
             encrypted_message,
         } => {
-            let eph_keys = sealed_sender_v1::EphemeralKeys::calculate(
-                &our_identity.into(),
-                &ephemeral_public,
-                Direction::Receiving,
-            )?;
-
-            let message_key_bytes = match crypto::aes256_ctr_hmacsha256_decrypt(
-                &encrypted_static,
-                &eph_keys.cipher_key,
-                &eph_keys.mac_key,
-            ) {
-                Ok(plaintext) => plaintext,
-                Err(crypto::DecryptionError::BadKeyOrIv) => {
-                    unreachable!("just derived these keys; they should be valid");
-                }
-                Err(crypto::DecryptionError::BadCiphertext(msg)) => {
-                    log::error!("failed to decrypt sealed sender v1 message key: {}", msg);
-                    return Err(SignalProtocolError::InvalidSealedSenderMessage(
-                        "failed to decrypt sealed sender v1 message key".to_owned(),
-                    ));
-                }
-            };
-
-            let static_key = PublicKey::try_from(&message_key_bytes[..])?;
-
-            let static_keys = sealed_sender_v1::StaticKeys::calculate(
-                &our_identity,
-                &static_key,
-                &eph_keys.chain_key,
-                &encrypted_static,
-            )?;
+            let (message_key_bytes, static_keys) =
+                decrypt_v1_static_key(&our_identity, &ephemeral_public, &encrypted_static)?;
 
             let message_bytes = match crypto::aes256_ctr_hmacsha256_decrypt(
                 &encrypted_message,
@@ -1938,6 +1946,360 @@ pub async fn sealed_sender_decrypt_to_usmc(
     }
 }
 
+/// Recovers the sender's serialized static key from a v1 envelope, along with the keys protecting
+/// the message body.
+fn decrypt_v1_static_key(
+    our_identity: &IdentityKeyPair,
+    ephemeral_public: &PublicKey,
+    encrypted_static: &[u8],
+) -> Result<(Vec<u8>, sealed_sender_v1::StaticKeys)> {
+    let eph_keys = sealed_sender_v1::EphemeralKeys::calculate(
+        &(*our_identity).into(),
+        ephemeral_public,
+        Direction::Receiving,
+    )?;
+
+    let message_key_bytes = match crypto::aes256_ctr_hmacsha256_decrypt(
+        encrypted_static,
+        &eph_keys.cipher_key,
+        &eph_keys.mac_key,
+    ) {
+        Ok(plaintext) => plaintext,
+        Err(crypto::DecryptionError::BadKeyOrIv) => {
+            unreachable!("just derived these keys; they should be valid");
+        }
+        Err(crypto::DecryptionError::BadCiphertext(msg)) => {
+            log::error!("failed to decrypt sealed sender v1 message key: {}", msg);
+            return Err(SignalProtocolError::InvalidSealedSenderMessage(
+                "failed to decrypt sealed sender v1 message key".to_owned(),
+            ));
+        }
+    };
+
+    let static_key = PublicKey::try_from(&message_key_bytes[..])?;
+
+    let static_keys = sealed_sender_v1::StaticKeys::calculate(
+        our_identity,
+        &static_key,
+        &eph_keys.chain_key,
+        encrypted_static,
+    )?;
+
+    Ok((message_key_bytes, static_keys))
+}
+
+/// How much of a v1 envelope [`SealedSenderDecryptStream`] will buffer before the encrypted
+/// message starts.
+const DECRYPT_STREAM_MAX_HEADER_LEN: usize = 256;
+/// How much plaintext [`SealedSenderDecryptStream`] will decrypt while looking for the sender
+/// certificate.
+const DECRYPT_STREAM_MAX_SENDER_CERTIFICATE_PREFIX_LEN: usize = 16 * 1024;
+
+/// Incrementally decrypts the payload of a sealed-sender v1 message.
+///
+/// The output of [`finalize`](Self::finalize) is the same as the serialized result of
+/// [`sealed_sender_decrypt_to_usmc`], but the ciphertext can be passed in as it arrives. Sealed
+/// sender v2 messages can't be decrypted this way, because AES-GCM-SIV needs the entire
+/// ciphertext before it can produce any plaintext.
+///
+/// The v1 MAC covers the whole message, so the plaintext is buffered and only handed out once
+/// `finalize` has verified it.
+pub struct SealedSenderDecryptStream {
+    our_identity: IdentityKeyPair,
+    state: DecryptStreamState,
+}
+
+enum DecryptStreamState {
+    /// Collecting the envelope up to the start of the encrypted message.
+    Header(Vec<u8>),
+    Body(Box<DecryptStreamBody>),
+    /// Finalized, or failed partway through.
+    Done,
+}
+
+struct DecryptStreamBody {
+    static_key: Vec<u8>,
+    decryption: crypto::Aes256CtrHmacSha256Decryption,
+    /// Not yet authenticated.
+    plaintext: Vec<u8>,
+    sender_certificate: Option<SenderCertificate>,
+}
+
+struct DecryptStreamHeader {
+    ephemeral_public: PublicKey,
+    encrypted_static: Vec<u8>,
+    header_len: usize,
+    encrypted_message_len: usize,
+}
+
+impl SealedSenderDecryptStream {
+    pub fn new(our_identity: IdentityKeyPair) -> Self {
+        Self {
+            our_identity,
+            state: DecryptStreamState::Header(Vec::new()),
+        }
+    }
+
+    /// Decrypts the next chunk of the message.
+    ///
+    /// After an error, the stream can't be used again.
+    pub fn update(&mut self, input: &[u8]) -> Result<()> {
+        let result = self.update_impl(input);
+        if result.is_err() {
+            self.state = DecryptStreamState::Done;
+        }
+        result
+    }
+
+    fn update_impl(&mut self, input: &[u8]) -> Result<()> {
+        match &mut self.state {
+            DecryptStreamState::Header(header) => {
+                // Anything buffered by an earlier call is part of the header, so all of the body
+                // bytes found here come from `input`.
+                header.extend_from_slice(input);
+                let Some(parsed) = DecryptStreamHeader::parse(header)? else {
+                    if header.len() > DECRYPT_STREAM_MAX_HEADER_LEN {
+                        return Err(SignalProtocolError::InvalidSealedSenderMessage(
+                            "sealed sender v1 header too long".to_owned(),
+                        ));
+                    }
+                    return Ok(());
+                };
+                let body_start = header.split_off(parsed.header_len);
+                let mut body = DecryptStreamBody::new(&self.our_identity, parsed)?;
+                body.update(&body_start)?;
+                self.state = DecryptStreamState::Body(Box::new(body));
+                Ok(())
+            }
+            DecryptStreamState::Body(body) => body.update(input),
+            DecryptStreamState::Done => Err(SignalProtocolError::InvalidState(
+                "update",
+                "stream already finished".to_owned(),
+            )),
+        }
+    }
+
+    /// Checks the message's MAC and that its sender certificate matches the key it was sealed
+    /// with, then returns the serialized [`UnidentifiedSenderMessageContent`].
+    ///
+    /// Fails with [`SignalProtocolError::SealedSenderMacVerificationFailed`] if the MAC is wrong.
+    /// The stream can't be used again afterwards, whether or not this succeeds.
+    pub fn finalize(&mut self) -> Result<Vec<u8>> {
+        let body = match std::mem::replace(&mut self.state, DecryptStreamState::Done) {
+            DecryptStreamState::Body(body) => body,
+            DecryptStreamState::Header(_) => {
+                return Err(SignalProtocolError::InvalidSealedSenderMessage(
+                    "truncated sealed sender v1 message".to_owned(),
+                ));
+            }
+            DecryptStreamState::Done => {
+                return Err(SignalProtocolError::InvalidState(
+                    "finalize",
+                    "stream already finished".to_owned(),
+                ));
+            }
+        };
+
+        let DecryptStreamBody {
+            static_key,
+            decryption,
+            plaintext,
+            sender_certificate,
+        } = *body;
+        let mac_matches = decryption.finalize().map_err(|e| {
+            log::error!(
+                "failed to decrypt sealed sender v1 message contents: {:?}",
+                e
+            );
+            DecryptStreamBody::decryption_failed()
+        })?;
+        if !mac_matches {
+            log::error!("sealed sender v1 message failed MAC verification");
+            return Err(SignalProtocolError::SealedSenderMacVerificationFailed);
+        }
+
+        let Some(sender_certificate) = sender_certificate else {
+            return Err(SignalProtocolError::InvalidSealedSenderMessage(
+                "message has no sender certificate".to_owned(),
+            ));
+        };
+        if !bool::from(static_key.ct_eq(&sender_certificate.key()?.serialize())) {
+            return Err(SignalProtocolError::InvalidSealedSenderMessage(
+                "sender certificate key does not match message key".to_string(),
+            ));
+        }
+        Ok(plaintext)
+    }
+}
+
+impl DecryptStreamBody {
+    fn new(our_identity: &IdentityKeyPair, header: DecryptStreamHeader) -> Result<Self> {
+        let (static_key, static_keys) = decrypt_v1_static_key(
+            our_identity,
+            &header.ephemeral_public,
+            &header.encrypted_static,
+        )?;
+        let decryption = crypto::Aes256CtrHmacSha256Decryption::new(
+            &static_keys.cipher_key,
+            &static_keys.mac_key,
+            header.encrypted_message_len,
+        )
+        .map_err(|_| Self::decryption_failed())?;
+        Ok(Self {
+            static_key,
+            decryption,
+            plaintext: Vec::new(),
+            sender_certificate: None,
+        })
+    }
+
+    fn decryption_failed() -> SignalProtocolError {
+        SignalProtocolError::InvalidSealedSenderMessage(
+            "failed to decrypt sealed sender v1 message contents".to_owned(),
+        )
+    }
+
+    fn update(&mut self, input: &[u8]) -> Result<()> {
+        self.decryption
+            .update(input, &mut self.plaintext)
+            .map_err(|_| Self::decryption_failed())?;
+
+        if self.sender_certificate.is_none() {
+            if let Some(certificate) = find_sender_certificate(&self.plaintext)? {
+                self.sender_certificate = Some(SenderCertificate::deserialize(certificate)?);
+            } else if self.plaintext.len() > DECRYPT_STREAM_MAX_SENDER_CERTIFICATE_PREFIX_LEN {
+                return Err(SignalProtocolError::InvalidSealedSenderMessage(
+                    "sender certificate not found at start of message".to_owned(),
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl DecryptStreamHeader {
+    const EPHEMERAL_PUBLIC_FIELD: u64 = 1;
+    const ENCRYPTED_STATIC_FIELD: u64 = 2;
+    const ENCRYPTED_MESSAGE_FIELD: u64 = 3;
+
+    /// Parses a v1 envelope up to the start of the encrypted message, or returns `None` if more
+    /// bytes are needed.
+    ///
+    /// The encrypted message must be the last field, which is how
+    /// [`sealed_sender_encrypt_from_usmc`] serializes it.
+    fn parse(data: &[u8]) -> Result<Option<Self>> {
+        let Some((&version_byte, mut remaining)) = data.split_first() else {
+            return Ok(None);
+        };
+        match version_byte >> 4 {
+            0 | SEALED_SENDER_V1_MAJOR_VERSION => {}
+            SEALED_SENDER_V2_MAJOR_VERSION => {
+                return Err(SignalProtocolError::InvalidSealedSenderMessage(
+                    "sealed sender v2 messages can't be decrypted incrementally".to_owned(),
+                ));
+            }
+            version => return Err(SignalProtocolError::UnknownSealedSenderVersion(version)),
+        }
+
+        let mut ephemeral_public = None;
+        let mut encrypted_static = None;
+        loop {
+            let Some(key) = take_varint(&mut remaining)? else {
+                return Ok(None);
+            };
+            if key & 0x7 != PROTOBUF_WIRE_TYPE_LENGTH_DELIMITED {
+                return Err(SignalProtocolError::InvalidProtobufEncoding);
+            }
+            let Some(len) = take_varint(&mut remaining)? else {
+                return Ok(None);
+            };
+            let len =
+                usize::try_from(len).map_err(|_| SignalProtocolError::InvalidProtobufEncoding)?;
+
+            if key >> 3 == Self::ENCRYPTED_MESSAGE_FIELD {
+                let (Some(ephemeral_public), Some(encrypted_static)) =
+                    (ephemeral_public, encrypted_static)
+                else {
+                    return Err(SignalProtocolError::InvalidProtobufEncoding);
+                };
+                return Ok(Some(Self {
+                    ephemeral_public: PublicKey::try_from(ephemeral_public)?,
+                    encrypted_static: encrypted_static.to_vec(),
+                    header_len: data.len() - remaining.len(),
+                    encrypted_message_len: len,
+                }));
+            }
+
+            if remaining.len() < len {
+                return Ok(None);
+            }
+            let (value, rest) = remaining.split_at(len);
+            remaining = rest;
+            match key >> 3 {
+                Self::EPHEMERAL_PUBLIC_FIELD => ephemeral_public = Some(value),
+                Self::ENCRYPTED_STATIC_FIELD => encrypted_static = Some(value),
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Finds the serialized sender certificate in a prefix of a serialized
+/// [`UnidentifiedSenderMessageContent`], or returns `None` if more bytes are needed.
+fn find_sender_certificate(mut data: &[u8]) -> Result<Option<&[u8]>> {
+    const SENDER_CERTIFICATE_FIELD: u64 = 2;
+
+    loop {
+        let Some(key) = take_varint(&mut data)? else {
+            return Ok(None);
+        };
+        match key & 0x7 {
+            PROTOBUF_WIRE_TYPE_VARINT => {
+                if take_varint(&mut data)?.is_none() {
+                    return Ok(None);
+                }
+            }
+            PROTOBUF_WIRE_TYPE_LENGTH_DELIMITED => {
+                let Some(len) = take_varint(&mut data)? else {
+                    return Ok(None);
+                };
+                let len = usize::try_from(len)
+                    .map_err(|_| SignalProtocolError::InvalidProtobufEncoding)?;
+                if data.len() < len {
+                    return Ok(None);
+                }
+                let (value, rest) = data.split_at(len);
+                if key >> 3 == SENDER_CERTIFICATE_FIELD {
+                    return Ok(Some(value));
+                }
+                data = rest;
+            }
+            _ => return Err(SignalProtocolError::InvalidProtobufEncoding),
+        }
+    }
+}
+
+const PROTOBUF_WIRE_TYPE_VARINT: u64 = 0;
+const PROTOBUF_WIRE_TYPE_LENGTH_DELIMITED: u64 = 2;
+
+/// Reads a protobuf varint from the front of `data`, or returns `None` if it is incomplete.
+fn take_varint(data: &mut &[u8]) -> Result<Option<u64>> {
+    const MAX_VARINT_LEN: usize = 10;
+
+    let mut value = 0;
+    for (i, &byte) in data.iter().take(MAX_VARINT_LEN).enumerate() {
+        value |= u64::from(byte & 0x7F) << (7 * i);
+        if byte & 0x80 == 0 {
+            *data = &data[i + 1..];
+            return Ok(Some(value));
+        }
+    }
+    if data.len() >= MAX_VARINT_LEN {
+        return Err(SignalProtocolError::InvalidProtobufEncoding);
+    }
+    Ok(None)
+}
+
 #[derive(Debug)]
 pub struct SealedSenderDecryptionResult {
     pub sender_uuid: String,
@@ -1971,6 +2333,9 @@ impl SealedSenderDecryptionResult {
 /// the embedded [`SenderCertificate`]. The sender certificate (signed by the [`ServerCertificate`])
 /// is then validated against the `trust_root` baked into the client to ensure that the sender's
 /// identity was not forged.
+///
+/// `local_e164` may be `None` for accounts without a phone number, in which case messages from this
+/// device are recognized by `local_uuid` alone.
 #[allow(clippy::too_many_arguments)]
 pub async fn sealed_sender_decrypt(
     ciphertext: &[u8],
@@ -235,12 +235,18 @@ pub struct SenderKeyRecord {
 }
 
 impl SenderKeyRecord {
-    pub(crate) fn new_empty() -> Self {
+    /// Creates a record with no sender key states, for stores to fill in.
+    pub fn new_empty() -> Self {
         Self {
             states: VecDeque::with_capacity(consts::MAX_SENDER_KEY_STATES),
         }
     }
 
+    /// Returns true if this record holds no sender key states.
+    pub fn is_empty(&self) -> bool {
+        self.states.is_empty()
+    }
+
     pub fn deserialize(buf: &[u8]) -> Result<SenderKeyRecord, SignalProtocolError> {
         let skr = storage_proto::SenderKeyRecordStructure::decode(buf)
             .map_err(|_| SignalProtocolError::InvalidProtobufEncoding)?;
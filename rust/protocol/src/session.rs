@@ -39,6 +39,35 @@ pub async fn process_prekey(
     pre_key_store: &dyn PreKeyStore,
     signed_prekey_store: &dyn SignedPreKeyStore,
     kyber_prekey_store: &dyn KyberPreKeyStore,
+) -> Result<PreKeysUsed> {
+    let pre_keys_used = process_prekey_without_saving_identity(
+        message,
+        remote_address,
+        session_record,
+        identity_store,
+        pre_key_store,
+        signed_prekey_store,
+        kyber_prekey_store,
+    )
+    .await?;
+
+    identity_store
+        .save_identity(remote_address, message.identity_key())
+        .await?;
+
+    Ok(pre_keys_used)
+}
+
+/// Like [`process_prekey`], but leaves saving the sender's identity to the caller, so that nothing
+/// is written to the identity store if the message is rejected afterwards.
+pub(crate) async fn process_prekey_without_saving_identity(
+    message: &PreKeySignalMessage,
+    remote_address: &ProtocolAddress,
+    session_record: &mut SessionRecord,
+    identity_store: &dyn IdentityKeyStore,
+    pre_key_store: &dyn PreKeyStore,
+    signed_prekey_store: &dyn SignedPreKeyStore,
+    kyber_prekey_store: &dyn KyberPreKeyStore,
 ) -> Result<PreKeysUsed> {
     let their_identity_key = message.identity_key();
 
@@ -51,7 +80,7 @@ pub async fn process_prekey(
         ));
     }
 
-    let pre_keys_used = process_prekey_impl(
+    process_prekey_impl(
         message,
         remote_address,
         session_record,
@@ -60,13 +89,7 @@ pub async fn process_prekey(
         pre_key_store,
         identity_store,
     )
-    .await?;
-
-    identity_store
-        .save_identity(remote_address, their_identity_key)
-        .await?;
-
-    Ok(pre_keys_used)
+    .await
 }
 
 async fn process_prekey_impl(
@@ -159,10 +182,7 @@ pub async fn process_prekey_bundle<R: Rng + CryptoRng>(
         ));
     }
 
-    if !their_identity_key.public_key().verify_signature(
-        &bundle.signed_pre_key_public()?.serialize(),
-        bundle.signed_pre_key_signature()?,
-    )? {
+    if !bundle.verify_signed_pre_key_signature()? {
         return Err(SignalProtocolError::SignatureValidationFailed);
     }
 
@@ -7,7 +7,7 @@ use std::time::SystemTime;
 
 use rand::{CryptoRng, Rng};
 
-use crate::consts::{MAX_FORWARD_JUMPS, MAX_UNACKNOWLEDGED_SESSION_AGE};
+use crate::consts::{MAX_FORWARD_JUMPS, MAX_MESSAGE_KEYS, MAX_UNACKNOWLEDGED_SESSION_AGE};
 use crate::ratchet::{ChainKey, MessageKeys};
 use crate::state::{InvalidSessionError, SessionState};
 use crate::{
@@ -32,6 +32,20 @@ pub async fn message_encrypt(
         .ok_or_else(|| SignalProtocolError::SessionNotFound(remote_address.clone()))?;
 
     let chain_key = session_state.get_sender_chain_key()?;
+    if chain_key.index() == u32::MAX {
+        // The counter can't advance any further, and only the remote side can start a new chain.
+        log::error!(
+            "sender chain for {} has exhausted its message counter",
+            remote_address
+        );
+        return Err(SignalProtocolError::InvalidState(
+            "message_encrypt",
+            format!(
+                "sender chain counter for {} would overflow; a new session is required",
+                remote_address
+            ),
+        ));
+    }
 
     let message_keys = chain_key.message_keys();
 
@@ -196,13 +210,76 @@ pub async fn message_decrypt_prekey<R: Rng + CryptoRng>(
     kyber_pre_key_store: &mut dyn KyberPreKeyStore,
     csprng: &mut R,
 ) -> Result<Vec<u8>> {
+    decrypt_prekey_message(
+        ciphertext,
+        remote_address,
+        session_store,
+        identity_store,
+        pre_key_store,
+        signed_pre_key_store,
+        kyber_pre_key_store,
+        DecryptLimits::default(),
+        csprng,
+    )
+    .await
+}
+
+/// Like [`message_decrypt_prekey`], but rejects a message whose plaintext is longer than
+/// `max_plaintext_len` bytes.
+///
+/// The rejection happens before anything is written to the stores, so the message is not consumed:
+/// the session, identity, and prekeys are left as they were. A message whose ciphertext is too
+/// long to hold a plaintext within the limit is rejected before it is decrypted at all.
+#[allow(clippy::too_many_arguments)]
+pub async fn message_decrypt_prekey_with_max_plaintext_len<R: Rng + CryptoRng>(
+    ciphertext: &PreKeySignalMessage,
+    remote_address: &ProtocolAddress,
+    session_store: &mut dyn SessionStore,
+    identity_store: &mut dyn IdentityKeyStore,
+    pre_key_store: &mut dyn PreKeyStore,
+    signed_pre_key_store: &dyn SignedPreKeyStore,
+    kyber_pre_key_store: &mut dyn KyberPreKeyStore,
+    max_plaintext_len: usize,
+    csprng: &mut R,
+) -> Result<Vec<u8>> {
+    decrypt_prekey_message(
+        ciphertext,
+        remote_address,
+        session_store,
+        identity_store,
+        pre_key_store,
+        signed_pre_key_store,
+        kyber_pre_key_store,
+        DecryptLimits {
+            max_plaintext_len,
+            ..Default::default()
+        },
+        csprng,
+    )
+    .await
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn decrypt_prekey_message<R: Rng + CryptoRng>(
+    ciphertext: &PreKeySignalMessage,
+    remote_address: &ProtocolAddress,
+    session_store: &mut dyn SessionStore,
+    identity_store: &mut dyn IdentityKeyStore,
+    pre_key_store: &mut dyn PreKeyStore,
+    signed_pre_key_store: &dyn SignedPreKeyStore,
+    kyber_pre_key_store: &mut dyn KyberPreKeyStore,
+    limits: DecryptLimits,
+    csprng: &mut R,
+) -> Result<Vec<u8>> {
+    limits.check_ciphertext_len(ciphertext.message(), CiphertextMessageType::PreKey)?;
+
     let mut session_record = session_store
         .load_session(remote_address)
         .await?
         .unwrap_or_else(SessionRecord::new_fresh);
 
     // Make sure we log the session state if we fail to process the pre-key.
-    let pre_key_used_or_err = session::process_prekey(
+    let pre_key_used_or_err = session::process_prekey_without_saving_identity(
         ciphertext,
         remote_address,
         &mut session_record,
@@ -236,9 +313,14 @@ pub async fn message_decrypt_prekey<R: Rng + CryptoRng>(
         &mut session_record,
         ciphertext.message(),
         CiphertextMessageType::PreKey,
+        limits,
         csprng,
     )?;
 
+    identity_store
+        .save_identity(remote_address, ciphertext.identity_key())
+        .await?;
+
     session_store
         .store_session(remote_address, &session_record)
         .await?;
@@ -263,6 +345,175 @@ pub async fn message_decrypt_signal<R: Rng + CryptoRng>(
     identity_store: &mut dyn IdentityKeyStore,
     csprng: &mut R,
 ) -> Result<Vec<u8>> {
+    let (ptext, _identity_changed) = decrypt_signal_message(
+        ciphertext,
+        remote_address,
+        session_store,
+        identity_store,
+        DecryptLimits::default(),
+        false,
+        csprng,
+    )
+    .await?;
+    Ok(ptext)
+}
+
+/// Like [`message_decrypt_signal`], but refuses a message that would leave more than
+/// `max_skipped_keys` keys stored for skipped messages in its receiving chain.
+///
+/// Keys already stored for earlier out-of-order messages count towards the limit, alongside the
+/// ones this message would add. The limit can only be tightened: values above the default of 2,000
+/// (the most any receiving chain keeps) are treated as 2,000. Where [`message_decrypt_signal`]
+/// would discard the oldest stored keys to make room, this fails with
+/// [`SignalProtocolError::TooManySkippedKeys`] before any keys are derived, and the stored session
+/// is left untouched.
+pub async fn message_decrypt_signal_with_max_skipped_keys<R: Rng + CryptoRng>(
+    ciphertext: &SignalMessage,
+    remote_address: &ProtocolAddress,
+    session_store: &mut dyn SessionStore,
+    identity_store: &mut dyn IdentityKeyStore,
+    max_skipped_keys: u32,
+    csprng: &mut R,
+) -> Result<Vec<u8>> {
+    let (ptext, _identity_changed) = decrypt_signal_message(
+        ciphertext,
+        remote_address,
+        session_store,
+        identity_store,
+        DecryptLimits {
+            max_skipped_keys: MAX_MESSAGE_KEYS.min(max_skipped_keys as usize),
+            ..Default::default()
+        },
+        false,
+        csprng,
+    )
+    .await?;
+    Ok(ptext)
+}
+
+/// Like [`message_decrypt_signal`], but rejects a message whose plaintext is longer than
+/// `max_plaintext_len` bytes.
+///
+/// The rejection happens before anything is written to the stores, so the message is not consumed
+/// and the stored session is left untouched. A message whose ciphertext is too long to hold a
+/// plaintext within the limit is rejected before it is decrypted at all.
+pub async fn message_decrypt_signal_with_max_plaintext_len<R: Rng + CryptoRng>(
+    ciphertext: &SignalMessage,
+    remote_address: &ProtocolAddress,
+    session_store: &mut dyn SessionStore,
+    identity_store: &mut dyn IdentityKeyStore,
+    max_plaintext_len: usize,
+    csprng: &mut R,
+) -> Result<Vec<u8>> {
+    let (ptext, _identity_changed) = decrypt_signal_message(
+        ciphertext,
+        remote_address,
+        session_store,
+        identity_store,
+        DecryptLimits {
+            max_plaintext_len,
+            ..Default::default()
+        },
+        false,
+        csprng,
+    )
+    .await?;
+    Ok(ptext)
+}
+
+/// Like [`message_decrypt_signal`], but also reports whether the sender's identity key is one
+/// that `identity_store` does not trust, such as after the sender reinstalled.
+///
+/// If `allow_identity_change` is false, an untrusted identity fails with
+/// [`SignalProtocolError::UntrustedIdentity`] just as in [`message_decrypt_signal`]. If it is
+/// true, the message is decrypted anyway and the second value returned is `true`. The untrusted
+/// identity is *not* saved in that case; the caller decides whether to accept it with
+/// [`IdentityKeyStore::save_identity`].
+pub async fn message_decrypt_signal_reporting_identity_change<R: Rng + CryptoRng>(
+    ciphertext: &SignalMessage,
+    remote_address: &ProtocolAddress,
+    session_store: &mut dyn SessionStore,
+    identity_store: &mut dyn IdentityKeyStore,
+    allow_identity_change: bool,
+    csprng: &mut R,
+) -> Result<(Vec<u8>, bool)> {
+    decrypt_signal_message(
+        ciphertext,
+        remote_address,
+        session_store,
+        identity_store,
+        DecryptLimits::default(),
+        allow_identity_change,
+        csprng,
+    )
+    .await
+}
+
+/// Bounds on what a single incoming 1:1 message may cost, checked before the updated session is
+/// committed.
+#[derive(Clone, Copy)]
+struct DecryptLimits {
+    max_forward_jumps: usize,
+    /// Beyond this many stored skipped keys in a chain, the message is rejected rather than
+    /// evicting the oldest ones.
+    max_skipped_keys: usize,
+    max_plaintext_len: usize,
+}
+
+impl Default for DecryptLimits {
+    fn default() -> Self {
+        Self {
+            max_forward_jumps: MAX_FORWARD_JUMPS,
+            max_skipped_keys: usize::MAX,
+            max_plaintext_len: usize::MAX,
+        }
+    }
+}
+
+impl DecryptLimits {
+    /// Rejects a message whose ciphertext is too long to hold a plaintext within the limit.
+    ///
+    /// The body is AES-CBC with PKCS#7 padding, which adds between 1 and 16 bytes.
+    fn check_ciphertext_len(
+        &self,
+        ciphertext: &SignalMessage,
+        original_message_type: CiphertextMessageType,
+    ) -> Result<()> {
+        if ciphertext.body().len().saturating_sub(16) > self.max_plaintext_len {
+            return Err(SignalProtocolError::InvalidMessage(
+                original_message_type,
+                "plaintext exceeds maximum length",
+            ));
+        }
+        Ok(())
+    }
+
+    fn check_plaintext_len(
+        &self,
+        ptext: &[u8],
+        original_message_type: CiphertextMessageType,
+    ) -> Result<()> {
+        if ptext.len() > self.max_plaintext_len {
+            return Err(SignalProtocolError::InvalidMessage(
+                original_message_type,
+                "plaintext exceeds maximum length",
+            ));
+        }
+        Ok(())
+    }
+}
+
+async fn decrypt_signal_message<R: Rng + CryptoRng>(
+    ciphertext: &SignalMessage,
+    remote_address: &ProtocolAddress,
+    session_store: &mut dyn SessionStore,
+    identity_store: &mut dyn IdentityKeyStore,
+    limits: DecryptLimits,
+    allow_identity_change: bool,
+    csprng: &mut R,
+) -> Result<(Vec<u8>, bool)> {
+    limits.check_ciphertext_len(ciphertext, CiphertextMessageType::Whisper)?;
+
     let mut session_record = session_store
         .load_session(remote_address)
         .await?
@@ -273,6 +524,7 @@ pub async fn message_decrypt_signal<R: Rng + CryptoRng>(
         &mut session_record,
         ciphertext,
         CiphertextMessageType::Whisper,
+        limits,
         csprng,
     )?;
 
@@ -284,10 +536,10 @@ pub async fn message_decrypt_signal<R: Rng + CryptoRng>(
         .expect("successfully decrypted; must have a remote identity key")
         .expect("successfully decrypted; must have a remote identity key");
 
-    if !identity_store
+    let identity_changed = !identity_store
         .is_trusted_identity(remote_address, &their_identity_key, Direction::Receiving)
-        .await?
-    {
+        .await?;
+    if identity_changed {
         log::warn!(
             "Identity key {} is not trusted for remote address {}",
             their_identity_key
@@ -296,20 +548,22 @@ pub async fn message_decrypt_signal<R: Rng + CryptoRng>(
                 .map_or_else(|e| format!("<error: {}>", e), hex::encode),
             remote_address,
         );
-        return Err(SignalProtocolError::UntrustedIdentity(
-            remote_address.clone(),
-        ));
+        if !allow_identity_change {
+            return Err(SignalProtocolError::UntrustedIdentity(
+                remote_address.clone(),
+            ));
+        }
+    } else {
+        identity_store
+            .save_identity(remote_address, &their_identity_key)
+            .await?;
     }
 
-    identity_store
-        .save_identity(remote_address, &their_identity_key)
-        .await?;
-
     session_store
         .store_session(remote_address, &session_record)
         .await?;
 
-    Ok(ptext)
+    Ok((ptext, identity_changed))
 }
 
 fn create_decryption_failure_log(
@@ -410,6 +664,7 @@ fn decrypt_message_with_record<R: Rng + CryptoRng>(
     record: &mut SessionRecord,
     ciphertext: &SignalMessage,
     original_message_type: CiphertextMessageType,
+    limits: DecryptLimits,
     csprng: &mut R,
 ) -> Result<Vec<u8>> {
     debug_assert!(matches!(
@@ -446,6 +701,7 @@ fn decrypt_message_with_record<R: Rng + CryptoRng>(
             ciphertext,
             original_message_type,
             remote_address,
+            limits,
             csprng,
         );
 
@@ -459,10 +715,14 @@ fn decrypt_message_with_record<R: Rng + CryptoRng>(
                         .sender_ratchet_key_for_logging()
                         .expect("successful decrypt always has a valid base key"),
                 );
+                limits.check_plaintext_len(&ptext, original_message_type)?;
                 record.set_session_state(current_state); // update the state
                 return Ok(ptext);
             }
-            Err(SignalProtocolError::DuplicatedMessage(_, _)) => {
+            Err(
+                SignalProtocolError::DuplicatedMessage(_, _)
+                | SignalProtocolError::TooManySkippedKeys(_),
+            ) => {
                 return result;
             }
             Err(e) => {
@@ -484,6 +744,7 @@ fn decrypt_message_with_record<R: Rng + CryptoRng>(
             ciphertext,
             original_message_type,
             remote_address,
+            limits,
             csprng,
         );
 
@@ -500,7 +761,10 @@ fn decrypt_message_with_record<R: Rng + CryptoRng>(
                 updated_session = Some((ptext, idx, previous));
                 break;
             }
-            Err(SignalProtocolError::DuplicatedMessage(_, _)) => {
+            Err(
+                SignalProtocolError::DuplicatedMessage(_, _)
+                | SignalProtocolError::TooManySkippedKeys(_),
+            ) => {
                 return result;
             }
             Err(e) => {
@@ -511,6 +775,7 @@ fn decrypt_message_with_record<R: Rng + CryptoRng>(
     }
 
     if let Some((ptext, idx, updated_session)) = updated_session {
+        limits.check_plaintext_len(&ptext, original_message_type)?;
         record.promote_old_session(idx, updated_session);
         Ok(ptext)
     } else {
@@ -563,6 +828,7 @@ fn decrypt_message_with_state<R: Rng + CryptoRng>(
     ciphertext: &SignalMessage,
     original_message_type: CiphertextMessageType,
     remote_address: &ProtocolAddress,
+    limits: DecryptLimits,
     csprng: &mut R,
 ) -> Result<Vec<u8>> {
     // Check for a completely empty or invalid session state before we do anything else.
@@ -590,6 +856,7 @@ fn decrypt_message_with_state<R: Rng + CryptoRng>(
         original_message_type,
         &chain_key,
         counter,
+        limits,
     )?;
 
     let their_identity_key =
@@ -685,6 +952,7 @@ fn get_or_create_message_key(
     original_message_type: CiphertextMessageType,
     chain_key: &ChainKey,
     counter: u32,
+    limits: DecryptLimits,
 ) -> Result<MessageKeys> {
     let chain_index = chain_key.index();
 
@@ -706,7 +974,7 @@ fn get_or_create_message_key(
 
     let jump = (counter - chain_index) as usize;
 
-    if jump > MAX_FORWARD_JUMPS {
+    if jump > limits.max_forward_jumps {
         if state.session_with_self()? {
             log::info!(
                 "{} Jumping ahead {} messages (index: {}, counter: {})",
@@ -719,7 +987,7 @@ fn get_or_create_message_key(
             log::error!(
                 "{} Exceeded future message limit: {}, index: {}, counter: {})",
                 remote_address,
-                MAX_FORWARD_JUMPS,
+                limits.max_forward_jumps,
                 chain_index,
                 counter
             );
@@ -730,6 +998,19 @@ fn get_or_create_message_key(
         }
     }
 
+    if state.message_key_count(their_ephemeral)? + jump > limits.max_skipped_keys {
+        log::error!(
+            "{} Exceeded skipped message key limit: {}, index: {}, counter: {}",
+            remote_address,
+            limits.max_skipped_keys,
+            chain_index,
+            counter
+        );
+        return Err(SignalProtocolError::TooManySkippedKeys(
+            limits.max_skipped_keys,
+        ));
+    }
+
     let mut chain_key = chain_key.clone();
 
     while chain_key.index() < counter {
@@ -741,3 +1022,65 @@ fn get_or_create_message_key(
     state.set_receiver_chain_key(their_ephemeral, &chain_key.next_chain_key())?;
     Ok(chain_key.message_keys())
 }
+
+#[cfg(test)]
+mod test {
+    use futures_util::FutureExt;
+    use rand::rngs::OsRng;
+
+    use super::*;
+    use crate::{
+        initialize_alice_session_record, AliceSignalProtocolParameters, IdentityKeyPair,
+        InMemSignalProtocolStore,
+    };
+
+    #[test]
+    fn test_encrypt_refuses_to_overflow_sender_counter() -> Result<()> {
+        let mut csprng = OsRng;
+        let alice_identity = IdentityKeyPair::generate(&mut csprng);
+        let bob_identity = IdentityKeyPair::generate(&mut csprng);
+        let bob_base_key = KeyPair::generate(&mut csprng);
+
+        let alice_params = AliceSignalProtocolParameters::new(
+            alice_identity,
+            KeyPair::generate(&mut csprng),
+            *bob_identity.identity_key(),
+            bob_base_key.public_key,
+            bob_base_key.public_key,
+        );
+        let mut record = initialize_alice_session_record(&alice_params, &mut csprng)?;
+
+        let state = record.session_state_mut().expect("just initialized");
+        let chain_key = state.get_sender_chain_key()?;
+        state.set_sender_chain_key(&ChainKey::new(*chain_key.key(), u32::MAX - 1));
+        assert_eq!(record.sender_counter()?, u32::MAX - 1);
+
+        let bob_address = ProtocolAddress::new("+14158888888".to_owned(), 1.into());
+        let mut store = InMemSignalProtocolStore::new(alice_identity, 1)?;
+        store
+            .store_session(&bob_address, &record)
+            .now_or_never()
+            .expect("sync")?;
+
+        let mut encrypt = || {
+            message_encrypt(
+                b"counting",
+                &bob_address,
+                &mut store.session_store,
+                &mut store.identity_store,
+                SystemTime::now(),
+            )
+            .now_or_never()
+            .expect("sync")
+        };
+
+        // The last counter value can still be used...
+        encrypt()?;
+        // ...but there's nothing after it.
+        assert!(matches!(
+            encrypt(),
+            Err(SignalProtocolError::InvalidState("message_encrypt", _))
+        ));
+        Ok(())
+    }
+}
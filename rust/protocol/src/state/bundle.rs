@@ -217,6 +217,17 @@ impl PreKeyBundle {
         Ok(&self.identity_key)
     }
 
+    /// Checks that the signed pre-key was signed by this bundle's identity key.
+    ///
+    /// Returns `Ok(false)` for a signature that doesn't verify; errors are reserved for keys that
+    /// can't be checked at all.
+    pub fn verify_signed_pre_key_signature(&self) -> Result<bool> {
+        self.identity_key.public_key().verify_signature(
+            &self.ec_signed_pre_key.public_key.serialize(),
+            &self.ec_signed_pre_key.signature,
+        )
+    }
+
     pub fn has_kyber_pre_key(&self) -> bool {
         self.kyber_pre_key.is_some()
     }
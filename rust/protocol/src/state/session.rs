@@ -4,16 +4,23 @@
 //
 
 use std::result::Result;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::time::{Duration, SystemTime};
 
 use prost::Message;
+use sha2::{Digest, Sha256};
 use subtle::ConstantTimeEq;
+use zeroize::Zeroize;
 
 use crate::ratchet::{ChainKey, MessageKeys, RootKey};
-use crate::{kem, IdentityKey, KeyPair, PrivateKey, PublicKey, SignalProtocolError};
+use crate::{
+    kem, IdentityKey, IdentityKeyStore, KeyPair, PrivateKey, ProtocolAddress, PublicKey,
+    SignalProtocolError,
+};
 
 use crate::consts;
 use crate::proto::storage::{session_structure, RecordStructure, SessionStructure};
+use crate::protocol::{CIPHERTEXT_MESSAGE_CURRENT_VERSION, CIPHERTEXT_MESSAGE_PRE_KYBER_VERSION};
 use crate::state::{KyberPreKeyId, PreKeyId, SignedPreKeyId};
 
 /// A distinct error type to keep from accidentally propagating deserialization errors.
@@ -225,6 +232,10 @@ impl SessionState {
         Ok(true)
     }
 
+    pub(crate) fn has_sender_chain(&self) -> bool {
+        self.session.sender_chain.is_some()
+    }
+
     pub(crate) fn all_receiver_chain_logging_info(&self) -> Vec<(Vec<u8>, Option<u32>)> {
         let mut results = vec![];
         for chain in self.session.receiver_chains.iter() {
@@ -273,6 +284,78 @@ impl SessionState {
         }
     }
 
+    /// Checks that the most recent receiver chain advanced past `to_counter` and that every message
+    /// key skipped between `from_counter` and `to_counter` is still available.
+    pub(crate) fn receiver_chain_is_continuous(
+        &self,
+        from_counter: u32,
+        to_counter: u32,
+    ) -> Result<bool, InvalidSessionError> {
+        if from_counter > to_counter {
+            return Ok(false);
+        }
+        let chain = match self.session.receiver_chains.last() {
+            Some(chain) => chain,
+            None => return Ok(false),
+        };
+        let chain_key = chain
+            .chain_key
+            .as_ref()
+            .ok_or(InvalidSessionError("missing receiver chain key"))?;
+        if chain_key.index <= to_counter {
+            return Ok(false);
+        }
+        Ok((from_counter.saturating_add(1)..to_counter)
+            .all(|counter| chain.message_keys.iter().any(|m| m.index == counter)))
+    }
+
+    /// Returns the counter of the next message expected on the most recent receiver chain.
+    pub(crate) fn receiver_counter(&self) -> Result<u32, InvalidSessionError> {
+        let chain = self
+            .session
+            .receiver_chains
+            .last()
+            .ok_or(InvalidSessionError("missing receiver chain"))?;
+        let chain_key = chain
+            .chain_key
+            .as_ref()
+            .ok_or(InvalidSessionError("missing receiver chain key"))?;
+        Ok(chain_key.index)
+    }
+
+    /// Hashes the root key and every chain key, sender chain first.
+    ///
+    /// Each chain's commitment also covers its ratchet key and index, so a sender chain commits to
+    /// the same value as the peer's matching receiver chain once both have reached the same index.
+    pub(crate) fn key_commitments(&self) -> Result<Vec<[u8; 32]>, InvalidSessionError> {
+        let mut commitments = vec![Sha256::new()
+            .chain_update(b"Signal_RootKeyCommitment")
+            .chain_update(&self.session.root_key)
+            .finalize()
+            .into()];
+        for chain in self
+            .session
+            .sender_chain
+            .iter()
+            .chain(&self.session.receiver_chains)
+        {
+            let chain_key = chain
+                .chain_key
+                .as_ref()
+                .ok_or(InvalidSessionError("missing chain key"))?;
+            commitments.push(
+                Sha256::new()
+                    .chain_update(b"Signal_ChainKeyCommitment")
+                    .chain_update(&chain.sender_ratchet_key)
+                    .chain_update(chain_key.index.to_be_bytes())
+                    .chain_update(&chain_key.key)
+                    .finalize()
+                    .into(),
+            );
+        }
+        Ok(commitments)
+    }
+
     pub(crate) fn add_receiver_chain(&mut self, sender: &PublicKey, chain_key: &ChainKey) {
         let chain_key = session_structure::chain::ChainKey {
             index: chain_key.index(),
@@ -411,6 +494,16 @@ impl SessionState {
         Ok(None)
     }
 
+    /// The number of keys stored for skipped messages in the receiving chain for `sender`.
+    pub(crate) fn message_key_count(
+        &self,
+        sender: &PublicKey,
+    ) -> Result<usize, InvalidSessionError> {
+        Ok(self
+            .get_receiver_chain(sender)?
+            .map_or(0, |(chain, _)| chain.message_keys.len()))
+    }
+
     pub(crate) fn set_message_keys(
         &mut self,
         sender: &PublicKey,
@@ -457,6 +550,49 @@ impl SessionState {
         Ok(())
     }
 
+    /// Zeroes the root key and every chain, ratchet, and message key in this state.
+    ///
+    /// Identities, registration IDs, and the rest of the session metadata are left in place.
+    pub(crate) fn wipe_secrets(&mut self) {
+        self.session.root_key.zeroize();
+        let chains = self
+            .session
+            .sender_chain
+            .iter_mut()
+            .chain(self.session.receiver_chains.iter_mut());
+        for chain in chains {
+            chain.sender_ratchet_key_private.zeroize();
+            if let Some(chain_key) = &mut chain.chain_key {
+                chain_key.key.zeroize();
+            }
+            for message_key in &mut chain.message_keys {
+                message_key.cipher_key.zeroize();
+                message_key.mac_key.zeroize();
+                message_key.iv.zeroize();
+            }
+            chain.message_keys.clear();
+        }
+    }
+
+    /// Keeps at most `limit` stored message keys, zeroing and dropping the rest.
+    ///
+    /// Keys for the newest receiver chains are kept first, and within a chain the most recently
+    /// stored keys are kept first. Returns the number of keys kept.
+    pub(crate) fn trim_message_keys(&mut self, limit: usize) -> usize {
+        let mut kept = 0;
+        for chain in self.session.receiver_chains.iter_mut().rev() {
+            let keep = chain.message_keys.len().min(limit - kept);
+            for message_key in &mut chain.message_keys[keep..] {
+                message_key.cipher_key.zeroize();
+                message_key.mac_key.zeroize();
+                message_key.iv.zeroize();
+            }
+            chain.message_keys.truncate(keep);
+            kept += keep;
+        }
+        kept
+    }
+
     pub(crate) fn set_unacknowledged_pre_key_message(
         &mut self,
         pre_key_id: Option<PreKeyId>,
@@ -581,10 +717,21 @@ impl From<&SessionState> for SessionStructure {
     }
 }
 
-#[derive(Clone)]
 pub struct SessionRecord {
     current_session: Option<SessionState>,
     previous_sessions: Vec<Vec<u8>>,
+    /// Set whenever the record is modified, and cleared when it is serialized.
+    needs_persist: AtomicBool,
+}
+
+impl Clone for SessionRecord {
+    fn clone(&self) -> Self {
+        Self {
+            current_session: self.current_session.clone(),
+            previous_sessions: self.previous_sessions.clone(),
+            needs_persist: AtomicBool::new(self.needs_persist()),
+        }
+    }
 }
 
 impl SessionRecord {
@@ -592,6 +739,7 @@ impl SessionRecord {
         Self {
             current_session: None,
             previous_sessions: Vec::new(),
+            needs_persist: AtomicBool::new(false),
         }
     }
 
@@ -599,6 +747,7 @@ impl SessionRecord {
         Self {
             current_session: Some(state),
             previous_sessions: Vec::new(),
+            needs_persist: AtomicBool::new(true),
         }
     }
 
@@ -609,9 +758,68 @@ impl SessionRecord {
         Ok(Self {
             current_session: record.current_session.map(|s| s.into()),
             previous_sessions: record.previous_sessions,
+            needs_persist: AtomicBool::new(false),
         })
     }
 
+    /// Returns `true` if the record has been modified since it was deserialized or last
+    /// serialized.
+    ///
+    /// Stores can use this to skip writing back a record that an operation only read from.
+    pub fn needs_persist(&self) -> bool {
+        self.needs_persist.load(Ordering::Relaxed)
+    }
+
+    fn mark_modified(&mut self) {
+        *self.needs_persist.get_mut() = true;
+    }
+
+    /// Deserializes a session stored in the legacy on-disk format, which was a bare
+    /// `SessionStructure` with no archived states, and converts it to a current record.
+    ///
+    /// Data that can't be read in the legacy format is rejected with
+    /// [`SignalProtocolError::InvalidProtobufEncoding`], so that callers migrating stored sessions
+    /// can tell it apart from a current-format record that failed [`Self::deserialize`].
+    pub fn deserialize_legacy(bytes: &[u8]) -> Result<Self, SignalProtocolError> {
+        let session = SessionStructure::decode(bytes)
+            .map_err(|_| SignalProtocolError::InvalidProtobufEncoding)?;
+        let state = SessionState::from_session_structure(session);
+        // An empty or truncated blob still decodes, so check for the fields every session has.
+        if state.root_key().is_err() || state.local_identity_key().is_err() {
+            return Err(SignalProtocolError::InvalidProtobufEncoding);
+        }
+        Ok(Self::new(state))
+    }
+
+    /// Deserializes a record restored from a backup, accepting it only if its current session is
+    /// with the identity that `identity_store` has saved for `address`.
+    ///
+    /// Returns [`SignalProtocolError::UntrustedIdentity`] if the identities differ or there is no
+    /// saved identity to compare against.
+    pub async fn deserialize_verified(
+        bytes: &[u8],
+        address: &ProtocolAddress,
+        identity_store: &dyn IdentityKeyStore,
+    ) -> Result<Self, SignalProtocolError> {
+        let record = Self::deserialize(bytes)?;
+        let remote_identity = record
+            .session_state()
+            .ok_or_else(|| {
+                SignalProtocolError::InvalidState(
+                    "deserialize_verified",
+                    "No current session".into(),
+                )
+            })?
+            .remote_identity_key()?;
+        let saved_identity = identity_store.get_identity(address).await?;
+        match (remote_identity, saved_identity) {
+            (Some(remote_identity), Some(saved_identity)) if remote_identity == saved_identity => {
+                Ok(record)
+            }
+            _ => Err(SignalProtocolError::UntrustedIdentity(address.clone())),
+        }
+    }
+
     pub(crate) fn has_session_state(
         &self,
         version: u32,
@@ -644,10 +852,12 @@ impl SessionRecord {
     }
 
     pub(crate) fn session_state_mut(&mut self) -> Option<&mut SessionState> {
+        self.mark_modified();
         self.current_session.as_mut()
     }
 
     pub(crate) fn set_session_state(&mut self, session: SessionState) {
+        self.mark_modified();
         self.current_session = Some(session);
     }
 
@@ -667,11 +877,13 @@ impl SessionRecord {
         updated_session: SessionState,
     ) {
         self.previous_sessions.remove(old_session);
+        self.mark_modified();
         self.promote_state(updated_session)
     }
 
     pub(crate) fn promote_state(&mut self, new_state: SessionState) {
         self.archive_current_state_inner();
+        self.mark_modified();
         self.current_session = Some(new_state);
     }
 
@@ -686,6 +898,7 @@ impl SessionRecord {
             current_session.clear_unacknowledged_pre_key_message();
             self.previous_sessions
                 .insert(0, current_session.session.encode_to_vec());
+            self.mark_modified();
             true
         } else {
             false
@@ -699,7 +912,66 @@ impl SessionRecord {
         Ok(())
     }
 
+    /// Zeroes the key material of the current and all archived session states.
+    ///
+    /// The record keeps its metadata (identities, registration IDs, session versions), but can no
+    /// longer be used to encrypt or decrypt messages.
+    pub fn wipe_secrets(&mut self) -> Result<(), SignalProtocolError> {
+        self.mark_modified();
+        if let Some(current_session) = &mut self.current_session {
+            current_session.wipe_secrets();
+        }
+        for previous in &mut self.previous_sessions {
+            let mut state: SessionState = SessionStructure::decode(&previous[..])
+                .map_err(|_| InvalidSessionError("failed to decode previous session protobuf"))?
+                .into();
+            state.wipe_secrets();
+            previous.zeroize();
+            *previous = state.session.encode_to_vec();
+        }
+        Ok(())
+    }
+
+    /// Drops stored message keys and archived states beyond the most recent `keep_messages`
+    /// messages of history.
+    ///
+    /// Message keys are counted starting from the current session, newest first, followed by
+    /// each archived state in order. Once `keep_messages` keys have been kept, any remaining keys
+    /// are dropped, as are any archived states that come after that point. The current session
+    /// itself is always kept.
+    pub fn trim_history(&mut self, keep_messages: usize) -> Result<(), SignalProtocolError> {
+        self.mark_modified();
+        let mut remaining = keep_messages;
+        if let Some(current_session) = &mut self.current_session {
+            remaining -= current_session.trim_message_keys(remaining);
+        }
+
+        let mut trimmed_sessions = Vec::with_capacity(self.previous_sessions.len());
+        for previous in &mut self.previous_sessions {
+            if remaining == 0 {
+                previous.zeroize();
+                continue;
+            }
+            let mut state: SessionState = SessionStructure::decode(&previous[..])
+                .map_err(|_| InvalidSessionError("failed to decode previous session protobuf"))?
+                .into();
+            remaining -= state.trim_message_keys(remaining);
+            previous.zeroize();
+            trimmed_sessions.push(state.session.encode_to_vec());
+        }
+        self.previous_sessions = trimmed_sessions;
+        Ok(())
+    }
+
     pub fn serialize(&self) -> Result<Vec<u8>, SignalProtocolError> {
+        let serialized = self.serialize_inner()?;
+        self.needs_persist.store(false, Ordering::Relaxed);
+        Ok(serialized)
+    }
+
+    /// Like [`serialize`](Self::serialize), but for output that isn't being written back to the
+    /// store, so [`needs_persist`](Self::needs_persist) is left alone.
+    fn serialize_inner(&self) -> Result<Vec<u8>, SignalProtocolError> {
         let record = RecordStructure {
             current_session: self.current_session.as_ref().map(|s| s.into()),
             previous_sessions: self.previous_sessions.clone(),
@@ -707,6 +979,62 @@ impl SessionRecord {
         Ok(record.encode_to_vec())
     }
 
+    /// Serializes the record for clients that only support sessions up to `target_version`.
+    ///
+    /// When targeting a pre-Kyber version, any pending Kyber pre-key is stripped from every
+    /// session. Fails if the current session or any archived session uses a newer version than
+    /// `target_version`, since its keys depend on the newer handshake and can't be converted.
+    pub fn serialize_for_version(
+        &self,
+        target_version: u32,
+    ) -> Result<Vec<u8>, SignalProtocolError> {
+        let supported_versions = u32::from(CIPHERTEXT_MESSAGE_PRE_KYBER_VERSION)
+            ..=u32::from(CIPHERTEXT_MESSAGE_CURRENT_VERSION);
+        if !supported_versions.contains(&target_version) {
+            return Err(SignalProtocolError::InvalidArgument(format!(
+                "unsupported session version {}",
+                target_version
+            )));
+        }
+
+        let current_versions = self
+            .current_session
+            .iter()
+            .map(|state| state.session_version());
+        let previous_versions = self
+            .previous_session_states()
+            .map(|state| state?.session_version());
+        for version in current_versions.chain(previous_versions) {
+            let version = version?;
+            if version > target_version {
+                return Err(SignalProtocolError::InvalidState(
+                    "serialize_for_version",
+                    format!(
+                        "cannot represent a version {} session as version {}",
+                        version, target_version
+                    ),
+                ));
+            }
+        }
+
+        if target_version > u32::from(CIPHERTEXT_MESSAGE_PRE_KYBER_VERSION) {
+            return self.serialize_inner();
+        }
+
+        let mut downgraded = self.clone();
+        if let Some(state) = &mut downgraded.current_session {
+            state.session.pending_kyber_pre_key = None;
+        }
+        for previous in &mut downgraded.previous_sessions {
+            let mut session = SessionStructure::decode(&previous[..])
+                .map_err(|_| InvalidSessionError("failed to decode previous session protobuf"))?;
+            session.pending_kyber_pre_key = None;
+            previous.zeroize();
+            *previous = session.encode_to_vec();
+        }
+        downgraded.serialize_inner()
+    }
+
     pub fn remote_registration_id(&self) -> Result<u32, SignalProtocolError> {
         Ok(self
             .session_state()
@@ -764,6 +1092,20 @@ impl SessionRecord {
             .remote_identity_key_bytes()?)
     }
 
+    /// Returns `true` if the record has a current session, whether or not it can still be used.
+    pub fn has_current_session_state(&self) -> bool {
+        self.current_session.is_some()
+    }
+
+    /// Returns `true` if the current session has a sending chain, ignoring whether it has expired.
+    ///
+    /// Use [`Self::has_usable_sender_chain`] to check whether the session can be used to encrypt.
+    pub fn has_sender_chain(&self) -> bool {
+        self.current_session
+            .as_ref()
+            .is_some_and(SessionState::has_sender_chain)
+    }
+
     pub fn has_usable_sender_chain(&self, now: SystemTime) -> Result<bool, SignalProtocolError> {
         match &self.current_session {
             Some(session) => Ok(session.has_usable_sender_chain(now)?),
@@ -818,6 +1160,112 @@ impl SessionRecord {
         }
     }
 
+    /// Returns the counter that will be used for the next message sent in the current session.
+    pub fn sender_counter(&self) -> Result<u32, SignalProtocolError> {
+        Ok(self
+            .session_state()
+            .ok_or_else(|| {
+                SignalProtocolError::InvalidState("sender_counter", "No current session".into())
+            })?
+            .get_sender_chain_key()?
+            .index())
+    }
+
+    /// Returns the ratchet key the current session sends with.
+    ///
+    /// This is the key the peer sees as
+    /// [`SignalMessage::sender_ratchet_key`](crate::SignalMessage::sender_ratchet_key) on the next
+    /// message.
+    pub fn sender_ratchet_key(&self) -> Result<PublicKey, SignalProtocolError> {
+        Ok(self
+            .session_state()
+            .ok_or_else(|| {
+                SignalProtocolError::InvalidState("sender_ratchet_key", "No current session".into())
+            })?
+            .sender_ratchet_key()?)
+    }
+
+    /// Returns the counter of the next message expected on the current session's most recent
+    /// receiving chain.
+    ///
+    /// This is an error if the current session doesn't have a receiving chain yet.
+    pub fn receiver_counter(&self) -> Result<u32, SignalProtocolError> {
+        Ok(self
+            .session_state()
+            .ok_or_else(|| {
+                SignalProtocolError::InvalidState("receiver_counter", "No current session".into())
+            })?
+            .receiver_counter()?)
+    }
+
+    /// Returns `true` if the current receiving chain is unbroken between two received counters.
+    ///
+    /// After receiving message `from_counter` and then message `to_counter`, the keys for every
+    /// message in between should still be waiting for their (possibly delayed) messages. If any of
+    /// them are missing, or the chain never reached `to_counter`, the chain was not continuous.
+    pub fn verify_chain_continuity(
+        &self,
+        from_counter: u32,
+        to_counter: u32,
+    ) -> Result<bool, SignalProtocolError> {
+        Ok(self
+            .session_state()
+            .ok_or_else(|| {
+                SignalProtocolError::InvalidState(
+                    "verify_chain_continuity",
+                    "No current session".into(),
+                )
+            })?
+            .receiver_chain_is_continuous(from_counter, to_counter)?)
+    }
+
+    /// Returns SHA-256 commitments to the current session's root key and chain keys.
+    ///
+    /// The first commitment is for the root key, followed by the sender chain and then each
+    /// receiver chain. Two parties can compare commitments to check that they share chain keys
+    /// without revealing them: Alice's sender chain matches Bob's receiver chain for Alice's
+    /// ratchet key once Bob has caught up to the same message counter.
+    pub fn key_commitments(&self) -> Result<Vec<[u8; 32]>, SignalProtocolError> {
+        Ok(self
+            .session_state()
+            .ok_or_else(|| {
+                SignalProtocolError::InvalidState("key_commitments", "No current session".into())
+            })?
+            .key_commitments()?)
+    }
+
+    /// Fills `output` with a key for ephemeral signals such as typing indicators.
+    ///
+    /// The key is derived from the current session's root key with its own label, so it is
+    /// distinct from every message key. It stays the same until the ratchet next advances.
+    pub fn derive_ephemeral_signal_key(
+        &self,
+        output: &mut [u8],
+    ) -> Result<(), SignalProtocolError> {
+        let root_key = self
+            .session_state()
+            .ok_or_else(|| {
+                SignalProtocolError::InvalidState(
+                    "derive_ephemeral_signal_key",
+                    "No current session".into(),
+                )
+            })?
+            .root_key()?;
+        hkdf::Hkdf::<sha2::Sha256>::new(None, root_key.key())
+            .expand(b"Signal_EphemeralSignalKey", output)
+            .map_err(|_| {
+                SignalProtocolError::InvalidArgument(format!("output too long ({})", output.len()))
+            })
+    }
+
+    /// Returns a SHA-256 hash of the serialized record, including archived sessions.
+    ///
+    /// Two copies of the same record always hash the same, so processes sharing a store can
+    /// compare hashes to detect when their copies have diverged.
+    pub fn state_hash(&self) -> Result<[u8; 32], SignalProtocolError> {
+        Ok(Sha256::digest(self.serialize_inner()?).into())
+    }
+
     pub fn get_kyber_ciphertext(&self) -> Result<Option<&Vec<u8>>, SignalProtocolError> {
         Ok(self
             .session_state()
@@ -38,6 +38,51 @@ fn group_no_send_session() -> Result<(), SignalProtocolError> {
     Ok(())
 }
 
+#[test]
+fn group_empty_sender_key_record() -> Result<(), SignalProtocolError> {
+    async {
+        let mut csprng = OsRng;
+
+        let sender_address = ProtocolAddress::new("+14159999111".to_owned(), 1.into());
+        let distribution_id = Uuid::from_u128(0xd1d1d1d1_7000_11eb_b32a_33b8a8a487a6);
+
+        let empty = SenderKeyRecord::new_empty();
+        assert!(empty.is_empty());
+        assert!(SenderKeyRecord::deserialize(&empty.serialize()?)?.is_empty());
+
+        let mut alice_store = test_in_memory_protocol_store()?;
+        let mut bob_store = test_in_memory_protocol_store()?;
+
+        let sent_distribution_message = create_sender_key_distribution_message(
+            &sender_address,
+            distribution_id,
+            &mut alice_store,
+            &mut csprng,
+        )
+        .await?;
+
+        bob_store
+            .store_sender_key(&sender_address, distribution_id, &empty)
+            .await?;
+        process_sender_key_distribution_message(
+            &sender_address,
+            &SenderKeyDistributionMessage::try_from(sent_distribution_message.serialized())?,
+            &mut bob_store,
+        )
+        .await?;
+
+        let record = bob_store
+            .load_sender_key(&sender_address, distribution_id)
+            .await?
+            .expect("stored");
+        assert!(!record.is_empty());
+
+        Ok(())
+    }
+    .now_or_never()
+    .expect("sync")
+}
+
 #[test]
 fn group_no_recv_session() -> Result<(), SignalProtocolError> {
     async {
@@ -141,6 +186,110 @@ fn group_basic_encrypt_decrypt() -> Result<(), SignalProtocolError> {
     .expect("sync")
 }
 
+#[test]
+fn group_encrypt_decrypt_with_expiry() -> Result<(), SignalProtocolError> {
+    async {
+        let mut csprng = OsRng;
+
+        let sender_address = ProtocolAddress::new("+14159999111".to_owned(), 1.into());
+        let distribution_id = Uuid::from_u128(0xd1d1d1d1_7000_11eb_b32a_33b8a8a487a6);
+
+        let mut alice_store = test_in_memory_protocol_store()?;
+        let mut bob_store = test_in_memory_protocol_store()?;
+
+        let sent_distribution_message = create_sender_key_distribution_message(
+            &sender_address,
+            distribution_id,
+            &mut alice_store,
+            &mut csprng,
+        )
+        .await?;
+        let recv_distribution_message =
+            SenderKeyDistributionMessage::try_from(sent_distribution_message.serialized())?;
+        process_sender_key_distribution_message(
+            &sender_address,
+            &recv_distribution_message,
+            &mut bob_store,
+        )
+        .await?;
+
+        let expiry_seconds = 7 * 24 * 60 * 60;
+        let alice_ciphertext = group_encrypt_with_expiry(
+            &mut alice_store,
+            &sender_address,
+            distribution_id,
+            "space camp?".as_bytes(),
+            expiry_seconds,
+            &mut csprng,
+        )
+        .await?;
+
+        let (bob_plaintext, bob_expiry_seconds) = group_decrypt_with_expiry(
+            alice_ciphertext.serialized(),
+            &mut bob_store,
+            &sender_address,
+        )
+        .await?;
+        assert_eq!(
+            String::from_utf8(bob_plaintext).expect("valid utf8"),
+            "space camp?"
+        );
+        assert_eq!(bob_expiry_seconds, Some(expiry_seconds));
+
+        // A plain group message has no timer, even if it is long enough to hold one, and its
+        // plaintext comes through untouched.
+        for plaintext in [
+            vec![1, 2, 3],
+            b"space camp?".to_vec(),
+            vec![0, 0, 0, 60, 1, 2, 3],
+        ] {
+            let unmarked_ciphertext = group_encrypt(
+                &mut alice_store,
+                &sender_address,
+                distribution_id,
+                &plaintext,
+                &mut csprng,
+            )
+            .await?;
+            assert_eq!(
+                group_decrypt_with_expiry(
+                    unmarked_ciphertext.serialized(),
+                    &mut bob_store,
+                    &sender_address
+                )
+                .await?,
+                (plaintext, None)
+            );
+        }
+
+        // A marked message with a malformed wrapper is rejected.
+        let malformed_ciphertext = group_encrypt(
+            &mut alice_store,
+            &sender_address,
+            distribution_id,
+            b"\xFFexpiry\x01\xFF",
+            &mut csprng,
+        )
+        .await?;
+        assert!(matches!(
+            group_decrypt_with_expiry(
+                malformed_ciphertext.serialized(),
+                &mut bob_store,
+                &sender_address
+            )
+            .await,
+            Err(SignalProtocolError::InvalidMessage(
+                CiphertextMessageType::SenderKey,
+                _
+            ))
+        ));
+
+        Ok(())
+    }
+    .now_or_never()
+    .expect("sync")
+}
+
 #[test]
 fn group_sealed_sender() -> Result<(), SignalProtocolError> {
     async {
@@ -1071,6 +1220,152 @@ fn group_late_join() -> Result<(), SignalProtocolError> {
     .expect("sync")
 }
 
+#[test]
+fn group_distribution_message_tracks_sender_state() -> Result<(), SignalProtocolError> {
+    async {
+        let mut csprng = OsRng;
+
+        let sender_address = ProtocolAddress::new("+14159999111".to_owned(), 1.into());
+        let distribution_id = Uuid::from_u128(0xd1d1d1d1_7000_11eb_b32a_33b8a8a487a6);
+
+        let mut alice_store = test_in_memory_protocol_store()?;
+
+        let first_distribution_message = create_sender_key_distribution_message(
+            &sender_address,
+            distribution_id,
+            &mut alice_store,
+            &mut csprng,
+        )
+        .await?;
+        assert_eq!(
+            first_distribution_message.distribution_id()?,
+            distribution_id
+        );
+        assert_eq!(first_distribution_message.iteration()?, 0);
+
+        let alice_ciphertext = group_encrypt(
+            &mut alice_store,
+            &sender_address,
+            distribution_id,
+            "space camp?".as_bytes(),
+            &mut csprng,
+        )
+        .await?;
+        assert_eq!(
+            alice_ciphertext.chain_id(),
+            first_distribution_message.chain_id()?
+        );
+        assert_eq!(
+            alice_ciphertext.iteration(),
+            first_distribution_message.iteration()?
+        );
+
+        // A later distribution message reuses the same chain, but starts where the sender is now.
+        let second_distribution_message = create_sender_key_distribution_message(
+            &sender_address,
+            distribution_id,
+            &mut alice_store,
+            &mut csprng,
+        )
+        .await?;
+        assert_eq!(
+            second_distribution_message.chain_id()?,
+            first_distribution_message.chain_id()?
+        );
+        assert_eq!(second_distribution_message.iteration()?, 1);
+
+        // The getters survive a serialization round trip.
+        let recv_distribution_message =
+            SenderKeyDistributionMessage::try_from(second_distribution_message.serialized())?;
+        assert_eq!(
+            recv_distribution_message.chain_id()?,
+            second_distribution_message.chain_id()?
+        );
+        assert_eq!(recv_distribution_message.iteration()?, 1);
+
+        Ok(())
+    }
+    .now_or_never()
+    .expect("sync")
+}
+
+#[test]
+fn group_reprocess_distribution_message() -> Result<(), SignalProtocolError> {
+    async {
+        let mut csprng = OsRng;
+
+        let sender_address = ProtocolAddress::new("+14159999111".to_owned(), 1.into());
+        let distribution_id = Uuid::from_u128(0xd1d1d1d1_7000_11eb_b32a_33b8a8a487a6);
+
+        let mut alice_store = test_in_memory_protocol_store()?;
+        let mut bob_store = test_in_memory_protocol_store()?;
+
+        let sent_distribution_message = create_sender_key_distribution_message(
+            &sender_address,
+            distribution_id,
+            &mut alice_store,
+            &mut csprng,
+        )
+        .await?;
+
+        let recv_distribution_message =
+            SenderKeyDistributionMessage::try_from(sent_distribution_message.serialized())?;
+
+        process_sender_key_distribution_message(
+            &sender_address,
+            &recv_distribution_message,
+            &mut bob_store,
+        )
+        .await?;
+
+        let mut ciphertexts = Vec::new();
+        for i in 0..3 {
+            ciphertexts.push(
+                group_encrypt(
+                    &mut alice_store,
+                    &sender_address,
+                    distribution_id,
+                    format!("message {}", i).as_bytes(),
+                    &mut csprng,
+                )
+                .await?,
+            );
+        }
+
+        let bob_plaintext =
+            group_decrypt(ciphertexts[2].serialized(), &mut bob_store, &sender_address).await?;
+        assert_eq!(
+            String::from_utf8(bob_plaintext).expect("valid utf8"),
+            "message 2"
+        );
+
+        // Processing the same distribution message again must not rewind Bob's chain.
+        process_sender_key_distribution_message(
+            &sender_address,
+            &recv_distribution_message,
+            &mut bob_store,
+        )
+        .await?;
+
+        assert!(matches!(
+            group_decrypt(ciphertexts[2].serialized(), &mut bob_store, &sender_address).await,
+            Err(SignalProtocolError::DuplicatedMessage(3, 2))
+        ));
+
+        // ...or throw away the keys saved for skipped messages.
+        let bob_plaintext =
+            group_decrypt(ciphertexts[0].serialized(), &mut bob_store, &sender_address).await?;
+        assert_eq!(
+            String::from_utf8(bob_plaintext).expect("valid utf8"),
+            "message 0"
+        );
+
+        Ok(())
+    }
+    .now_or_never()
+    .expect("sync")
+}
+
 #[test]
 fn group_out_of_order() -> Result<(), SignalProtocolError> {
     async {
@@ -1140,6 +1435,98 @@ fn group_out_of_order() -> Result<(), SignalProtocolError> {
     .expect("sync")
 }
 
+#[test]
+fn group_decrypt_respects_max_jump() -> Result<(), SignalProtocolError> {
+    async {
+        let mut csprng = OsRng;
+
+        let sender_address = ProtocolAddress::new("+14159999111".to_owned(), 1.into());
+        let distribution_id = Uuid::from_u128(0xd1d1d1d1_7000_11eb_b32a_33b8a8a487a6);
+
+        let mut alice_store = test_in_memory_protocol_store()?;
+        let mut bob_store = test_in_memory_protocol_store()?;
+
+        let sent_distribution_message = create_sender_key_distribution_message(
+            &sender_address,
+            distribution_id,
+            &mut alice_store,
+            &mut csprng,
+        )
+        .await?;
+
+        let recv_distribution_message =
+            SenderKeyDistributionMessage::try_from(sent_distribution_message.serialized())?;
+
+        process_sender_key_distribution_message(
+            &sender_address,
+            &recv_distribution_message,
+            &mut bob_store,
+        )
+        .await?;
+
+        for i in 0..10 {
+            group_encrypt(
+                &mut alice_store,
+                &sender_address,
+                distribution_id,
+                format!("nefarious plotting {}", i).as_bytes(),
+                &mut csprng,
+            )
+            .await?;
+        }
+
+        let alice_ciphertext = group_encrypt(
+            &mut alice_store,
+            &sender_address,
+            distribution_id,
+            "you got the plan?".as_bytes(),
+            &mut csprng,
+        )
+        .await?;
+        assert_eq!(alice_ciphertext.iteration(), 10);
+
+        let record_before = bob_store
+            .load_sender_key(&sender_address, distribution_id)
+            .await?
+            .expect("present")
+            .serialize()?;
+
+        assert!(matches!(
+            group_decrypt_with_max_jump(
+                alice_ciphertext.serialized(),
+                &mut bob_store,
+                &sender_address,
+                9,
+            )
+            .await,
+            Err(SignalProtocolError::IterationTooLarge(0, 10))
+        ));
+
+        // No message keys were derived for the rejected message.
+        let record_after = bob_store
+            .load_sender_key(&sender_address, distribution_id)
+            .await?
+            .expect("present")
+            .serialize()?;
+        assert_eq!(record_before, record_after);
+
+        assert_eq!(
+            group_decrypt_with_max_jump(
+                alice_ciphertext.serialized(),
+                &mut bob_store,
+                &sender_address,
+                10,
+            )
+            .await?,
+            b"you got the plan?"
+        );
+
+        Ok(())
+    }
+    .now_or_never()
+    .expect("sync")
+}
+
 #[test]
 #[ignore = "slow to run locally"]
 fn group_too_far_in_the_future() -> Result<(), SignalProtocolError> {
@@ -133,6 +133,308 @@ fn test_sender_cert() -> Result<(), SignalProtocolError> {
     Ok(())
 }
 
+#[test]
+fn test_sender_cert_pinned_server_key() -> Result<(), SignalProtocolError> {
+    let mut rng = OsRng;
+    let trust_root = KeyPair::generate(&mut rng);
+    let pinned_server_key = KeyPair::generate(&mut rng);
+    let other_server_key = KeyPair::generate(&mut rng);
+    let key = KeyPair::generate(&mut rng);
+
+    let device_id: DeviceId = 42.into();
+    let expires = Timestamp::from_epoch_millis(1605722925);
+
+    let sender_cert_from = |server_key: &KeyPair, rng: &mut OsRng| {
+        let server_cert =
+            ServerCertificate::new(1, server_key.public_key, &trust_root.private_key, rng)?;
+        SenderCertificate::new(
+            "9d0652a3-dcc3-4d11-975f-74d61598733f".to_string(),
+            Some("+14152222222".to_string()),
+            key.public_key,
+            device_id,
+            expires,
+            server_cert,
+            &server_key.private_key,
+            rng,
+        )
+    };
+
+    let pinned_cert = sender_cert_from(&pinned_server_key, &mut rng)?;
+    assert!(pinned_cert.validate_with_pinned_server_key(&pinned_server_key.public_key, expires)?);
+    // Expired.
+    assert!(!pinned_cert
+        .validate_with_pinned_server_key(&pinned_server_key.public_key, expires.add_millis(1))?);
+
+    // Signed by the same trust root, but not by the pinned server.
+    let other_cert = sender_cert_from(&other_server_key, &mut rng)?;
+    assert!(other_cert.validate(&trust_root.public_key, expires)?);
+    assert!(!other_cert.validate_with_pinned_server_key(&pinned_server_key.public_key, expires)?);
+
+    Ok(())
+}
+
+#[test]
+fn test_sealed_sender_rejects_truncated_envelope() -> Result<(), SignalProtocolError> {
+    async {
+        let mut rng = OsRng;
+
+        let alice_uuid = "9d0652a3-dcc3-4d11-975f-74d61598733f".to_string();
+        let bob_uuid_address =
+            ProtocolAddress::new("796abedb-ca4e-4f18-8803-1fde5b921f9f".to_owned(), 42.into());
+
+        let mut alice_store = support::test_in_memory_protocol_store()?;
+        let mut bob_store = support::test_in_memory_protocol_store()?;
+
+        let alice_pubkey = *alice_store.get_identity_key_pair().await?.public_key();
+
+        let bob_pre_key_bundle = create_pre_key_bundle(&mut bob_store, &mut rng).await?;
+
+        process_prekey_bundle(
+            &bob_uuid_address,
+            &mut alice_store.session_store,
+            &mut alice_store.identity_store,
+            &bob_pre_key_bundle,
+            SystemTime::now(),
+            &mut rng,
+        )
+        .await?;
+
+        let trust_root = KeyPair::generate(&mut rng);
+        let server_key = KeyPair::generate(&mut rng);
+
+        let server_cert =
+            ServerCertificate::new(1, server_key.public_key, &trust_root.private_key, &mut rng)?;
+
+        let sender_cert = SenderCertificate::new(
+            alice_uuid,
+            None,
+            alice_pubkey,
+            23.into(),
+            Timestamp::from_epoch_millis(1605722925),
+            server_cert,
+            &server_key.private_key,
+            &mut rng,
+        )?;
+
+        let alice_ctext = sealed_sender_encrypt(
+            &bob_uuid_address,
+            &sender_cert,
+            &[1, 2, 3],
+            &mut alice_store.session_store,
+            &mut alice_store.identity_store,
+            SystemTime::now(),
+            &mut rng,
+        )
+        .await?;
+
+        // The complete envelope is fine...
+        sealed_sender_decrypt_to_usmc(&alice_ctext, &bob_store.identity_store).await?;
+
+        // ...but any truncation of it is rejected as malformed.
+        assert!(matches!(
+            sealed_sender_decrypt_to_usmc(&[], &bob_store.identity_store).await,
+            Err(SignalProtocolError::InvalidSealedSenderMessage(_))
+        ));
+        for len in [1, alice_ctext.len() / 2, alice_ctext.len() - 1] {
+            let result =
+                sealed_sender_decrypt_to_usmc(&alice_ctext[..len], &bob_store.identity_store).await;
+            assert!(
+                matches!(
+                    result,
+                    Err(SignalProtocolError::InvalidProtobufEncoding
+                        | SignalProtocolError::InvalidSealedSenderMessage(_))
+                ),
+                "length {len}: {:?}",
+                result.err()
+            );
+        }
+
+        Ok(())
+    }
+    .now_or_never()
+    .expect("sync")
+}
+
+#[test]
+fn test_sealed_sender_envelope_fingerprint() -> Result<(), SignalProtocolError> {
+    async {
+        let mut rng = OsRng;
+
+        let alice_uuid = "9d0652a3-dcc3-4d11-975f-74d61598733f".to_string();
+        let bob_uuid_address =
+            ProtocolAddress::new("796abedb-ca4e-4f18-8803-1fde5b921f9f".to_owned(), 42.into());
+
+        let mut alice_store = support::test_in_memory_protocol_store()?;
+        let mut bob_store = support::test_in_memory_protocol_store()?;
+
+        let alice_pubkey = *alice_store.get_identity_key_pair().await?.public_key();
+
+        let bob_pre_key_bundle = create_pre_key_bundle(&mut bob_store, &mut rng).await?;
+
+        process_prekey_bundle(
+            &bob_uuid_address,
+            &mut alice_store.session_store,
+            &mut alice_store.identity_store,
+            &bob_pre_key_bundle,
+            SystemTime::now(),
+            &mut rng,
+        )
+        .await?;
+
+        let trust_root = KeyPair::generate(&mut rng);
+        let server_key = KeyPair::generate(&mut rng);
+
+        let server_cert =
+            ServerCertificate::new(1, server_key.public_key, &trust_root.private_key, &mut rng)?;
+
+        let sender_cert = SenderCertificate::new(
+            alice_uuid,
+            None,
+            alice_pubkey,
+            23.into(),
+            Timestamp::from_epoch_millis(1605722925),
+            server_cert,
+            &server_key.private_key,
+            &mut rng,
+        )?;
+
+        let alice_ctext = sealed_sender_encrypt(
+            &bob_uuid_address,
+            &sender_cert,
+            &[1, 2, 3],
+            &mut alice_store.session_store,
+            &mut alice_store.identity_store,
+            SystemTime::now(),
+            &mut rng,
+        )
+        .await?;
+
+        // An identical upload fingerprints identically...
+        let fingerprint = sealed_sender_envelope_fingerprint(&alice_ctext)?;
+        assert_eq!(
+            fingerprint,
+            sealed_sender_envelope_fingerprint(&alice_ctext.clone())?
+        );
+
+        // ...but any change to the envelope changes the fingerprint.
+        let mut modified_ctext = alice_ctext.clone();
+        *modified_ctext.last_mut().expect("not empty") ^= 1;
+        assert_ne!(
+            fingerprint,
+            sealed_sender_envelope_fingerprint(&modified_ctext)?
+        );
+
+        assert!(matches!(
+            sealed_sender_envelope_fingerprint(&[]),
+            Err(SignalProtocolError::InvalidSealedSenderMessage(_))
+        ));
+
+        Ok(())
+    }
+    .now_or_never()
+    .expect("sync")
+}
+
+#[test]
+fn test_sealed_sender_decrypt_stream() -> Result<(), SignalProtocolError> {
+    async {
+        let mut rng = OsRng;
+
+        let alice_uuid = "9d0652a3-dcc3-4d11-975f-74d61598733f".to_string();
+        let bob_uuid_address =
+            ProtocolAddress::new("796abedb-ca4e-4f18-8803-1fde5b921f9f".to_owned(), 42.into());
+
+        let mut alice_store = support::test_in_memory_protocol_store()?;
+        let mut bob_store = support::test_in_memory_protocol_store()?;
+
+        let alice_pubkey = *alice_store.get_identity_key_pair().await?.public_key();
+
+        let bob_pre_key_bundle = create_pre_key_bundle(&mut bob_store, &mut rng).await?;
+
+        process_prekey_bundle(
+            &bob_uuid_address,
+            &mut alice_store.session_store,
+            &mut alice_store.identity_store,
+            &bob_pre_key_bundle,
+            SystemTime::now(),
+            &mut rng,
+        )
+        .await?;
+
+        let trust_root = KeyPair::generate(&mut rng);
+        let server_key = KeyPair::generate(&mut rng);
+
+        let server_cert =
+            ServerCertificate::new(1, server_key.public_key, &trust_root.private_key, &mut rng)?;
+
+        let sender_cert = SenderCertificate::new(
+            alice_uuid,
+            None,
+            alice_pubkey,
+            23.into(),
+            Timestamp::from_epoch_millis(1605722925),
+            server_cert,
+            &server_key.private_key,
+            &mut rng,
+        )?;
+
+        let payload: Vec<u8> = (0..4 * 1024 * 1024).map(|i| (i % 251) as u8).collect();
+        let usmc = UnidentifiedSenderMessageContent::new(
+            CiphertextMessageType::Plaintext,
+            sender_cert,
+            payload,
+            ContentHint::Default,
+            None,
+        )?;
+        let alice_ctext = sealed_sender_encrypt_from_usmc(
+            &bob_uuid_address,
+            &usmc,
+            &alice_store.identity_store,
+            &mut rng,
+        )
+        .await?;
+
+        let one_shot = sealed_sender_decrypt_to_usmc(&alice_ctext, &bob_store.identity_store)
+            .await?
+            .serialized()?
+            .to_vec();
+
+        let bob_identity = bob_store.get_identity_key_pair().await?;
+        let stream_decrypt = |ciphertext: &[u8]| -> Result<Vec<u8>, SignalProtocolError> {
+            let mut stream = SealedSenderDecryptStream::new(bob_identity);
+            for chunk in ciphertext.chunks(4093) {
+                stream.update(chunk)?;
+            }
+            stream.finalize()
+        };
+
+        assert_eq!(stream_decrypt(&alice_ctext)?, one_shot);
+
+        // A bad MAC is only caught at the end, and no plaintext is released.
+        let mut modified_ctext = alice_ctext.clone();
+        *modified_ctext.last_mut().expect("not empty") ^= 1;
+        assert!(matches!(
+            stream_decrypt(&modified_ctext),
+            Err(SignalProtocolError::SealedSenderMacVerificationFailed)
+        ));
+
+        let mut stream = SealedSenderDecryptStream::new(bob_identity);
+        stream.update(&alice_ctext[..alice_ctext.len() - 1])?;
+        assert!(matches!(
+            stream.finalize(),
+            Err(SignalProtocolError::InvalidSealedSenderMessage(_))
+        ));
+        assert!(matches!(
+            stream.update(&alice_ctext[alice_ctext.len() - 1..]),
+            Err(SignalProtocolError::InvalidState(..))
+        ));
+
+        Ok(())
+    }
+    .now_or_never()
+    .expect("sync")
+}
+
 #[test]
 fn test_sealed_sender() -> Result<(), SignalProtocolError> {
     async {
@@ -301,6 +603,113 @@ fn test_sealed_sender() -> Result<(), SignalProtocolError> {
     .expect("sync")
 }
 
+#[test]
+fn test_sealed_sender_uuid_only() -> Result<(), SignalProtocolError> {
+    async {
+        let mut rng = OsRng;
+
+        let alice_device_id: DeviceId = 23.into();
+        let bob_device_id: DeviceId = 42.into();
+
+        let alice_uuid = "9d0652a3-dcc3-4d11-975f-74d61598733f".to_string();
+        let bob_uuid = "796abedb-ca4e-4f18-8803-1fde5b921f9f".to_string();
+
+        let bob_uuid_address = ProtocolAddress::new(bob_uuid.clone(), bob_device_id);
+
+        let mut alice_store = support::test_in_memory_protocol_store()?;
+        let mut bob_store = support::test_in_memory_protocol_store()?;
+
+        let alice_pubkey = *alice_store.get_identity_key_pair().await?.public_key();
+
+        let bob_pre_key_bundle = create_pre_key_bundle(&mut bob_store, &mut rng).await?;
+
+        process_prekey_bundle(
+            &bob_uuid_address,
+            &mut alice_store.session_store,
+            &mut alice_store.identity_store,
+            &bob_pre_key_bundle,
+            SystemTime::now(),
+            &mut rng,
+        )
+        .await?;
+
+        let trust_root = KeyPair::generate(&mut rng);
+        let server_key = KeyPair::generate(&mut rng);
+
+        let server_cert =
+            ServerCertificate::new(1, server_key.public_key, &trust_root.private_key, &mut rng)?;
+
+        let expires = Timestamp::from_epoch_millis(1605722925);
+
+        let sender_cert = SenderCertificate::new(
+            alice_uuid.clone(),
+            None,
+            alice_pubkey,
+            alice_device_id,
+            expires,
+            server_cert,
+            &server_key.private_key,
+            &mut rng,
+        )?;
+
+        let alice_ptext = vec![1, 2, 3, 23, 99];
+        let alice_ctext = sealed_sender_encrypt(
+            &bob_uuid_address,
+            &sender_cert,
+            &alice_ptext,
+            &mut alice_store.session_store,
+            &mut alice_store.identity_store,
+            SystemTime::now(),
+            &mut rng,
+        )
+        .await?;
+
+        // Without a local e164, the self-send check still matches on UUID.
+        let self_send = sealed_sender_decrypt(
+            &alice_ctext,
+            &trust_root.public_key,
+            expires.sub_millis(1),
+            None,
+            alice_uuid.clone(),
+            alice_device_id,
+            &mut bob_store.identity_store,
+            &mut bob_store.session_store,
+            &mut bob_store.pre_key_store,
+            &bob_store.signed_pre_key_store,
+            &mut bob_store.kyber_pre_key_store,
+        )
+        .await;
+        assert!(matches!(
+            self_send,
+            Err(SignalProtocolError::SealedSenderSelfSend)
+        ));
+
+        let bob_ptext = sealed_sender_decrypt(
+            &alice_ctext,
+            &trust_root.public_key,
+            expires.sub_millis(1),
+            None,
+            bob_uuid.clone(),
+            bob_device_id,
+            &mut bob_store.identity_store,
+            &mut bob_store.session_store,
+            &mut bob_store.pre_key_store,
+            &bob_store.signed_pre_key_store,
+            &mut bob_store.kyber_pre_key_store,
+        )
+        .await?;
+
+        assert_eq!(bob_ptext.message, alice_ptext);
+        assert_eq!(bob_ptext.sender_uuid, alice_uuid);
+        assert_eq!(bob_ptext.sender_e164, None);
+        assert_eq!(bob_ptext.device_id, alice_device_id);
+
+        Ok(())
+    }
+    .now_or_never()
+    .expect("sync")
+}
+
 #[test]
 fn test_sender_key_in_sealed_sender() -> Result<(), SignalProtocolError> {
     async {
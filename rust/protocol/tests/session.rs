@@ -406,6 +406,10 @@ fn test_bad_signed_pre_key_signature() -> TestResult {
             .is_err());
         }
 
+        // None of the rejected bundles should have left anything behind.
+        assert!(alice_store.load_session(&bob_address).await?.is_none());
+        assert!(alice_store.get_identity(&bob_address).await?.is_none());
+
         // Finally check that the non-corrupted signature is accepted:
         process_prekey_bundle(
             &bob_address,
@@ -423,6 +427,33 @@ fn test_bad_signed_pre_key_signature() -> TestResult {
     .expect("sync")
 }
 
+#[test]
+fn test_verify_signed_pre_key_signature() -> TestResult {
+    let bob_store_builder = TestStoreBuilder::new().with_signed_pre_key(22.into());
+    let good_bundle = bob_store_builder.make_bundle_with_latest_keys(1.into());
+    assert!(good_bundle.verify_signed_pre_key_signature()?);
+
+    let mut bad_signature = good_bundle.signed_pre_key_signature()?.to_vec();
+    bad_signature[0] ^= 0x01;
+    let tampered_bundle = good_bundle
+        .clone()
+        .modify(|content| content.ec_pre_key_signature = Some(bad_signature))?;
+    assert!(!tampered_bundle.verify_signed_pre_key_signature()?);
+
+    let truncated_bundle = good_bundle
+        .clone()
+        .modify(|content| content.ec_pre_key_signature = Some(vec![0; 10]))?;
+    assert!(!truncated_bundle.verify_signed_pre_key_signature()?);
+
+    let other_identity = IdentityKey::new(KeyPair::generate(&mut OsRng).public_key);
+    let mismatched_bundle = good_bundle
+        .clone()
+        .modify(|content| content.identity_key = Some(other_identity))?;
+    assert!(!mismatched_bundle.verify_signed_pre_key_signature()?);
+
+    Ok(())
+}
+
 #[test]
 fn test_repeat_bundle_message() -> TestResult {
     let mut alice_store_builder = TestStoreBuilder::new();
@@ -820,6 +851,1189 @@ fn test_message_key_limits() -> TestResult {
     Ok(())
 }
 
+#[test]
+fn test_chain_continuity_across_gap() -> TestResult {
+    async {
+        let (alice_session_record, bob_session_record) = initialize_sessions_v4()?;
+
+        let alice_address = ProtocolAddress::new("+14159999999".to_owned(), 1.into());
+        let bob_address = ProtocolAddress::new("+14158888888".to_owned(), 1.into());
+
+        let mut alice_store = TestStoreBuilder::new().store;
+        let mut bob_store = TestStoreBuilder::new().store;
+
+        alice_store
+            .store_session(&bob_address, &alice_session_record)
+            .await?;
+        bob_store
+            .store_session(&alice_address, &bob_session_record)
+            .await?;
+
+        let mut inflight = Vec::new();
+        for i in 0..6 {
+            inflight.push(encrypt(&mut alice_store, &bob_address, &format!("msg {}", i)).await?);
+        }
+
+        decrypt(&mut bob_store, &alice_address, &inflight[0]).await?;
+        decrypt(&mut bob_store, &alice_address, &inflight[4]).await?;
+
+        let bob_record = bob_store
+            .load_session(&alice_address)
+            .await?
+            .expect("session found");
+        assert!(bob_record.verify_chain_continuity(0, 4)?);
+        assert!(bob_record.verify_chain_continuity(1, 3)?);
+        // The chain never reached counter 5.
+        assert!(!bob_record.verify_chain_continuity(0, 5)?);
+        assert!(!bob_record.verify_chain_continuity(4, 0)?);
+
+        // Once a key inside the gap is gone, the gap is no longer known to be intact.
+        decrypt(&mut bob_store, &alice_address, &inflight[2]).await?;
+        let bob_record = bob_store
+            .load_session(&alice_address)
+            .await?
+            .expect("session found");
+        assert!(!bob_record.verify_chain_continuity(0, 4)?);
+        assert!(bob_record.verify_chain_continuity(2, 4)?);
+
+        assert!(matches!(
+            SessionRecord::new_fresh().verify_chain_continuity(0, 1),
+            Err(SignalProtocolError::InvalidState(_, _))
+        ));
+
+        Ok(())
+    }
+    .now_or_never()
+    .expect("sync")
+}
+
+#[test]
+fn test_trim_history() -> TestResult {
+    async {
+        let (alice_session_record, bob_session_record) = initialize_sessions_v4()?;
+
+        let alice_address = ProtocolAddress::new("+14159999999".to_owned(), 1.into());
+        let bob_address = ProtocolAddress::new("+14158888888".to_owned(), 1.into());
+
+        let mut alice_store = TestStoreBuilder::new().store;
+        let mut bob_store = TestStoreBuilder::new().store;
+
+        alice_store
+            .store_session(&bob_address, &alice_session_record)
+            .await?;
+        bob_store
+            .store_session(&alice_address, &bob_session_record)
+            .await?;
+
+        let mut inflight = Vec::new();
+        for i in 0..8 {
+            inflight.push(encrypt(&mut alice_store, &bob_address, &format!("msg {}", i)).await?);
+        }
+
+        // Skipping ahead leaves keys for messages 0 through 6 waiting.
+        decrypt(&mut bob_store, &alice_address, &inflight[7]).await?;
+        let bob_record = bob_store
+            .load_session(&alice_address)
+            .await?
+            .expect("session found");
+        assert!(bob_record.verify_chain_continuity(0, 7)?);
+
+        let mut trimmed_record = bob_record.clone();
+        trimmed_record.trim_history(3)?;
+        assert!(trimmed_record.verify_chain_continuity(3, 7)?);
+        assert!(!trimmed_record.verify_chain_continuity(2, 7)?);
+
+        bob_store
+            .store_session(&alice_address, &trimmed_record)
+            .await?;
+        assert_eq!(
+            decrypt(&mut bob_store, &alice_address, &inflight[4]).await?,
+            b"msg 4"
+        );
+        assert!(matches!(
+            decrypt(&mut bob_store, &alice_address, &inflight[3]).await,
+            Err(SignalProtocolError::DuplicatedMessage(_, 3))
+        ));
+
+        // Archived states share the same budget, and are dropped once it runs out.
+        let mut archived_record = bob_record.clone();
+        archived_record.archive_current_state()?;
+        archived_record.trim_history(2)?;
+        bob_store
+            .store_session(&alice_address, &archived_record)
+            .await?;
+        assert_eq!(
+            decrypt(&mut bob_store, &alice_address, &inflight[5]).await?,
+            b"msg 5"
+        );
+        assert!(decrypt(&mut bob_store, &alice_address, &inflight[4])
+            .await
+            .is_err());
+
+        let mut archived_record = bob_record.clone();
+        archived_record.archive_current_state()?;
+        archived_record.trim_history(0)?;
+        bob_store
+            .store_session(&alice_address, &archived_record)
+            .await?;
+        assert!(decrypt(&mut bob_store, &alice_address, &inflight[6])
+            .await
+            .is_err());
+
+        Ok(())
+    }
+    .now_or_never()
+    .expect("sync")
+}
+
+#[test]
+fn test_late_message_promotes_archived_state() -> TestResult {
+    async {
+        let (alice_session_record, bob_session_record) = initialize_sessions_v4()?;
+
+        let alice_address = ProtocolAddress::new("+14159999999".to_owned(), 1.into());
+        let bob_address = ProtocolAddress::new("+14158888888".to_owned(), 1.into());
+
+        let mut alice_store = TestStoreBuilder::new().store;
+        let mut bob_store = TestStoreBuilder::new().store;
+
+        alice_store
+            .store_session(&bob_address, &alice_session_record)
+            .await?;
+        bob_store
+            .store_session(&alice_address, &bob_session_record)
+            .await?;
+
+        let late_message = encrypt(&mut alice_store, &bob_address, "running late").await?;
+
+        let mut archived_record = bob_store
+            .load_session(&alice_address)
+            .await?
+            .expect("session found");
+        archived_record.archive_current_state()?;
+        assert!(!archived_record.has_current_session_state());
+        bob_store
+            .store_session(&alice_address, &archived_record)
+            .await?;
+
+        assert_eq!(
+            decrypt(&mut bob_store, &alice_address, &late_message).await?,
+            b"running late"
+        );
+
+        // The archived state that decrypted the message is current again.
+        let bob_record = bob_store
+            .load_session(&alice_address)
+            .await?
+            .expect("session found");
+        assert!(bob_record.has_current_session_state());
+        let reply = encrypt(&mut bob_store, &alice_address, "no worries").await?;
+        assert_eq!(
+            decrypt(&mut alice_store, &bob_address, &reply).await?,
+            b"no worries"
+        );
+
+        Ok(())
+    }
+    .now_or_never()
+    .expect("sync")
+}
+
+#[test]
+fn test_empty_plaintext_round_trip() -> TestResult {
+    async {
+        let (alice_session_record, bob_session_record) = initialize_sessions_v4()?;
+
+        let alice_address = ProtocolAddress::new("+14159999999".to_owned(), 1.into());
+        let bob_address = ProtocolAddress::new("+14158888888".to_owned(), 1.into());
+
+        let mut alice_store = TestStoreBuilder::new().store;
+        let mut bob_store = TestStoreBuilder::new().store;
+
+        alice_store
+            .store_session(&bob_address, &alice_session_record)
+            .await?;
+        bob_store
+            .store_session(&alice_address, &bob_session_record)
+            .await?;
+
+        let empty = encrypt(&mut alice_store, &bob_address, "").await?;
+        assert!(!empty.serialize().is_empty());
+        assert_eq!(decrypt(&mut bob_store, &alice_address, &empty).await?, b"");
+
+        // The chain keeps advancing normally afterwards.
+        let reply = encrypt(&mut bob_store, &alice_address, "").await?;
+        assert_eq!(decrypt(&mut alice_store, &bob_address, &reply).await?, b"");
+
+        Ok(())
+    }
+    .now_or_never()
+    .expect("sync")
+}
+
+#[test]
+fn test_decrypt_respects_max_skipped_keys() -> TestResult {
+    async {
+        let (alice_session_record, bob_session_record) = initialize_sessions_v3()?;
+
+        let alice_address = ProtocolAddress::new("+14159999999".to_owned(), 1.into());
+        let bob_address = ProtocolAddress::new("+14158888888".to_owned(), 1.into());
+
+        let mut alice_store = TestStoreBuilder::new().store;
+        let mut bob_store = TestStoreBuilder::new().store;
+
+        alice_store
+            .store_session(&bob_address, &alice_session_record)
+            .await?;
+        bob_store
+            .store_session(&alice_address, &bob_session_record)
+            .await?;
+
+        let session_size = |store: &InMemSignalProtocolStore| {
+            store
+                .session_store
+                .load_existing_sessions(&[&alice_address])
+                .expect("session found")[0]
+                .serialize()
+                .expect("can serialize")
+                .len()
+        };
+
+        // Start Bob's receiving chain, so that only skipped keys change the record size below.
+        let first_message = encrypt(&mut alice_store, &bob_address, "hello").await?;
+        assert_eq!(
+            decrypt(&mut bob_store, &alice_address, &first_message).await?,
+            b"hello"
+        );
+        let size_without_skipped_keys = session_size(&bob_store);
+
+        async fn skip_then_encrypt(
+            store: &mut InMemSignalProtocolStore,
+            address: &ProtocolAddress,
+            skipped: usize,
+        ) -> Result<SignalMessage, SignalProtocolError> {
+            for i in 0..skipped {
+                encrypt(store, address, &format!("skipped {i}")).await?;
+            }
+            match encrypt(store, address, "catch up").await? {
+                CiphertextMessage::SignalMessage(m) => Ok(m),
+                other => panic!("unexpected message type {:?}", other.message_type()),
+            }
+        }
+
+        let message = skip_then_encrypt(&mut alice_store, &bob_address, 20).await?;
+        let ptext = message_decrypt_signal_with_max_skipped_keys(
+            &message,
+            &alice_address,
+            &mut bob_store.session_store,
+            &mut bob_store.identity_store,
+            30,
+            &mut OsRng,
+        )
+        .await?;
+        assert_eq!(ptext, b"catch up");
+        let size_with_20_keys = session_size(&bob_store);
+        let bytes_per_key = (size_with_20_keys - size_without_skipped_keys) / 20;
+        assert!(bytes_per_key > 0);
+
+        // The 20 keys already stored count towards the limit too.
+        let message = skip_then_encrypt(&mut alice_store, &bob_address, 15).await?;
+        let result = message_decrypt_signal_with_max_skipped_keys(
+            &message,
+            &alice_address,
+            &mut bob_store.session_store,
+            &mut bob_store.identity_store,
+            30,
+            &mut OsRng,
+        )
+        .await;
+        assert!(matches!(
+            result,
+            Err(SignalProtocolError::TooManySkippedKeys(30))
+        ));
+        // Nothing was cached for the rejected message.
+        assert_eq!(session_size(&bob_store), size_with_20_keys);
+
+        let ptext = message_decrypt_signal_with_max_skipped_keys(
+            &message,
+            &alice_address,
+            &mut bob_store.session_store,
+            &mut bob_store.identity_store,
+            35,
+            &mut OsRng,
+        )
+        .await?;
+        assert_eq!(ptext, b"catch up");
+        assert_eq!(
+            session_size(&bob_store),
+            size_with_20_keys + 15 * bytes_per_key
+        );
+
+        Ok(())
+    }
+    .now_or_never()
+    .expect("sync")
+}
+
+#[test]
+fn test_decrypt_over_plaintext_limit_leaves_session_unchanged() -> TestResult {
+    async {
+        let (alice_session_record, bob_session_record) = initialize_sessions_v3()?;
+
+        let alice_address = ProtocolAddress::new("+14159999999".to_owned(), 1.into());
+        let bob_address = ProtocolAddress::new("+14158888888".to_owned(), 1.into());
+
+        let mut alice_store = TestStoreBuilder::new().store;
+        let mut bob_store = TestStoreBuilder::new().store;
+
+        alice_store
+            .store_session(&bob_address, &alice_session_record)
+            .await?;
+        bob_store
+            .store_session(&alice_address, &bob_session_record)
+            .await?;
+
+        let message = match encrypt(&mut alice_store, &bob_address, "twenty bytes exactly").await? {
+            CiphertextMessage::SignalMessage(m) => m,
+            other => panic!("unexpected message type {:?}", other.message_type()),
+        };
+
+        let serialized_session = |store: &InMemSignalProtocolStore| {
+            store
+                .session_store
+                .load_existing_sessions(&[&alice_address])
+                .expect("session found")[0]
+                .serialize()
+                .expect("can serialize")
+        };
+        let session_before = serialized_session(&bob_store);
+
+        // 4 is rejected from the ciphertext length alone; 19 only once the plaintext is known.
+        for max_plaintext_len in [4, 19] {
+            let result = message_decrypt_signal_with_max_plaintext_len(
+                &message,
+                &alice_address,
+                &mut bob_store.session_store,
+                &mut bob_store.identity_store,
+                max_plaintext_len,
+                &mut OsRng,
+            )
+            .await;
+            assert!(matches!(
+                result,
+                Err(SignalProtocolError::InvalidMessage(
+                    CiphertextMessageType::Whisper,
+                    "plaintext exceeds maximum length"
+                ))
+            ));
+            assert_eq!(serialized_session(&bob_store), session_before);
+            assert_eq!(bob_store.get_identity(&alice_address).await?, None);
+        }
+
+        // The message wasn't consumed, so it can still be read.
+        let ptext = message_decrypt_signal_with_max_plaintext_len(
+            &message,
+            &alice_address,
+            &mut bob_store.session_store,
+            &mut bob_store.identity_store,
+            20,
+            &mut OsRng,
+        )
+        .await?;
+        assert_eq!(ptext, b"twenty bytes exactly");
+
+        Ok(())
+    }
+    .now_or_never()
+    .expect("sync")
+}
+
+#[test]
+fn test_decrypt_reporting_identity_change() -> TestResult {
+    async {
+        let (alice_session_record, bob_session_record) = initialize_sessions_v3()?;
+
+        let alice_address = ProtocolAddress::new("+14159999999".to_owned(), 1.into());
+        let bob_address = ProtocolAddress::new("+14158888888".to_owned(), 1.into());
+
+        let mut alice_store = TestStoreBuilder::new().store;
+        let mut bob_store = TestStoreBuilder::new().store;
+
+        alice_store
+            .store_session(&bob_address, &alice_session_record)
+            .await?;
+        bob_store
+            .store_session(&alice_address, &bob_session_record)
+            .await?;
+
+        let stale_identity = *IdentityKeyPair::generate(&mut OsRng).identity_key();
+        bob_store
+            .save_identity(&alice_address, &stale_identity)
+            .await?;
+
+        let message = match encrypt(&mut alice_store, &bob_address, "new phone").await? {
+            CiphertextMessage::SignalMessage(m) => m,
+            other => panic!("unexpected message type {:?}", other.message_type()),
+        };
+
+        let result = message_decrypt_signal_reporting_identity_change(
+            &message,
+            &alice_address,
+            &mut bob_store.session_store,
+            &mut bob_store.identity_store,
+            false,
+            &mut OsRng,
+        )
+        .await;
+        assert!(matches!(
+            result,
+            Err(SignalProtocolError::UntrustedIdentity(a)) if a == alice_address
+        ));
+
+        let (ptext, identity_changed) = message_decrypt_signal_reporting_identity_change(
+            &message,
+            &alice_address,
+            &mut bob_store.session_store,
+            &mut bob_store.identity_store,
+            true,
+            &mut OsRng,
+        )
+        .await?;
+        assert_eq!(ptext, b"new phone");
+        assert!(identity_changed);
+        // Accepting the new identity is left to the caller.
+        assert_eq!(
+            bob_store.get_identity(&alice_address).await?,
+            Some(stale_identity)
+        );
+
+        Ok(())
+    }
+    .now_or_never()
+    .expect("sync")
+}
+
+#[test]
+fn test_session_record_needs_persist() -> TestResult {
+    async {
+        let (alice_session_record, bob_session_record) = initialize_sessions_v3()?;
+        assert!(bob_session_record.needs_persist());
+
+        let alice_address = ProtocolAddress::new("+14159999999".to_owned(), 1.into());
+        let bob_address = ProtocolAddress::new("+14158888888".to_owned(), 1.into());
+
+        let mut alice_store = TestStoreBuilder::new().store;
+        let mut bob_store = TestStoreBuilder::new().store;
+
+        alice_store
+            .store_session(&bob_address, &alice_session_record)
+            .await?;
+        let loaded = SessionRecord::deserialize(&bob_session_record.serialize()?)?;
+        assert!(!bob_session_record.needs_persist());
+        assert!(!loaded.needs_persist());
+        bob_store.store_session(&alice_address, &loaded).await?;
+
+        let load_bob_record = |store: &InMemSignalProtocolStore| {
+            store
+                .load_session(&alice_address)
+                .now_or_never()
+                .expect("sync")
+                .expect("can load")
+                .expect("session found")
+        };
+
+        // Reading the record doesn't dirty it.
+        let record = load_bob_record(&bob_store);
+        record.session_version()?;
+        record.has_usable_sender_chain(SystemTime::now())?;
+        record.remote_registration_id()?;
+        assert!(!record.needs_persist());
+
+        let message = encrypt(&mut alice_store, &bob_address, "hi bob").await?;
+        assert_eq!(
+            decrypt(&mut bob_store, &alice_address, &message).await?,
+            b"hi bob"
+        );
+        let record = load_bob_record(&bob_store);
+        assert!(record.needs_persist());
+
+        record.serialize()?;
+        assert!(!record.needs_persist());
+
+        Ok(())
+    }
+    .now_or_never()
+    .expect("sync")
+}
+
+/// Recovers the legacy on-disk form of a record with no archived states.
+///
+/// Legacy sessions were stored as a bare `SessionStructure`; the current format wraps that as
+/// field 1 of a `RecordStructure`.
+fn legacy_session_blob(record: &SessionRecord) -> Vec<u8> {
+    let serialized = record.serialize().expect("can serialize");
+    let mut rest = &serialized[..];
+    assert_eq!(
+        prost::encoding::decode_varint(&mut rest).expect("tag"),
+        0x0a
+    );
+    let len = prost::encoding::decode_varint(&mut rest).expect("length");
+    assert_eq!(len as usize, rest.len(), "record has archived states");
+    rest.to_vec()
+}
+
+#[test]
+fn test_deserialize_legacy_session() -> TestResult {
+    async {
+        let (alice_session_record, bob_session_record) = initialize_sessions_v3()?;
+        let legacy = legacy_session_blob(&alice_session_record);
+
+        let migrated = SessionRecord::deserialize_legacy(&legacy)?;
+        assert_eq!(migrated.serialize()?, alice_session_record.serialize()?);
+        assert_eq!(migrated.session_version()?, 3);
+
+        // Each format is rejected by the other's parser, with distinct errors.
+        assert!(matches!(
+            SessionRecord::deserialize(&legacy),
+            Err(SignalProtocolError::InvalidSessionStructure(_))
+        ));
+        assert!(matches!(
+            SessionRecord::deserialize_legacy(&alice_session_record.serialize()?),
+            Err(SignalProtocolError::InvalidProtobufEncoding)
+        ));
+        assert!(matches!(
+            SessionRecord::deserialize_legacy(&[]),
+            Err(SignalProtocolError::InvalidProtobufEncoding)
+        ));
+
+        let alice_address = ProtocolAddress::new("+14159999999".to_owned(), 1.into());
+        let bob_address = ProtocolAddress::new("+14158888888".to_owned(), 1.into());
+
+        let mut alice_store = TestStoreBuilder::new().store;
+        let mut bob_store = TestStoreBuilder::new().store;
+
+        alice_store.store_session(&bob_address, &migrated).await?;
+        bob_store
+            .store_session(&alice_address, &bob_session_record)
+            .await?;
+
+        let message = encrypt(&mut alice_store, &bob_address, "still here").await?;
+        assert_eq!(
+            decrypt(&mut bob_store, &alice_address, &message).await?,
+            b"still here"
+        );
+
+        Ok(())
+    }
+    .now_or_never()
+    .expect("sync")
+}
+
+#[test]
+fn test_serialize_for_older_version() -> TestResult {
+    let (alice_v3_record, _) = initialize_sessions_v3()?;
+    let (alice_v4_record, _) = initialize_sessions_v4()?;
+
+    // A v3-only record can be written for either version, and reads back unchanged.
+    for target_version in [3, 4] {
+        let serialized = alice_v3_record.serialize_for_version(target_version)?;
+        let deserialized = SessionRecord::deserialize(&serialized)?;
+        assert_eq!(deserialized.session_version()?, 3);
+        assert_eq!(deserialized.serialize()?, alice_v3_record.serialize()?);
+    }
+
+    assert!(alice_v4_record.serialize_for_version(4).is_ok());
+    assert!(matches!(
+        alice_v4_record.serialize_for_version(3),
+        Err(SignalProtocolError::InvalidState(_, _))
+    ));
+
+    // Archived sessions count too.
+    let mut archived_record = alice_v4_record.clone();
+    archived_record.archive_current_state()?;
+    assert!(archived_record.serialize_for_version(3).is_err());
+
+    assert!(matches!(
+        alice_v3_record.serialize_for_version(2),
+        Err(SignalProtocolError::InvalidArgument(_))
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn test_serialize_for_older_version_strips_kyber_fields() -> TestResult {
+    let (alice_v3_record, _) = initialize_sessions_v3()?;
+
+    // Graft a PendingKyberPreKey (field 14) onto the v3 session, and use that session as both
+    // the current and an archived state.
+    let mut pending_kyber_pre_key = Vec::new();
+    prost::encoding::uint32::encode(1, &7, &mut pending_kyber_pre_key);
+    prost::encoding::bytes::encode(2, &vec![0xAA; 8], &mut pending_kyber_pre_key);
+    let mut session = legacy_session_blob(&alice_v3_record);
+    prost::encoding::bytes::encode(14, &pending_kyber_pre_key, &mut session);
+    let mut record_bytes = Vec::new();
+    prost::encoding::bytes::encode(1, &session, &mut record_bytes);
+    prost::encoding::bytes::encode(2, &session, &mut record_bytes);
+
+    let record = SessionRecord::deserialize(&record_bytes)?;
+    assert_eq!(record.get_kyber_ciphertext()?, Some(&vec![0xAA; 8]));
+    let count_kyber_pre_keys = |bytes: &[u8]| {
+        bytes
+            .windows(pending_kyber_pre_key.len())
+            .filter(|w| *w == pending_kyber_pre_key)
+            .count()
+    };
+
+    assert_eq!(count_kyber_pre_keys(&record.serialize_for_version(4)?), 2);
+
+    let downgraded_bytes = record.serialize_for_version(3)?;
+    assert_eq!(count_kyber_pre_keys(&downgraded_bytes), 0);
+    let downgraded = SessionRecord::deserialize(&downgraded_bytes)?;
+    assert_eq!(downgraded.session_version()?, 3);
+    assert_eq!(downgraded.get_kyber_ciphertext()?, None);
+    assert_eq!(downgraded.alice_base_key()?, record.alice_base_key()?);
+
+    Ok(())
+}
+
+#[test]
+fn test_session_record_state_getters() -> TestResult {
+    let fresh_record = SessionRecord::new_fresh();
+    assert!(!fresh_record.has_current_session_state());
+    assert!(!fresh_record.has_sender_chain());
+    assert!(fresh_record.session_version().is_err());
+    assert!(matches!(
+        fresh_record.remote_registration_id(),
+        Err(SignalProtocolError::InvalidState(_, _))
+    ));
+
+    let (alice_record, bob_record) = initialize_sessions_v4()?;
+    for record in [&alice_record, &bob_record] {
+        assert!(record.has_current_session_state());
+        assert!(record.has_sender_chain());
+        assert_eq!(record.session_version()?, 4);
+    }
+
+    let mut archived_record = alice_record.clone();
+    archived_record.archive_current_state()?;
+    assert!(!archived_record.has_current_session_state());
+    assert!(!archived_record.has_sender_chain());
+    assert!(archived_record.session_version().is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_ephemeral_signal_key() -> TestResult {
+    let (alice_record, _) = initialize_sessions_v4()?;
+
+    let mut key = [0u8; 32];
+    alice_record.derive_ephemeral_signal_key(&mut key)?;
+    assert_ne!(key, [0u8; 32]);
+    assert_ne!(key.as_slice(), alice_record.get_sender_chain_key_bytes()?);
+
+    let reloaded_record = SessionRecord::deserialize(&alice_record.serialize()?)?;
+    let mut reloaded_key = [0u8; 32];
+    reloaded_record.derive_ephemeral_signal_key(&mut reloaded_key)?;
+    assert_eq!(key, reloaded_key);
+
+    // Shorter outputs are prefixes of longer ones, as with any HKDF expansion.
+    let mut short_key = [0u8; 16];
+    alice_record.derive_ephemeral_signal_key(&mut short_key)?;
+    assert_eq!(short_key, key[..16]);
+
+    assert!(matches!(
+        SessionRecord::new_fresh().derive_ephemeral_signal_key(&mut key),
+        Err(SignalProtocolError::InvalidState(_, _))
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn test_session_record_state_hash() -> TestResult {
+    async {
+        let (alice_session_record, _) = initialize_sessions_v4()?;
+        assert!(alice_session_record.needs_persist());
+        let hash = alice_session_record.state_hash()?;
+        // Neither hashing nor exporting counts as writing the record back.
+        alice_session_record.serialize_for_version(4)?;
+        assert!(alice_session_record.needs_persist());
+
+        let reloaded_record = SessionRecord::deserialize(&alice_session_record.serialize()?)?;
+        assert_eq!(reloaded_record.state_hash()?, hash);
+
+        let bob_address = ProtocolAddress::new("+14158888888".to_owned(), 1.into());
+        let mut alice_store = TestStoreBuilder::new().store;
+        alice_store
+            .store_session(&bob_address, &alice_session_record)
+            .await?;
+        encrypt(&mut alice_store, &bob_address, "diverge").await?;
+
+        let advanced_record = alice_store
+            .load_session(&bob_address)
+            .await?
+            .expect("session found");
+        assert_ne!(advanced_record.state_hash()?, hash);
+
+        Ok(())
+    }
+    .now_or_never()
+    .expect("sync")
+}
+
+#[test]
+#[cfg(feature = "interop-testing")]
+fn test_handshake_transcript_hash_known_answer() -> TestResult {
+    let key_pair = |fill: u8| -> Result<KeyPair, SignalProtocolError> {
+        let private_key = PrivateKey::deserialize(&[fill; 32])?;
+        Ok(KeyPair::new(private_key.public_key()?, private_key))
+    };
+    let alice_identity = IdentityKeyPair::from(key_pair(1)?);
+    let bob_identity = IdentityKeyPair::from(key_pair(2)?);
+    let bob_signed_pre_key = key_pair(3)?;
+    let bob_one_time_pre_key = key_pair(4)?;
+    let alice_base_key = key_pair(5)?;
+
+    for (one_time_pre_key, expected) in [
+        (
+            Some(bob_one_time_pre_key),
+            hex_literal::hex!("e2a4a30425cd0b70530c98a1fe9ff117ccf8ceb787d0e4bd0878f25eea0d7fb0"),
+        ),
+        (
+            None,
+            hex_literal::hex!("d32975e938852153a4d74aa0b89c497d97b8c057ad015930b21edeedd827a970"),
+        ),
+    ] {
+        let mut alice_params = AliceSignalProtocolParameters::new(
+            alice_identity,
+            alice_base_key,
+            *bob_identity.identity_key(),
+            bob_signed_pre_key.public_key,
+            bob_signed_pre_key.public_key,
+        );
+        if let Some(one_time_pre_key) = one_time_pre_key {
+            alice_params.set_their_one_time_pre_key(one_time_pre_key.public_key);
+        }
+        let bob_params = BobSignalProtocolParameters::new(
+            bob_identity,
+            bob_signed_pre_key,
+            one_time_pre_key,
+            bob_signed_pre_key,
+            None,
+            *alice_identity.identity_key(),
+            alice_base_key.public_key,
+            None,
+        );
+
+        // Make sure these parameters really produce a working session.
+        let alice_session = initialize_alice_session_record(&alice_params, &mut OsRng)?;
+        let bob_session = initialize_bob_session_record(&bob_params)?;
+        run_session_interaction(alice_session, bob_session)?;
+
+        assert_eq!(alice_params.transcript_hash(None)?, expected);
+        assert_eq!(bob_params.transcript_hash()?, expected);
+    }
+
+    Ok(())
+}
+
+#[test]
+#[cfg(feature = "interop-testing")]
+fn test_handshake_transcript_hash_covers_kyber() -> TestResult {
+    let mut csprng = OsRng;
+    let alice_identity = IdentityKeyPair::generate(&mut csprng);
+    let bob_identity = IdentityKeyPair::generate(&mut csprng);
+    let alice_base_key = KeyPair::generate(&mut csprng);
+    let bob_signed_pre_key = KeyPair::generate(&mut csprng);
+    let bob_one_time_pre_key = KeyPair::generate(&mut csprng);
+    let bob_kyber_pre_key = kem::KeyPair::generate(kem::KeyType::Kyber1024);
+
+    let alice_params = AliceSignalProtocolParameters::new(
+        alice_identity,
+        alice_base_key,
+        *bob_identity.identity_key(),
+        bob_signed_pre_key.public_key,
+        bob_signed_pre_key.public_key,
+    )
+    .with_their_one_time_pre_key(bob_one_time_pre_key.public_key)
+    .with_their_kyber_pre_key(&bob_kyber_pre_key.public_key);
+    let alice_session = initialize_alice_session_record(&alice_params, &mut csprng)?;
+    let kyber_ciphertext = alice_session
+        .get_kyber_ciphertext()?
+        .expect("has kyber ciphertext")
+        .clone()
+        .into_boxed_slice();
+
+    let bob_params = BobSignalProtocolParameters::new(
+        bob_identity,
+        bob_signed_pre_key,
+        Some(bob_one_time_pre_key),
+        bob_signed_pre_key,
+        Some(bob_kyber_pre_key.clone()),
+        *alice_identity.identity_key(),
+        alice_base_key.public_key,
+        Some(&kyber_ciphertext),
+    );
+    let bob_session = initialize_bob_session_record(&bob_params)?;
+    assert_eq!(bob_session.session_version()?, 4);
+    run_session_interaction(alice_session, bob_session)?;
+
+    let hash = alice_params.transcript_hash(Some(&kyber_ciphertext))?;
+    assert_eq!(bob_params.transcript_hash()?, hash);
+    assert_eq!(
+        handshake_transcript_hash(
+            alice_identity.identity_key(),
+            &alice_base_key.public_key,
+            bob_identity.identity_key(),
+            &bob_signed_pre_key.public_key,
+            Some(&bob_one_time_pre_key.public_key),
+            Some((&bob_kyber_pre_key.public_key, &kyber_ciphertext)),
+        ),
+        hash
+    );
+
+    // The Kyber inputs are part of the hash.
+    let mut other_ciphertext = kyber_ciphertext.to_vec();
+    other_ciphertext[0] ^= 1;
+    assert_ne!(alice_params.transcript_hash(Some(&other_ciphertext))?, hash);
+    assert!(matches!(
+        alice_params.transcript_hash(None),
+        Err(SignalProtocolError::InvalidArgument(_))
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn test_session_record_deserialize_verified() -> TestResult {
+    async {
+        let (alice_session_record, _) = initialize_sessions_v4()?;
+        let serialized = alice_session_record.serialize()?;
+        let bob_identity = IdentityKey::decode(
+            &alice_session_record
+                .remote_identity_key_bytes()?
+                .expect("has remote identity"),
+        )?;
+
+        let bob_address = ProtocolAddress::new("+14158888888".to_owned(), 1.into());
+        let mut alice_store = TestStoreBuilder::new().store;
+
+        // Nothing saved to compare against.
+        assert!(matches!(
+            SessionRecord::deserialize_verified(&serialized, &bob_address, &alice_store).await,
+            Err(SignalProtocolError::UntrustedIdentity(_))
+        ));
+
+        alice_store
+            .save_identity(&bob_address, &bob_identity)
+            .await?;
+        let imported =
+            SessionRecord::deserialize_verified(&serialized, &bob_address, &alice_store).await?;
+        assert_eq!(imported.serialize()?, serialized);
+
+        // The saved identity doesn't match the one in the record.
+        alice_store
+            .save_identity(
+                &bob_address,
+                &IdentityKeyPair::generate(&mut OsRng).identity_key().clone(),
+            )
+            .await?;
+        assert!(matches!(
+            SessionRecord::deserialize_verified(&serialized, &bob_address, &alice_store).await,
+            Err(SignalProtocolError::UntrustedIdentity(_))
+        ));
+
+        Ok(())
+    }
+    .now_or_never()
+    .expect("sync")
+}
+
+#[test]
+fn test_current_ratchet_key_matches() -> TestResult {
+    async {
+        let (alice_session_record, bob_session_record) = initialize_sessions_v4()?;
+
+        let bob_address = ProtocolAddress::new("+14158888888".to_owned(), 1.into());
+
+        let mut alice_store = TestStoreBuilder::new().store;
+        alice_store
+            .store_session(&bob_address, &alice_session_record)
+            .await?;
+
+        let message = match encrypt(&mut alice_store, &bob_address, "ratchet").await? {
+            CiphertextMessage::SignalMessage(message) => message,
+            _ => panic!("expected a SignalMessage"),
+        };
+
+        let alice_record = alice_store
+            .load_session(&bob_address)
+            .await?
+            .expect("session found");
+        assert!(alice_record.current_ratchet_key_matches(message.sender_ratchet_key())?);
+        assert!(
+            !alice_record.current_ratchet_key_matches(&KeyPair::generate(&mut OsRng).public_key)?
+        );
+
+        // Bob's sending ratchet is a different key.
+        assert!(!bob_session_record.current_ratchet_key_matches(message.sender_ratchet_key())?);
+
+        let mut archived_record = alice_record.clone();
+        archived_record.archive_current_state()?;
+        assert!(!archived_record.current_ratchet_key_matches(message.sender_ratchet_key())?);
+
+        Ok(())
+    }
+    .now_or_never()
+    .expect("sync")
+}
+
+#[test]
+fn test_ratchet_state_getters() -> TestResult {
+    async {
+        assert!(matches!(
+            SessionRecord::new_fresh().sender_ratchet_key(),
+            Err(SignalProtocolError::InvalidState(_, _))
+        ));
+        assert!(matches!(
+            SessionRecord::new_fresh().receiver_counter(),
+            Err(SignalProtocolError::InvalidState(_, _))
+        ));
+
+        let (alice_session_record, bob_session_record) = initialize_sessions_v4()?;
+
+        // Alice starts with Bob's ratchet key as a receiving chain; Bob hasn't received anything.
+        assert_eq!(alice_session_record.receiver_counter()?, 0);
+        assert!(matches!(
+            bob_session_record.receiver_counter(),
+            Err(SignalProtocolError::InvalidSessionStructure(_))
+        ));
+
+        let alice_address = ProtocolAddress::new("+14159999999".to_owned(), 1.into());
+        let bob_address = ProtocolAddress::new("+14158888888".to_owned(), 1.into());
+
+        let mut alice_store = TestStoreBuilder::new().store;
+        let mut bob_store = TestStoreBuilder::new().store;
+
+        alice_store
+            .store_session(&bob_address, &alice_session_record)
+            .await?;
+        bob_store
+            .store_session(&alice_address, &bob_session_record)
+            .await?;
+
+        for i in 0..3 {
+            let message = encrypt(&mut bob_store, &alice_address, &format!("msg {}", i)).await?;
+            let CiphertextMessage::SignalMessage(signal_message) = &message else {
+                panic!("expected a SignalMessage");
+            };
+            let bob_record = bob_store
+                .load_session(&alice_address)
+                .await?
+                .expect("session found");
+            assert_eq!(
+                &bob_record.sender_ratchet_key()?,
+                signal_message.sender_ratchet_key()
+            );
+
+            decrypt(&mut alice_store, &bob_address, &message).await?;
+        }
+
+        let alice_record = alice_store
+            .load_session(&bob_address)
+            .await?
+            .expect("session found");
+        assert_eq!(alice_record.receiver_counter()?, 3);
+
+        // Alice's reply carries a ratchet key Bob hasn't seen, so Bob steps his own ratchet.
+        let reply = encrypt(&mut alice_store, &bob_address, "reply").await?;
+        decrypt(&mut bob_store, &alice_address, &reply).await?;
+        let bob_record = bob_store
+            .load_session(&alice_address)
+            .await?
+            .expect("session found");
+        assert_eq!(bob_record.receiver_counter()?, 1);
+        assert_ne!(
+            bob_record.sender_ratchet_key()?,
+            bob_session_record.sender_ratchet_key()?
+        );
+
+        Ok(())
+    }
+    .now_or_never()
+    .expect("sync")
+}
+
+#[test]
+fn test_session_moves_to_new_address() -> TestResult {
+    async {
+        let (alice_session_record, bob_session_record) = initialize_sessions_v4()?;
+
+        // Bob first knows Alice by her PNI, then learns her ACI.
+        let alice_pni_address = ProtocolAddress::new(
+            "PNI:8c78cd2a-16ff-427d-83dc-1a5e36ce713d".to_owned(),
+            1.into(),
+        );
+        let alice_aci_address =
+            ProtocolAddress::new("9d0652a3-dcc3-4d11-975f-74d61598733f".to_owned(), 1.into());
+        let bob_address = ProtocolAddress::new("+14158888888".to_owned(), 1.into());
+
+        let mut alice_store = TestStoreBuilder::new().store;
+        let mut bob_store = TestStoreBuilder::new().store;
+
+        alice_store
+            .store_session(&bob_address, &alice_session_record)
+            .await?;
+        bob_store
+            .store_session(&alice_pni_address, &bob_session_record)
+            .await?;
+
+        let message = encrypt(&mut alice_store, &bob_address, "before").await?;
+        assert_eq!(
+            decrypt(&mut bob_store, &alice_pni_address, &message).await?,
+            b"before"
+        );
+
+        // The record doesn't contain the remote address, so moving it is just storing it again.
+        let record = bob_store
+            .load_session(&alice_pni_address)
+            .await?
+            .expect("session found");
+        bob_store.store_session(&alice_aci_address, &record).await?;
+
+        let message = encrypt(&mut alice_store, &bob_address, "after").await?;
+        assert_eq!(
+            decrypt(&mut bob_store, &alice_aci_address, &message).await?,
+            b"after"
+        );
+
+        Ok(())
+    }
+    .now_or_never()
+    .expect("sync")
+}
+
+#[test]
+fn test_wipe_secrets() -> TestResult {
+    async {
+        let (alice_session_record, bob_session_record) = initialize_sessions_v3()?;
+
+        let alice_address = ProtocolAddress::new("+14159999999".to_owned(), 1.into());
+        let bob_address = ProtocolAddress::new("+14158888888".to_owned(), 1.into());
+
+        let mut alice_store = TestStoreBuilder::new().store;
+        let mut bob_store = TestStoreBuilder::new().store;
+
+        alice_store
+            .store_session(&bob_address, &alice_session_record)
+            .await?;
+        bob_store
+            .store_session(&alice_address, &bob_session_record)
+            .await?;
+
+        let message = encrypt(&mut alice_store, &bob_address, "before the wipe").await?;
+
+        let bob_record = bob_store
+            .load_session(&alice_address)
+            .await?
+            .expect("session found");
+
+        let mut wiped_record = bob_record.clone();
+        wiped_record.wipe_secrets()?;
+        assert_eq!(
+            wiped_record.remote_registration_id()?,
+            bob_record.remote_registration_id()?
+        );
+        assert_eq!(
+            wiped_record.remote_identity_key_bytes()?,
+            bob_record.remote_identity_key_bytes()?
+        );
+        assert!(wiped_record.get_sender_chain_key_bytes().is_err());
+
+        bob_store
+            .store_session(&alice_address, &wiped_record)
+            .await?;
+        let err = decrypt(&mut bob_store, &alice_address, &message)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, SignalProtocolError::InvalidMessage(_, _)));
+
+        // Archived states are wiped too.
+        let mut archived_record = bob_record.clone();
+        archived_record.archive_current_state()?;
+        archived_record.wipe_secrets()?;
+        bob_store
+            .store_session(&alice_address, &archived_record)
+            .await?;
+        let err = decrypt(&mut bob_store, &alice_address, &message)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, SignalProtocolError::InvalidMessage(_, _)));
+
+        Ok(())
+    }
+    .now_or_never()
+    .expect("sync")
+}
+
+#[test]
+fn test_key_commitments_match_across_endpoints() -> TestResult {
+    async {
+        let (alice_session_record, bob_session_record) = initialize_sessions_v4()?;
+
+        let alice_address = ProtocolAddress::new("+14159999999".to_owned(), 1.into());
+        let bob_address = ProtocolAddress::new("+14158888888".to_owned(), 1.into());
+
+        let mut alice_store = TestStoreBuilder::new().store;
+        let mut bob_store = TestStoreBuilder::new().store;
+
+        alice_store
+            .store_session(&bob_address, &alice_session_record)
+            .await?;
+        bob_store
+            .store_session(&alice_address, &bob_session_record)
+            .await?;
+
+        let message = encrypt(&mut alice_store, &bob_address, "commit to this").await?;
+        decrypt(&mut bob_store, &alice_address, &message).await?;
+
+        let alice_commitments = alice_store
+            .load_session(&bob_address)
+            .await?
+            .expect("session found")
+            .key_commitments()?;
+        let bob_commitments = bob_store
+            .load_session(&alice_address)
+            .await?
+            .expect("session found")
+            .key_commitments()?;
+
+        // [root, sender chain, receiver chain] on each side. Bob ratcheted forward when he
+        // received Alice's message, so only Alice's sending chain is shared.
+        assert_eq!(alice_commitments.len(), 3);
+        assert_eq!(bob_commitments.len(), 3);
+        assert_eq!(alice_commitments[1], bob_commitments[2]);
+        assert_ne!(alice_commitments[0], bob_commitments[0]);
+
+        let (unrelated_record, _) = initialize_sessions_v4()?;
+        let unrelated_commitments = unrelated_record.key_commitments()?;
+        assert!(unrelated_commitments
+            .iter()
+            .all(|commitment| !bob_commitments.contains(commitment)));
+
+        assert!(matches!(
+            SessionRecord::new_fresh().key_commitments(),
+            Err(SignalProtocolError::InvalidState(_, _))
+        ));
+
+        Ok(())
+    }
+    .now_or_never()
+    .expect("sync")
+}
+
 #[test]
 fn test_basic_simultaneous_initiate() -> TestResult {
     let mut alice_store_builder = TestStoreBuilder::new()
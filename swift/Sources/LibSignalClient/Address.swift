@@ -16,6 +16,15 @@ public class ProtocolAddress: ClonableHandleOwner {
         self.init(owned: handle!)
     }
 
+    /// Parses the `<name>.<deviceId>` form produced by `description`.
+    ///
+    /// Only the last `.` separates the device ID, so names may themselves contain dots.
+    public convenience init(parsing string: String) throws {
+        var handle: OpaquePointer?
+        try checkError(signal_address_parse(&handle, string))
+        self.init(owned: handle!)
+    }
+
     /// Creates a ProtocolAddress using the **uppercase** string representation of a service ID, for backward compatibility.
     public convenience init(_ serviceId: ServiceId, deviceId: UInt32) {
         do {
@@ -63,6 +72,18 @@ public class ProtocolAddress: ClonableHandleOwner {
     }
 }
 
+extension ProtocolAddress: CustomStringConvertible {
+    public var description: String {
+        return withNativeHandle { nativeHandle in
+            failOnError {
+                try invokeFnReturningString {
+                    signal_address_to_string($0, nativeHandle)
+                }
+            }
+        }
+    }
+}
+
 extension ProtocolAddress: CustomDebugStringConvertible {
     public var debugDescription: String {
         return "\(self.name).\(self.deviceId)"
@@ -24,6 +24,12 @@ public protocol IdentityKeyStore: AnyObject {
     func identity(for address: ProtocolAddress, context: StoreContext) throws -> IdentityKey?
 }
 
+/// An identity store that also tracks whether each saved identity has been verified.
+public protocol IdentityVerificationStore: IdentityKeyStore {
+    /// Only called for addresses with a saved identity.
+    func verificationLevel(for address: ProtocolAddress, context: StoreContext) throws -> IdentityVerificationLevel
+}
+
 public protocol PreKeyStore: AnyObject {
     func loadPreKey(id: UInt32, context: StoreContext) throws -> PreKeyRecord
     func storePreKey(_ record: PreKeyRecord, id: UInt32, context: StoreContext) throws
@@ -13,17 +13,20 @@ public enum SignalError: Error {
     case invalidArgument(String)
     case invalidType(String)
     case invalidUtf8String(String)
+    case bufferTooSmall(String)
     case protobufError(String)
     case legacyCiphertextVersion(String)
     case unknownCiphertextVersion(String)
     case unrecognizedMessageVersion(String)
     case invalidMessage(String)
     case invalidKey(String)
+    case invalidKeyLength(String)
     case invalidSignature(String)
     case invalidAttestationData(String)
     case fingerprintVersionMismatch(String)
     case fingerprintParsingError(String)
     case sealedSenderSelfSend(String)
+    case sealedSenderMacVerificationFailed(String)
     case untrustedIdentity(String)
     case invalidKeyIdentifier(String)
     case sessionNotFound(String)
@@ -31,6 +34,8 @@ public enum SignalError: Error {
     case invalidRegistrationId(address: ProtocolAddress, message: String)
     case invalidSenderKeySession(distributionId: UUID, message: String)
     case duplicatedMessage(String)
+    case iterationTooLarge(String)
+    case tooManySkippedKeys(String)
     case verificationFailed(String)
     case nicknameCannotBeEmpty(String)
     case nicknameCannotStartWithDigit(String)
@@ -94,6 +99,8 @@ internal func checkError(_ error: SignalFfiErrorRef?) throws {
         throw SignalError.invalidType(errStr)
     case SignalErrorCodeInvalidUtf8String:
         throw SignalError.invalidUtf8String(errStr)
+    case SignalErrorCodeBufferTooSmall:
+        throw SignalError.bufferTooSmall(errStr)
     case SignalErrorCodeProtobufError:
         throw SignalError.protobufError(errStr)
     case SignalErrorCodeLegacyCiphertextVersion:
@@ -108,8 +115,12 @@ internal func checkError(_ error: SignalFfiErrorRef?) throws {
         throw SignalError.fingerprintParsingError(errStr)
     case SignalErrorCodeSealedSenderSelfSend:
         throw SignalError.sealedSenderSelfSend(errStr)
+    case SignalErrorCodeSealedSenderMacVerificationFailed:
+        throw SignalError.sealedSenderMacVerificationFailed(errStr)
     case SignalErrorCodeInvalidKey:
         throw SignalError.invalidKey(errStr)
+    case SignalErrorCodeInvalidKeyLength:
+        throw SignalError.invalidKeyLength(errStr)
     case SignalErrorCodeInvalidSignature:
         throw SignalError.invalidSignature(errStr)
     case SignalErrorCodeInvalidAttestationData:
@@ -136,6 +147,10 @@ internal func checkError(_ error: SignalFfiErrorRef?) throws {
         throw SignalError.invalidSenderKeySession(distributionId: distributionId, message: errStr)
     case SignalErrorCodeDuplicatedMessage:
         throw SignalError.duplicatedMessage(errStr)
+    case SignalErrorCodeIterationTooLarge:
+        throw SignalError.iterationTooLarge(errStr)
+    case SignalErrorCodeTooManySkippedKeys:
+        throw SignalError.tooManySkippedKeys(errStr)
     case SignalErrorCodeVerificationFailure:
         throw SignalError.verificationFailed(errStr)
     case SignalErrorCodeUsernameCannotBeEmpty:
@@ -13,6 +13,12 @@ public struct DisplayableFingerprint {
 public struct ScannableFingerprint {
     public let encoding: [UInt8]
 
+    /// The format version of ``encoding``.
+    ///
+    /// ``compare(againstEncoding:)`` throws ``SignalError/fingerprintVersionMismatch(_:)`` when
+    /// the two encodings' versions differ.
+    public let version: Int
+
     /// Returns `true` if this fingerprint matches the fingerprint encoding `other`, `false` if not.
     ///
     /// Throws an error if `other` is not a valid fingerprint encoding, or if it uses an
@@ -77,7 +83,10 @@ public struct NumericFingerprintGenerator {
         let scannableBits = try invokeFnReturningArray {
             signal_fingerprint_scannable_encoding($0, obj)
         }
-        let scannable = ScannableFingerprint(encoding: scannableBits)
+        let scannableVersion = try invokeFnReturningInteger {
+            signal_fingerprint_get_version($0, obj)
+        }
+        let scannable = ScannableFingerprint(encoding: scannableBits, version: Int(scannableVersion))
         try checkError(signal_fingerprint_destroy(obj))
 
         return Fingerprint(displayable: displayable, scannable: scannable)
@@ -30,6 +30,26 @@ public struct IdentityKey: Equatable {
         }
         return result
     }
+
+    /// Returns `true` if this (incoming) identity differs from the `stored` one.
+    ///
+    /// With no `stored` identity this is a first contact, which is trusted on first use, so the
+    /// result is `false`.
+    public func hasChanged(from stored: IdentityKey?) -> Bool {
+        return publicKey.withNativeHandle { incomingHandle in
+            func changed(_ storedHandle: OpaquePointer?) -> Bool {
+                failOnError {
+                    try invokeFnReturningBool {
+                        signal_identity_key_changed($0, storedHandle, incomingHandle)
+                    }
+                }
+            }
+            guard let stored else {
+                return changed(nil)
+            }
+            return stored.publicKey.withNativeHandle { changed($0) }
+        }
+    }
 }
 
 public struct IdentityKeyPair {
@@ -32,6 +32,19 @@ public func hkdf(
     return output
 }
 
+/// Derives the 32-byte key used to encrypt link preview thumbnails from a message's master key.
+public func deriveLinkPreviewKey(masterKey: some ContiguousBytes) throws -> [UInt8] {
+    var output = Array(repeating: UInt8(0x00), count: 32)
+
+    try output.withUnsafeMutableBytes { outputBuffer in
+        try masterKey.withUnsafeBorrowedBuffer { masterKeyBuffer in
+            try checkError(signal_derive_link_preview_key(.init(outputBuffer), masterKeyBuffer))
+        }
+    }
+
+    return output
+}
+
 @available(*, deprecated, message: "Remove the 'version' parameter for standard HKDF behavior")
 public func hkdf(
     outputLength: Int,
@@ -32,13 +32,17 @@ public func signalDecrypt(
     from address: ProtocolAddress,
     sessionStore: SessionStore,
     identityStore: IdentityKeyStore,
-    context: StoreContext
+    context: StoreContext,
+    maxPlaintextLength: UInt32? = nil
 ) throws -> [UInt8] {
     return try withNativeHandles(message, address) { messageHandle, addressHandle in
         try withSessionStore(sessionStore, context) { ffiSessionStore in
             try withIdentityKeyStore(identityStore, context) { ffiIdentityStore in
                 try invokeFnReturningArray {
-                    signal_decrypt_message($0, messageHandle, addressHandle, ffiSessionStore, ffiIdentityStore)
+                    if let maxPlaintextLength {
+                        return signal_decrypt_message_with_limit($0, messageHandle, addressHandle, ffiSessionStore, ffiIdentityStore, maxPlaintextLength)
+                    }
+                    return signal_decrypt_message($0, messageHandle, addressHandle, ffiSessionStore, ffiIdentityStore)
                 }
             }
         }
@@ -53,7 +57,8 @@ public func signalDecryptPreKey(
     preKeyStore: PreKeyStore,
     signedPreKeyStore: SignedPreKeyStore,
     kyberPreKeyStore: KyberPreKeyStore,
-    context: StoreContext
+    context: StoreContext,
+    maxPlaintextLength: UInt32? = nil
 ) throws -> [UInt8] {
     return try withNativeHandles(message, address) { messageHandle, addressHandle in
         try withSessionStore(sessionStore, context) { ffiSessionStore in
@@ -62,7 +67,10 @@ public func signalDecryptPreKey(
                     try withSignedPreKeyStore(signedPreKeyStore, context) { ffiSignedPreKeyStore in
                         try withKyberPreKeyStore(kyberPreKeyStore, context) { ffiKyberPreKeyStore in
                             try invokeFnReturningArray {
-                                signal_decrypt_pre_key_message($0, messageHandle, addressHandle, ffiSessionStore, ffiIdentityStore, ffiPreKeyStore, ffiSignedPreKeyStore, ffiKyberPreKeyStore)
+                                if let maxPlaintextLength {
+                                    return signal_decrypt_pre_key_message_with_limit($0, messageHandle, addressHandle, ffiSessionStore, ffiIdentityStore, ffiPreKeyStore, ffiSignedPreKeyStore, ffiKyberPreKeyStore, maxPlaintextLength)
+                                }
+                                return signal_decrypt_pre_key_message($0, messageHandle, addressHandle, ffiSessionStore, ffiIdentityStore, ffiPreKeyStore, ffiSignedPreKeyStore, ffiKyberPreKeyStore)
                             }
                         }
                     }
@@ -72,6 +80,60 @@ public func signalDecryptPreKey(
     }
 }
 
+/// How far an address's saved identity is verified, as reported by ``identityVerificationLevels(for:identityStore:context:)``.
+public enum IdentityVerificationLevel: UInt8 {
+    /// No identity has been saved for the address.
+    case unknown = 0
+    /// The saved identity has not been verified.
+    case untrusted = 1
+    /// The saved identity has been verified.
+    case trusted = 2
+}
+
+public func identityVerificationLevels(
+    for addresses: [ProtocolAddress],
+    identityStore: IdentityVerificationStore,
+    context: StoreContext
+) throws -> [IdentityVerificationLevel] {
+    func ffiShimGetVerificationLevel(
+        ctx: UnsafeMutableRawPointer?,
+        levelp: UnsafeMutablePointer<UInt8>?,
+        address: OpaquePointer?
+    ) -> Int32 {
+        let storeContext = ctx!.assumingMemoryBound(to: ErrorHandlingContext<(IdentityVerificationStore, StoreContext)>.self)
+        return storeContext.pointee.catchCallbackErrors { store, context in
+            var address = ProtocolAddress(borrowing: address)
+            defer { cloneOrForgetAsNeeded(&address) }
+            levelp!.pointee = try store.verificationLevel(for: address, context: context).rawValue
+            return 0
+        }
+    }
+
+    var levels = [UInt8](repeating: 0, count: addresses.count)
+    // Use withExtendedLifetime instead of withNativeHandle for the array of wrapper objects,
+    // which isn't compatible with withNativeHandle's simple lexical scoping.
+    try withExtendedLifetime(addresses) {
+        let addressHandles = addresses.map { $0.unsafeNativeHandle }
+        try withIdentityKeyStore(identityStore, context) { ffiIdentityStore in
+            try rethrowCallbackErrors((identityStore, context)) { verificationContext in
+                try addressHandles.withUnsafeBufferPointer { addressHandles in
+                    try levels.withUnsafeMutableBufferPointer { levels in
+                        try checkError(signal_identity_store_get_verified_batch(
+                            ffiIdentityStore,
+                            addressHandles.baseAddress,
+                            addressHandles.count,
+                            verificationContext,
+                            ffiShimGetVerificationLevel,
+                            levels.baseAddress
+                        ))
+                    }
+                }
+            }
+        }
+    }
+    return levels.map { IdentityVerificationLevel(rawValue: $0)! }
+}
+
 public func processPreKeyBundle(
     _ bundle: PreKeyBundle,
     for address: ProtocolAddress,
@@ -126,6 +188,61 @@ public func groupDecrypt<Bytes: ContiguousBytes>(
     }
 }
 
+/// Encrypts `message` for a group along with a disappearing-message timer.
+///
+/// Recipients must use ``groupDecryptWithExpiry(_:from:store:context:)`` to read it.
+public func groupEncrypt<Bytes: ContiguousBytes>(
+    _ message: Bytes,
+    from sender: ProtocolAddress,
+    distributionId: UUID,
+    expirySeconds: UInt32,
+    store: SenderKeyStore,
+    context: StoreContext
+) throws -> CiphertextMessage {
+    return try sender.withNativeHandle { senderHandle in
+        try message.withUnsafeBorrowedBuffer { messageBuffer in
+            try withUnsafePointer(to: distributionId.uuid) { distributionId in
+                try withSenderKeyStore(store, context) { ffiStore in
+                    try invokeFnReturningNativeHandle {
+                        signal_group_encrypt_with_expiry($0, senderHandle, distributionId, messageBuffer, expirySeconds, ffiStore)
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// The result of ``groupDecryptWithExpiry(_:from:store:context:)``.
+public struct GroupDecryptionResult {
+    public var message: [UInt8]
+    /// The disappearing-message timer the sender sealed into the message, or `nil` if the message
+    /// was sent without one.
+    public var expirySeconds: UInt32?
+    /// The sender key iteration the message was encrypted with.
+    public var iteration: UInt32
+}
+
+public func groupDecryptWithExpiry<Bytes: ContiguousBytes>(
+    _ message: Bytes,
+    from sender: ProtocolAddress,
+    store: SenderKeyStore,
+    context: StoreContext
+) throws -> GroupDecryptionResult {
+    var hasExpiry = false
+    var expirySeconds: UInt32 = 0
+    var iteration: UInt32 = 0
+    let plaintext = try sender.withNativeHandle { senderHandle in
+        try message.withUnsafeBorrowedBuffer { messageBuffer in
+            try withSenderKeyStore(store, context) { ffiStore in
+                try invokeFnReturningArray {
+                    signal_group_decrypt_with_expiry($0, &hasExpiry, &expirySeconds, &iteration, senderHandle, messageBuffer, ffiStore)
+                }
+            }
+        }
+    }
+    return GroupDecryptionResult(message: plaintext, expirySeconds: hasExpiry ? expirySeconds : nil, iteration: iteration)
+}
+
 public func processSenderKeyDistributionMessage(
     _ message: SenderKeyDistributionMessage,
     from sender: ProtocolAddress,
@@ -7,6 +7,14 @@ import Foundation
 import SignalFfi
 
 public class PublicKey: ClonableHandleOwner {
+    /// The encodings supported by ``serialize(format:)`` and ``init(_:format:)``.
+    public enum SerializationFormat: UInt8 {
+        /// The default encoding, prefixed with the key type (33 bytes).
+        case prefixed = 0
+        /// The bare 32-byte public key, as returned by ``keyBytes``.
+        case raw = 1
+    }
+
     public convenience init<Bytes: ContiguousBytes>(_ bytes: Bytes) throws {
         let handle: OpaquePointer? = try bytes.withUnsafeBorrowedBuffer {
             var result: OpaquePointer?
@@ -16,6 +24,49 @@ public class PublicKey: ClonableHandleOwner {
         self.init(owned: handle!)
     }
 
+    public convenience init<Bytes: ContiguousBytes>(_ bytes: Bytes, format: SerializationFormat) throws {
+        let handle: OpaquePointer? = try bytes.withUnsafeBorrowedBuffer {
+            var result: OpaquePointer?
+            try checkError(signal_publickey_deserialize_with_format(&result, $0, format.rawValue))
+            return result
+        }
+        self.init(owned: handle!)
+    }
+
+    /// Deserializes each of `keys`, returning `nil` for any that are malformed.
+    public static func deserialize(batch keys: [[UInt8]]) throws -> [PublicKey?] {
+        var handles = [OpaquePointer?](repeating: nil, count: keys.count)
+        var results = [Bool](repeating: false, count: keys.count)
+        let lengths = keys.map { $0.count }
+        // The pointers from withUnsafeBufferPointer are only valid inside the closure, so copy
+        // each key into a buffer that outlives the call.
+        let buffers = keys.map { key -> UnsafeMutableBufferPointer<UInt8> in
+            let buffer = UnsafeMutableBufferPointer<UInt8>.allocate(capacity: max(key.count, 1))
+            _ = buffer.initialize(from: key)
+            return buffer
+        }
+        defer { buffers.forEach { $0.deallocate() } }
+        let data = buffers.map { UnsafePointer($0.baseAddress) }
+        try data.withUnsafeBufferPointer { data in
+            try lengths.withUnsafeBufferPointer { lengths in
+                try handles.withUnsafeMutableBufferPointer { handles in
+                    try results.withUnsafeMutableBufferPointer { results in
+                        try checkError(signal_publickey_deserialize_validate_batch(
+                            data.baseAddress,
+                            lengths.baseAddress,
+                            keys.count,
+                            handles.baseAddress,
+                            results.baseAddress
+                        ))
+                    }
+                }
+            }
+        }
+        return zip(handles, results).map { handle, valid in
+            valid ? PublicKey(owned: handle!) : nil
+        }
+    }
+
     override internal class func destroyNativeHandle(_ handle: OpaquePointer) -> SignalFfiErrorRef? {
         return signal_publickey_destroy(handle)
     }
@@ -44,6 +95,16 @@ public class PublicKey: ClonableHandleOwner {
         }
     }
 
+    public func serialize(format: SerializationFormat) -> [UInt8] {
+        return withNativeHandle { nativeHandle in
+            failOnError {
+                try invokeFnReturningArray {
+                    signal_publickey_serialize_with_format($0, nativeHandle, format.rawValue)
+                }
+            }
+        }
+    }
+
     public func verifySignature(message: some ContiguousBytes, signature: some ContiguousBytes) throws -> Bool {
         var result = false
         try withNativeHandle { nativeHandle in
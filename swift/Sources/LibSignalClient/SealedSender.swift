@@ -57,6 +57,19 @@ public class UnidentifiedSenderMessageContent: NativeHandleOwner {
         }
     }
 
+    public convenience init<Bytes: ContiguousBytes>(bytes: Bytes) throws {
+        let handle: OpaquePointer? = try bytes.withUnsafeBorrowedBuffer {
+            var result: OpaquePointer?
+            try checkError(signal_unidentified_sender_message_content_deserialize(&result, $0))
+            return result
+        }
+        self.init(owned: handle!)
+    }
+
+    /// Decrypts the outer layer of a sealed-sender message.
+    ///
+    /// The embedded sender certificate is *not* validated; callers must check it themselves before
+    /// trusting the sender or decrypting `contents`.
     public convenience init<Bytes: ContiguousBytes>(
         message sealedSenderMessage: Bytes,
         identityStore: IdentityKeyStore,
@@ -102,6 +115,16 @@ public class UnidentifiedSenderMessageContent: NativeHandleOwner {
         return signal_unidentified_sender_message_content_destroy(handle)
     }
 
+    public func serialize() -> [UInt8] {
+        return withNativeHandle { nativeHandle in
+            failOnError {
+                try invokeFnReturningArray {
+                    signal_unidentified_sender_message_content_serialize($0, nativeHandle)
+                }
+            }
+        }
+    }
+
     public var senderCertificate: SenderCertificate {
         return withNativeHandle { nativeHandle in
             failOnError {
@@ -255,6 +278,8 @@ public struct SealedSenderAddress: Hashable {
 public struct SealedSenderResult {
     public var message: [UInt8]
     public var sender: SealedSenderAddress
+    /// Which kind of service ID the sender's certificate identified them by.
+    public var senderServiceIdKind: ServiceIdKind
 }
 
 public func sealedSenderDecrypt<Bytes: ContiguousBytes>(
@@ -271,6 +296,7 @@ public func sealedSenderDecrypt<Bytes: ContiguousBytes>(
     var senderE164: UnsafePointer<CChar>?
     var senderUUID: UnsafePointer<CChar>?
     var senderDeviceId: UInt32 = 0
+    var senderServiceIdType: UInt8 = 0
 
     let plaintext = try trustRoot.withNativeHandle { trustRootHandle in
         try message.withUnsafeBorrowedBuffer { messageBuffer in
@@ -284,6 +310,7 @@ public func sealedSenderDecrypt<Bytes: ContiguousBytes>(
                                     &senderE164,
                                     &senderUUID,
                                     &senderDeviceId,
+                                    &senderServiceIdType,
                                     messageBuffer,
                                     trustRootHandle,
                                     timestamp,
@@ -314,6 +341,7 @@ public func sealedSenderDecrypt<Bytes: ContiguousBytes>(
             e164: senderE164.map(String.init(cString:)),
             uuidString: String(cString: senderUUID!),
             deviceId: senderDeviceId
-        )
+        ),
+        senderServiceIdKind: ServiceIdKind(rawValue: senderServiceIdType)!
     )
 }
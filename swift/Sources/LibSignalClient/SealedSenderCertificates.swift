@@ -78,6 +78,16 @@ public class ServerCertificate: NativeHandleOwner {
             }
         }
     }
+
+    /// Returns `true` if this certificate was signed by `trustRoot` and its key has not been
+    /// revoked.
+    public func validate(trustRoot: PublicKey) throws -> Bool {
+        var result = false
+        try withNativeHandles(self, trustRoot) { certificateHandle, trustRootHandle in
+            try checkError(signal_server_certificate_validate(&result, certificateHandle, trustRootHandle))
+        }
+        return result
+    }
 }
 
 public class SenderCertificate: NativeHandleOwner {
@@ -182,6 +192,17 @@ public class SenderCertificate: NativeHandleOwner {
         }
     }
 
+    /// The raw 16 bytes of ``senderUuid``.
+    ///
+    /// Throws if the certificate's sender UUID is malformed.
+    public func senderUuidBytes() throws -> [UInt8] {
+        return try withNativeHandle { nativeHandle in
+            try invokeFnReturningFixedLengthArray {
+                signal_sender_certificate_get_sender_uuid_bytes($0, nativeHandle)
+            }
+        }
+    }
+
     /// Returns an ACI if the sender is a valid UUID, `nil` otherwise.
     ///
     /// In a future release SenderCertificate will *only* support ACIs.
@@ -222,4 +243,28 @@ public class SenderCertificate: NativeHandleOwner {
         }
         return result
     }
+
+    /// Validates each of `certificates`, returning one result per certificate.
+    public static func validate(_ certificates: [SenderCertificate], trustRoot: PublicKey, time: UInt64) throws -> [Bool] {
+        var results = [Bool](repeating: false, count: certificates.count)
+        // Use withExtendedLifetime instead of withNativeHandle for the array of wrapper objects,
+        // which isn't compatible with withNativeHandle's simple lexical scoping.
+        try withExtendedLifetime(certificates) {
+            let certificateHandles = certificates.map { $0.unsafeNativeHandle }
+            try trustRoot.withNativeHandle { trustRootHandle in
+                try certificateHandles.withUnsafeBufferPointer { certificateHandles in
+                    try results.withUnsafeMutableBufferPointer { results in
+                        try checkError(signal_sender_certificate_validate_batch(
+                            certificateHandles.baseAddress,
+                            certificateHandles.count,
+                            trustRootHandle,
+                            time,
+                            results.baseAddress
+                        ))
+                    }
+                }
+            }
+        }
+        return results
+    }
 }
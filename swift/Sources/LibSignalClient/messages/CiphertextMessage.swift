@@ -3,6 +3,7 @@
 // SPDX-License-Identifier: AGPL-3.0-only
 //
 
+import Foundation
 import SignalFfi
 
 public class CiphertextMessage: NativeHandleOwner {
@@ -45,6 +46,15 @@ public class CiphertextMessage: NativeHandleOwner {
         self.init(owned: result!)
     }
 
+    /// Reconstructs a message of the given type from its serialized form.
+    public convenience init<Bytes: ContiguousBytes>(bytes: Bytes, type: MessageType) throws {
+        var result: OpaquePointer?
+        try bytes.withUnsafeBorrowedBuffer {
+            try checkError(signal_ciphertext_message_from_serialized(&result, type.rawValue, $0))
+        }
+        self.init(owned: result!)
+    }
+
     public func serialize() -> [UInt8] {
         return withNativeHandle { nativeHandle in
             failOnError {
@@ -19,6 +19,34 @@ public class SenderKeyMessage: NativeHandleOwner {
         self.init(owned: result!)
     }
 
+    /// Builds a message from its parts, signed with `signingKey`.
+    public convenience init(
+        messageVersion: UInt8,
+        distributionId: UUID,
+        chainId: UInt32,
+        iteration: UInt32,
+        ciphertext: some ContiguousBytes,
+        signingKey: PrivateKey
+    ) throws {
+        var result: OpaquePointer?
+        try signingKey.withNativeHandle { keyHandle in
+            try ciphertext.withUnsafeBorrowedBuffer { ciphertextBuffer in
+                try withUnsafePointer(to: distributionId.uuid) { distributionId in
+                    try checkError(signal_sender_key_message_new(
+                        &result,
+                        messageVersion,
+                        distributionId,
+                        chainId,
+                        iteration,
+                        ciphertextBuffer,
+                        keyHandle
+                    ))
+                }
+            }
+        }
+        self.init(owned: result!)
+    }
+
     public var distributionId: UUID {
         return withNativeHandle { nativeHandle in
             failOnError {
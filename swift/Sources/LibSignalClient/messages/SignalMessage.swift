@@ -19,6 +19,40 @@ public class SignalMessage: NativeHandleOwner {
         self.init(owned: result!)
     }
 
+    /// Builds and MACs a message from its parts.
+    ///
+    /// `macKey` must be 32 bytes.
+    public convenience init(
+        messageVersion: UInt8,
+        macKey: some ContiguousBytes,
+        senderRatchetKey: PublicKey,
+        counter: UInt32,
+        previousCounter: UInt32,
+        ciphertext: some ContiguousBytes,
+        senderIdentityKey: PublicKey,
+        receiverIdentityKey: PublicKey
+    ) throws {
+        var result: OpaquePointer?
+        try withNativeHandles(senderRatchetKey, senderIdentityKey, receiverIdentityKey) { ratchetHandle, senderHandle, receiverHandle in
+            try macKey.withUnsafeBorrowedBuffer { macKeyBuffer in
+                try ciphertext.withUnsafeBorrowedBuffer { ciphertextBuffer in
+                    try checkError(signal_message_new(
+                        &result,
+                        messageVersion,
+                        macKeyBuffer,
+                        ratchetHandle,
+                        counter,
+                        previousCounter,
+                        ciphertextBuffer,
+                        senderHandle,
+                        receiverHandle
+                    ))
+                }
+            }
+        }
+        self.init(owned: result!)
+    }
+
     public var senderRatchetKey: PublicKey {
         return withNativeHandle { nativeHandle in
             failOnError {
@@ -69,6 +103,16 @@ public class SignalMessage: NativeHandleOwner {
         }
     }
 
+    public var previousCounter: UInt32 {
+        return withNativeHandle { nativeHandle in
+            failOnError {
+                try invokeFnReturningInteger {
+                    signal_message_get_previous_counter($0, nativeHandle)
+                }
+            }
+        }
+    }
+
     public func verifyMac<Bytes: ContiguousBytes>(
         sender: PublicKey,
         receiver: PublicKey,
@@ -272,4 +272,27 @@ public class PreKeyBundle: NativeHandleOwner {
         }
         return result.isEmpty ? nil : result
     }
+
+    /// Returns `true` if this bundle was issued for the device identified by `address`.
+    public func matches(_ address: ProtocolAddress) -> Bool {
+        return withNativeHandles(self, address) { bundleHandle, addressHandle in
+            failOnError {
+                try invokeFnReturningBool {
+                    signal_pre_key_bundle_matches_address($0, bundleHandle, addressHandle)
+                }
+            }
+        }
+    }
+
+    /// Returns `true` if the signed pre-key was signed by this bundle's identity key.
+    ///
+    /// `processPreKeyBundle` performs this check itself; this lets callers reject a bad bundle
+    /// before trying to process it.
+    public func verifySignedPreKey() throws -> Bool {
+        return try withNativeHandle { nativeHandle in
+            try invokeFnReturningBool {
+                signal_pre_key_bundle_verify_signed_pre_key($0, nativeHandle)
+            }
+        }
+    }
 }
@@ -24,6 +24,19 @@ public class SessionRecord: ClonableHandleOwner {
         self.init(owned: handle!)
     }
 
+    /// Migrates a session stored in the legacy on-disk format.
+    ///
+    /// Legacy data that can't be read throws `SignalError.protobufError`, rather than the
+    /// `SignalError.invalidSession` thrown by `init(bytes:)`.
+    public convenience init<Bytes: ContiguousBytes>(legacyBytes bytes: Bytes) throws {
+        let handle: OpaquePointer? = try bytes.withUnsafeBorrowedBuffer {
+            var result: OpaquePointer?
+            try checkError(signal_session_record_deserialize_legacy(&result, $0))
+            return result
+        }
+        self.init(owned: handle!)
+    }
+
     public func serialize() -> [UInt8] {
         return self.withNativeHandle { nativeHandle in
             failOnError {
@@ -46,12 +59,65 @@ public class SessionRecord: ClonableHandleOwner {
         return result
     }
 
+    /// The version of the current session, or 0 if there is no current session.
+    public var sessionVersion: UInt32 {
+        return self.withNativeHandle { nativeHandle in
+            failOnError {
+                try invokeFnReturningInteger {
+                    signal_session_record_get_session_version($0, nativeHandle)
+                }
+            }
+        }
+    }
+
+    /// Whether the record has a current session at all, even one that can no longer be used.
+    public var hasCurrentSessionState: Bool {
+        return self.withNativeHandle { nativeHandle in
+            failOnError {
+                try invokeFnReturningBool {
+                    signal_session_record_has_current_session_state($0, nativeHandle)
+                }
+            }
+        }
+    }
+
+    /// Whether the current session has a sending chain, ignoring whether it has expired.
+    public var hasSenderChain: Bool {
+        return self.withNativeHandle { nativeHandle in
+            failOnError {
+                try invokeFnReturningBool {
+                    signal_session_record_has_sender_chain($0, nativeHandle)
+                }
+            }
+        }
+    }
+
+    /// A hash of the full serialized record, for checking that two copies hold the same state.
+    public var stateHash: [UInt8] {
+        return self.withNativeHandle { nativeHandle in
+            failOnError {
+                try invokeFnReturningFixedLengthArray {
+                    signal_session_record_state_hash($0, nativeHandle)
+                }
+            }
+        }
+    }
+
     public func archiveCurrentState() {
         self.withNativeHandle { nativeHandle in
             failOnError(signal_session_record_archive_current_state(nativeHandle))
         }
     }
 
+    /// Zeroes all key material in the record, keeping its metadata.
+    ///
+    /// Afterwards the record can no longer be used to encrypt or decrypt messages.
+    public func wipeSecrets() throws {
+        try self.withNativeHandle { nativeHandle in
+            try checkError(signal_session_record_wipe_secrets(nativeHandle))
+        }
+    }
+
     public func remoteRegistrationId() throws -> UInt32 {
         return try self.withNativeHandle { nativeHandle in
             try invokeFnReturningInteger {
@@ -113,6 +113,46 @@ SPDX-License-Identifier: AGPL-3.0-only
  */
 #define SignalSECONDS_PER_DAY 86400
 
+/**
+ * Result written by [`signal_publickey_verify_batch`] for an item that couldn't be checked at all.
+ */
+#define SignalVERIFY_BATCH_MALFORMED_ITEM 255
+
+/**
+ * Built with the C (Swift) bridge.
+ */
+#define SignalBUILD_FEATURE_FFI 1
+
+/**
+ * Built with the JNI (Java) bridge.
+ */
+#define SignalBUILD_FEATURE_JNI 2
+
+/**
+ * Built with the Node bridge.
+ */
+#define SignalBUILD_FEATURE_NODE 4
+
+/**
+ * Sealed sender is always compiled in; the bit exists so callers don't have to assume that.
+ */
+#define SignalBUILD_FEATURE_SEALED_SENDER 8
+
+/**
+ * Built with `testing-seeded-rng`, so bridge randomness may be seeded. Never set in release builds.
+ */
+#define SignalBUILD_FEATURE_TESTING_SEEDED_RNG 16
+
+/**
+ * Built with `signal-media`.
+ */
+#define SignalBUILD_FEATURE_SIGNAL_MEDIA 32
+
+/**
+ * Built with `testing-fns`.
+ */
+#define SignalBUILD_FEATURE_TESTING_FNS 64
+
 typedef enum {
   SignalCiphertextMessageTypeWhisper = 2,
   SignalCiphertextMessageTypePreKey = 3,
@@ -148,15 +188,18 @@ typedef enum {
   SignalErrorCodeInvalidType = 6,
   SignalErrorCodeInvalidUtf8String = 7,
   SignalErrorCodeCancelled = 8,
+  SignalErrorCodeBufferTooSmall = 9,
   SignalErrorCodeProtobufError = 10,
   SignalErrorCodeLegacyCiphertextVersion = 21,
   SignalErrorCodeUnknownCiphertextVersion = 22,
   SignalErrorCodeUnrecognizedMessageVersion = 23,
   SignalErrorCodeInvalidMessage = 30,
   SignalErrorCodeSealedSenderSelfSend = 31,
+  SignalErrorCodeSealedSenderMacVerificationFailed = 32,
   SignalErrorCodeInvalidKey = 40,
   SignalErrorCodeInvalidSignature = 41,
   SignalErrorCodeInvalidAttestationData = 42,
+  SignalErrorCodeInvalidKeyLength = 43,
   SignalErrorCodeFingerprintVersionMismatch = 51,
   SignalErrorCodeFingerprintParsingError = 52,
   SignalErrorCodeUntrustedIdentity = 60,
@@ -166,6 +209,8 @@ typedef enum {
   SignalErrorCodeInvalidSession = 82,
   SignalErrorCodeInvalidSenderKeySession = 83,
   SignalErrorCodeDuplicatedMessage = 90,
+  SignalErrorCodeIterationTooLarge = 91,
+  SignalErrorCodeTooManySkippedKeys = 92,
   SignalErrorCodeCallbackError = 100,
   SignalErrorCodeVerificationFailure = 110,
   SignalErrorCodeUsernameCannotBeEmpty = 120,
@@ -226,6 +271,10 @@ typedef struct SignalHsmEnclaveClient SignalHsmEnclaveClient;
 
 typedef struct SignalHttpRequest SignalHttpRequest;
 
+typedef struct SignalIdentityKey SignalIdentityKey;
+
+typedef struct SignalIdentityKeyPair SignalIdentityKeyPair;
+
 typedef struct SignalIncrementalMac SignalIncrementalMac;
 
 typedef struct SignalKeyPair SignalKeyPair;
@@ -268,6 +317,19 @@ typedef struct SignalPublicKey SignalPublicKey;
  */
 typedef struct SignalSanitizedMetadata SignalSanitizedMetadata;
 
+/**
+ * Incrementally decrypts the payload of a sealed-sender v1 message.
+ *
+ * The concatenated output is the same as the serialized result of
+ * [`sealed_sender_decrypt_to_usmc`], but the message never has to be held in memory all at once.
+ * Sealed sender v2 messages can't be decrypted this way, because AES-GCM-SIV needs the entire
+ * ciphertext before it can produce any plaintext.
+ *
+ * Plaintext produced by [`update`](Self::update) is unauthenticated. Callers must discard all of
+ * it unless [`finalize`](Self::finalize) succeeds.
+ */
+typedef struct SignalSealedSenderDecryptStream SignalSealedSenderDecryptStream;
+
 typedef struct SignalSenderCertificate SignalSenderCertificate;
 
 typedef struct SignalSenderKeyDistributionMessage SignalSenderKeyDistributionMessage;
@@ -398,10 +460,16 @@ typedef int (*SignalGetIdentityKeyPair)(void *store_ctx, SignalPrivateKey **keyp
 
 typedef int (*SignalGetLocalRegistrationId)(void *store_ctx, uint32_t *idp);
 
+/**
+ * `public_key` is only valid for the duration of the call; clone the handle to keep it.
+ */
 typedef int (*SignalSaveIdentityKey)(void *store_ctx, const SignalProtocolAddress *address, const SignalPublicKey *public_key);
 
 typedef int (*SignalGetIdentityKey)(void *store_ctx, SignalPublicKey **public_keyp, const SignalProtocolAddress *address);
 
+/**
+ * `public_key` is only valid for the duration of the call; clone the handle to keep it.
+ */
 typedef int (*SignalIsTrustedIdentity)(void *store_ctx, const SignalProtocolAddress *address, const SignalPublicKey *public_key, unsigned int direction);
 
 typedef struct {
@@ -737,10 +805,20 @@ typedef struct {
 
 typedef uint8_t SignalRandomnessBytes[SignalRANDOMNESS_LEN];
 
+/**
+ * Reports the store's verification level for the identity saved for `address`.
+ */
+typedef int (*SignalGetIdentityVerificationLevel)(void *ctx, uint8_t *levelp, const SignalProtocolAddress *address);
+
 void signal_print_ptr(const void *p);
 
 void signal_free_string(const char *buf);
 
+/**
+ * Frees a byte buffer returned by libsignal, such as a serialized key.
+ *
+ * Does nothing if `buf` is null.
+ */
 void signal_free_buffer(const unsigned char *buf, size_t buf_len);
 
 void signal_free_list_of_strings(SignalOwnedBufferOfCStringPtr buffer);
@@ -749,6 +827,13 @@ void signal_free_lookup_response_entry_list(SignalOwnedBufferOfFfiCdsiLookupResp
 
 void signal_free_bytestring_array(SignalBytestringArray array);
 
+/**
+ * Writes the version of libsignal this library was built from to `out`, such as "0.54.0".
+ *
+ * The string must be freed with `signal_free_string`.
+ */
+SignalFfiError *signal_get_version_string(const char **out);
+
 SignalFfiError *signal_error_get_message(const SignalFfiError *err, const char **out);
 
 SignalFfiError *signal_error_get_address(const SignalFfiError *err, SignalProtocolAddress **out);
@@ -763,11 +848,194 @@ SignalFfiError *signal_error_get_tries_remaining(const SignalFfiError *err, uint
 
 SignalFfiError *signal_error_get_unknown_fields(const SignalFfiError *err, SignalStringArray *out);
 
+/**
+ * Writes the backtrace recorded when `err` was created to `out`.
+ *
+ * Backtraces are only recorded when built with the `capture-backtrace` feature; otherwise this
+ * writes an empty string. The string must be freed with `signal_free_string`.
+ */
+SignalFfiError *signal_error_get_backtrace(const SignalFfiError *err, const char **out);
+
 void signal_error_free(SignalFfiError *err);
 
+/**
+ * Returns the code of the most recent error returned on the calling thread, or 0 if there is none.
+ *
+ * Errors are still returned (and must still be freed) as usual; this is only a convenience for
+ * callers that don't inspect every result.
+ */
+uint32_t signal_error_get_last_type(void);
+
+/**
+ * Writes the description of the most recent error returned on the calling thread to `out`, or
+ * null if there is none, and then forgets that error.
+ *
+ * The string must be freed with `signal_free_string`.
+ */
+SignalFfiError *signal_error_take_last_message(const char **out);
+
+void signal_error_clear_last(void);
+
 SignalFfiError *signal_identitykeypair_deserialize(SignalPrivateKey **private_key, SignalPublicKey **public_key, SignalBorrowedBuffer input);
 
-SignalFfiError *signal_sealed_session_cipher_decrypt(SignalOwnedBuffer *out, const char **sender_e164, const char **sender_uuid, uint32_t *sender_device_id, SignalBorrowedBuffer ctext, const SignalPublicKey *trust_root, uint64_t timestamp, const char *local_e164, const char *local_uuid, unsigned int local_device_id, const SignalSessionStore *session_store, const SignalIdentityKeyStore *identity_store, const SignalPreKeyStore *prekey_store, const SignalSignedPreKeyStore *signed_prekey_store);
+SignalFfiError *signal_sealed_session_cipher_decrypt(SignalOwnedBuffer *out, const char **sender_e164, const char **sender_uuid, uint32_t *sender_device_id, uint8_t *sender_service_id_type, SignalBorrowedBuffer ctext, const SignalPublicKey *trust_root, uint64_t timestamp, const char *local_e164, const char *local_uuid, unsigned int local_device_id, const SignalSessionStore *session_store, const SignalIdentityKeyStore *identity_store, const SignalPreKeyStore *prekey_store, const SignalSignedPreKeyStore *signed_prekey_store);
+
+/**
+ * Decrypts a group message, writing the plaintext to `out` and the sender key iteration the
+ * message was encrypted with to `out_iteration`.
+ *
+ * If the message came from `signal_group_encrypt_with_expiry`, writes `true` to `out_has_expiry`
+ * and the disappearing-message timer to `out_expiry_seconds`. Otherwise writes `false` and 0.
+ */
+SignalFfiError *signal_group_decrypt_with_expiry(SignalOwnedBuffer *out, bool *out_has_expiry, uint32_t *out_expiry_seconds, uint32_t *out_iteration, const SignalProtocolAddress *sender, SignalBorrowedBuffer message, const SignalSenderKeyStore *store);
+
+/**
+ * Decrypts a message like `signal_decrypt_pre_key_message`, and also writes the version of the
+ * resulting session to `out_session_version`.
+ *
+ * If `out_session_version` is null the version is not reported.
+ */
+SignalFfiError *signal_decrypt_pre_key_message_with_session_version(SignalOwnedBuffer *out, uint32_t *out_session_version, const SignalPreKeySignalMessage *message, const SignalProtocolAddress *protocol_address, const SignalSessionStore *session_store, const SignalIdentityKeyStore *identity_key_store, const SignalPreKeyStore *prekey_store, const SignalSignedPreKeyStore *signed_prekey_store, const SignalKyberPreKeyStore *kyber_prekey_store);
+
+/**
+ * Decrypts a message like `signal_decrypt_message`, and also writes to `out_identity_changed`
+ * whether the sender's identity key is untrusted by `identity_key_store`.
+ *
+ * If `allow_identity_change` is false, an untrusted identity is an error, as in
+ * `signal_decrypt_message`. Otherwise the message is decrypted anyway, and the new identity is
+ * left for the caller to save if it accepts it.
+ */
+SignalFfiError *signal_decrypt_message_reporting_identity_change(SignalOwnedBuffer *out, bool *out_identity_changed, const SignalMessage *message, const SignalProtocolAddress *protocol_address, const SignalSessionStore *session_store, const SignalIdentityKeyStore *identity_key_store, bool allow_identity_change);
+
+SignalFfiError *signal_session_record_serialize_into(size_t *out_written, const SignalSessionRecord *obj, SignalBorrowedMutableBuffer buffer);
+
+SignalFfiError *signal_sender_key_record_serialize_into(size_t *out_written, const SignalSenderKeyRecord *obj, SignalBorrowedMutableBuffer buffer);
+
+SignalFfiError *signal_pre_key_record_serialize_into(size_t *out_written, const SignalPreKeyRecord *obj, SignalBorrowedMutableBuffer buffer);
+
+SignalFfiError *signal_signed_pre_key_record_serialize_into(size_t *out_written, const SignalSignedPreKeyRecord *obj, SignalBorrowedMutableBuffer buffer);
+
+SignalFfiError *signal_kyber_pre_key_record_serialize_into(size_t *out_written, const SignalKyberPreKeyRecord *obj, SignalBorrowedMutableBuffer buffer);
+
+/**
+ * Validates `count` sender certificates against `trust_root` at `timestamp`, writing one result
+ * per certificate to `out_results`.
+ */
+SignalFfiError *signal_sender_certificate_validate_batch(const SignalSenderCertificate *const *certs, size_t count, const SignalPublicKey *trust_root, uint64_t timestamp, bool *out_results);
+
+/**
+ * Looks up the verification level of each of `count` addresses, writing one level per address
+ * to `out_levels`: 0 if no identity is saved, and otherwise whatever `get_verification_level`
+ * reports for the address (1 if its identity has not been verified, 2 if it has).
+ *
+ * `get_verification_level` is called with `ctx` and is only asked about addresses with a saved
+ * identity.
+ */
+SignalFfiError *signal_identity_store_get_verified_batch(const SignalIdentityKeyStore *identity_store, const SignalProtocolAddress *const *addresses, size_t count, void *ctx, SignalGetIdentityVerificationLevel get_verification_level, uint8_t *out_levels);
+
+/**
+ * Deserializes each of `count` public keys, where key `i` is the `lengths[i]` bytes at
+ * `data_array[i]`.
+ *
+ * Writes a handle for each key to `out_handles` and whether it was valid to `out_results`. An
+ * invalid key, or a null entry in `data_array`, gets a null handle rather than failing the whole
+ * batch.
+ */
+SignalFfiError *signal_publickey_deserialize_validate_batch(const unsigned char *const *data_array, const size_t *lengths, size_t count, SignalPublicKey **out_handles, bool *out_results);
+
+/**
+ * Verifies each of `count` signatures, where item `i` is `signatures[i]` (`signature_lens[i]`
+ * bytes) over `messages[i]` (`message_lens[i]` bytes) by `keys[i]`.
+ *
+ * Writes one result per item to `out_results`: 1 if the signature is valid, 0 if it is not, and
+ * [`VERIFY_BATCH_MALFORMED_ITEM`] if the key is null or a message or signature is null with a
+ * nonzero length. A malformed item does not stop the rest of the batch from being checked.
+ */
+SignalFfiError *signal_publickey_verify_batch(const SignalPublicKey *const *keys, const unsigned char *const *messages, const size_t *message_lens, const unsigned char *const *signatures, const size_t *signature_lens, size_t count, uint8_t *out_results);
+
+/**
+ * Generates `count` one-time prekeys with consecutive ids starting at `start_id`, writing a
+ * handle for each to `out_handles`.
+ *
+ * Ids are assigned modulo 2^32, so a batch that runs past `u32::MAX` continues from 0. Since
+ * that would repeat ids, `count` may not exceed 2^32.
+ */
+SignalFfiError *signal_generate_pre_keys(SignalPreKeyRecord **out_handles, uint32_t start_id, size_t count);
+
+/**
+ * Generates a signed prekey with the given id and timestamp (in milliseconds since the epoch),
+ * signed by `identity_key_pair`.
+ *
+ * This is also how a client rotates its signed prekey: each call generates a fresh key pair.
+ */
+SignalFfiError *signal_generate_signed_pre_key(SignalSignedPreKeyRecord **out, const SignalIdentityKeyPair *identity_key_pair, uint32_t signed_pre_key_id, uint64_t timestamp);
+
+/**
+ * Extracts the key pair of a signed prekey record as separate handles.
+ *
+ * The keys are copies, so each can be destroyed independently of the other and of `record`.
+ */
+SignalFfiError *signal_signed_pre_key_record_get_key_pair(SignalPublicKey **out_public, SignalPrivateKey **out_private, const SignalSignedPreKeyRecord *record);
+
+/**
+ * Destroys each of the `count` handles in `ptrs` in a single call, skipping nulls.
+ *
+ * This is equivalent to calling `signal_pre_key_record_destroy` on each one, such as for the
+ * output of [`signal_generate_pre_keys`]. The array itself is not freed.
+ */
+SignalFfiError *signal_pre_key_record_destroy_array(SignalPreKeyRecord *const *ptrs, size_t count);
+
+/**
+ * Serializes each of the `count` records in `records`, writing a newly-allocated buffer for each
+ * to `out_buffers` and its length to `out_lens`.
+ *
+ * Each buffer must be freed with [`signal_free_buffer`]. A null record fails the whole call
+ * (before anything is allocated) rather than being skipped.
+ */
+SignalFfiError *signal_pre_key_record_serialize_all(const SignalPreKeyRecord *const *records, size_t count, const unsigned char **out_buffers, size_t *out_lens);
+
+/**
+ * Checks whether any two of the `count` records in `records` share an id.
+ *
+ * If so, writes `true` to `out_found` and the index of the first record whose id appeared earlier
+ * in the array to `out_index`. Otherwise writes `false` and 0. A null record fails the whole call.
+ */
+SignalFfiError *signal_pre_key_record_find_duplicate_id(const SignalPreKeyRecord *const *records, size_t count, bool *out_found, size_t *out_index);
+
+/**
+ * Like [`signal_pre_key_record_find_duplicate_id`], for signed prekeys.
+ */
+SignalFfiError *signal_signed_pre_key_record_find_duplicate_id(const SignalSignedPreKeyRecord *const *records, size_t count, bool *out_found, size_t *out_index);
+
+/**
+ * Starts incrementally decrypting a sealed-sender v1 message addressed to `identity_key_pair`.
+ *
+ * See [`SealedSenderDecryptStream`] for which messages are supported.
+ */
+SignalFfiError *signal_sealed_sender_decrypt_stream_new(SignalSealedSenderDecryptStream **out, const SignalIdentityKeyPair *identity_key_pair);
+
+/**
+ * Feeds the next chunk of a sealed-sender message to `stream`.
+ *
+ * No plaintext is produced here; it is held back until
+ * [`signal_sealed_sender_decrypt_stream_finalize`] has verified the message.
+ */
+SignalFfiError *signal_sealed_sender_decrypt_stream_update(SignalSealedSenderDecryptStream *stream, SignalBorrowedBuffer input);
+
+/**
+ * Verifies a sealed-sender message after all of it has been passed to
+ * [`signal_sealed_sender_decrypt_stream_update`], writing the plaintext to `out` only if the MAC
+ * matched.
+ *
+ * A MAC mismatch is reported as `SignalErrorCodeSealedSenderMacVerificationFailed`, and leaves
+ * `out` untouched. The stream can't be used again either way, but must still be destroyed.
+ */
+SignalFfiError *signal_sealed_sender_decrypt_stream_finalize(SignalOwnedBuffer *out, SignalSealedSenderDecryptStream *stream);
+
+/**
+ * Returns a bitmask of the `BUILD_FEATURE_*` capabilities compiled into this library.
+ */
+SignalFfiError *signal_get_build_features(uint32_t *out);
 
 bool signal_init_logger(SignalLogLevel max_level, SignalFfiLogger logger);
 
@@ -801,6 +1069,33 @@ SignalFfiError *signal_aes256_gcm_siv_encrypt(SignalOwnedBuffer *out, const Sign
 
 SignalFfiError *signal_aes256_gcm_siv_decrypt(SignalOwnedBuffer *out, const SignalAes256GcmSiv *aes_gcm_siv, SignalBorrowedBuffer ctext, SignalBorrowedBuffer nonce, SignalBorrowedBuffer associated_data);
 
+/**
+ * Compares `a` and `b` in time that depends only on their lengths.
+ *
+ * Inputs of different lengths are unequal rather than an error.
+ */
+SignalFfiError *signal_constant_time_equals(bool *out, SignalBorrowedBuffer a, SignalBorrowedBuffer b);
+
+/**
+ * Encodes `input` as lowercase hex.
+ */
+SignalFfiError *signal_hex_encode(const char **out, SignalBorrowedBuffer input);
+
+/**
+ * Decodes hex in either case, rejecting odd lengths and non-hex characters.
+ */
+SignalFfiError *signal_hex_decode(SignalOwnedBuffer *out, const char *input);
+
+/**
+ * Encodes `input` as standard, padded base64.
+ */
+SignalFfiError *signal_base64_encode(const char **out, SignalBorrowedBuffer input);
+
+/**
+ * Decodes standard, padded base64, rejecting missing or extra padding and invalid characters.
+ */
+SignalFfiError *signal_base64_decode(SignalOwnedBuffer *out, const char *input);
+
 SignalFfiError *signal_ciphertext_message_destroy(SignalCiphertextMessage *p);
 
 SignalFfiError *signal_decryption_error_message_destroy(SignalDecryptionErrorMessage *p);
@@ -811,6 +1106,14 @@ SignalFfiError *signal_fingerprint_destroy(SignalFingerprint *p);
 
 SignalFfiError *signal_fingerprint_clone(SignalFingerprint **new_obj, const SignalFingerprint *obj);
 
+SignalFfiError *signal_identity_key_destroy(SignalIdentityKey *p);
+
+SignalFfiError *signal_identity_key_clone(SignalIdentityKey **new_obj, const SignalIdentityKey *obj);
+
+SignalFfiError *signal_identity_key_pair_destroy(SignalIdentityKeyPair *p);
+
+SignalFfiError *signal_identity_key_pair_clone(SignalIdentityKeyPair **new_obj, const SignalIdentityKeyPair *obj);
+
 SignalFfiError *signal_plaintext_content_destroy(SignalPlaintextContent *p);
 
 SignalFfiError *signal_plaintext_content_clone(SignalPlaintextContent **new_obj, const SignalPlaintextContent *obj);
@@ -877,6 +1180,8 @@ SignalFfiError *signal_kyber_pre_key_record_clone(SignalKyberPreKeyRecord **new_
 
 SignalFfiError *signal_unidentified_sender_message_content_destroy(SignalUnidentifiedSenderMessageContent *p);
 
+SignalFfiError *signal_sealed_sender_decrypt_stream_destroy(SignalSealedSenderDecryptStream *p);
+
 SignalFfiError *signal_kyber_key_pair_destroy(SignalKyberKeyPair *p);
 
 SignalFfiError *signal_kyber_key_pair_clone(SignalKyberKeyPair **new_obj, const SignalKyberKeyPair *obj);
@@ -891,6 +1196,11 @@ SignalFfiError *signal_kyber_secret_key_clone(SignalKyberSecretKey **new_obj, co
 
 SignalFfiError *signal_hkdf_derive(SignalBorrowedMutableBuffer output, SignalBorrowedBuffer ikm, SignalBorrowedBuffer label, SignalBorrowedBuffer salt);
 
+/**
+ * Derives the key used to encrypt link preview thumbnails from a message's master key.
+ */
+SignalFfiError *signal_derive_link_preview_key(SignalBorrowedMutableBuffer output, SignalBorrowedBuffer master_key);
+
 SignalFfiError *signal_service_id_service_id_binary(SignalOwnedBuffer *out, const SignalServiceIdFixedWidthBinaryBytes *value);
 
 SignalFfiError *signal_service_id_service_id_string(const char **out, const SignalServiceIdFixedWidthBinaryBytes *value);
@@ -909,10 +1219,22 @@ SignalFfiError *signal_publickey_serialize(SignalOwnedBuffer *out, const SignalP
 
 SignalFfiError *signal_publickey_get_public_key_bytes(SignalOwnedBuffer *out, const SignalPublicKey *obj);
 
+SignalFfiError *signal_publickey_serialized_len(size_t *out, const SignalPublicKey *key);
+
+SignalFfiError *signal_publickey_get_type(uint8_t *out, const SignalPublicKey *key);
+
+SignalFfiError *signal_publickey_serialize_with_format(SignalOwnedBuffer *out, const SignalPublicKey *key, uint8_t format);
+
+SignalFfiError *signal_publickey_deserialize_with_format(SignalPublicKey **out, SignalBorrowedBuffer data, uint8_t format);
+
 SignalFfiError *signal_address_get_device_id(uint32_t *out, const SignalProtocolAddress *obj);
 
 SignalFfiError *signal_address_get_name(const char **out, const SignalProtocolAddress *obj);
 
+SignalFfiError *signal_address_to_string(const char **out, const SignalProtocolAddress *obj);
+
+SignalFfiError *signal_address_parse(SignalProtocolAddress **out, const char *s);
+
 SignalFfiError *signal_publickey_equals(bool *out, const SignalPublicKey *lhs, const SignalPublicKey *rhs);
 
 SignalFfiError *signal_publickey_compare(int32_t *out, const SignalPublicKey *key1, const SignalPublicKey *key2);
@@ -923,6 +1245,8 @@ SignalFfiError *signal_privatekey_deserialize(SignalPrivateKey **out, SignalBorr
 
 SignalFfiError *signal_privatekey_serialize(SignalOwnedBuffer *out, const SignalPrivateKey *obj);
 
+SignalFfiError *signal_privatekey_serialized_len(size_t *out, const SignalPrivateKey *key);
+
 SignalFfiError *signal_privatekey_generate(SignalPrivateKey **out);
 
 SignalFfiError *signal_privatekey_get_public_key(SignalPublicKey **out, const SignalPrivateKey *k);
@@ -951,14 +1275,42 @@ SignalFfiError *signal_identitykeypair_serialize(SignalOwnedBuffer *out, const S
 
 SignalFfiError *signal_identitykeypair_sign_alternate_identity(SignalOwnedBuffer *out, const SignalPublicKey *public_key, const SignalPrivateKey *private_key, const SignalPublicKey *other_identity);
 
+SignalFfiError *signal_identity_key_pair_generate(SignalIdentityKeyPair **out);
+
+SignalFfiError *signal_identity_key_pair_deserialize(SignalIdentityKeyPair **out, SignalBorrowedBuffer data);
+
+/**
+ * Serializes in the same `IdentityKeyPairStructure` format as [`IdentityKeyPair_Serialize`].
+ */
+SignalFfiError *signal_identity_key_pair_serialize(SignalOwnedBuffer *out, const SignalIdentityKeyPair *key_pair);
+
+SignalFfiError *signal_identity_key_pair_get_public_key(SignalPublicKey **out, const SignalIdentityKeyPair *key_pair);
+
+SignalFfiError *signal_identity_key_pair_get_private_key(SignalPrivateKey **out, const SignalIdentityKeyPair *key_pair);
+
+SignalFfiError *signal_identity_key_from_public_key(SignalIdentityKey **out, const SignalPublicKey *public_key);
+
+SignalFfiError *signal_identity_key_deserialize(SignalIdentityKey **out, SignalBorrowedBuffer data);
+
+/**
+ * Serializes in the same format as `PublicKey_Serialize` on the underlying key.
+ */
+SignalFfiError *signal_identity_key_serialize(SignalOwnedBuffer *out, const SignalIdentityKey *identity_key);
+
+SignalFfiError *signal_identity_key_get_public_key(SignalPublicKey **out, const SignalIdentityKey *identity_key);
+
 SignalFfiError *signal_identitykey_verify_alternate_identity(bool *out, const SignalPublicKey *public_key, const SignalPublicKey *other_identity, SignalBorrowedBuffer signature);
 
+SignalFfiError *signal_identity_key_changed(bool *out, const SignalPublicKey *stored_key, const SignalPublicKey *incoming_key);
+
 SignalFfiError *signal_fingerprint_new(SignalFingerprint **out, uint32_t iterations, uint32_t version, SignalBorrowedBuffer local_identifier, const SignalPublicKey *local_key, SignalBorrowedBuffer remote_identifier, const SignalPublicKey *remote_key);
 
 SignalFfiError *signal_fingerprint_scannable_encoding(SignalOwnedBuffer *out, const SignalFingerprint *obj);
 
 SignalFfiError *signal_fingerprint_display_string(const char **out, const SignalFingerprint *obj);
 
+SignalFfiError *signal_fingerprint_get_version(uint32_t *out, const SignalFingerprint *obj);
+
 SignalFfiError *signal_fingerprint_compare(bool *out, SignalBorrowedBuffer fprint1, SignalBorrowedBuffer fprint2);
 
 SignalFfiError *signal_message_deserialize(SignalMessage **out, SignalBorrowedBuffer data);
@@ -969,12 +1321,16 @@ SignalFfiError *signal_message_get_serialized(SignalOwnedBuffer *out, const Sign
 
 SignalFfiError *signal_message_get_counter(uint32_t *out, const SignalMessage *obj);
 
+SignalFfiError *signal_message_get_previous_counter(uint32_t *out, const SignalMessage *obj);
+
 SignalFfiError *signal_message_get_message_version(uint32_t *out, const SignalMessage *obj);
 
 SignalFfiError *signal_message_new(SignalMessage **out, uint8_t message_version, SignalBorrowedBuffer mac_key, const SignalPublicKey *sender_ratchet_key, uint32_t counter, uint32_t previous_counter, SignalBorrowedBuffer ciphertext, const SignalPublicKey *sender_identity_key, const SignalPublicKey *receiver_identity_key);
 
 SignalFfiError *signal_message_verify_mac(bool *out, const SignalMessage *msg, const SignalPublicKey *sender_identity_key, const SignalPublicKey *receiver_identity_key, SignalBorrowedBuffer mac_key);
 
+SignalFfiError *signal_message_expected_mac_length(size_t *out, uint8_t version);
+
 SignalFfiError *signal_message_get_sender_ratchet_key(SignalPublicKey **out, const SignalMessage *m);
 
 SignalFfiError *signal_pre_key_signal_message_new(SignalPreKeySignalMessage **out, uint8_t message_version, uint32_t registration_id, uint32_t pre_key_id, uint32_t signed_pre_key_id, const SignalPublicKey *base_key, const SignalPublicKey *identity_key, const SignalMessage *signal_message);
@@ -1009,6 +1365,8 @@ SignalFfiError *signal_sender_key_message_get_chain_id(uint32_t *out, const Sign
 
 SignalFfiError *signal_sender_key_message_get_iteration(uint32_t *out, const SignalSenderKeyMessage *obj);
 
+SignalFfiError *signal_sender_key_message_get_signature(SignalOwnedBuffer *out, const SignalSenderKeyMessage *obj);
+
 SignalFfiError *signal_sender_key_message_new(SignalSenderKeyMessage **out, uint8_t message_version, const uint8_t (*distribution_id)[16], uint32_t chain_id, uint32_t iteration, SignalBorrowedBuffer ciphertext, const SignalPrivateKey *pk);
 
 SignalFfiError *signal_sender_key_message_verify_signature(bool *out, const SignalSenderKeyMessage *skm, const SignalPublicKey *pubkey);
@@ -1025,6 +1383,8 @@ SignalFfiError *signal_sender_key_distribution_message_get_chain_id(uint32_t *ou
 
 SignalFfiError *signal_sender_key_distribution_message_get_iteration(uint32_t *out, const SignalSenderKeyDistributionMessage *obj);
 
+SignalFfiError *signal_sender_key_distribution_message_digest(uint8_t (*out)[32], const SignalSenderKeyDistributionMessage *obj);
+
 SignalFfiError *signal_sender_key_distribution_message_new(SignalSenderKeyDistributionMessage **out, uint8_t message_version, const uint8_t (*distribution_id)[16], uint32_t chain_id, uint32_t iteration, SignalBorrowedBuffer chainkey, const SignalPublicKey *pk);
 
 SignalFfiError *signal_sender_key_distribution_message_get_signature_key(SignalPublicKey **out, const SignalSenderKeyDistributionMessage *m);
@@ -1053,7 +1413,7 @@ SignalFfiError *signal_plaintext_content_from_decryption_error_message(SignalPla
 
 SignalFfiError *signal_pre_key_bundle_new(SignalPreKeyBundle **out, uint32_t registration_id, uint32_t device_id, uint32_t prekey_id, const SignalPublicKey *prekey, uint32_t signed_prekey_id, const SignalPublicKey *signed_prekey, SignalBorrowedBuffer signed_prekey_signature, const SignalPublicKey *identity_key, uint32_t kyber_prekey_id, const SignalKyberPublicKey *kyber_prekey, SignalBorrowedBuffer kyber_prekey_signature);
 
-SignalFfiError *signal_pre_key_bundle_get_identity_key(SignalPublicKey **out, const SignalPreKeyBundle *p);
+SignalFfiError *signal_pre_key_bundle_get_identity_key(SignalPublicKey **out, const SignalPreKeyBundle *obj);
 
 SignalFfiError *signal_pre_key_bundle_get_signed_pre_key_signature(SignalOwnedBuffer *out, const SignalPreKeyBundle *obj);
 
@@ -1075,6 +1435,10 @@ SignalFfiError *signal_pre_key_bundle_get_kyber_pre_key_public(SignalKyberPublic
 
 SignalFfiError *signal_pre_key_bundle_get_kyber_pre_key_signature(SignalOwnedBuffer *out, const SignalPreKeyBundle *bundle);
 
+SignalFfiError *signal_pre_key_bundle_matches_address(bool *out, const SignalPreKeyBundle *bundle, const SignalProtocolAddress *address);
+
+SignalFfiError *signal_pre_key_bundle_verify_signed_pre_key(bool *out, const SignalPreKeyBundle *bundle);
+
 SignalFfiError *signal_signed_pre_key_record_deserialize(SignalSignedPreKeyRecord **out, SignalBorrowedBuffer data);
 
 SignalFfiError *signal_signed_pre_key_record_get_signature(SignalOwnedBuffer *out, const SignalSignedPreKeyRecord *obj);
@@ -1121,10 +1485,22 @@ SignalFfiError *signal_pre_key_record_get_private_key(SignalPrivateKey **out, co
 
 SignalFfiError *signal_pre_key_record_new(SignalPreKeyRecord **out, uint32_t id, const SignalPublicKey *pub_key, const SignalPrivateKey *priv_key);
 
+/**
+ * Generates a random registration id, matching the ranges used by `KeyHelper` in Java.
+ *
+ * By default the id is in `1..=16380`, which keeps its protobuf encoding small; `extended`
+ * allows anything in `1..i32::MAX`.
+ */
+SignalFfiError *signal_generate_registration_id(uint32_t *out, bool extended);
+
 SignalFfiError *signal_sender_key_record_deserialize(SignalSenderKeyRecord **out, SignalBorrowedBuffer data);
 
 SignalFfiError *signal_sender_key_record_serialize(SignalOwnedBuffer *out, const SignalSenderKeyRecord *obj);
 
+SignalFfiError *signal_sender_key_record_new_empty(SignalSenderKeyRecord **out);
+
+SignalFfiError *signal_sender_key_record_is_empty(bool *out, const SignalSenderKeyRecord *obj);
+
 SignalFfiError *signal_server_certificate_deserialize(SignalServerCertificate **out, SignalBorrowedBuffer data);
 
 SignalFfiError *signal_server_certificate_get_serialized(SignalOwnedBuffer *out, const SignalServerCertificate *obj);
@@ -1139,6 +1515,8 @@ SignalFfiError *signal_server_certificate_get_key(SignalPublicKey **out, const S
 
 SignalFfiError *signal_server_certificate_new(SignalServerCertificate **out, uint32_t key_id, const SignalPublicKey *server_key, const SignalPrivateKey *trust_root);
 
+SignalFfiError *signal_server_certificate_validate(bool *out, const SignalServerCertificate *cert, const SignalPublicKey *trust_root);
+
 SignalFfiError *signal_sender_certificate_deserialize(SignalSenderCertificate **out, SignalBorrowedBuffer data);
 
 SignalFfiError *signal_sender_certificate_get_serialized(SignalOwnedBuffer *out, const SignalSenderCertificate *obj);
@@ -1149,6 +1527,8 @@ SignalFfiError *signal_sender_certificate_get_signature(SignalOwnedBuffer *out,
 
 SignalFfiError *signal_sender_certificate_get_sender_uuid(const char **out, const SignalSenderCertificate *obj);
 
+SignalFfiError *signal_sender_certificate_get_sender_uuid_bytes(uint8_t (*out)[16], const SignalSenderCertificate *obj);
+
 SignalFfiError *signal_sender_certificate_get_sender_e164(const char **out, const SignalSenderCertificate *obj);
 
 SignalFfiError *signal_sender_certificate_get_expiration(uint64_t *out, const SignalSenderCertificate *obj);
@@ -1159,6 +1539,8 @@ SignalFfiError *signal_sender_certificate_get_key(SignalPublicKey **out, const S
 
 SignalFfiError *signal_sender_certificate_validate(bool *out, const SignalSenderCertificate *cert, const SignalPublicKey *key, uint64_t time);
 
+SignalFfiError *signal_sender_certificate_validate_pinned(bool *out, const SignalSenderCertificate *cert, const SignalPublicKey *pinned_server_key, uint64_t time);
+
 SignalFfiError *signal_sender_certificate_get_server_certificate(SignalServerCertificate **out, const SignalSenderCertificate *cert);
 
 SignalFfiError *signal_sender_certificate_new(SignalSenderCertificate **out, const char *sender_uuid, const char *sender_e164, uint32_t sender_device_id, const SignalPublicKey *sender_key, uint64_t expiration, const SignalServerCertificate *signer_cert, const SignalPrivateKey *signer_key);
@@ -1185,20 +1567,59 @@ SignalFfiError *signal_ciphertext_message_serialize(SignalOwnedBuffer *out, cons
 
 SignalFfiError *signal_ciphertext_message_from_plaintext_content(SignalCiphertextMessage **out, const SignalPlaintextContent *m);
 
+SignalFfiError *signal_ciphertext_message_from_serialized(SignalCiphertextMessage **out, uint8_t message_type, SignalBorrowedBuffer data);
+
+SignalFfiError *signal_session_record_get_session_version(uint32_t *out, const SignalSessionRecord *s);
+
 SignalFfiError *signal_session_record_archive_current_state(SignalSessionRecord *session_record);
 
 SignalFfiError *signal_session_record_has_usable_sender_chain(bool *out, const SignalSessionRecord *s, uint64_t now);
 
 SignalFfiError *signal_session_record_current_ratchet_key_matches(bool *out, const SignalSessionRecord *s, const SignalPublicKey *key);
 
+SignalFfiError *signal_session_record_verify_chain_continuity(bool *out, const SignalSessionRecord *s, uint32_t from_counter, uint32_t to_counter);
+
+SignalFfiError *signal_session_record_wipe_secrets(SignalSessionRecord *session_record);
+
+SignalFfiError *signal_session_record_trim_history(SignalSessionRecord *session_record, uint32_t keep_messages);
+
 SignalFfiError *signal_session_record_deserialize(SignalSessionRecord **out, SignalBorrowedBuffer data);
 
+SignalFfiError *signal_session_record_deserialize_into(SignalSessionRecord *session_record, SignalBorrowedBuffer data);
+
+SignalFfiError *signal_session_record_deserialize_legacy(SignalSessionRecord **out, SignalBorrowedBuffer data);
+
 SignalFfiError *signal_session_record_serialize(SignalOwnedBuffer *out, const SignalSessionRecord *obj);
 
+SignalFfiError *signal_session_record_serialize_version(SignalOwnedBuffer *out, const SignalSessionRecord *s, uint32_t target_version);
+
 SignalFfiError *signal_session_record_get_local_registration_id(uint32_t *out, const SignalSessionRecord *obj);
 
 SignalFfiError *signal_session_record_get_remote_registration_id(uint32_t *out, const SignalSessionRecord *obj);
 
+SignalFfiError *signal_session_record_sender_counter(uint32_t *out, const SignalSessionRecord *obj);
+
+SignalFfiError *signal_session_record_get_sender_ratchet_key(SignalPublicKey **out, const SignalSessionRecord *obj);
+
+SignalFfiError *signal_session_record_receiver_counter(uint32_t *out, const SignalSessionRecord *obj);
+
+SignalFfiError *signal_session_record_has_current_session_state(bool *out, const SignalSessionRecord *obj);
+
+SignalFfiError *signal_session_record_has_sender_chain(bool *out, const SignalSessionRecord *obj);
+
+SignalFfiError *signal_session_record_needs_persist(bool *out, const SignalSessionRecord *obj);
+
+/**
+ * Returns the concatenated 32-byte commitments from [`SessionRecord::key_commitments`].
+ */
+SignalFfiError *signal_session_record_key_commitments(SignalOwnedBuffer *out, const SignalSessionRecord *s);
+
+SignalFfiError *signal_session_record_derive_ephemeral_signal_key(const SignalSessionRecord *s, SignalBorrowedMutableBuffer output);
+
+SignalFfiError *signal_session_record_state_hash(uint8_t (*out)[32], const SignalSessionRecord *obj);
+
+SignalFfiError *signal_session_record_import_verified(SignalSessionRecord **out, SignalBorrowedBuffer data, const SignalIdentityKeyStore *identity_store, const SignalProtocolAddress *address);
+
 SignalFfiError *signal_process_prekey_bundle(const SignalPreKeyBundle *bundle, const SignalProtocolAddress *protocol_address, const SignalSessionStore *session_store, const SignalIdentityKeyStore *identity_key_store, uint64_t now);
 
 SignalFfiError *signal_encrypt_message(SignalCiphertextMessage **out, SignalBorrowedBuffer ptext, const SignalProtocolAddress *protocol_address, const SignalSessionStore *session_store, const SignalIdentityKeyStore *identity_key_store, uint64_t now);
@@ -1207,12 +1628,43 @@ SignalFfiError *signal_decrypt_message(SignalOwnedBuffer *out, const SignalMessa
 
 SignalFfiError *signal_decrypt_pre_key_message(SignalOwnedBuffer *out, const SignalPreKeySignalMessage *message, const SignalProtocolAddress *protocol_address, const SignalSessionStore *session_store, const SignalIdentityKeyStore *identity_key_store, const SignalPreKeyStore *prekey_store, const SignalSignedPreKeyStore *signed_prekey_store, const SignalKyberPreKeyStore *kyber_prekey_store);
 
+SignalFfiError *signal_decrypt_message_with_limit(SignalOwnedBuffer *out, const SignalMessage *message, const SignalProtocolAddress *protocol_address, const SignalSessionStore *session_store, const SignalIdentityKeyStore *identity_key_store, uint32_t max_plaintext_len);
+
+/**
+ * Like `SessionCipher_DecryptSignalMessage`, but rejects a message that would leave more than
+ * `max_skipped_keys` keys stored for skipped messages in its receiving chain, with
+ * `SignalErrorCodeTooManySkippedKeys`. The limit can only be lowered from the default of 2,000.
+ */
+SignalFfiError *signal_decrypt_message_with_max_skipped_keys(SignalOwnedBuffer *out, const SignalMessage *message, const SignalProtocolAddress *protocol_address, const SignalSessionStore *session_store, const SignalIdentityKeyStore *identity_key_store, uint32_t max_skipped_keys);
+
+SignalFfiError *signal_decrypt_pre_key_message_with_limit(SignalOwnedBuffer *out, const SignalPreKeySignalMessage *message, const SignalProtocolAddress *protocol_address, const SignalSessionStore *session_store, const SignalIdentityKeyStore *identity_key_store, const SignalPreKeyStore *prekey_store, const SignalSignedPreKeyStore *signed_prekey_store, const SignalKyberPreKeyStore *kyber_prekey_store, uint32_t max_plaintext_len);
+
 SignalFfiError *signal_sealed_session_cipher_encrypt(SignalOwnedBuffer *out, const SignalProtocolAddress *destination, const SignalUnidentifiedSenderMessageContent *content, const SignalIdentityKeyStore *identity_key_store);
 
+/**
+ * Like `signal_sealed_session_cipher_encrypt`, but draws the message's ephemeral key from
+ * `random`. Only available in builds with `testing-fns`.
+ *
+ * Never pass the same `random` bytes for two messages: they would share an ephemeral key, and
+ * so an encryption keystream, which breaks the confidentiality of both.
+ */
+SignalFfiError *signal_sealed_session_cipher_encrypt_with_randomness(SignalOwnedBuffer *out, const SignalProtocolAddress *destination, const SignalUnidentifiedSenderMessageContent *content, const SignalIdentityKeyStore *identity_key_store, SignalBorrowedBuffer random);
+
 SignalFfiError *signal_sealed_sender_multi_recipient_encrypt(SignalOwnedBuffer *out, SignalBorrowedSliceOfProtocolAddress recipients, SignalBorrowedSliceOfSessionRecord recipient_sessions, SignalBorrowedBuffer excluded_recipients, const SignalUnidentifiedSenderMessageContent *content, const SignalIdentityKeyStore *identity_key_store);
 
+/**
+ * Like `signal_sealed_sender_multi_recipient_encrypt`, but draws the message's ephemeral keys
+ * from `random`. Only available in builds with `testing-fns`.
+ *
+ * Never pass the same `random` bytes for two messages: they would share ephemeral keys, and so
+ * encryption keystreams, which breaks the confidentiality of both.
+ */
+SignalFfiError *signal_sealed_sender_multi_recipient_encrypt_with_randomness(SignalOwnedBuffer *out, SignalBorrowedSliceOfProtocolAddress recipients, SignalBorrowedSliceOfSessionRecord recipient_sessions, SignalBorrowedBuffer excluded_recipients, const SignalUnidentifiedSenderMessageContent *content, const SignalIdentityKeyStore *identity_key_store, SignalBorrowedBuffer random);
+
 SignalFfiError *signal_sealed_sender_multi_recipient_message_for_single_recipient(SignalOwnedBuffer *out, SignalBorrowedBuffer encoded_multi_recipient_message);
 
+SignalFfiError *signal_sealed_sender_envelope_fingerprint(uint8_t (*out)[32], SignalBorrowedBuffer ciphertext);
+
 SignalFfiError *signal_sealed_session_cipher_decrypt_to_usmc(SignalUnidentifiedSenderMessageContent **out, SignalBorrowedBuffer ctext, const SignalIdentityKeyStore *identity_store);
 
 SignalFfiError *signal_sender_key_distribution_message_create(SignalSenderKeyDistributionMessage **out, const SignalProtocolAddress *sender, const uint8_t (*distribution_id)[16], const SignalSenderKeyStore *store);
@@ -1221,8 +1673,18 @@ SignalFfiError *signal_process_sender_key_distribution_message(const SignalProto
 
 SignalFfiError *signal_group_encrypt_message(SignalCiphertextMessage **out, const SignalProtocolAddress *sender, const uint8_t (*distribution_id)[16], SignalBorrowedBuffer message, const SignalSenderKeyStore *store);
 
+SignalFfiError *signal_group_encrypt_message_with_randomness(SignalCiphertextMessage **out, const SignalProtocolAddress *sender, const uint8_t (*distribution_id)[16], SignalBorrowedBuffer message, const SignalSenderKeyStore *store, SignalBorrowedBuffer random);
+
+SignalFfiError *signal_group_encrypt_with_expiry(SignalCiphertextMessage **out, const SignalProtocolAddress *sender, const uint8_t (*distribution_id)[16], SignalBorrowedBuffer message, uint32_t expiry_seconds, const SignalSenderKeyStore *store);
+
 SignalFfiError *signal_group_decrypt_message(SignalOwnedBuffer *out, const SignalProtocolAddress *sender, SignalBorrowedBuffer message, const SignalSenderKeyStore *store);
 
+/**
+ * Like `GroupCipher_DecryptMessage`, but rejects a message that would advance the sender's chain
+ * by more than `max_iteration_jump` messages. The limit can only be lowered from the default.
+ */
+SignalFfiError *signal_group_decrypt_message_with_max_jump(SignalOwnedBuffer *out, const SignalProtocolAddress *sender, SignalBorrowedBuffer message, const SignalSenderKeyStore *store, uint32_t max_iteration_jump);
+
 SignalFfiError *signal_device_transfer_generate_private_key(SignalOwnedBuffer *out);
 
 SignalFfiError *signal_device_transfer_generate_private_key_with_format(SignalOwnedBuffer *out, uint8_t key_format);
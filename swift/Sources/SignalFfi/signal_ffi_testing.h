@@ -20,6 +20,24 @@ typedef struct SignalComparableBackup SignalComparableBackup;
 
 SignalFfiError *signal_test_only_fn_returns_123(uint32_t *out);
 
+/**
+ * Derives the AES-CBC IV that message `counter` of a 1:1 session chain is encrypted with, given
+ * the chain's key at index 0.
+ *
+ * Exposed so that interop test vectors can be checked against each client.
+ */
+SignalFfiError *signal_derive_nonce_from_counter(uint8_t (*out)[16], const uint8_t (*chain_key)[32], uint32_t counter);
+
+/**
+ * Hashes the public inputs of an X3DH or PQXDH handshake, using the same computation as the
+ * `transcript_hash` methods on `AliceSignalProtocolParameters` and
+ * `BobSignalProtocolParameters`.
+ *
+ * Keys are in their serialized form. Pass an empty `bob_one_time_pre_key` if the handshake didn't
+ * use one, and an empty `bob_kyber_pre_key` and `kyber_ciphertext` for a pre-Kyber handshake.
+ */
+SignalFfiError *signal_x3dh_transcript_hash(uint8_t (*out)[32], SignalBorrowedBuffer alice_identity_key, SignalBorrowedBuffer alice_base_key, SignalBorrowedBuffer bob_identity_key, SignalBorrowedBuffer bob_signed_pre_key, SignalBorrowedBuffer bob_one_time_pre_key, SignalBorrowedBuffer bob_kyber_pre_key, SignalBorrowedBuffer kyber_ciphertext);
+
 SignalFfiError *signal_comparable_backup_destroy(SignalComparableBackup *p);
 
 SignalFfiError *signal_comparable_backup_read_unencrypted(SignalComparableBackup **out, const SignalInputStream *stream, uint64_t len, uint8_t purpose);
@@ -9,11 +9,447 @@ import SignalFfi
 import XCTest
 
 final class NativeTests: XCTestCase {
+    func testLastError() throws {
+        signal_error_clear_last()
+        XCTAssertEqual(signal_error_get_last_type(), 0)
+        XCTAssertNil(try invokeFnReturningOptionalString(fn: signal_error_take_last_message))
+
+        var key: OpaquePointer?
+        let error = [UInt8]([0x05, 1, 2, 3]).withUnsafeBorrowedBuffer {
+            signal_publickey_deserialize(&key, $0)
+        }
+        XCTAssertNotNil(error)
+        XCTAssertEqual(signal_error_get_last_type(), SignalErrorCodeInvalidKey.rawValue)
+        XCTAssertEqual(signal_error_get_last_type(), signal_error_get_type(error))
+        let expectedMessage = try invokeFnReturningString { signal_error_get_message(error, $0) }
+        signal_error_free(error)
+
+        let message = try invokeFnReturningOptionalString(fn: signal_error_take_last_message)
+        XCTAssertEqual(message, expectedMessage)
+        XCTAssertNil(try invokeFnReturningOptionalString(fn: signal_error_take_last_message))
+        XCTAssertEqual(signal_error_get_last_type(), 0)
+    }
+
+    func testSerializeInto() throws {
+        let record = try PreKeyRecord(id: 7, privateKey: PrivateKey.generate())
+        let expected = record.serialize()
+
+        func serializeInto(_ buffer: inout [UInt8]) -> (SignalFfiErrorRef?, Int) {
+            var written = 0
+            let error = record.withNativeHandle { handle in
+                buffer.withUnsafeMutableBytes {
+                    signal_pre_key_record_serialize_into(&written, handle, SignalBorrowedMutableBuffer($0))
+                }
+            }
+            return (error, written)
+        }
+
+        var exact = [UInt8](repeating: 0, count: expected.count)
+        let (exactError, exactWritten) = serializeInto(&exact)
+        XCTAssertNil(exactError)
+        XCTAssertEqual(exactWritten, expected.count)
+        XCTAssertEqual(exact, expected)
+
+        var tooSmall = [UInt8](repeating: 0, count: expected.count - 1)
+        let (tooSmallError, required) = serializeInto(&tooSmall)
+        XCTAssertNotNil(tooSmallError)
+        XCTAssertEqual(signal_error_get_type(tooSmallError), SignalErrorCodeBufferTooSmall.rawValue)
+        signal_error_free(tooSmallError)
+        XCTAssertEqual(required, expected.count)
+        XCTAssert(tooSmall.allSatisfy { $0 == 0 })
+
+        var oversized = [UInt8](repeating: 0, count: expected.count + 16)
+        let (oversizedError, oversizedWritten) = serializeInto(&oversized)
+        XCTAssertNil(oversizedError)
+        XCTAssertEqual(oversizedWritten, expected.count)
+        XCTAssertEqual(Array(oversized.prefix(expected.count)), expected)
+        XCTAssert(oversized.dropFirst(expected.count).allSatisfy { $0 == 0 })
+    }
+
+    func testIdentityKeyPairHandle() throws {
+        var keyPair: OpaquePointer?
+        try checkError(signal_identity_key_pair_generate(&keyPair))
+        defer { signal_identity_key_pair_destroy(keyPair) }
+
+        let serialized = try invokeFnReturningArray { signal_identity_key_pair_serialize($0, keyPair) }
+        let legacyPair = try IdentityKeyPair(bytes: serialized)
+        XCTAssertEqual(legacyPair.serialize(), serialized)
+
+        var roundTripped: OpaquePointer?
+        try serialized.withUnsafeBorrowedBuffer {
+            try checkError(signal_identity_key_pair_deserialize(&roundTripped, $0))
+        }
+        defer { signal_identity_key_pair_destroy(roundTripped) }
+        XCTAssertEqual(try invokeFnReturningArray { signal_identity_key_pair_serialize($0, roundTripped) }, serialized)
+
+        let publicKey: PublicKey = try invokeFnReturningNativeHandle {
+            signal_identity_key_pair_get_public_key($0, roundTripped)
+        }
+        let privateKey: PrivateKey = try invokeFnReturningNativeHandle {
+            signal_identity_key_pair_get_private_key($0, roundTripped)
+        }
+        XCTAssertEqual(publicKey, privateKey.publicKey)
+        XCTAssertEqual(publicKey, legacyPair.publicKey)
+    }
+
+    func testConstantTimeEquals() throws {
+        func constantTimeEquals(_ a: [UInt8], _ b: [UInt8]) throws -> Bool {
+            try a.withUnsafeBorrowedBuffer { a in
+                try b.withUnsafeBorrowedBuffer { b in
+                    try invokeFnReturningBool { signal_constant_time_equals($0, a, b) }
+                }
+            }
+        }
+
+        XCTAssert(try constantTimeEquals([], []))
+        XCTAssert(try constantTimeEquals([1, 2, 3], [1, 2, 3]))
+        XCTAssertFalse(try constantTimeEquals([1, 2, 3], [1, 2, 4]))
+        XCTAssertFalse(try constantTimeEquals([1, 2, 3], [1, 2]))
+        XCTAssertFalse(try constantTimeEquals([], [0]))
+
+        // A short-circuiting comparison would return almost immediately when the first byte differs.
+        let base = [UInt8](repeating: 0xA5, count: 1 << 16)
+        var differsFirst = base
+        differsFirst[0] ^= 1
+        var differsLast = base
+        differsLast[base.count - 1] ^= 1
+
+        func fastest(_ other: [UInt8]) throws -> UInt64 {
+            var best = UInt64.max
+            for _ in 0..<50 {
+                let start = DispatchTime.now().uptimeNanoseconds
+                XCTAssertFalse(try constantTimeEquals(base, other))
+                best = min(best, DispatchTime.now().uptimeNanoseconds - start)
+            }
+            return best
+        }
+
+        let ratio = Double(try fastest(differsFirst)) / Double(try fastest(differsLast))
+        XCTAssert((0.25...4).contains(ratio), "timing ratio \(ratio)")
+    }
+
 // These testing endpoints aren't generated in device builds, to save on code size.
 #if !os(iOS) || targetEnvironment(simulator)
     func testTestingFnsAreAvailable() async throws {
         let output = try invokeFnReturningInteger(fn: SignalFfi.signal_test_only_fn_returns_123)
         XCTAssertEqual(output, 123)
     }
+
+    func testDeriveNonceFromCounter() throws {
+        // The same chain key as the Rust ChainKey derivation tests.
+        let chainKey = [UInt8](fromHexString: "8ab72d6f4cc5ac0d387eaf463378ddb28edd07385b1cb01250c715982e7ad48f")!
+        func withFixedSizeArray<FixedSize, Result>(_ callback: (UnsafePointer<FixedSize>) throws -> Result) rethrows -> Result {
+            precondition(MemoryLayout<FixedSize>.size == chainKey.count)
+            return try chainKey.withUnsafeBytes { try callback($0.baseAddress!.assumingMemoryBound(to: FixedSize.self)) }
+        }
+        func deriveNonce(_ counter: UInt32) throws -> [UInt8] {
+            try withFixedSizeArray { chainKey in
+                try invokeFnReturningFixedLengthArray {
+                    signal_derive_nonce_from_counter($0, chainKey, counter)
+                }
+            }
+        }
+
+        XCTAssertEqual(try deriveNonce(0), [UInt8](fromHexString: "afa8207986b692a116d4b40bbff72d6c"))
+        XCTAssertEqual(try deriveNonce(1), [UInt8](fromHexString: "a313b409a9113f7d31ce86be9938d42d"))
+        XCTAssertEqual(try deriveNonce(5), [UInt8](fromHexString: "61accf1c13e0bd20ef8e096ce6ac7a82"))
+    }
+
+    func testPublicKeyRejectsUnknownFormat() throws {
+        let raw = IdentityKeyPair.generate().publicKey.keyBytes
+        XCTAssertThrowsError(try raw.withUnsafeBorrowedBuffer { buffer -> PublicKey in
+            try invokeFnReturningNativeHandle {
+                signal_publickey_deserialize_with_format($0, buffer, 2)
+            }
+        }) { error in
+            guard case SignalError.invalidArgument(_) = error else {
+                XCTFail("wrong error thrown: \(error)")
+                return
+            }
+        }
+    }
+
+    func testPublicKeyType() throws {
+        let key = try PublicKey(IdentityKeyPair.generate().publicKey.serialize())
+        let keyType = try key.withNativeHandle { keyHandle in
+            try invokeFnReturningInteger { signal_publickey_get_type($0, keyHandle) }
+        }
+        XCTAssertEqual(keyType, 0x05)
+        XCTAssertEqual(keyType, key.serialize()[0])
+    }
+
+    func testKeySerializedLen() throws {
+        let privateKey = PrivateKey.generate()
+        let publicKey = privateKey.publicKey
+
+        let publicLen = try publicKey.withNativeHandle { handle in
+            try invokeFnReturningInteger { signal_publickey_serialized_len($0, handle) }
+        }
+        XCTAssertEqual(publicLen, publicKey.serialize().count)
+
+        let privateLen = try privateKey.withNativeHandle { handle in
+            try invokeFnReturningInteger { signal_privatekey_serialized_len($0, handle) }
+        }
+        XCTAssertEqual(privateLen, privateKey.serialize().count)
+    }
+
+    func testSessionRecordDeserializeInto() throws {
+        let aliceStore = InMemorySignalProtocolStore(identity: IdentityKeyPair.generate(), registrationId: 1)
+
+        func sessionWithRegistrationId(_ registrationId: UInt32) throws -> [UInt8] {
+            let bobIdentity = IdentityKeyPair.generate()
+            let signedPreKey = PrivateKey.generate().publicKey
+            let bundle = try PreKeyBundle(
+                registrationId: registrationId,
+                deviceId: 1,
+                signedPrekeyId: 2,
+                signedPrekey: signedPreKey,
+                signedPrekeySignature: bobIdentity.privateKey.generateSignature(message: signedPreKey.serialize()),
+                identity: bobIdentity.identityKey
+            )
+            let address = try ProtocolAddress(name: "bob\(registrationId)", deviceId: 1)
+            try processPreKeyBundle(bundle, for: address, sessionStore: aliceStore, identityStore: aliceStore, context: NullContext())
+            return try XCTUnwrap(aliceStore.loadSession(for: address, context: NullContext())).serialize()
+        }
+
+        let first = try sessionWithRegistrationId(111)
+        let second = try sessionWithRegistrationId(222)
+
+        let record = try SessionRecord(bytes: first)
+        let originalHandle = record.withNativeHandle { $0 }
+
+        for (bytes, expectedRegistrationId) in [(second, UInt32(222)), (first, UInt32(111))] {
+            try record.withNativeHandle { handle in
+                try bytes.withUnsafeBorrowedBuffer {
+                    try checkError(signal_session_record_deserialize_into(handle, $0))
+                }
+            }
+            XCTAssertEqual(record.withNativeHandle { $0 }, originalHandle)
+            XCTAssertEqual(try record.remoteRegistrationId(), expectedRegistrationId)
+            XCTAssertEqual(record.serialize(), bytes)
+        }
+
+        let error = record.withNativeHandle { handle in
+            [UInt8]([1, 2, 3]).withUnsafeBorrowedBuffer {
+                signal_session_record_deserialize_into(handle, $0)
+            }
+        }
+        XCTAssertNotNil(error)
+        signal_error_free(error)
+        XCTAssertEqual(record.serialize(), first)
+    }
+
+    func testDecryptPreKeyMessageReportsSessionVersion() throws {
+        let aliceAddress = try ProtocolAddress(name: "alice", deviceId: 1)
+        let bobAddress = try ProtocolAddress(name: "bob", deviceId: 1)
+        let aliceStore = InMemorySignalProtocolStore()
+        let bobStore = InMemorySignalProtocolStore()
+
+        let bobIdentity = try bobStore.identityKeyPair(context: NullContext())
+        let signedPreKey = PrivateKey.generate()
+        let signedPreKeySignature = bobIdentity.privateKey.generateSignature(message: signedPreKey.publicKey.serialize())
+        try bobStore.storeSignedPreKey(
+            SignedPreKeyRecord(id: 2, timestamp: 42000, privateKey: signedPreKey, signature: signedPreKeySignature),
+            id: 2,
+            context: NullContext()
+        )
+        let bundle = try PreKeyBundle(
+            registrationId: bobStore.localRegistrationId(context: NullContext()),
+            deviceId: 1,
+            signedPrekeyId: 2,
+            signedPrekey: signedPreKey.publicKey,
+            signedPrekeySignature: signedPreKeySignature,
+            identity: bobIdentity.identityKey
+        )
+        try processPreKeyBundle(bundle, for: bobAddress, sessionStore: aliceStore, identityStore: aliceStore, context: NullContext())
+
+        func decrypt(_ plaintext: [UInt8], sessionVersion: UnsafeMutablePointer<UInt32>?) throws -> [UInt8] {
+            let message = try PreKeySignalMessage(bytes: signalEncrypt(
+                message: plaintext,
+                for: bobAddress,
+                sessionStore: aliceStore,
+                identityStore: aliceStore,
+                context: NullContext()
+            ).serialize())
+            return try withNativeHandles(message, aliceAddress) { messageHandle, addressHandle in
+                try withSessionStore(bobStore, NullContext()) { ffiSessionStore in
+                    try withIdentityKeyStore(bobStore, NullContext()) { ffiIdentityStore in
+                        try withPreKeyStore(bobStore, NullContext()) { ffiPreKeyStore in
+                            try withSignedPreKeyStore(bobStore, NullContext()) { ffiSignedPreKeyStore in
+                                try withKyberPreKeyStore(bobStore, NullContext()) { ffiKyberPreKeyStore in
+                                    try invokeFnReturningArray {
+                                        signal_decrypt_pre_key_message_with_session_version($0, sessionVersion, messageHandle, addressHandle, ffiSessionStore, ffiIdentityStore, ffiPreKeyStore, ffiSignedPreKeyStore, ffiKyberPreKeyStore)
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        // A null out-pointer skips reporting the version.
+        XCTAssertEqual(try decrypt([1], sessionVersion: nil), [1])
+
+        var sessionVersion: UInt32 = 0
+        XCTAssertEqual(try decrypt([2], sessionVersion: &sessionVersion), [2])
+        XCTAssertEqual(sessionVersion, 3)
+        XCTAssertEqual(try bobStore.loadSession(for: aliceAddress, context: NullContext())?.sessionVersion, sessionVersion)
+    }
+
+    func testEncryptAcceptsNullEmptyPlaintext() throws {
+        let aliceAddress = try ProtocolAddress(name: "alice", deviceId: 1)
+        let bobAddress = try ProtocolAddress(name: "bob", deviceId: 1)
+        let aliceStore = InMemorySignalProtocolStore()
+        let bobStore = InMemorySignalProtocolStore()
+
+        let bobIdentity = try bobStore.identityKeyPair(context: NullContext())
+        let signedPreKey = PrivateKey.generate()
+        let signedPreKeySignature = bobIdentity.privateKey.generateSignature(message: signedPreKey.publicKey.serialize())
+        try bobStore.storeSignedPreKey(
+            SignedPreKeyRecord(id: 2, timestamp: 42000, privateKey: signedPreKey, signature: signedPreKeySignature),
+            id: 2,
+            context: NullContext()
+        )
+        let bundle = try PreKeyBundle(
+            registrationId: bobStore.localRegistrationId(context: NullContext()),
+            deviceId: 1,
+            signedPrekeyId: 2,
+            signedPrekey: signedPreKey.publicKey,
+            signedPrekeySignature: signedPreKeySignature,
+            identity: bobIdentity.identityKey
+        )
+        try processPreKeyBundle(bundle, for: bobAddress, sessionStore: aliceStore, identityStore: aliceStore, context: NullContext())
+
+        let ciphertext: CiphertextMessage = try bobAddress.withNativeHandle { addressHandle in
+            try withSessionStore(aliceStore, NullContext()) { ffiSessionStore in
+                try withIdentityKeyStore(aliceStore, NullContext()) { ffiIdentityStore in
+                    try invokeFnReturningNativeHandle {
+                        signal_encrypt_message($0, SignalBorrowedBuffer(base: nil, length: 0), addressHandle, ffiSessionStore, ffiIdentityStore, 42000)
+                    }
+                }
+            }
+        }
+        XCTAssertEqual(ciphertext.messageType, .preKey)
+
+        let plaintext = try signalDecryptPreKey(
+            message: PreKeySignalMessage(bytes: ciphertext.serialize()),
+            from: aliceAddress,
+            sessionStore: bobStore,
+            identityStore: bobStore,
+            preKeyStore: bobStore,
+            signedPreKeyStore: bobStore,
+            kyberPreKeyStore: bobStore,
+            context: NullContext()
+        )
+        XCTAssertEqual(plaintext, [])
+
+        // A null base with a nonzero length is still rejected.
+        XCTAssertThrowsError(try bobAddress.withNativeHandle { addressHandle in
+            try withSessionStore(aliceStore, NullContext()) { ffiSessionStore in
+                try withIdentityKeyStore(aliceStore, NullContext()) { ffiIdentityStore in
+                    try invokeFnReturningNativeHandle {
+                        signal_encrypt_message($0, SignalBorrowedBuffer(base: nil, length: 1), addressHandle, ffiSessionStore, ffiIdentityStore, 42000)
+                    } as CiphertextMessage
+                }
+            }
+        })
+    }
+
+    func testTaggedHandlesRejectMismatchedTypes() throws {
+        let message = try SenderKeyMessage(
+            messageVersion: 3,
+            distributionId: UUID(),
+            chainId: 1,
+            iteration: 2,
+            ciphertext: [1, 2, 3],
+            signingKey: PrivateKey.generate()
+        )
+
+        message.withNativeHandle { handle in
+            var counter: UInt32 = 0
+            let error = signal_message_get_counter(&counter, handle)
+            XCTAssertNotNil(error)
+            XCTAssertEqual(signal_error_get_type(error), SignalErrorCodeInvalidType.rawValue)
+            signal_error_free(error)
+
+            let destroyError = signal_message_destroy(handle)
+            XCTAssertNotNil(destroyError)
+            XCTAssertEqual(signal_error_get_type(destroyError), SignalErrorCodeInvalidType.rawValue)
+            signal_error_free(destroyError)
+        }
+
+        // The mismatched destroy must not have freed the message.
+        XCTAssertEqual(message.iteration, 2)
+        XCTAssertEqual(message.chainId, 1)
+    }
+
+    func testTaggedEntryPointsRejectUntaggedHandles() throws {
+        let key = PrivateKey.generate()
+
+        key.withNativeHandle { handle in
+            var counter: UInt32 = 0
+            let error = signal_message_get_counter(&counter, handle)
+            XCTAssertNotNil(error)
+            XCTAssertEqual(signal_error_get_type(error), SignalErrorCodeInvalidType.rawValue)
+            signal_error_free(error)
+
+            let destroyError = signal_message_destroy(handle)
+            XCTAssertNotNil(destroyError)
+            XCTAssertEqual(signal_error_get_type(destroyError), SignalErrorCodeInvalidType.rawValue)
+            signal_error_free(destroyError)
+        }
+
+        // The key is still intact.
+        XCTAssertEqual(key.serialize().count, 32)
+    }
+
+    func testGenerateRegistrationId() throws {
+        for (extended, range) in [(false, UInt32(1)...16380), (true, UInt32(1)...UInt32(Int32.max - 1))] {
+            let ids = try (0..<200).map { _ in
+                try invokeFnReturningInteger { signal_generate_registration_id($0, extended) }
+            }
+            XCTAssert(ids.allSatisfy { range.contains($0) }, "out of range: \(ids)")
+            XCTAssertGreaterThan(Set(ids).count, 1)
+        }
+    }
+
+    func testX3dhTranscriptHash() throws {
+        func withBorrowedBuffers<Result>(_ inputs: ArraySlice<[UInt8]>, _ buffers: [SignalBorrowedBuffer] = [], _ body: ([SignalBorrowedBuffer]) throws -> Result) throws -> Result {
+            guard let first = inputs.first else {
+                return try body(buffers)
+            }
+            return try first.withUnsafeBorrowedBuffer {
+                try withBorrowedBuffers(inputs.dropFirst(), buffers + [$0], body)
+            }
+        }
+        func transcriptHash(_ inputs: [[UInt8]]) throws -> [UInt8] {
+            try withBorrowedBuffers(inputs[...]) { buffers in
+                try invokeFnReturningFixedLengthArray {
+                    signal_x3dh_transcript_hash($0, buffers[0], buffers[1], buffers[2], buffers[3], buffers[4], buffers[5], buffers[6])
+                }
+            }
+        }
+
+        // The same fixed keys as the Rust test, which checks that they make a working session.
+        func key(_ fill: UInt8) throws -> [UInt8] {
+            try PrivateKey(Array(repeating: fill, count: 32)).publicKey.serialize()
+        }
+        let (aliceIdentity, bobIdentity, bobSignedPreKey, bobOneTimePreKey, aliceBaseKey) =
+            try (key(1), key(2), key(3), key(4), key(5))
+
+        XCTAssertEqual(
+            try transcriptHash([aliceIdentity, aliceBaseKey, bobIdentity, bobSignedPreKey, bobOneTimePreKey, [], []]),
+            [UInt8](fromHexString: "e2a4a30425cd0b70530c98a1fe9ff117ccf8ceb787d0e4bd0878f25eea0d7fb0")
+        )
+        // Without a one-time pre-key.
+        XCTAssertEqual(
+            try transcriptHash([aliceIdentity, aliceBaseKey, bobIdentity, bobSignedPreKey, [], [], []]),
+            [UInt8](fromHexString: "d32975e938852153a4d74aa0b89c497d97b8c057ad015930b21edeedd827a970")
+        )
+        // A Kyber pre-key needs a ciphertext to go with it.
+        let kyberPreKey = KEMKeyPair.generate().publicKey.serialize()
+        XCTAssertThrowsError(try transcriptHash([aliceIdentity, aliceBaseKey, bobIdentity, bobSignedPreKey, [], kyberPreKey, []]))
+    }
 #endif
 }
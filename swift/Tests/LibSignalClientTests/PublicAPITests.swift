@@ -25,6 +25,27 @@ class PublicAPITests: TestCaseBase {
         XCTAssertEqual(derived, okm)
     }
 
+    func testHkdfRejectsEmptyOutput() {
+        XCTAssertThrowsError(try hkdf(outputLength: 0, inputKeyMaterial: [0x0B], salt: [], info: [])) { error in
+            guard case SignalError.invalidArgument(_) = error else {
+                XCTFail("wrong error thrown: \(error)")
+                return
+            }
+        }
+    }
+
+    func testDeriveLinkPreviewKey() {
+        let masterKey: [UInt8] = Array(0..<32)
+        let expected: [UInt8] = [
+            0xBA, 0xBF, 0xCE, 0xA4, 0x5A, 0x92, 0x91, 0xC1, 0xCA, 0x78, 0x76, 0x7C, 0xAB, 0x79,
+            0xA9, 0x5A, 0x5B, 0x1A, 0x5F, 0x70, 0xD6, 0xBD, 0x33, 0x5F, 0xC5, 0x3A, 0xB6, 0x67,
+            0x7A, 0x19, 0x6E, 0xF9,
+        ]
+
+        XCTAssertEqual(try! deriveLinkPreviewKey(masterKey: masterKey), expected)
+        XCTAssertNotEqual(try! deriveLinkPreviewKey(masterKey: Array(1...32)), expected)
+    }
+
     func testHkdfUsingRFCExample() {
         // https://tools.ietf.org/html/rfc5869 A.2
         let ikm: [UInt8] = Array(0...0x4F)
@@ -130,6 +151,20 @@ class PublicAPITests: TestCaseBase {
         XCTAssertEqual(addr.deviceId, 5)
     }
 
+    func testAddressRoundTripString() {
+        let addr = try! ProtocolAddress(name: "alice.example.org", deviceId: 12)
+        XCTAssertEqual(addr.description, "alice.example.org.12")
+
+        let parsed = try! ProtocolAddress(parsing: addr.description)
+        XCTAssertEqual(parsed, addr)
+        XCTAssertEqual(parsed.name, "alice.example.org")
+        XCTAssertEqual(parsed.deviceId, 12)
+
+        XCTAssertThrowsError(try ProtocolAddress(parsing: "alice"))
+        XCTAssertThrowsError(try ProtocolAddress(parsing: "alice.one"))
+        XCTAssertThrowsError(try ProtocolAddress(parsing: "alice."))
+    }
+
     func testAddressRoundTripServiceId() {
         let uuid = UUID()
         let aci = Aci(fromUUID: uuid)
@@ -256,11 +291,21 @@ class PublicAPITests: TestCaseBase {
         XCTAssertEqual(aliceFingerprint2.scannable.encoding, ALICE_SCANNABLE_FINGERPRINT_V2)
         XCTAssertEqual(bobFingerprint2.scannable.encoding, BOB_SCANNABLE_FINGERPRINT_V2)
 
+        XCTAssertEqual(aliceFingerprint.scannable.version, VERSION_1)
+        XCTAssertEqual(aliceFingerprint2.scannable.version, VERSION_2)
+        XCTAssertEqual(bobFingerprint2.scannable.version, VERSION_2)
+
         XCTAssertTrue(try! bobFingerprint2.scannable.compare(againstEncoding: aliceFingerprint2.scannable.encoding))
         XCTAssertTrue(try! aliceFingerprint2.scannable.compare(againstEncoding: bobFingerprint2.scannable.encoding))
 
-        XCTAssertThrowsError(try bobFingerprint2.scannable.compare(againstEncoding: aliceFingerprint.scannable.encoding))
-        XCTAssertThrowsError(try bobFingerprint.scannable.compare(againstEncoding: aliceFingerprint2.scannable.encoding))
+        for (local, remote) in [(bobFingerprint2, aliceFingerprint), (bobFingerprint, aliceFingerprint2)] {
+            XCTAssertThrowsError(try local.scannable.compare(againstEncoding: remote.scannable.encoding)) { error in
+                guard case SignalError.fingerprintVersionMismatch(_) = error else {
+                    XCTFail("unexpected error: \(error)")
+                    return
+                }
+            }
+        }
 
         // testMismatchingFingerprints
 
@@ -348,6 +393,45 @@ class PublicAPITests: TestCaseBase {
         let b_ptext = try! groupDecrypt(a_ctext, from: sender, store: b_store, context: NullContext())
 
         XCTAssertEqual(b_ptext, [1, 2, 3])
+
+        XCTAssertThrowsError(try groupDecrypt(a_ctext, from: sender, store: b_store, context: NullContext())) { error in
+            guard case SignalError.duplicatedMessage(_) = error else {
+                XCTFail("wrong error thrown: \(error)")
+                return
+            }
+        }
+    }
+
+    func testGroupCipherWithExpiry() throws {
+        let sender = try ProtocolAddress(name: "+14159999111", deviceId: 4)
+        let distribution_id = UUID(uuidString: "d1d1d1d1-7000-11eb-b32a-33b8a8a487a6")!
+
+        let a_store = InMemorySignalProtocolStore()
+        let skdm = try SenderKeyDistributionMessage(from: sender, distributionId: distribution_id, store: a_store, context: NullContext())
+
+        let b_store = InMemorySignalProtocolStore()
+        try processSenderKeyDistributionMessage(
+            SenderKeyDistributionMessage(bytes: skdm.serialize()),
+            from: sender,
+            store: b_store,
+            context: NullContext()
+        )
+
+        let expirySeconds: UInt32 = 7 * 24 * 60 * 60
+        for expectedIteration: UInt32 in 0..<2 {
+            let a_ctext = try groupEncrypt([1, 2, 3], from: sender, distributionId: distribution_id, expirySeconds: expirySeconds, store: a_store, context: NullContext()).serialize()
+            let result = try groupDecryptWithExpiry(a_ctext, from: sender, store: b_store, context: NullContext())
+            XCTAssertEqual(result.message, [1, 2, 3])
+            XCTAssertEqual(result.expirySeconds, expirySeconds)
+            XCTAssertEqual(result.iteration, expectedIteration)
+        }
+
+        // A message sent without a timer decrypts with none.
+        let plainCtext = try groupEncrypt([0, 0, 0, 60, 1, 2, 3], from: sender, distributionId: distribution_id, store: a_store, context: NullContext()).serialize()
+        let plainResult = try groupDecryptWithExpiry(plainCtext, from: sender, store: b_store, context: NullContext())
+        XCTAssertEqual(plainResult.message, [0, 0, 0, 60, 1, 2, 3])
+        XCTAssertNil(plainResult.expirySeconds)
+        XCTAssertEqual(plainResult.iteration, 2)
     }
 
     func testGroupCipherWithContext() {
@@ -409,6 +493,8 @@ class PublicAPITests: TestCaseBase {
         XCTAssertEqual(senderCert.publicKey.serialize().count, 33)
 
         XCTAssertEqual(senderCert.senderUuid, "9d0652a3-dcc3-4d11-975f-74d61598733f")
+        let expectedUuid = UUID(uuidString: "9d0652a3-dcc3-4d11-975f-74d61598733f")!
+        XCTAssertEqual(try senderCert.senderUuidBytes(), withUnsafeBytes(of: expectedUuid.uuid) { Array($0) })
         XCTAssertEqual(senderCert.senderAci.serviceIdString, "9d0652a3-dcc3-4d11-975f-74d61598733f")
         XCTAssertEqual(senderCert.senderE164, Optional("+14152222222"))
 
@@ -419,6 +505,22 @@ class PublicAPITests: TestCaseBase {
         XCTAssertEqual(serverCert.signatureBytes.count, 64)
     }
 
+    func testServerCertificateValidate() throws {
+        let trustRoot = IdentityKeyPair.generate()
+        let serverKeys = IdentityKeyPair.generate()
+        let serverCert = try ServerCertificate(keyId: 7, publicKey: serverKeys.publicKey, trustRoot: trustRoot.privateKey)
+
+        XCTAssertEqual(serverCert.keyId, 7)
+        XCTAssertTrue(try serverCert.validate(trustRoot: trustRoot.publicKey))
+        XCTAssertTrue(try ServerCertificate(serverCert.serialize()).validate(trustRoot: trustRoot.publicKey))
+
+        XCTAssertFalse(try serverCert.validate(trustRoot: IdentityKeyPair.generate().publicKey))
+        XCTAssertFalse(try serverCert.validate(trustRoot: serverKeys.publicKey))
+
+        let forged = try ServerCertificate(keyId: 7, publicKey: serverKeys.publicKey, trustRoot: serverKeys.privateKey)
+        XCTAssertFalse(try forged.validate(trustRoot: trustRoot.publicKey))
+    }
+
     func testSenderCertificateGetSenderAci() {
         let aci = Aci(fromUUID: UUID())
         let trustRoot = IdentityKeyPair.generate()
@@ -437,6 +539,194 @@ class PublicAPITests: TestCaseBase {
         XCTAssertEqual(aci, senderCert.senderAci)
     }
 
+    func testSenderCertificateIssueAndValidate() throws {
+        let trustRoot = IdentityKeyPair.generate()
+        let serverKeys = IdentityKeyPair.generate()
+        let serverCert = try ServerCertificate(keyId: 7, publicKey: serverKeys.publicKey, trustRoot: trustRoot.privateKey)
+        let senderKey = IdentityKeyPair.generate().publicKey
+
+        for e164 in [nil, "+14152222222"] {
+            let aci = Aci(fromUUID: UUID())
+            let senderCert = try SenderCertificate(
+                sender: SealedSenderAddress(e164: e164, aci: aci, deviceId: 3),
+                publicKey: senderKey,
+                expiration: 31337,
+                signerCertificate: serverCert,
+                signerKey: serverKeys.privateKey
+            )
+
+            XCTAssertTrue(try senderCert.validate(trustRoot: trustRoot.publicKey, time: 31336))
+            XCTAssertFalse(try senderCert.validate(trustRoot: trustRoot.publicKey, time: 31338))
+            XCTAssertFalse(try senderCert.validate(trustRoot: serverKeys.publicKey, time: 31336))
+
+            XCTAssertEqual(senderCert.senderAci, aci)
+            XCTAssertEqual(senderCert.senderUuid, aci.rawUUID.uuidString.lowercased())
+            XCTAssertEqual(senderCert.senderE164, e164)
+            XCTAssertEqual(senderCert.deviceId, 3)
+            XCTAssertEqual(senderCert.expiration, 31337)
+            XCTAssertEqual(senderCert.publicKey, senderKey)
+            XCTAssertEqual(senderCert.serverCertificate.keyId, 7)
+            XCTAssertEqual(senderCert.serverCertificate.serialize(), serverCert.serialize())
+        }
+    }
+
+    func testSenderCertificateEmptyE164IsNotNil() throws {
+        let trustRoot = IdentityKeyPair.generate()
+        let serverKeys = IdentityKeyPair.generate()
+        let serverCert = try ServerCertificate(keyId: 1, publicKey: serverKeys.publicKey, trustRoot: trustRoot.privateKey)
+
+        func roundTrip(e164: String?) throws -> SenderCertificate {
+            let senderCert = try SenderCertificate(
+                sender: SealedSenderAddress(e164: e164, aci: Aci(fromUUID: UUID()), deviceId: 1),
+                publicKey: IdentityKeyPair.generate().publicKey,
+                expiration: 31337,
+                signerCertificate: serverCert,
+                signerKey: serverKeys.privateKey
+            )
+            return try SenderCertificate(senderCert.serialize())
+        }
+
+        XCTAssertNil(try roundTrip(e164: nil).senderE164)
+        XCTAssertEqual(try roundTrip(e164: "").senderE164, Optional(""))
+        XCTAssertEqual(try roundTrip(e164: "+14152222222").senderE164, Optional("+14152222222"))
+    }
+
+    func testSenderCertificateValidateBatch() throws {
+        let trustRoot = IdentityKeyPair.generate()
+        let serverKeys = IdentityKeyPair.generate()
+        let serverCert = try ServerCertificate(keyId: 1, publicKey: serverKeys.publicKey, trustRoot: trustRoot.privateKey)
+
+        let expirations: [UInt64] = [31337, 100, 40000]
+        let certificates = try expirations.map { expiration in
+            try SenderCertificate(
+                sender: SealedSenderAddress(aci: Aci(fromUUID: UUID()), deviceId: 1),
+                publicKey: IdentityKeyPair.generate().publicKey,
+                expiration: expiration,
+                signerCertificate: serverCert,
+                signerKey: serverKeys.privateKey
+            )
+        }
+
+        let results = try SenderCertificate.validate(certificates, trustRoot: trustRoot.publicKey, time: 31000)
+        XCTAssertEqual(results, [true, false, true])
+        XCTAssertEqual(results[1], try certificates[1].validate(trustRoot: trustRoot.publicKey, time: 31000))
+
+        XCTAssertEqual(try SenderCertificate.validate([], trustRoot: trustRoot.publicKey, time: 31000), [])
+    }
+
+    func testPublicKeyDeserializeBatch() throws {
+        let keys = (0..<2).map { _ in IdentityKeyPair.generate().publicKey }
+        let malformed: [UInt8] = [0x05] + Array(repeating: 0x01, count: 16)
+
+        let results = try PublicKey.deserialize(batch: [keys[0].serialize(), malformed, keys[1].serialize()])
+        XCTAssertEqual(results.count, 3)
+        XCTAssertEqual(results[0]?.serialize(), keys[0].serialize())
+        XCTAssertNil(results[1])
+        XCTAssertEqual(results[2]?.serialize(), keys[1].serialize())
+
+        XCTAssertEqual(try PublicKey.deserialize(batch: []).count, 0)
+    }
+
+    func testSenderKeyMessageFromParts() throws {
+        let signingKey = IdentityKeyPair.generate()
+        let distributionId = UUID(uuidString: "d1d1d1d1-7000-11eb-b32a-33b8a8a487a6")!
+
+        let message = try SenderKeyMessage(
+            messageVersion: 3,
+            distributionId: distributionId,
+            chainId: 7,
+            iteration: 42,
+            ciphertext: [1, 2, 3],
+            signingKey: signingKey.privateKey
+        )
+        XCTAssertEqual(message.distributionId, distributionId)
+        XCTAssertEqual(message.chainId, 7)
+        XCTAssertEqual(message.iteration, 42)
+        XCTAssertEqual(message.ciphertext, [1, 2, 3])
+        XCTAssertTrue(try message.verifySignature(against: signingKey.publicKey))
+        XCTAssertFalse(try message.verifySignature(against: IdentityKeyPair.generate().publicKey))
+
+        let roundTripped = try SenderKeyMessage(bytes: message.serialize())
+        XCTAssertEqual(roundTripped.iteration, 42)
+        XCTAssertTrue(try roundTripped.verifySignature(against: signingKey.publicKey))
+    }
+
+    func testSignalMessageFromParts() throws {
+        let macKey = [UInt8](repeating: 0x42, count: 32)
+        let ratchetKey = IdentityKeyPair.generate().publicKey
+        let sender = IdentityKeyPair.generate().publicKey
+        let receiver = IdentityKeyPair.generate().publicKey
+
+        let message = try SignalMessage(
+            messageVersion: 4,
+            macKey: macKey,
+            senderRatchetKey: ratchetKey,
+            counter: 42,
+            previousCounter: 41,
+            ciphertext: [1, 2, 3],
+            senderIdentityKey: sender,
+            receiverIdentityKey: receiver
+        )
+        let roundTripped = try SignalMessage(bytes: message.serialize())
+        XCTAssertEqual(roundTripped.serialize(), message.serialize())
+        XCTAssertEqual(roundTripped.body, [1, 2, 3])
+        XCTAssertEqual(roundTripped.counter, 42)
+        XCTAssertEqual(roundTripped.previousCounter, 41)
+        XCTAssertEqual(roundTripped.messageVersion, 4)
+        XCTAssertEqual(roundTripped.senderRatchetKey, ratchetKey)
+        XCTAssertTrue(try roundTripped.verifyMac(sender: sender, receiver: receiver, macKey: macKey))
+        XCTAssertFalse(try roundTripped.verifyMac(sender: receiver, receiver: sender, macKey: macKey))
+
+        XCTAssertThrowsError(try SignalMessage(
+            messageVersion: 4,
+            macKey: [UInt8](repeating: 0x42, count: 31),
+            senderRatchetKey: ratchetKey,
+            counter: 42,
+            previousCounter: 41,
+            ciphertext: [1, 2, 3],
+            senderIdentityKey: sender,
+            receiverIdentityKey: receiver
+        )) { error in
+            guard case SignalError.invalidKey(_) = error else {
+                XCTFail("wrong error thrown: \(error)")
+                return
+            }
+        }
+    }
+
+    func testPublicKeySerializationFormats() throws {
+        let key = IdentityKeyPair.generate().publicKey
+
+        let prefixed = key.serialize(format: .prefixed)
+        XCTAssertEqual(prefixed, key.serialize())
+        XCTAssertEqual(try PublicKey(prefixed, format: .prefixed), key)
+
+        let raw = key.serialize(format: .raw)
+        XCTAssertEqual(raw, key.keyBytes)
+        XCTAssertEqual(raw.count, 32)
+        XCTAssertEqual(try PublicKey(raw, format: .raw), key)
+
+        XCTAssertThrowsError(try PublicKey(raw, format: .prefixed))
+    }
+
+    func testKeyLengthErrors() {
+        for length in [31, 34] {
+            let bytes = [UInt8](repeating: 9, count: length)
+            XCTAssertThrowsError(try PublicKey(bytes, format: .raw)) { error in
+                guard case SignalError.invalidKeyLength(_) = error else {
+                    XCTFail("wrong error thrown: \(error)")
+                    return
+                }
+            }
+            XCTAssertThrowsError(try PrivateKey(bytes)) { error in
+                guard case SignalError.invalidKeyLength(_) = error else {
+                    XCTFail("wrong error thrown: \(error)")
+                    return
+                }
+            }
+        }
+    }
+
     private func testRoundTrip<Handle>(_ initial: Handle, serialize: (Handle) -> [UInt8], deserialize: ([UInt8]) throws -> Handle, line: UInt = #line) {
         let bytes = serialize(initial)
         let roundTripBytes = serialize(try! deserialize(bytes))
@@ -487,6 +777,14 @@ class PublicAPITests: TestCaseBase {
         XCTAssert(try! secondary.identityKey.verifyAlternateIdentity(primary.identityKey, signature: signature))
     }
 
+    func testIdentityKeyHasChanged() throws {
+        let stored = IdentityKeyPair.generate().identityKey
+
+        XCTAssertFalse(stored.hasChanged(from: nil), "first contact is trusted")
+        XCTAssertFalse(try IdentityKey(bytes: stored.serialize()).hasChanged(from: stored))
+        XCTAssertTrue(IdentityKeyPair.generate().identityKey.hasChanged(from: stored))
+    }
+
     func testPreKeyBundleAccessors() {
         let registrationId: UInt32 = 123
         let deviceId: UInt32 = 5
@@ -551,4 +849,31 @@ class PublicAPITests: TestCaseBase {
             XCTAssertEqual(bundle.kyberPreKeySignature, kyberPreKeySignature)
         }
     }
+
+    func testPreKeyBundleMatchesAddress() {
+        let identityKeyPair = IdentityKeyPair.generate()
+        let signedPreKey = IdentityKeyPair.generate().publicKey
+        let signedPreKeySignature = identityKeyPair.privateKey.generateSignature(message: signedPreKey.serialize())
+        let bundle = try! PreKeyBundle(registrationId: 123, deviceId: 5, signedPrekeyId: 20, signedPrekey: signedPreKey, signedPrekeySignature: signedPreKeySignature, identity: identityKeyPair.identityKey)
+
+        XCTAssertTrue(bundle.matches(try! ProtocolAddress(name: "+14151111111", deviceId: 5)))
+        XCTAssertFalse(bundle.matches(try! ProtocolAddress(name: "+14151111111", deviceId: 6)))
+    }
+
+    func testPreKeyBundleVerifySignedPreKey() throws {
+        let identityKeyPair = IdentityKeyPair.generate()
+        let signedPreKey = IdentityKeyPair.generate().publicKey
+        let signedPreKeySignature = identityKeyPair.privateKey.generateSignature(message: signedPreKey.serialize())
+
+        let bundle = try PreKeyBundle(registrationId: 123, deviceId: 5, signedPrekeyId: 20, signedPrekey: signedPreKey, signedPrekeySignature: signedPreKeySignature, identity: identityKeyPair.identityKey)
+        XCTAssertTrue(try bundle.verifySignedPreKey())
+
+        var tamperedSignature = signedPreKeySignature
+        tamperedSignature[0] ^= 1
+        let tampered = try PreKeyBundle(registrationId: 123, deviceId: 5, signedPrekeyId: 20, signedPrekey: signedPreKey, signedPrekeySignature: tamperedSignature, identity: identityKeyPair.identityKey)
+        XCTAssertFalse(try tampered.verifySignedPreKey())
+
+        let mismatched = try PreKeyBundle(registrationId: 123, deviceId: 5, signedPrekeyId: 20, signedPrekey: signedPreKey, signedPrekeySignature: signedPreKeySignature, identity: IdentityKeyPair.generate().identityKey)
+        XCTAssertFalse(try mismatched.verifySignedPreKey())
+    }
 }
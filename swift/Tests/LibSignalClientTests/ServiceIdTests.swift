@@ -52,6 +52,13 @@ class ServiceIdTests: TestCaseBase {
         let _: Pni = try! Pni.parseFrom(
             serviceIdString: "PNI:" + Self.TEST_UUID_STRING)
 
+        for (input, kind) in [(Self.TEST_UUID_STRING, ServiceIdKind.aci), ("PNI:" + Self.TEST_UUID_STRING, .pni)] {
+            let parsed = try ServiceId.parseFrom(serviceIdString: input)
+            XCTAssertEqual(kind, parsed.kind)
+            XCTAssertEqual(Self.TEST_UUID, parsed.rawUUID)
+            XCTAssertEqual(input, parsed.serviceIdString)
+        }
+
         do {
             _ = try ServiceId.parseFrom(serviceIdString: "ACI:" + Self.TEST_UUID_STRING)
             XCTFail("Should have failed")
@@ -251,6 +251,7 @@ class SessionTests: TestCaseBase {
         XCTAssertEqual(plaintext.message, message)
         XCTAssertEqual(plaintext.sender, sender_addr)
         XCTAssertEqual(plaintext.sender.senderAci, alice_address.serviceId)
+        XCTAssertEqual(plaintext.senderServiceIdKind, .aci)
 
         let innerMessage = try signalEncrypt(
             message: [],
@@ -283,6 +284,220 @@ class SessionTests: TestCaseBase {
         }
     }
 
+    func testSealedSenderDecryptToContent() throws {
+        let alice_address = try ProtocolAddress(name: "9d0652a3-dcc3-4d11-975f-74d61598733f", deviceId: 1)
+        let bob_address = try ProtocolAddress(name: "6838237D-02F6-4098-B110-698253D15961", deviceId: 1)
+
+        let alice_store = InMemorySignalProtocolStore()
+        let bob_store = InMemorySignalProtocolStore()
+
+        initializeSessionsV3(alice_store: alice_store, bob_store: bob_store, bob_address: bob_address)
+
+        let trust_root = IdentityKeyPair.generate()
+        let server_keys = IdentityKeyPair.generate()
+        let server_cert = try ServerCertificate(keyId: 1, publicKey: server_keys.publicKey, trustRoot: trust_root.privateKey)
+        let sender_addr = try SealedSenderAddress(e164: nil, uuidString: alice_address.name, deviceId: 1)
+        let sender_cert = try SenderCertificate(
+            sender: sender_addr,
+            publicKey: alice_store.identityKeyPair(context: NullContext()).publicKey,
+            expiration: 31337,
+            signerCertificate: server_cert,
+            signerKey: server_keys.privateKey
+        )
+
+        let message = Array("routed later".utf8)
+        let ciphertext = try sealedSenderEncrypt(
+            message: message,
+            for: bob_address,
+            from: sender_cert,
+            sessionStore: alice_store,
+            identityStore: alice_store,
+            context: NullContext()
+        )
+
+        // Decrypting the outer layer doesn't check the sender; that's left to the caller.
+        let content = try UnidentifiedSenderMessageContent(
+            message: ciphertext,
+            identityStore: bob_store,
+            context: NullContext()
+        )
+        XCTAssertEqual(content.senderCertificate.serialize(), sender_cert.serialize())
+        XCTAssertTrue(try content.senderCertificate.validate(trustRoot: trust_root.publicKey, time: 31335))
+        XCTAssertFalse(try content.senderCertificate.validate(trustRoot: server_keys.publicKey, time: 31335))
+        XCTAssertEqual(content.messageType, .preKey)
+
+        let plaintext = try signalDecryptPreKey(
+            message: PreKeySignalMessage(bytes: content.contents),
+            from: alice_address,
+            sessionStore: bob_store,
+            identityStore: bob_store,
+            preKeyStore: bob_store,
+            signedPreKeyStore: bob_store,
+            kyberPreKeyStore: bob_store,
+            context: NullContext()
+        )
+        XCTAssertEqual(plaintext, message)
+    }
+
+    func testUnidentifiedSenderMessageContentRoundTrip() throws {
+        let alice_address = try ProtocolAddress(name: "9d0652a3-dcc3-4d11-975f-74d61598733f", deviceId: 1)
+        let bob_address = try ProtocolAddress(name: "6838237D-02F6-4098-B110-698253D15961", deviceId: 1)
+
+        let alice_store = InMemorySignalProtocolStore()
+        let bob_store = InMemorySignalProtocolStore()
+
+        initializeSessionsV3(alice_store: alice_store, bob_store: bob_store, bob_address: bob_address)
+
+        // Complete the handshake so that Bob's reply is a plain SignalMessage.
+        let ctext = try signalEncrypt(
+            message: [],
+            for: bob_address,
+            sessionStore: alice_store,
+            identityStore: alice_store,
+            context: NullContext()
+        )
+        _ = try signalDecryptPreKey(
+            message: PreKeySignalMessage(bytes: ctext.serialize()),
+            from: alice_address,
+            sessionStore: bob_store,
+            identityStore: bob_store,
+            preKeyStore: bob_store,
+            signedPreKeyStore: bob_store,
+            kyberPreKeyStore: bob_store,
+            context: NullContext()
+        )
+        let reply = try signalEncrypt(
+            message: [1, 2, 3],
+            for: alice_address,
+            sessionStore: bob_store,
+            identityStore: bob_store,
+            context: NullContext()
+        )
+        XCTAssertEqual(reply.messageType, .whisper)
+
+        let trust_root = IdentityKeyPair.generate()
+        let server_keys = IdentityKeyPair.generate()
+        let server_cert = try ServerCertificate(keyId: 1, publicKey: server_keys.publicKey, trustRoot: trust_root.privateKey)
+        let sender_cert = try SenderCertificate(
+            sender: SealedSenderAddress(e164: nil, uuidString: bob_address.name, deviceId: 1),
+            publicKey: bob_store.identityKeyPair(context: NullContext()).publicKey,
+            expiration: 31337,
+            signerCertificate: server_cert,
+            signerKey: server_keys.privateKey
+        )
+
+        let content = try UnidentifiedSenderMessageContent(
+            reply,
+            from: sender_cert,
+            contentHint: .resendable,
+            groupId: [7, 7]
+        )
+        XCTAssertEqual(content.messageType, .whisper)
+        XCTAssertEqual(content.contents, reply.serialize())
+
+        let roundTripped = try UnidentifiedSenderMessageContent(bytes: content.serialize())
+        XCTAssertEqual(roundTripped.serialize(), content.serialize())
+        XCTAssertEqual(roundTripped.messageType, .whisper)
+        XCTAssertEqual(roundTripped.contents, reply.serialize())
+        XCTAssertEqual(roundTripped.contentHint, .resendable)
+        XCTAssertEqual(roundTripped.groupId, [7, 7])
+        XCTAssertEqual(roundTripped.senderCertificate.serialize(), sender_cert.serialize())
+    }
+
+    func testUnidentifiedSenderMessageContentReportsInnerType() throws {
+        let alice_address = try ProtocolAddress(name: "9d0652a3-dcc3-4d11-975f-74d61598733f", deviceId: 1)
+        let bob_address = try ProtocolAddress(name: "6838237D-02F6-4098-B110-698253D15961", deviceId: 1)
+
+        let alice_store = InMemorySignalProtocolStore()
+        let bob_store = InMemorySignalProtocolStore()
+
+        initializeSessionsV3(alice_store: alice_store, bob_store: bob_store, bob_address: bob_address)
+
+        let trust_root = IdentityKeyPair.generate()
+        let server_keys = IdentityKeyPair.generate()
+        let server_cert = try ServerCertificate(keyId: 1, publicKey: server_keys.publicKey, trustRoot: trust_root.privateKey)
+        let sender_cert = try SenderCertificate(
+            sender: SealedSenderAddress(e164: nil, uuidString: alice_address.name, deviceId: 1),
+            publicKey: alice_store.identityKeyPair(context: NullContext()).publicKey,
+            expiration: 31337,
+            signerCertificate: server_cert,
+            signerKey: server_keys.privateKey
+        )
+
+        let preKeyMessage = try signalEncrypt(
+            message: [],
+            for: bob_address,
+            sessionStore: alice_store,
+            identityStore: alice_store,
+            context: NullContext()
+        )
+        _ = try signalDecryptPreKey(
+            message: PreKeySignalMessage(bytes: preKeyMessage.serialize()),
+            from: alice_address,
+            sessionStore: bob_store,
+            identityStore: bob_store,
+            preKeyStore: bob_store,
+            signedPreKeyStore: bob_store,
+            kyberPreKeyStore: bob_store,
+            context: NullContext()
+        )
+        let reply = try signalEncrypt(
+            message: [],
+            for: alice_address,
+            sessionStore: bob_store,
+            identityStore: bob_store,
+            context: NullContext()
+        )
+        _ = try signalDecrypt(
+            message: SignalMessage(bytes: reply.serialize()),
+            from: bob_address,
+            sessionStore: alice_store,
+            identityStore: alice_store,
+            context: NullContext()
+        )
+        let whisperMessage = try signalEncrypt(
+            message: [],
+            for: bob_address,
+            sessionStore: alice_store,
+            identityStore: alice_store,
+            context: NullContext()
+        )
+
+        let distributionId = UUID(uuidString: "d1d1d1d1-7000-11eb-b32a-33b8a8a487a6")!
+        _ = try SenderKeyDistributionMessage(from: alice_address, distributionId: distributionId, store: alice_store, context: NullContext())
+        let senderKeyMessage = try groupEncrypt([], from: alice_address, distributionId: distributionId, store: alice_store, context: NullContext())
+
+        let plaintextMessage = try CiphertextMessage(PlaintextContent(DecryptionErrorMessage(
+            originalMessageBytes: reply.serialize(),
+            type: reply.messageType,
+            timestamp: 408,
+            originalSenderDeviceId: 1
+        )))
+
+        let cases: [(CiphertextMessage, CiphertextMessage.MessageType)] = [
+            (preKeyMessage, .preKey),
+            (whisperMessage, .whisper),
+            (senderKeyMessage, .senderKey),
+            (plaintextMessage, .plaintext),
+        ]
+        for (message, expectedType) in cases {
+            XCTAssertEqual(message.messageType, expectedType)
+            let reconstructed = try CiphertextMessage(bytes: message.serialize(), type: expectedType)
+            XCTAssertEqual(reconstructed.messageType, expectedType)
+            XCTAssertEqual(reconstructed.serialize(), message.serialize())
+            let content = try UnidentifiedSenderMessageContent(
+                message,
+                from: sender_cert,
+                contentHint: .default,
+                groupId: []
+            )
+            XCTAssertEqual(content.messageType, expectedType)
+            XCTAssertEqual(content.senderCertificate.serialize(), sender_cert.serialize())
+            XCTAssertEqual(try UnidentifiedSenderMessageContent(bytes: content.serialize()).messageType, expectedType)
+        }
+        XCTAssertThrowsError(try CiphertextMessage(bytes: whisperMessage.serialize(), type: .init(rawValue: 99)))
+    }
+
     func testArchiveSession() throws {
         let bob_address = try! ProtocolAddress(name: "+14151111112", deviceId: 1)
 
@@ -294,13 +509,327 @@ class SessionTests: TestCaseBase {
         let session: SessionRecord! = try! alice_store.loadSession(for: bob_address, context: NullContext())
         XCTAssertNotNil(session)
         XCTAssertTrue(session.hasCurrentState)
+        XCTAssertTrue(session.hasCurrentSessionState)
+        XCTAssertTrue(session.hasSenderChain)
+        XCTAssertEqual(session.sessionVersion, 3)
         XCTAssertFalse(try! session.currentRatchetKeyMatches(IdentityKeyPair.generate().publicKey))
         session.archiveCurrentState()
         XCTAssertFalse(session.hasCurrentState)
+        XCTAssertFalse(session.hasCurrentSessionState)
+        XCTAssertFalse(session.hasSenderChain)
+        XCTAssertEqual(session.sessionVersion, 0)
         XCTAssertFalse(try! session.currentRatchetKeyMatches(IdentityKeyPair.generate().publicKey))
         // A redundant archive shouldn't break anything.
         session.archiveCurrentState()
         XCTAssertFalse(session.hasCurrentState)
+
+        // The archived record survives a round trip.
+        let reloaded = try SessionRecord(bytes: session.serialize())
+        XCTAssertFalse(reloaded.hasCurrentSessionState)
+        XCTAssertEqual(reloaded.serialize(), session.serialize())
+        XCTAssertEqual(reloaded.stateHash, session.stateHash)
+
+        // With no current session, there's no remote registration ID to report.
+        XCTAssertThrowsError(try session.remoteRegistrationId()) { error in
+            guard case SignalError.invalidState(_) = error else {
+                XCTFail("wrong error thrown: \(error)")
+                return
+            }
+        }
+    }
+
+    func testWipeSessionSecrets() throws {
+        let alice_address = try! ProtocolAddress(name: "+14151111111", deviceId: 1)
+        let bob_address = try! ProtocolAddress(name: "+14151111112", deviceId: 1)
+
+        let alice_store = InMemorySignalProtocolStore()
+        let bob_store = InMemorySignalProtocolStore()
+
+        initializeSessionsV3(alice_store: alice_store, bob_store: bob_store, bob_address: bob_address)
+
+        let ctext = try signalEncrypt(
+            message: [1, 2, 3],
+            for: bob_address,
+            sessionStore: alice_store,
+            identityStore: alice_store,
+            context: NullContext()
+        )
+        _ = try signalDecryptPreKey(
+            message: PreKeySignalMessage(bytes: ctext.serialize()),
+            from: alice_address,
+            sessionStore: bob_store,
+            identityStore: bob_store,
+            preKeyStore: bob_store,
+            signedPreKeyStore: bob_store,
+            kyberPreKeyStore: bob_store,
+            context: NullContext()
+        )
+        let reply = try signalEncrypt(
+            message: [4, 5, 6],
+            for: alice_address,
+            sessionStore: bob_store,
+            identityStore: bob_store,
+            context: NullContext()
+        )
+
+        let session: SessionRecord! = try alice_store.loadSession(for: bob_address, context: NullContext())
+        let registrationId = try session.remoteRegistrationId()
+        try session.wipeSecrets()
+        XCTAssertEqual(try session.remoteRegistrationId(), registrationId)
+        try alice_store.storeSession(session, for: bob_address, context: NullContext())
+
+        XCTAssertThrowsError(try signalDecrypt(
+            message: SignalMessage(bytes: reply.serialize()),
+            from: bob_address,
+            sessionStore: alice_store,
+            identityStore: alice_store,
+            context: NullContext()
+        ))
+    }
+
+    func testDecryptWithPlaintextLimit() throws {
+        let alice_address = try! ProtocolAddress(name: "+14151111111", deviceId: 1)
+        let bob_address = try! ProtocolAddress(name: "+14151111112", deviceId: 1)
+
+        let alice_store = InMemorySignalProtocolStore()
+        let bob_store = InMemorySignalProtocolStore()
+
+        initializeSessionsV3(alice_store: alice_store, bob_store: bob_store, bob_address: bob_address)
+
+        let ctext = try signalEncrypt(
+            message: [1, 2, 3],
+            for: bob_address,
+            sessionStore: alice_store,
+            identityStore: alice_store,
+            context: NullContext()
+        )
+        let ptext = try signalDecryptPreKey(
+            message: PreKeySignalMessage(bytes: ctext.serialize()),
+            from: alice_address,
+            sessionStore: bob_store,
+            identityStore: bob_store,
+            preKeyStore: bob_store,
+            signedPreKeyStore: bob_store,
+            kyberPreKeyStore: bob_store,
+            context: NullContext(),
+            maxPlaintextLength: 3
+        )
+        XCTAssertEqual(ptext, [1, 2, 3])
+
+        let reply = try signalEncrypt(
+            message: [4, 5, 6, 7],
+            for: alice_address,
+            sessionStore: bob_store,
+            identityStore: bob_store,
+            context: NullContext()
+        )
+        XCTAssertThrowsError(try signalDecrypt(
+            message: SignalMessage(bytes: reply.serialize()),
+            from: bob_address,
+            sessionStore: alice_store,
+            identityStore: alice_store,
+            context: NullContext(),
+            maxPlaintextLength: 3
+        )) { error in
+            guard case SignalError.invalidMessage(_) = error else {
+                XCTFail("unexpected error: \(error)")
+                return
+            }
+        }
+
+        // The rejected message wasn't consumed.
+        let replyPtext = try signalDecrypt(
+            message: SignalMessage(bytes: reply.serialize()),
+            from: bob_address,
+            sessionStore: alice_store,
+            identityStore: alice_store,
+            context: NullContext(),
+            maxPlaintextLength: 4
+        )
+        XCTAssertEqual(replyPtext, [4, 5, 6, 7])
+    }
+
+    func testDecryptSignalMessageErrors() throws {
+        let alice_address = try ProtocolAddress(name: "+14151111111", deviceId: 1)
+        let bob_address = try ProtocolAddress(name: "+14151111112", deviceId: 1)
+
+        let alice_store = InMemorySignalProtocolStore()
+        let bob_store = InMemorySignalProtocolStore()
+
+        initializeSessionsV3(alice_store: alice_store, bob_store: bob_store, bob_address: bob_address)
+
+        let ctext = try signalEncrypt(
+            message: [1, 2, 3],
+            for: bob_address,
+            sessionStore: alice_store,
+            identityStore: alice_store,
+            context: NullContext()
+        )
+        _ = try signalDecryptPreKey(
+            message: PreKeySignalMessage(bytes: ctext.serialize()),
+            from: alice_address,
+            sessionStore: bob_store,
+            identityStore: bob_store,
+            preKeyStore: bob_store,
+            signedPreKeyStore: bob_store,
+            kyberPreKeyStore: bob_store,
+            context: NullContext()
+        )
+
+        let replies = try [[4], [5]].map { message in
+            try SignalMessage(bytes: signalEncrypt(
+                message: message,
+                for: alice_address,
+                sessionStore: bob_store,
+                identityStore: bob_store,
+                context: NullContext()
+            ).serialize())
+        }
+
+        for (reply, expected) in zip(replies, [[4], [5]] as [[UInt8]]) {
+            XCTAssertEqual(
+                try signalDecrypt(message: reply, from: bob_address, sessionStore: alice_store, identityStore: alice_store, context: NullContext()),
+                expected
+            )
+        }
+
+        XCTAssertThrowsError(try signalDecrypt(message: replies[0], from: bob_address, sessionStore: alice_store, identityStore: alice_store, context: NullContext())) { error in
+            guard case SignalError.duplicatedMessage(_) = error else {
+                XCTFail("wrong error thrown: \(error)")
+                return
+            }
+        }
+
+        let stranger_store = InMemorySignalProtocolStore()
+        XCTAssertThrowsError(try signalDecrypt(message: replies[1], from: bob_address, sessionStore: stranger_store, identityStore: stranger_store, context: NullContext())) { error in
+            guard case SignalError.sessionNotFound(_) = error else {
+                XCTFail("wrong error thrown: \(error)")
+                return
+            }
+        }
+
+        let third = try signalEncrypt(
+            message: [6],
+            for: alice_address,
+            sessionStore: bob_store,
+            identityStore: bob_store,
+            context: NullContext()
+        )
+        var tampered = third.serialize()
+        tampered[tampered.count - 1] ^= 1
+        XCTAssertThrowsError(try signalDecrypt(message: SignalMessage(bytes: tampered), from: bob_address, sessionStore: alice_store, identityStore: alice_store, context: NullContext())) { error in
+            guard case SignalError.invalidMessage(_) = error else {
+                XCTFail("wrong error thrown: \(error)")
+                return
+            }
+        }
+    }
+
+    func testIdentityVerificationLevels() throws {
+        class VerifyingStore: InMemorySignalProtocolStore, IdentityVerificationStore {
+            var verified: Set<ProtocolAddress> = []
+
+            func verificationLevel(for address: ProtocolAddress, context: StoreContext) throws -> IdentityVerificationLevel {
+                self.verified.contains(address) ? .trusted : .untrusted
+            }
+        }
+
+        let trusted_address = try ProtocolAddress(name: "+14151111111", deviceId: 1)
+        let untrusted_address = try ProtocolAddress(name: "+14151111112", deviceId: 1)
+        let unknown_address = try ProtocolAddress(name: "+14151111113", deviceId: 1)
+
+        let store = VerifyingStore()
+        _ = try store.saveIdentity(IdentityKeyPair.generate().identityKey, for: trusted_address, context: NullContext())
+        _ = try store.saveIdentity(IdentityKeyPair.generate().identityKey, for: untrusted_address, context: NullContext())
+        store.verified.insert(trusted_address)
+
+        // Both saved identities are trusted on first use; only one has been verified.
+        XCTAssertTrue(try store.isTrustedIdentity(store.identity(for: untrusted_address, context: NullContext())!, for: untrusted_address, direction: .sending, context: NullContext()))
+
+        let levels = try identityVerificationLevels(
+            for: [trusted_address, untrusted_address, unknown_address],
+            identityStore: store,
+            context: NullContext()
+        )
+        XCTAssertEqual(levels, [.trusted, .untrusted, .unknown])
+    }
+
+    func testTrustCheckDirections() throws {
+        class DirectionRecordingStore: InMemorySignalProtocolStore {
+            var directions: [Direction] = []
+
+            override func isTrustedIdentity(_ identity: IdentityKey, for address: ProtocolAddress, direction: Direction, context: StoreContext) throws -> Bool {
+                self.directions.append(direction)
+                return try super.isTrustedIdentity(identity, for: address, direction: direction, context: context)
+            }
+        }
+
+        let alice_address = try ProtocolAddress(name: "+14151111111", deviceId: 1)
+        let bob_address = try ProtocolAddress(name: "+14151111112", deviceId: 1)
+
+        let alice_store = DirectionRecordingStore()
+        let bob_store = DirectionRecordingStore()
+
+        initializeSessionsV4(alice_store: alice_store, bob_store: bob_store, bob_address: bob_address)
+        alice_store.directions = []
+
+        let ctext = try signalEncrypt(
+            message: [1, 2, 3],
+            for: bob_address,
+            sessionStore: alice_store,
+            identityStore: alice_store,
+            context: NullContext()
+        )
+        XCTAssertFalse(alice_store.directions.isEmpty)
+        XCTAssert(alice_store.directions.allSatisfy { $0 == .sending })
+
+        _ = try signalDecryptPreKey(
+            message: PreKeySignalMessage(bytes: ctext.serialize()),
+            from: alice_address,
+            sessionStore: bob_store,
+            identityStore: bob_store,
+            preKeyStore: bob_store,
+            signedPreKeyStore: bob_store,
+            kyberPreKeyStore: bob_store,
+            context: NullContext()
+        )
+        XCTAssertFalse(bob_store.directions.isEmpty)
+        XCTAssert(bob_store.directions.allSatisfy { $0 == .receiving })
+    }
+
+    func testProcessPreKeyBundleUntrustedIdentity() throws {
+        let bob_address = try ProtocolAddress(name: "+14151111112", deviceId: 1)
+
+        let alice_store = InMemorySignalProtocolStore()
+        let bob_store = InMemorySignalProtocolStore()
+
+        // Alice has already seen a different identity for Bob.
+        _ = try alice_store.saveIdentity(IdentityKeyPair.generate().identityKey, for: bob_address, context: NullContext())
+
+        let bob_signed_pre_key = PrivateKey.generate()
+        let bob_identity_key_pair = try bob_store.identityKeyPair(context: NullContext())
+        let bob_bundle = try PreKeyBundle(
+            registrationId: bob_store.localRegistrationId(context: NullContext()),
+            deviceId: 1,
+            signedPrekeyId: 3006,
+            signedPrekey: bob_signed_pre_key.publicKey,
+            signedPrekeySignature: bob_identity_key_pair.privateKey.generateSignature(message: bob_signed_pre_key.publicKey.serialize()),
+            identity: bob_identity_key_pair.identityKey
+        )
+
+        XCTAssertThrowsError(try processPreKeyBundle(
+            bob_bundle,
+            for: bob_address,
+            sessionStore: alice_store,
+            identityStore: alice_store,
+            context: NullContext()
+        )) { error in
+            guard case SignalError.untrustedIdentity(_) = error else {
+                XCTFail("wrong error thrown: \(error)")
+                return
+            }
+        }
+        XCTAssertNil(try alice_store.loadSession(for: bob_address, context: NullContext()))
     }
 
     func testSealedSenderGroupCipher() throws {
@@ -406,6 +935,97 @@ class SessionTests: TestCaseBase {
         XCTAssertEqual(1, another_skdm.iteration)
     }
 
+    func testSenderKeyDistributionMessageSignatureKey() throws {
+        let alice_address = try ProtocolAddress(name: "9d0652a3-dcc3-4d11-975f-74d61598733f", deviceId: 1)
+        let alice_store = InMemorySignalProtocolStore()
+        let distribution_id = UUID(uuidString: "d1d1d1d1-7000-11eb-b32a-33b8a8a487a6")!
+
+        // Let the distribution message go before using its key; the key owns its own handle.
+        let signatureKey = try SenderKeyDistributionMessage(
+            from: alice_address,
+            distributionId: distribution_id,
+            store: alice_store,
+            context: NullContext()
+        ).signatureKey
+
+        let message = try groupEncrypt(
+            [1, 2, 3],
+            from: alice_address,
+            distributionId: distribution_id,
+            store: alice_store,
+            context: NullContext()
+        )
+        let senderKeyMessage = try SenderKeyMessage(bytes: message.serialize())
+        XCTAssertTrue(try senderKeyMessage.verifySignature(against: signatureKey))
+        XCTAssertFalse(try senderKeyMessage.verifySignature(against: IdentityKeyPair.generate().publicKey))
+    }
+
+    func testCiphertextMessageTypes() throws {
+        let alice_address = try! ProtocolAddress(name: "9d0652a3-dcc3-4d11-975f-74d61598733f", deviceId: 1)
+        let bob_address = try! ProtocolAddress(name: "6838237D-02F6-4098-B110-698253D15961", deviceId: 1)
+
+        let alice_store = InMemorySignalProtocolStore()
+        let bob_store = InMemorySignalProtocolStore()
+
+        initializeSessionsV4(alice_store: alice_store, bob_store: bob_store, bob_address: bob_address)
+
+        let pre_key_message = try signalEncrypt(
+            message: [1],
+            for: bob_address,
+            sessionStore: alice_store,
+            identityStore: alice_store,
+            context: NullContext()
+        )
+        XCTAssertEqual(pre_key_message.messageType, .preKey)
+        _ = try signalDecryptPreKey(
+            message: PreKeySignalMessage(bytes: pre_key_message.serialize()),
+            from: alice_address,
+            sessionStore: bob_store,
+            identityStore: bob_store,
+            preKeyStore: bob_store,
+            signedPreKeyStore: bob_store,
+            kyberPreKeyStore: bob_store,
+            context: NullContext()
+        )
+
+        let whisper_message = try signalEncrypt(
+            message: [2],
+            for: alice_address,
+            sessionStore: bob_store,
+            identityStore: bob_store,
+            context: NullContext()
+        )
+        XCTAssertEqual(whisper_message.messageType, .whisper)
+        _ = try SignalMessage(bytes: whisper_message.serialize())
+
+        let distribution_id = UUID(uuidString: "d1d1d1d1-7000-11eb-b32a-33b8a8a487a6")!
+        _ = try SenderKeyDistributionMessage(
+            from: alice_address,
+            distributionId: distribution_id,
+            store: alice_store,
+            context: NullContext()
+        )
+        let sender_key_message = try groupEncrypt(
+            [3],
+            from: alice_address,
+            distributionId: distribution_id,
+            store: alice_store,
+            context: NullContext()
+        )
+        XCTAssertEqual(sender_key_message.messageType, .senderKey)
+        XCTAssertEqual(try SenderKeyMessage(bytes: sender_key_message.serialize()).distributionId, distribution_id)
+
+        let error_message = try DecryptionErrorMessage(
+            originalMessageBytes: whisper_message.serialize(),
+            type: whisper_message.messageType,
+            timestamp: 408,
+            originalSenderDeviceId: bob_address.deviceId
+        )
+        let plaintext_message = CiphertextMessage(PlaintextContent(error_message))
+        XCTAssertEqual(plaintext_message.messageType, .plaintext)
+        _ = try PlaintextContent(bytes: plaintext_message.serialize())
+    }
+
     func testSealedSenderGroupCipherWithBadRegistrationId() throws {
         let alice_address = try! ProtocolAddress(name: "9d0652a3-dcc3-4d11-975f-74d61598733f", deviceId: 1)
         let bob_address = try! ProtocolAddress(name: "6838237D-02F6-4098-B110-698253D15961", deviceId: 1)